@@ -1,18 +1,134 @@
+use crate::config::{AppConfig, ConnectionConfig, TimeoutConfig};
+use crate::content_type;
+use crate::error::{Result, XynoxaError};
+use crate::telemetry::TelemetryReport;
+use crate::trpc;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::Client;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::path::Path;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 use tokio::fs::File;
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
 
 const MAX_UPLOAD_BYTES: u64 = 5 * 1024 * 1024 * 1024; // 5 GB
 const CHUNK_THRESHOLD_BYTES: u64 = 50 * 1024 * 1024; // 50 MB
-const CHUNK_SIZE_BYTES: usize = 1 * 1024 * 1024; // 1 MB (align with web uploader; avoid proxy body limits)
+const MAX_BATCH_CALLS: usize = 50; // keeps batched tRPC URLs well below proxy limits
+/// Range of server API versions this client can sync with.
+pub const MIN_API_VERSION: u32 = 1;
+pub const MAX_API_VERSION: u32 = 1;
+pub const CHUNK_SIZE_BYTES: usize = 1 * 1024 * 1024; // 1 MB (align with web uploader; avoid proxy body limits)
+/// Downloads report a new [`ResumePoint`] at most this often (bytes).
+const RESUME_INTERVAL_BYTES: u64 = 8 * 1024 * 1024;
+/// Downloads from this size on fetch several ranges at once, which helps on
+/// high-latency connections where a single stream cannot fill the line.
+const PARALLEL_DOWNLOAD_BYTES: u64 = 256 * 1024 * 1024;
+/// Ranges a parallel download fetches at once.
+const PARALLEL_RANGES: u64 = 4;
+/// Appended to the name of a file while its download is in progress.
+pub const PARTIAL_SUFFIX: &str = ".xynoxa-part";
+
+/// HTTP clients built so far with the options they were built for, newest
+/// last. A clone shares the connection pool, so the sync worker keeps its
+/// warm connections across restarts and shares them with UI commands.
+static SHARED_CLIENTS: Mutex<Vec<(ClientOptions, Client)>> = Mutex::new(Vec::new());
+/// Option sets whose clients are kept; older ones are dropped.
+const MAX_SHARED_CLIENTS: usize = 4;
+
+/// Connection settings for [`XynoxaClient`], taken from `AppConfig`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientOptions {
+    pub timeouts: TimeoutConfig,
+    pub connection: ConnectionConfig,
+    /// Sent as `X-Client-Device`; `None` uses the host name.
+    pub device_name: Option<String>,
+}
+
+impl ClientOptions {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            timeouts: config.timeouts.clone(),
+            connection: config.connection.clone(),
+            device_name: config.device_name.clone(),
+        }
+    }
+}
+
+/// The client for `options`, built on first use.
+fn shared_client(options: &ClientOptions) -> Client {
+    let mut clients = SHARED_CLIENTS.lock().unwrap();
+    if let Some(index) = clients.iter().position(|(o, _)| o == options) {
+        let entry = clients.remove(index);
+        let client = entry.1.clone();
+        clients.push(entry);
+        return client;
+    }
+    let client = build_client(options);
+    if clients.len() >= MAX_SHARED_CLIENTS {
+        clients.remove(0);
+    }
+    clients.push((options.clone(), client.clone()));
+    client
+}
+
+fn build_client(options: &ClientOptions) -> Client {
+    let timeouts = &options.timeouts;
+    let connection = &options.connection;
+    let device_name = options
+        .device_name
+        .clone()
+        .unwrap_or_else(crate::config::default_device_name);
+
+    // Lets the server's session list show e.g. "Laptop - Desktop Client 1.0.0"
+    let mut headers = HeaderMap::new();
+    // Header values must be visible ASCII; percent-encode anything else
+    let device_header = if device_name
+        .chars()
+        .all(|c| c.is_ascii_graphic() || c == ' ')
+    {
+        HeaderValue::from_str(&device_name).ok()
+    } else {
+        HeaderValue::from_str(&urlencoding::encode(&device_name)).ok()
+    };
+    if let Some(value) = device_header {
+        headers.insert("X-Client-Device", value);
+    }
+
+    // [WARNING] SSL Verification Disabled for Dev/Testing
+    let mut builder = Client::builder()
+        .danger_accept_invalid_certs(true)
+        .user_agent(user_agent())
+        .default_headers(headers)
+        .connect_timeout(Duration::from_secs(timeouts.connect_secs))
+        .read_timeout(Duration::from_secs(timeouts.read_secs))
+        .timeout(Duration::from_secs(timeouts.request_secs))
+        .pool_max_idle_per_host(connection.max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(connection.idle_timeout_secs));
+    if connection.keep_alive_secs > 0 {
+        let interval = Duration::from_secs(connection.keep_alive_secs);
+        builder = builder
+            .tcp_keepalive(interval)
+            .http2_keep_alive_interval(interval)
+            .http2_keep_alive_while_idle(true);
+    }
+    if !connection.http2 {
+        builder = builder.http1_only();
+    }
+    builder.build().unwrap_or_else(|_| Client::new())
+}
 
 #[derive(Clone)]
 pub struct XynoxaClient {
     client: Client,
-    token: String,
+    /// `None` for [`XynoxaClient::anonymous`]
+    token: Option<String>,
     base_url: String,
+    // Overrides the client-wide request timeout for uploads and downloads
+    transfer_timeout: Duration,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -28,7 +144,7 @@ pub struct SyncEvent {
     pub data: Option<FileData>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct FileData {
     pub path: Option<String>, // Often just the filename for files
     pub name: Option<String>, // Name for folders
@@ -42,6 +158,10 @@ pub struct FileData {
     pub parent_id: Option<String>,
     pub hash: Option<String>,
     pub size: Option<String>,
+    /// Tag names as set in the web UI; absent when the event does not touch them
+    pub tags: Option<Vec<String>>,
+    #[serde(rename = "isFavorite", alias = "favorite")]
+    pub favorite: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -49,6 +169,26 @@ pub struct SyncResponse {
     pub events: Vec<SyncEvent>,
     #[serde(rename = "nextCursor")]
     pub next_cursor: u64,
+    /// Set by servers that paginate explicitly. Older servers omit it.
+    #[serde(rename = "hasMore", default)]
+    pub has_more: Option<bool>,
+}
+
+impl SyncResponse {
+    /// Whether this page ends the event stream for a pull started at `cursor`.
+    ///
+    /// Trusts `hasMore` when the server sends it; otherwise the stream ends on an
+    /// empty page. A cursor that does not advance always ends the stream so a
+    /// misbehaving server cannot cause an endless loop.
+    pub fn is_last_page(&self, cursor: u64) -> bool {
+        if self.next_cursor <= cursor {
+            return true;
+        }
+        match self.has_more {
+            Some(has_more) => !has_more,
+            None => self.events.is_empty(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -64,6 +204,419 @@ pub struct FolderEntry {
     pub name: Option<String>,
 }
 
+/// Answer of `/api/version`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerInfo {
+    pub api_version: u32,
+    /// Server release, for display only
+    pub version: Option<String>,
+}
+
+impl ServerInfo {
+    /// Fails with [`XynoxaError::Incompatible`] outside the supported API range.
+    pub fn check_compatibility(&self) -> Result<()> {
+        if (MIN_API_VERSION..=MAX_API_VERSION).contains(&self.api_version) {
+            return Ok(());
+        }
+        let hint = if self.api_version < MIN_API_VERSION {
+            "Please update the Xynoxa server"
+        } else {
+            "Please update the desktop client"
+        };
+        Err(XynoxaError::Incompatible(format!(
+            "Server API version {} ({}) is not supported; this client supports {}-{}. {}.",
+            self.api_version,
+            self.version.as_deref().unwrap_or("unknown release"),
+            MIN_API_VERSION,
+            MAX_API_VERSION,
+            hint
+        )))
+    }
+}
+
+/// Where the setup wizard found the Xynoxa API, see [`probe_server`].
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerProbe {
+    /// Normalized base URL to store as `server_url`
+    pub url: String,
+    /// Instance name, if the server reports one
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub info: ServerInfo,
+}
+
+/// Finds the Xynoxa API behind a URL typed by the user. Adds a missing scheme,
+/// drops `/api/...` suffixes, tries each parent path and follows redirects.
+pub async fn probe_server(input: &str, options: &ClientOptions) -> Result<ServerProbe> {
+    let client = XynoxaClient::with_options(String::new(), String::new(), options).client;
+    let mut last_error = None;
+    for candidate in probe_candidates(input)? {
+        match probe_candidate(&client, &candidate).await {
+            Ok(Some(probe)) => {
+                tracing::info!("Found Xynoxa API at {} (entered: {})", probe.url, input);
+                return Ok(probe);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::debug!("Probing {} failed: {}", candidate, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    // An unreachable host is more useful to report than "not found"
+    Err(match last_error {
+        Some(e @ XynoxaError::Network(_)) => e,
+        _ => XynoxaError::Incompatible(format!("No Xynoxa server found at {}", input.trim())),
+    })
+}
+
+/// Posts an anonymous telemetry report to the server at `base_url`. Sent
+/// without credentials, so it cannot be tied to the account. Only called when
+/// the user opted in.
+pub async fn send_telemetry(
+    base_url: &str,
+    options: &ClientOptions,
+    report: &TelemetryReport,
+) -> Result<()> {
+    let url = format!("{}/api/telemetry", base_url.trim_end_matches('/'));
+    let res = shared_client(options)
+        .post(&url)
+        .json(report)
+        .send()
+        .await?;
+
+    let status = res.status();
+    if !status.is_success() {
+        let body = res.text().await.unwrap_or_else(|_| "No body".to_string());
+        return Err(XynoxaError::from_status(
+            status,
+            format!("Telemetry report rejected. Body: {}", body),
+        ));
+    }
+    Ok(())
+}
+
+/// Base URLs to try for `input`, most specific first.
+fn probe_candidates(input: &str) -> Result<Vec<String>> {
+    let input = input.trim();
+    let with_scheme = if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("https://{}", input)
+    };
+    let invalid = || XynoxaError::Config(format!("Not a valid URL: {}", input));
+    let mut url = reqwest::Url::parse(&with_scheme).map_err(|_| invalid())?;
+    if url.host_str().is_none() || !matches!(url.scheme(), "http" | "https") {
+        return Err(invalid());
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+
+    let mut segments: Vec<String> = url
+        .path_segments()
+        .map(|segments| {
+            segments
+                .filter(|segment| !segment.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    // "https://host/api" and "https://host/api/trpc" are typos for "https://host"
+    if let Some(api) = segments.iter().position(|segment| segment == "api") {
+        segments.truncate(api);
+    }
+
+    let origin = url.origin().ascii_serialization();
+    Ok((0..=segments.len())
+        .rev()
+        .map(|depth| {
+            std::iter::once(origin.as_str())
+                .chain(segments[..depth].iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .collect())
+}
+
+/// `Ok(None)` if `base` answers but does not serve the Xynoxa API.
+async fn probe_candidate(client: &Client, base: &str) -> Result<Option<ServerProbe>> {
+    let res = client.get(format!("{}/api/version", base)).send().await?;
+    // Redirects (http -> https, www, moved instances) change the base URL
+    let Some(base) = res
+        .url()
+        .as_str()
+        .strip_suffix("/api/version")
+        .map(|base| base.trim_end_matches('/').to_string())
+    else {
+        return Ok(None);
+    };
+    let status = res.status();
+    if status.is_success() {
+        let body: serde_json::Value = match res.json().await {
+            Ok(body) => body,
+            Err(_) => return Ok(None),
+        };
+        let name = body
+            .get("name")
+            .and_then(|n| n.as_str())
+            .map(str::to_string);
+        return Ok(serde_json::from_value(body).ok().map(|info| ServerProbe {
+            url: base,
+            name,
+            info,
+        }));
+    }
+    if status != reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    // Servers predating /api/version: any tRPC error response gives them away
+    let res = client.get(format!("{}/api/trpc/", base)).send().await?;
+    let body: serde_json::Value = match res.json().await {
+        Ok(body) => body,
+        Err(_) => return Ok(None),
+    };
+    let is_trpc = match &body {
+        serde_json::Value::Array(items) => items.iter().any(|item| item.get("error").is_some()),
+        value => value.get("error").is_some(),
+    };
+    Ok(is_trpc.then_some(ServerProbe {
+        url: base,
+        name: None,
+        info: ServerInfo {
+            api_version: 1,
+            version: None,
+        },
+    }))
+}
+
+/// A client registered for the account, see `devices.*`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    pub platform: Option<String>,
+    pub client_version: Option<String>,
+    pub last_seen_at: Option<String>,
+}
+
+/// Answer of `auth.session`: who the token belongs to and what it may do.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub user: SessionUser,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// `None` for tokens that never expire
+    #[serde(default)]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionUser {
+    pub id: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+impl SessionInfo {
+    /// Time left if the token expires within `warning`, so users can renew it
+    /// before syncing stops.
+    pub fn expires_within(
+        &self,
+        warning: chrono::Duration,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<chrono::Duration> {
+        let left = self.expires_at? - now;
+        (left <= warning).then_some(left)
+    }
+}
+
+/// A device pairing waiting for approval in the web UI, see `devices.pairing.*`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PairingRequest {
+    pub pairing_id: String,
+    /// Short code the user compares with the one shown in the browser
+    pub code: String,
+    /// Approval page, also shown as a QR code
+    pub verification_url: String,
+    /// Seconds until the pairing expires
+    pub expires_in: u64,
+    /// Seconds between polls the server asks for
+    #[serde(default = "default_pairing_interval")]
+    pub interval: u64,
+}
+
+fn default_pairing_interval() -> u64 {
+    5
+}
+
+/// Answer of `devices.pairing.poll`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum PairingStatus {
+    Pending,
+    /// Carries a token scoped to this device
+    Approved {
+        token: String,
+        /// Id the approval registered this device under
+        #[serde(default, rename = "deviceId")]
+        device_id: Option<String>,
+    },
+    Denied,
+    Expired,
+}
+
+/// A file in the server trash (soft-deleted, still restorable).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrashedFile {
+    pub id: String,
+    pub name: String,
+    pub path: Option<String>,
+    #[serde(rename = "folderId")]
+    pub folder_id: Option<String>,
+    pub size: Option<String>,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<String>,
+}
+
+/// A stored version of a file on the server, newest first in listings.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileVersion {
+    pub version: i64,
+    pub size: Option<String>,
+    pub hash: Option<String>,
+    pub created_at: Option<String>,
+}
+
+/// A file the server's search found by name or content.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteSearchHit {
+    pub id: String,
+    pub name: String,
+    pub path: Option<String>,
+    pub folder_id: Option<String>,
+    pub size: Option<String>,
+    /// Matching text around the query, for content matches
+    #[serde(default)]
+    pub snippet: Option<String>,
+}
+
+/// Public share created for a file. Older servers only return the token.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShareLink {
+    pub id: String,
+    pub url: Option<String>,
+    pub token: Option<String>,
+}
+
+/// `User-Agent` sent with every request, e.g. `Xynoxa-Desktop-Client/1.0.0 (linux; x86_64)`.
+pub fn user_agent() -> String {
+    format!(
+        "Xynoxa-Desktop-Client/{} ({}; {})",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+}
+
+/// Result of a (conditional) download.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadOutcome {
+    Downloaded {
+        etag: Option<String>,
+    },
+    /// 304: the local copy matches one of the sent ETags
+    NotModified,
+}
+
+/// How far an interrupted transfer got, so it can continue after a restart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumePoint {
+    /// Chunked upload session id, or the ETag of the partially downloaded content
+    pub token: String,
+    /// Bytes the receiving side has confirmed
+    pub offset: u64,
+}
+
+/// Called with the new [`ResumePoint`] whenever more bytes are confirmed.
+pub type ResumeSink<'a> = &'a (dyn Fn(&ResumePoint) + Send + Sync);
+
+/// Splits `total` bytes into `count` ranges of about equal size.
+fn split_ranges(total: u64, count: u64) -> Vec<std::ops::Range<u64>> {
+    let size = total.div_ceil(count.max(1)).max(1);
+    (0..total)
+        .step_by(size as usize)
+        .map(|start| start..(start + size).min(total))
+        .collect()
+}
+
+/// Streams `range` of the body of `res` into its place in `partial`. The
+/// response may hold more (the first range reuses the full download), the
+/// rest is not read.
+async fn write_range(
+    mut res: reqwest::Response,
+    partial: &Path,
+    range: &std::ops::Range<u64>,
+) -> Result<()> {
+    let mut file = File::options().write(true).open(partial).await?;
+    file.seek(SeekFrom::Start(range.start)).await?;
+    let mut remaining = range.end - range.start;
+    while remaining > 0 {
+        let Some(chunk) = res.chunk().await? else {
+            return Err(XynoxaError::Network(format!(
+                "Download ended {} bytes early",
+                remaining
+            )));
+        };
+        let take = chunk.len().min(remaining as usize);
+        file.write_all(&chunk[..take]).await?;
+        remaining -= take as u64;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+/// Checks a downloaded file against its ETag if the server uses SHA-256
+/// content hashes as ETags. A mismatch discards the file.
+async fn verify_content_hash(path: &Path, etag: &str) -> Result<()> {
+    let expected = etag.trim_matches('"').to_ascii_lowercase();
+    if expected.len() != 64 || !expected.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Ok(());
+    }
+    let file = path.to_path_buf();
+    let actual = tokio::task::spawn_blocking(move || -> std::io::Result<String> {
+        use sha2::{Digest, Sha256};
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(file)?, &mut hasher)?;
+        Ok(hex::encode(hasher.finalize()))
+    })
+    .await
+    .map_err(|e| XynoxaError::Other(e.to_string()))??;
+    if actual != expected {
+        let _ = tokio::fs::remove_file(path).await;
+        return Err(XynoxaError::Decode(format!(
+            "Downloaded content hash {} does not match {}",
+            actual, expected
+        )));
+    }
+    Ok(())
+}
+
+/// Where the download of `local_path` is written until it is complete.
+pub fn partial_path(local_path: &Path) -> PathBuf {
+    let mut partial = local_path.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    PathBuf::from(partial)
+}
+
 // Upload API response wrapper: { file: { ... } }
 #[derive(Deserialize, Debug, Clone)]
 pub struct UploadResponse {
@@ -81,154 +634,293 @@ pub struct UploadedFile {
     pub storage_path: Option<String>,
 }
 
+/// Mutations queued during a sync cycle and sent as batched tRPC requests by
+/// [`XynoxaClient::execute_batch`].
+#[derive(Default)]
+pub struct MutationBatch {
+    calls: Vec<(String, serde_json::Value)>,
+}
+
+impl MutationBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Queued `(procedure, input)` pairs in queue order.
+    pub fn calls(&self) -> &[(String, serde_json::Value)] {
+        &self.calls
+    }
+
+    /// Queues a call and returns its index in the results of `execute_batch`.
+    pub fn push<T: Serialize>(&mut self, procedure: &str, input: &T) -> Result<usize> {
+        self.calls
+            .push((procedure.to_string(), serde_json::to_value(input)?));
+        Ok(self.calls.len() - 1)
+    }
+
+    pub fn soft_delete_file(&mut self, file_id: &str) -> Result<usize> {
+        #[derive(Serialize)]
+        struct Input<'a> {
+            #[serde(rename = "fileId")]
+            file_id: &'a str,
+        }
+        self.push("files.softDelete", &Input { file_id })
+    }
+
+    pub fn delete_folder(&mut self, folder_id: &str) -> Result<usize> {
+        #[derive(Serialize)]
+        struct Input<'a> {
+            id: &'a str,
+        }
+        self.push("folders.delete", &Input { id: folder_id })
+    }
+
+    pub fn create_folder(&mut self, name: &str, parent_id: Option<&str>) -> Result<usize> {
+        #[derive(Serialize)]
+        struct Input<'a> {
+            name: &'a str,
+            #[serde(rename = "parentId")]
+            parent_id: Option<&'a str>,
+        }
+        self.push("folders.create", &Input { name, parent_id })
+    }
+
+    pub fn rename_file(&mut self, file_id: &str, name: &str) -> Result<usize> {
+        #[derive(Serialize)]
+        struct Input<'a> {
+            id: &'a str,
+            name: &'a str,
+        }
+        self.push("files.rename", &Input { id: file_id, name })
+    }
+
+    pub fn move_file(&mut self, file_id: &str, folder_id: Option<&str>) -> Result<usize> {
+        #[derive(Serialize)]
+        struct Input<'a> {
+            id: &'a str,
+            #[serde(rename = "folderId")]
+            folder_id: Option<&'a str>,
+        }
+        self.push(
+            "files.move",
+            &Input {
+                id: file_id,
+                folder_id,
+            },
+        )
+    }
+}
+
 impl XynoxaClient {
     pub fn new(token: String, base_url: String) -> Self {
-        // [WARNING] SSL Verification Disabled for Dev/Testing
-        let client = Client::builder()
-            .danger_accept_invalid_certs(true)
-            .build()
-            .unwrap_or_else(|_| Client::new());
+        Self::with_options(token, base_url, &ClientOptions::default())
+    }
 
+    /// Client for the server at `base_url`. Reuses the connection pool of
+    /// earlier clients with the same `options`.
+    pub fn with_options(token: String, base_url: String, options: &ClientOptions) -> Self {
         Self {
-            client,
-            token,
-            base_url: base_url.trim_end_matches('/').to_string(),
+            token: Some(token),
+            ..Self::anonymous(base_url, options)
         }
     }
 
-    pub async fn sync_pull(&self, cursor: u64) -> Result<SyncResponse, String> {
-        let url = format!("{}/api/trpc/sync.pull", self.base_url);
-        // TRPC v10 standard batch format with 'json' wrapper (match mutation structure)
-        let input_json = format!(r#"{{"0":{{"json":{{"cursor":{}}}}}}}"#, cursor);
+    /// Client without a token, for the calls made before signing in (e.g.
+    /// pairing). Sends no `Authorization` header at all.
+    pub fn anonymous(base_url: String, options: &ClientOptions) -> Self {
+        Self {
+            client: shared_client(options),
+            token: None,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            transfer_timeout: Duration::from_secs(options.timeouts.transfer_secs),
+        }
+    }
 
-        log::debug!("Request URL: {}", url);
-        log::debug!("Request Input: {}", input_json);
+    /// The bearer token header, empty for an anonymous client.
+    fn auth_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let value = self
+            .token
+            .as_ref()
+            .and_then(|token| HeaderValue::from_str(&format!("Bearer {}", token)).ok());
+        if let Some(mut value) = value {
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+        headers
+    }
 
+    /// Queries the server API version. Servers predating the endpoint answer 404
+    /// and are treated as API version 1.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn server_info(&self) -> Result<ServerInfo> {
+        let url = format!("{}/api/version", self.base_url);
         let res = self
             .client
             .get(&url)
-            .bearer_auth(&self.token)
-            .query(&[("batch", "1"), ("input", &input_json)])
+            .headers(self.auth_headers())
             .send()
-            .await
-            .map_err(|e| e.to_string())?;
+            .await?;
 
-        // Debug: Read raw text first (always)
         let status = res.status();
-        let text = res.text().await.map_err(|e| e.to_string())?;
-        log::debug!("Response Status: {}", status);
-        log::debug!("Response Body: {}", text);
-
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(ServerInfo {
+                api_version: 1,
+                version: None,
+            });
+        }
+        let text = res.text().await?;
         if !status.is_success() {
-            return Err(format!("Sync Pull Error: {}. Body: {}", status, text));
+            return Err(XynoxaError::from_status(
+                status,
+                format!("Version check failed. Body: {}", text),
+            ));
         }
+        serde_json::from_str(&text).map_err(|e| {
+            XynoxaError::Incompatible(format!(
+                "Server did not report an API version ({}). Is {} a Xynoxa server?",
+                e, self.base_url
+            ))
+        })
+    }
 
-        // Logic: Try to decode as TrpcResult batch first. If that fails or data structure mismatch,
-        // fallback to direct structural checks if possible, or strict TRPC error handling.
-
-        #[derive(Deserialize)]
-        struct TrpcResult<T> {
-            result: TrpcData<T>,
-        }
-        #[derive(Deserialize)]
-        struct TrpcData<T> {
-            data: TrpcResponsePayload<T>,
-        }
-        #[derive(Deserialize)]
-        struct TrpcResponsePayload<T> {
-            json: T,
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn sync_pull(&self, cursor: u64, limit: Option<u32>) -> Result<SyncResponse> {
+        #[derive(Serialize)]
+        struct Input {
+            cursor: u64,
+            // The server applies its own default/maximum page size; it may return
+            // fewer events than requested, which is not an end-of-stream signal.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            limit: Option<u32>,
         }
+        self.trpc_query("sync.pull", &Input { cursor, limit }).await
+    }
 
-        // Try decoding as standar TRPC Batch format
-        if let Ok(wrapped) = serde_json::from_str::<Vec<TrpcResult<SyncResponse>>>(&text) {
-            if let Some(first) = wrapped.into_iter().next() {
-                return Ok(first.result.data.json);
-            }
-        }
+    #[tracing::instrument(level = "debug", skip_all, fields(procedure = router_procedure))]
+    async fn trpc_query<T: Serialize, R: DeserializeOwned>(
+        &self,
+        router_procedure: &str,
+        input: &T,
+    ) -> Result<R> {
+        let url = format!("{}/api/trpc/{}", self.base_url, router_procedure);
+        let input_json = trpc::batch_input(input)?;
 
-        // Sometimes TRPC (or server proxy) might return just the result data for single queries??
-        // Or duplicate wrapping?
-        // Let's try to parse the `json` object directly if it exists in a different structure?
-        // Actually, previous log showed `Error decoding response body`.
-        // If the server returns 400, we handled it. 500 handled.
-        // It must be success 200 but shape mismatch.
-        // Let's log raw text in verify step if this still fails.
-        // For now, let's also try to see if it returned a bare SyncResponse (unlikely for TRPC but possible if mocked).
+        tracing::debug!("Request URL: {}", url);
+        tracing::debug!("Request Input: {}", input_json);
 
-        if let Ok(direct) = serde_json::from_str::<SyncResponse>(&text) {
-            return Ok(direct);
-        }
+        let res = self
+            .client
+            .get(&url)
+            .headers(self.auth_headers())
+            .query(&[("batch", "1"), ("input", &input_json)])
+            .send()
+            .await?;
+
+        let status = res.status();
+        let text = res.text().await?;
+        tracing::debug!("Response Status: {}", status);
+        tracing::debug!("Response Body: {}", text);
 
-        Err(format!("Failed to decode response. Raw: {}", text))
+        trpc::decode_response(router_procedure, status, &text)
     }
 
+    #[tracing::instrument(level = "debug", skip_all, fields(procedure = router_procedure))]
     async fn trpc_mutation<T: Serialize, R: DeserializeOwned>(
         &self,
         router_procedure: &str,
         input: &T,
-    ) -> Result<R, String> {
+    ) -> Result<R> {
         let url = format!("{}/api/trpc/{}?batch=1", self.base_url, router_procedure);
-
-        #[derive(Serialize)]
-        struct TrpcBatch<T> {
-            #[serde(rename = "0")]
-            item: TrpcItem<T>,
-        }
-        #[derive(Serialize)]
-        struct TrpcItem<T> {
-            json: T,
-        }
-
-        let body = TrpcBatch {
-            item: TrpcItem { json: input },
-        };
+        let body = trpc::batch_input(input)?;
 
         let res = self
             .client
             .post(&url)
-            .bearer_auth(&self.token)
-            .json(&body)
+            .headers(self.auth_headers())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
             .send()
-            .await
-            .map_err(|e| e.to_string())?;
+            .await?;
 
-        if !res.status().is_success() {
-            let status = res.status();
-            let text = res.text().await.unwrap_or_else(|_| "No body".to_string());
-            return Err(format!(
-                "TRPC Mutation Error {}: {} Body: {}",
-                router_procedure, status, text
-            ));
-        }
+        // Error envelopes come back with non-2xx status, so always read the body
+        let status = res.status();
+        let text = res.text().await?;
+        tracing::debug!("Response Status: {}", status);
 
-        #[derive(Deserialize)]
-        struct TrpcResult<R> {
-            result: TrpcData<R>,
-        }
-        #[derive(Deserialize)]
-        struct TrpcData<R> {
-            data: TrpcPayload<R>,
-        }
-        #[derive(Deserialize)]
-        struct TrpcPayload<R> {
-            json: R,
+        trpc::decode_response(router_procedure, status, &text)
+    }
+
+    /// Sends all queued mutations, `MAX_BATCH_CALLS` per HTTP request. The outer
+    /// error is a transport failure; per-call results are returned in queue order.
+    pub async fn execute_batch(
+        &self,
+        batch: MutationBatch,
+    ) -> Result<Vec<Result<serde_json::Value>>> {
+        let mut results = Vec::with_capacity(batch.len());
+        for chunk in batch.calls.chunks(MAX_BATCH_CALLS) {
+            results.extend(self.send_batch_chunk(chunk).await?);
         }
+        Ok(results)
+    }
 
-        // TRPC returns an array of results for batch requests
-        // Read text first to debug decoding errors
-        let text = res.text().await.map_err(|e| e.to_string())?;
+    #[tracing::instrument(level = "debug", skip_all, fields(calls = calls.len()))]
+    async fn send_batch_chunk(
+        &self,
+        calls: &[(String, serde_json::Value)],
+    ) -> Result<Vec<Result<serde_json::Value>>> {
+        let procedures: Vec<&str> = calls.iter().map(|(p, _)| p.as_str()).collect();
+        let url = format!(
+            "{}/api/trpc/{}?batch=1",
+            self.base_url,
+            procedures.join(",")
+        );
+
+        let body: serde_json::Map<String, serde_json::Value> = calls
+            .iter()
+            .enumerate()
+            .map(|(i, (_, input))| (i.to_string(), serde_json::json!({ "json": input })))
+            .collect();
 
-        let wrapped: Vec<TrpcResult<R>> = serde_json::from_str(&text)
-            .map_err(|e| format!("Failed to decode TRPC response: {}. Body: {}", e, text))?;
+        let res = self
+            .client
+            .post(&url)
+            .headers(self.auth_headers())
+            .json(&body)
+            .send()
+            .await?;
 
-        if let Some(first) = wrapped.into_iter().next() {
-            Ok(first.result.data.json)
-        } else {
-            Err("Empty TRPC response".to_string())
+        // tRPC answers a batch with 207 Multi-Status when only some calls failed
+        let status = res.status();
+        let text = res.text().await?;
+        tracing::debug!("Batch Response Status: {}", status);
+
+        let items = trpc::decode_batch(status, &text)?;
+        if items.len() != calls.len() {
+            return Err(XynoxaError::Decode(format!(
+                "Batch returned {} results for {} calls. Body: {}",
+                items.len(),
+                calls.len(),
+                text
+            )));
         }
+
+        Ok(items
+            .into_iter()
+            .zip(procedures)
+            .map(|(item, procedure)| trpc::decode_item(procedure, status, item))
+            .collect())
     }
 
-    pub async fn soft_delete_file(&self, file_id: &str) -> Result<(), String> {
+    pub async fn soft_delete_file(&self, file_id: &str) -> Result<()> {
         #[derive(Serialize)]
         struct Input {
             #[serde(rename = "fileId")]
@@ -243,7 +935,7 @@ impl XynoxaClient {
         .await
     }
 
-    pub async fn rename_file(&self, file_id: &str, new_name: &str) -> Result<(), String> {
+    pub async fn rename_file(&self, file_id: &str, new_name: &str) -> Result<()> {
         #[derive(Serialize)]
         struct Input {
             id: String,
@@ -259,11 +951,7 @@ impl XynoxaClient {
         .await
     }
 
-    pub async fn move_file(
-        &self,
-        file_id: &str,
-        new_parent_id: Option<&str>,
-    ) -> Result<(), String> {
+    pub async fn move_file(&self, file_id: &str, new_parent_id: Option<&str>) -> Result<()> {
         #[derive(Serialize)]
         struct Input {
             id: String,
@@ -280,7 +968,99 @@ impl XynoxaClient {
         .await
     }
 
-    pub async fn restore_file(&self, file_id: &str) -> Result<(), String> {
+    pub async fn list_trashed_files(&self) -> Result<Vec<TrashedFile>> {
+        match self.trpc_query("files.listTrashed", &()).await {
+            // Servers before trash.empty only know the old name
+            Err(XynoxaError::Server { status: 404, .. }) => {
+                self.trpc_query("files.listTrash", &()).await
+            }
+            result => result,
+        }
+    }
+
+    /// Files on the server matching `query`, best matches first.
+    pub async fn search_files(&self, query: &str, limit: u32) -> Result<Vec<RemoteSearchHit>> {
+        #[derive(Serialize)]
+        struct Input<'a> {
+            query: &'a str,
+            limit: u32,
+        }
+        let input = Input { query, limit };
+        match self.trpc_query("search.query", &input).await {
+            // Servers without the search index only match names
+            Err(XynoxaError::Server { status: 404, .. }) => {
+                self.trpc_query("files.search", &input).await
+            }
+            result => result,
+        }
+    }
+
+    /// The stored versions of `file_id`, newest first.
+    pub async fn list_versions(&self, file_id: &str) -> Result<Vec<FileVersion>> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Input<'a> {
+            file_id: &'a str,
+        }
+        self.trpc_query("files.versions", &Input { file_id }).await
+    }
+
+    /// Downloads `version` of `file_id` to `local_path`, replacing it only
+    /// once the content is complete and matches the server's hash.
+    pub async fn download_version(
+        &self,
+        file_id: &str,
+        version: i64,
+        local_path: &Path,
+    ) -> Result<()> {
+        let _transfer = crate::io_scheduler::transfer();
+        let url = format!(
+            "{}/api/files/{}/content",
+            self.base_url,
+            urlencoding::encode(file_id)
+        );
+        let mut res = self
+            .client
+            .get(&url)
+            .query(&[("version", version)])
+            .headers(self.auth_headers())
+            .timeout(self.transfer_timeout)
+            .send()
+            .await?;
+        let status = res.status();
+        if !status.is_success() {
+            let body = res.text().await.unwrap_or_else(|_| "No body".to_string());
+            return Err(XynoxaError::from_status(
+                status,
+                format!("Version download failed. Body: {}", body),
+            ));
+        }
+        let etag = res
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        let partial = partial_path(local_path);
+        let mut file = File::create(&partial).await?;
+        while let Some(chunk) = res.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
+        if let Some(etag) = &etag {
+            verify_content_hash(&partial, etag).await?;
+        }
+        tokio::fs::rename(&partial, local_path).await?;
+        Ok(())
+    }
+
+    /// Deletes everything in the trash for good.
+    pub async fn empty_trash(&self) -> Result<()> {
+        self.trpc_mutation("trash.empty", &()).await
+    }
+
+    pub async fn restore_file(&self, file_id: &str) -> Result<()> {
         #[derive(Serialize)]
         struct Input {
             #[serde(rename = "fileId")]
@@ -295,7 +1075,7 @@ impl XynoxaClient {
         .await
     }
 
-    pub async fn permanent_delete_file(&self, file_id: &str) -> Result<(), String> {
+    pub async fn permanent_delete_file(&self, file_id: &str) -> Result<()> {
         #[derive(Serialize)]
         struct Input {
             #[serde(rename = "fileId")]
@@ -310,7 +1090,154 @@ impl XynoxaClient {
         .await
     }
 
-    pub async fn delete_folder(&self, folder_id: &str) -> Result<(), String> {
+    /// Registers this client as a device of the account.
+    pub async fn register_device(&self, name: &str) -> Result<Device> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Input<'a> {
+            name: &'a str,
+            platform: &'a str,
+            client_version: &'a str,
+        }
+        self.trpc_mutation(
+            "devices.register",
+            &Input {
+                name,
+                platform: std::env::consts::OS,
+                client_version: env!("CARGO_PKG_VERSION"),
+            },
+        )
+        .await
+    }
+
+    /// Starts pairing this client with a browser session. Needs no token.
+    pub async fn start_pairing(&self, name: &str) -> Result<PairingRequest> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Input<'a> {
+            name: &'a str,
+            platform: &'a str,
+            client_version: &'a str,
+        }
+        self.trpc_mutation(
+            "devices.pairing.start",
+            &Input {
+                name,
+                platform: std::env::consts::OS,
+                client_version: env!("CARGO_PKG_VERSION"),
+            },
+        )
+        .await
+    }
+
+    pub async fn poll_pairing(&self, pairing_id: &str) -> Result<PairingStatus> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Input<'a> {
+            pairing_id: &'a str,
+        }
+        self.trpc_query("devices.pairing.poll", &Input { pairing_id })
+            .await
+    }
+
+    /// Answers a [`crate::trpc::TwoFactorChallenge`]. `response` is
+    /// `{ "code": "123456" }` for TOTP or the serialized WebAuthn assertion.
+    /// The server may hand out a replacement token.
+    pub async fn verify_second_factor(
+        &self,
+        challenge_id: &str,
+        method: &str,
+        response: serde_json::Value,
+    ) -> Result<Option<String>> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Input<'a> {
+            challenge_id: &'a str,
+            method: &'a str,
+            response: serde_json::Value,
+        }
+        #[derive(Deserialize)]
+        struct Output {
+            #[serde(default)]
+            token: Option<String>,
+        }
+        let output: Option<Output> = self
+            .trpc_mutation(
+                "auth.twoFactor.verify",
+                &Input {
+                    challenge_id,
+                    method,
+                    response,
+                },
+            )
+            .await?;
+        Ok(output.and_then(|output| output.token))
+    }
+
+    /// Introspects the token this client authenticates with.
+    pub async fn session_info(&self) -> Result<SessionInfo> {
+        self.trpc_query("auth.session", &()).await
+    }
+
+    pub async fn list_devices(&self) -> Result<Vec<Device>> {
+        self.trpc_query("devices.list", &()).await
+    }
+
+    /// Key shared by all devices of the account for direct LAN transfers.
+    /// `None` if the server does not coordinate them.
+    pub async fn lan_key(&self) -> Result<Option<String>> {
+        #[derive(Deserialize)]
+        struct Output {
+            key: Option<String>,
+        }
+        match self.trpc_query::<_, Output>("devices.lanKey", &()).await {
+            Ok(output) => Ok(output.key),
+            Err(XynoxaError::Server { status: 404, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Revokes a device; the server invalidates its token.
+    pub async fn revoke_device(&self, device_id: &str) -> Result<()> {
+        #[derive(Serialize)]
+        struct Input<'a> {
+            id: &'a str,
+        }
+        self.trpc_mutation("devices.revoke", &Input { id: device_id })
+            .await
+    }
+
+    /// Creates a public share for `file_id` and returns its absolute URL.
+    pub async fn create_share_link(&self, file_id: &str) -> Result<String> {
+        #[derive(Serialize)]
+        struct Input {
+            #[serde(rename = "fileId")]
+            file_id: String,
+        }
+        let share: ShareLink = self
+            .trpc_mutation(
+                "shares.create",
+                &Input {
+                    file_id: file_id.to_string(),
+                },
+            )
+            .await?;
+        match (share.url, share.token) {
+            (Some(url), _) if url.starts_with("http") => Ok(url),
+            (Some(path), _) => Ok(format!(
+                "{}/{}",
+                self.base_url,
+                path.trim_start_matches('/')
+            )),
+            (None, Some(token)) => Ok(format!("{}/s/{}", self.base_url, token)),
+            (None, None) => Err(XynoxaError::Decode(format!(
+                "Share {} has neither url nor token",
+                share.id
+            ))),
+        }
+    }
+
+    pub async fn delete_folder(&self, folder_id: &str) -> Result<()> {
         #[derive(Serialize)]
         struct Input {
             id: String,
@@ -324,11 +1251,7 @@ impl XynoxaClient {
         .await
     }
 
-    pub async fn create_folder(
-        &self,
-        name: &str,
-        parent_id: Option<&str>,
-    ) -> Result<FolderEntry, String> {
+    pub async fn create_folder(&self, name: &str, parent_id: Option<&str>) -> Result<FolderEntry> {
         #[derive(Serialize)]
         struct Input {
             name: String,
@@ -345,59 +1268,95 @@ impl XynoxaClient {
         .await
     }
 
+    /// The folder named `name` directly below `parent_id` (root if `None`).
+    /// Servers without the lookup answer 404.
+    pub async fn find_folder(
+        &self,
+        name: &str,
+        parent_id: Option<&str>,
+    ) -> Result<Option<FolderEntry>> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Input<'a> {
+            name: &'a str,
+            parent_id: Option<&'a str>,
+        }
+        self.trpc_query("folders.getByNameAndParent", &Input { name, parent_id })
+            .await
+    }
+
     pub async fn upload_file(
         &self,
         local_path: &Path,
         file_id: Option<&str>,
         folder_id: Option<&str>,
         original_name: &str,
-    ) -> Result<UploadedFile, String> {
-        let metadata = tokio::fs::metadata(local_path)
+    ) -> Result<UploadedFile> {
+        self.upload_file_resumable(local_path, file_id, folder_id, original_name, None, &|_| {})
             .await
-            .map_err(|e| e.to_string())?;
+    }
+
+    /// Uploads `local_path`. Chunked uploads continue the session in `resume`
+    /// if the server still has it and report each confirmed chunk to
+    /// `on_progress`.
+    #[tracing::instrument(level = "debug", skip(self, local_path, on_progress))]
+    pub async fn upload_file_resumable(
+        &self,
+        local_path: &Path,
+        file_id: Option<&str>,
+        folder_id: Option<&str>,
+        original_name: &str,
+        resume: Option<ResumePoint>,
+        on_progress: ResumeSink<'_>,
+    ) -> Result<UploadedFile> {
+        let _transfer = crate::io_scheduler::transfer();
+        let metadata = tokio::fs::metadata(local_path).await?;
         let file_size = metadata.len();
 
         if file_size > MAX_UPLOAD_BYTES {
-            return Err(format!(
+            return Err(XynoxaError::Other(format!(
                 "File too large (max {} bytes).",
                 MAX_UPLOAD_BYTES
-            ));
+            )));
         }
 
         if file_size > CHUNK_THRESHOLD_BYTES {
             return self
-                .upload_file_chunked(local_path, file_id, folder_id, original_name, file_size)
+                .upload_file_chunked(
+                    local_path,
+                    file_id,
+                    folder_id,
+                    original_name,
+                    file_size,
+                    resume,
+                    on_progress,
+                )
                 .await;
         }
 
         // Safety check: Reject directories
         if local_path.is_dir() {
-            return Err(format!(
+            return Err(XynoxaError::Other(format!(
                 "Cannot upload directory as file: {}",
                 local_path.display()
-            ));
+            )));
         }
 
         let url = format!("{}/api/upload", self.base_url);
 
-        let mut file = File::open(local_path).await.map_err(|e| e.to_string())?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)
-            .await
-            .map_err(|e| e.to_string())?;
+        // Stream the file from disk; only a small read buffer is held in memory
+        let file = File::open(local_path).await?;
 
-        // Detect MIME type from file extension using mime_guess
-        let mime_type = mime_guess::from_path(local_path)
-            .first_or_octet_stream()
-            .to_string();
+        // From the extension, or the content for extension-less files
+        let mime_type = content_type::detect(local_path);
 
-        log::debug!("Uploading {} with MIME type: {}", original_name, mime_type);
+        tracing::debug!("Uploading {} with MIME type: {}", original_name, mime_type);
 
-        let body = reqwest::Body::from(buffer);
-        let part = reqwest::multipart::Part::stream(body)
+        let body = reqwest::Body::wrap_stream(ReaderStream::new(file));
+        let part = reqwest::multipart::Part::stream_with_length(body, file_size)
             .file_name(original_name.to_string())
             .mime_str(&mime_type)
-            .map_err(|e| format!("Invalid MIME type: {}", e))?;
+            .map_err(|e| XynoxaError::Other(format!("Invalid MIME type: {}", e)))?;
 
         let mut form = reqwest::multipart::Form::new()
             .part("file", part)
@@ -414,23 +1373,32 @@ impl XynoxaClient {
         let res = self
             .client
             .post(&url)
-            .bearer_auth(&self.token)
+            .headers(self.auth_headers())
+            .timeout(self.transfer_timeout)
             .multipart(form)
             .send()
-            .await
-            .map_err(|e| e.to_string())?;
+            .await?;
 
         if !res.status().is_success() {
             let status = res.status();
             let body = res.text().await.unwrap_or_else(|_| "No body".to_string());
-            return Err(format!("Upload failed: {}. Body: {}", status, body));
+            return Err(XynoxaError::from_status(
+                status,
+                format!("Upload failed. Body: {}", body),
+            ));
         }
 
         // API returns { file: { ... } } wrapper
-        let upload_response: UploadResponse = res.json().await.map_err(|e| e.to_string())?;
+        let upload_response: UploadResponse = res.json().await?;
         Ok(upload_response.file)
     }
 
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(
+        level = "debug",
+        skip(self, local_path, on_progress),
+        fields(upload_id)
+    )]
     async fn upload_file_chunked(
         &self,
         local_path: &Path,
@@ -438,18 +1406,41 @@ impl XynoxaClient {
         folder_id: Option<&str>,
         original_name: &str,
         file_size: u64,
-    ) -> Result<UploadedFile, String> {
+        resume: Option<ResumePoint>,
+        on_progress: ResumeSink<'_>,
+    ) -> Result<UploadedFile> {
         // Safety check: Reject directories
         if local_path.is_dir() {
-            return Err(format!(
+            return Err(XynoxaError::Other(format!(
                 "Cannot upload directory as file: {}",
                 local_path.display()
-            ));
+            )));
         }
 
-        let mime_type = mime_guess::from_path(local_path)
-            .first_or_octet_stream()
-            .to_string();
+        let mime_type = content_type::detect(local_path);
+
+        // Continue an earlier session; the server drops sessions after a while
+        let chunk_size = CHUNK_SIZE_BYTES as u64;
+        if let Some(point) = resume.filter(|p| p.offset < file_size && p.offset % chunk_size == 0) {
+            tracing::Span::current().record("upload_id", point.token.as_str());
+            tracing::info!("Resuming upload {} at byte {}", point.token, point.offset);
+            match self
+                .send_chunks(
+                    local_path,
+                    &point.token,
+                    point.offset / chunk_size,
+                    &mime_type,
+                    on_progress,
+                )
+                .await
+            {
+                Ok(()) => return self.complete_chunked_upload(point.token, folder_id).await,
+                Err(XynoxaError::Server {
+                    status: 404 | 410, ..
+                }) => tracing::info!("Upload session {} expired. Starting over.", point.token),
+                Err(e) => return Err(e),
+            }
+        }
 
         let total_chunks = ((file_size as f64) / (CHUNK_SIZE_BYTES as f64)).ceil() as u64;
 
@@ -485,30 +1476,49 @@ impl XynoxaClient {
         let start_res = self
             .client
             .post(&start_url)
-            .bearer_auth(&self.token)
+            .headers(self.auth_headers())
             .json(&start_payload)
             .send()
-            .await
-            .map_err(|e| e.to_string())?;
+            .await?;
 
         if !start_res.status().is_success() {
             let status = start_res.status();
-            let text = start_res.text().await.unwrap_or_else(|_| "No body".to_string());
-            return Err(format!("Chunk start failed: {}. Body: {}", status, text));
+            let text = start_res
+                .text()
+                .await
+                .unwrap_or_else(|_| "No body".to_string());
+            return Err(XynoxaError::from_status(
+                status,
+                format!("Chunk start failed. Body: {}", text),
+            ));
         }
 
-        let start_response: StartResponse = start_res.json().await.map_err(|e| e.to_string())?;
+        let start_response: StartResponse = start_res.json().await?;
         let upload_id = start_response.upload_id;
+        tracing::Span::current().record("upload_id", upload_id.as_str());
+
+        self.send_chunks(local_path, &upload_id, 0, &mime_type, on_progress)
+            .await?;
+        self.complete_chunked_upload(upload_id, folder_id).await
+    }
 
-        let mut file = File::open(local_path).await.map_err(|e| e.to_string())?;
-        let mut chunk_index: u64 = 0;
+    /// Sends the chunks of `local_path` from `first_chunk` on.
+    async fn send_chunks(
+        &self,
+        local_path: &Path,
+        upload_id: &str,
+        first_chunk: u64,
+        mime_type: &str,
+        on_progress: ResumeSink<'_>,
+    ) -> Result<()> {
+        let mut file = File::open(local_path).await?;
+        let mut chunk_index = first_chunk;
+        let mut offset = first_chunk * CHUNK_SIZE_BYTES as u64;
+        file.seek(SeekFrom::Start(offset)).await?;
         let mut buffer = vec![0u8; CHUNK_SIZE_BYTES];
 
         loop {
-            let bytes_read = file
-                .read(&mut buffer)
-                .await
-                .map_err(|e| e.to_string())?;
+            let bytes_read = file.read(&mut buffer).await?;
             if bytes_read == 0 {
                 break;
             }
@@ -516,11 +1526,10 @@ impl XynoxaClient {
             let chunk = buffer[..bytes_read].to_vec();
             let part = reqwest::multipart::Part::bytes(chunk)
                 .file_name(format!("{}.part", chunk_index))
-                .mime_str(&mime_type)
-                .map_err(|e| e.to_string())?;
+                .mime_str(mime_type)?;
 
             let form = reqwest::multipart::Form::new()
-                .text("uploadId", upload_id.clone())
+                .text("uploadId", upload_id.to_string())
                 .text("chunkIndex", chunk_index.to_string())
                 .part("file", part);
 
@@ -528,21 +1537,39 @@ impl XynoxaClient {
             let chunk_res = self
                 .client
                 .post(&chunk_url)
-                .bearer_auth(&self.token)
+                .headers(self.auth_headers())
+                .timeout(self.transfer_timeout)
                 .multipart(form)
                 .send()
-                .await
-                .map_err(|e| e.to_string())?;
+                .await?;
 
             if !chunk_res.status().is_success() {
                 let status = chunk_res.status();
-                let text = chunk_res.text().await.unwrap_or_else(|_| "No body".to_string());
-                return Err(format!("Chunk upload failed: {}. Body: {}", status, text));
+                let text = chunk_res
+                    .text()
+                    .await
+                    .unwrap_or_else(|_| "No body".to_string());
+                return Err(XynoxaError::from_status(
+                    status,
+                    format!("Chunk upload failed. Body: {}", text),
+                ));
             }
 
             chunk_index += 1;
+            offset += bytes_read as u64;
+            on_progress(&ResumePoint {
+                token: upload_id.to_string(),
+                offset,
+            });
         }
+        Ok(())
+    }
 
+    async fn complete_chunked_upload(
+        &self,
+        upload_id: String,
+        folder_id: Option<&str>,
+    ) -> Result<UploadedFile> {
         #[derive(Serialize)]
         struct CompletePayload {
             #[serde(rename = "uploadId")]
@@ -560,64 +1587,536 @@ impl XynoxaClient {
         let complete_res = self
             .client
             .post(&complete_url)
-            .bearer_auth(&self.token)
+            .headers(self.auth_headers())
             .json(&complete_payload)
             .send()
-            .await
-            .map_err(|e| e.to_string())?;
+            .await?;
 
         if !complete_res.status().is_success() {
             let status = complete_res.status();
-            let text = complete_res.text().await.unwrap_or_else(|_| "No body".to_string());
-            return Err(format!("Chunk complete failed: {}. Body: {}", status, text));
+            let text = complete_res
+                .text()
+                .await
+                .unwrap_or_else(|_| "No body".to_string());
+            return Err(XynoxaError::from_status(
+                status,
+                format!("Chunk complete failed. Body: {}", text),
+            ));
         }
 
-        let upload_response: UploadResponse = complete_res.json().await.map_err(|e| e.to_string())?;
+        let upload_response: UploadResponse = complete_res.json().await?;
         Ok(upload_response.file)
     }
 
-    pub async fn download_file(&self, file_id: &str, local_path: &Path) -> Result<(), String> {
+    /// Downloads the content of `file_id` to `local_path`. With `if_none_match`
+    /// (one or more ETags) the server may answer 304 and the local file is kept.
+    pub async fn download_file(
+        &self,
+        file_id: &str,
+        local_path: &Path,
+        if_none_match: Option<&str>,
+    ) -> Result<DownloadOutcome> {
+        self.download_file_resumable(file_id, local_path, if_none_match, None, &|_| {})
+            .await
+    }
+
+    /// Like [`XynoxaClient::download_file`], but the content is written to
+    /// [`partial_path`] first. A partial file from an earlier attempt is
+    /// continued at `resume` with a ranged request if the content did not
+    /// change; progress is reported to `on_progress`.
+    #[tracing::instrument(level = "debug", skip(self, local_path, on_progress))]
+    pub async fn download_file_resumable(
+        &self,
+        file_id: &str,
+        local_path: &Path,
+        if_none_match: Option<&str>,
+        resume: Option<ResumePoint>,
+        on_progress: ResumeSink<'_>,
+    ) -> Result<DownloadOutcome> {
+        let _transfer = crate::io_scheduler::transfer();
         // Use path parameter format - encode file_id for special characters
         let encoded_id = urlencoding::encode(file_id);
         let url = format!("{}/api/files/{}/content", self.base_url, encoded_id);
 
-        let res = self
+        // Only continue a partial file that still holds the confirmed bytes
+        let partial = partial_path(local_path);
+        let resume = match resume {
+            Some(point) if point.offset > 0 => match tokio::fs::metadata(&partial).await {
+                Ok(metadata) if metadata.len() >= point.offset => Some(point),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let mut req = self
             .client
             .get(&url)
-            .bearer_auth(&self.token)
-            .send()
-            .await
-            .map_err(|e| e.to_string())?;
+            .headers(self.auth_headers())
+            .timeout(self.transfer_timeout);
+        if let Some(tags) = if_none_match {
+            req = req.header(reqwest::header::IF_NONE_MATCH, tags);
+        }
+        if let Some(point) = &resume {
+            // If-Range: the server sends everything again if the content changed
+            req = req
+                .header(reqwest::header::RANGE, format!("bytes={}-", point.offset))
+                .header(reqwest::header::IF_RANGE, point.token.as_str());
+        }
+        let mut res = req.send().await?;
 
         let status = res.status();
-        log::debug!("Download Response Status: {}", status);
+        tracing::debug!("Download Response Status: {}", status);
+
+        if status == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(DownloadOutcome::NotModified);
+        }
 
         if !status.is_success() {
             let body = res.text().await.unwrap_or_else(|_| "No body".to_string());
-            log::error!("Download Error Body: {}", body);
-            return Err(format!("Download failed: {}. Body: {}", status, body));
+            tracing::error!("Download Error Body: {}", body);
+            return Err(XynoxaError::from_status(
+                status,
+                format!("Download failed. Body: {}", body),
+            ));
         }
 
-        let content = res.bytes().await.map_err(|e| e.to_string())?;
+        let etag = res
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        // If-Range needs a strong validator
+        let resume_token = etag.clone().filter(|etag| !etag.starts_with("W/"));
 
         if let Some(parent) = local_path.parent() {
-            tokio::fs::create_dir_all(parent)
-                .await
-                .map_err(|e| e.to_string())?;
+            tokio::fs::create_dir_all(parent).await?;
         }
 
-        tokio::fs::write(local_path, content)
-            .await
-            .map_err(|e| e.to_string())?;
+        let total = res.content_length().unwrap_or(0);
+        let accepts_ranges = res
+            .headers()
+            .get(reqwest::header::ACCEPT_RANGES)
+            .is_some_and(|v| v.as_bytes() == b"bytes");
+        if let Some(token) = resume_token.as_deref().filter(|_| {
+            status == reqwest::StatusCode::OK && accepts_ranges && total >= PARALLEL_DOWNLOAD_BYTES
+        }) {
+            self.download_ranges(res, &url, &partial, total, token, on_progress)
+                .await?;
+            verify_content_hash(&partial, token).await?;
+            tokio::fs::rename(&partial, local_path).await?;
+            return Ok(DownloadOutcome::Downloaded { etag });
+        }
+
+        let mut offset = match &resume {
+            Some(point) if status == reqwest::StatusCode::PARTIAL_CONTENT => {
+                tracing::info!("Resuming download of {} at byte {}", file_id, point.offset);
+                point.offset
+            }
+            _ => 0,
+        };
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&partial)
+            .await?;
+        file.set_len(offset).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+
+        let mut unconfirmed = 0;
+        while let Some(chunk) = res.chunk().await? {
+            file.write_all(&chunk).await?;
+            offset += chunk.len() as u64;
+            unconfirmed += chunk.len() as u64;
+            if unconfirmed >= RESUME_INTERVAL_BYTES {
+                unconfirmed = 0;
+                if let Some(token) = &resume_token {
+                    file.sync_data().await?;
+                    on_progress(&ResumePoint {
+                        token: token.clone(),
+                        offset,
+                    });
+                }
+            }
+        }
+        file.flush().await?;
+        drop(file);
+        tokio::fs::rename(&partial, local_path).await?;
+
+        Ok(DownloadOutcome::Downloaded { etag })
+    }
 
+    /// Fetches `total` bytes into `partial` as [`PARALLEL_RANGES`] ranges at
+    /// once. `first` already streams the whole content and provides the first
+    /// range; the others are requested with `If-Range` so they all come from
+    /// the same version. The confirmed prefix is reported to `on_progress`.
+    async fn download_ranges(
+        &self,
+        first: reqwest::Response,
+        url: &str,
+        partial: &Path,
+        total: u64,
+        token: &str,
+        on_progress: ResumeSink<'_>,
+    ) -> Result<()> {
+        let file = File::create(partial).await?;
+        // Sparse on most filesystems until the ranges arrive
+        file.set_len(total).await?;
+        drop(file);
+
+        let ranges = split_ranges(total, PARALLEL_RANGES);
+        tracing::info!("Downloading {} bytes in {} ranges", total, ranges.len());
+        let done = std::sync::Mutex::new(vec![false; ranges.len()]);
+        let mut first = Some(first);
+        let fetches = ranges.iter().enumerate().map(|(i, range)| {
+            let first = first.take();
+            let (done, ranges) = (&done, &ranges);
+            async move {
+                let res = match first {
+                    Some(res) => res,
+                    None => self.fetch_range(url, range, token).await?,
+                };
+                write_range(res, partial, range).await?;
+                let confirmed = {
+                    let mut done = done.lock().unwrap();
+                    done[i] = true;
+                    let prefix = done.iter().take_while(|finished| **finished).count();
+                    // Only a gapless prefix can be resumed
+                    (prefix > i).then(|| ranges[prefix - 1].end)
+                };
+                if let Some(offset) = confirmed {
+                    File::options()
+                        .write(true)
+                        .open(partial)
+                        .await?
+                        .sync_data()
+                        .await?;
+                    on_progress(&ResumePoint {
+                        token: token.to_string(),
+                        offset,
+                    });
+                }
+                Ok::<_, XynoxaError>(())
+            }
+        });
+        futures_util::future::try_join_all(fetches).await?;
         Ok(())
     }
+
+    async fn fetch_range(
+        &self,
+        url: &str,
+        range: &std::ops::Range<u64>,
+        token: &str,
+    ) -> Result<reqwest::Response> {
+        let res = self
+            .client
+            .get(url)
+            .headers(self.auth_headers())
+            .timeout(self.transfer_timeout)
+            .header(
+                reqwest::header::RANGE,
+                format!("bytes={}-{}", range.start, range.end - 1),
+            )
+            .header(reqwest::header::IF_RANGE, token)
+            .send()
+            .await?;
+        let status = res.status();
+        if status == reqwest::StatusCode::PARTIAL_CONTENT {
+            return Ok(res);
+        }
+        if status.is_success() {
+            // If-Range answered with the whole file: it changed meanwhile
+            return Err(XynoxaError::Conflict(
+                "File changed on the server during the download".to_string(),
+            ));
+        }
+        let body = res.text().await.unwrap_or_else(|_| "No body".to_string());
+        Err(XynoxaError::from_status(
+            status,
+            format!("Range download failed. Body: {}", body),
+        ))
+    }
+
+    /// Server-generated preview of `file_id`, at most `size` pixels wide and
+    /// high. `None` when the server has no thumbnail for this file type.
+    pub async fn fetch_thumbnail(&self, file_id: &str, size: u32) -> Result<Option<Vec<u8>>> {
+        let encoded_id = urlencoding::encode(file_id);
+        let url = format!(
+            "{}/api/files/{}/thumbnail?size={}",
+            self.base_url, encoded_id, size
+        );
+        let res = self
+            .client
+            .get(&url)
+            .headers(self.auth_headers())
+            .send()
+            .await?;
+
+        let status = res.status();
+        if status == reqwest::StatusCode::NOT_FOUND
+            || status == reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE
+            || status == reqwest::StatusCode::NO_CONTENT
+        {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let body = res.text().await.unwrap_or_else(|_| "No body".to_string());
+            return Err(XynoxaError::from_status(
+                status,
+                format!("Thumbnail failed. Body: {}", body),
+            ));
+        }
+        Ok(Some(res.bytes().await?.to_vec()))
+    }
+}
+
+/// Server operations the sync engine depends on. Implemented by
+/// [`XynoxaClient`]; tests inject [`crate::mock_api::MockApiClient`] instead.
+#[async_trait]
+pub trait ApiClient: Send + Sync {
+    async fn server_info(&self) -> Result<ServerInfo>;
+
+    async fn sync_pull(&self, cursor: u64, limit: Option<u32>) -> Result<SyncResponse>;
+
+    async fn execute_batch(&self, batch: MutationBatch) -> Result<Vec<Result<serde_json::Value>>>;
+
+    async fn create_folder(&self, name: &str, parent_id: Option<&str>) -> Result<FolderEntry>;
+
+    async fn find_folder(&self, name: &str, parent_id: Option<&str>)
+        -> Result<Option<FolderEntry>>;
+
+    async fn upload_file(
+        &self,
+        local_path: &Path,
+        file_id: Option<&str>,
+        folder_id: Option<&str>,
+        original_name: &str,
+    ) -> Result<UploadedFile>;
+
+    async fn download_file(
+        &self,
+        file_id: &str,
+        local_path: &Path,
+        if_none_match: Option<&str>,
+    ) -> Result<DownloadOutcome>;
+
+    /// [`ApiClient::upload_file`] continuing an interrupted upload. Clients
+    /// that cannot resume start over.
+    async fn upload_file_resumable(
+        &self,
+        local_path: &Path,
+        file_id: Option<&str>,
+        folder_id: Option<&str>,
+        original_name: &str,
+        resume: Option<ResumePoint>,
+        on_progress: ResumeSink<'_>,
+    ) -> Result<UploadedFile> {
+        let _ = (resume, on_progress);
+        self.upload_file(local_path, file_id, folder_id, original_name)
+            .await
+    }
+
+    /// [`ApiClient::download_file`] continuing an interrupted download.
+    /// Clients that cannot resume start over.
+    async fn download_file_resumable(
+        &self,
+        file_id: &str,
+        local_path: &Path,
+        if_none_match: Option<&str>,
+        resume: Option<ResumePoint>,
+        on_progress: ResumeSink<'_>,
+    ) -> Result<DownloadOutcome> {
+        let _ = (resume, on_progress);
+        self.download_file(file_id, local_path, if_none_match).await
+    }
+}
+
+#[async_trait]
+impl ApiClient for XynoxaClient {
+    async fn server_info(&self) -> Result<ServerInfo> {
+        XynoxaClient::server_info(self).await
+    }
+
+    async fn sync_pull(&self, cursor: u64, limit: Option<u32>) -> Result<SyncResponse> {
+        XynoxaClient::sync_pull(self, cursor, limit).await
+    }
+
+    async fn execute_batch(&self, batch: MutationBatch) -> Result<Vec<Result<serde_json::Value>>> {
+        XynoxaClient::execute_batch(self, batch).await
+    }
+
+    async fn create_folder(&self, name: &str, parent_id: Option<&str>) -> Result<FolderEntry> {
+        XynoxaClient::create_folder(self, name, parent_id).await
+    }
+
+    async fn find_folder(
+        &self,
+        name: &str,
+        parent_id: Option<&str>,
+    ) -> Result<Option<FolderEntry>> {
+        XynoxaClient::find_folder(self, name, parent_id).await
+    }
+
+    async fn upload_file(
+        &self,
+        local_path: &Path,
+        file_id: Option<&str>,
+        folder_id: Option<&str>,
+        original_name: &str,
+    ) -> Result<UploadedFile> {
+        XynoxaClient::upload_file(self, local_path, file_id, folder_id, original_name).await
+    }
+
+    async fn download_file(
+        &self,
+        file_id: &str,
+        local_path: &Path,
+        if_none_match: Option<&str>,
+    ) -> Result<DownloadOutcome> {
+        XynoxaClient::download_file(self, file_id, local_path, if_none_match).await
+    }
+
+    async fn upload_file_resumable(
+        &self,
+        local_path: &Path,
+        file_id: Option<&str>,
+        folder_id: Option<&str>,
+        original_name: &str,
+        resume: Option<ResumePoint>,
+        on_progress: ResumeSink<'_>,
+    ) -> Result<UploadedFile> {
+        XynoxaClient::upload_file_resumable(
+            self,
+            local_path,
+            file_id,
+            folder_id,
+            original_name,
+            resume,
+            on_progress,
+        )
+        .await
+    }
+
+    async fn download_file_resumable(
+        &self,
+        file_id: &str,
+        local_path: &Path,
+        if_none_match: Option<&str>,
+        resume: Option<ResumePoint>,
+        on_progress: ResumeSink<'_>,
+    ) -> Result<DownloadOutcome> {
+        XynoxaClient::download_file_resumable(
+            self,
+            file_id,
+            local_path,
+            if_none_match,
+            resume,
+            on_progress,
+        )
+        .await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_sync_response_last_page() {
+        let page = |next_cursor, has_more| SyncResponse {
+            events: vec![],
+            next_cursor,
+            has_more,
+        };
+        assert!(page(10, None).is_last_page(5));
+        assert!(!page(10, Some(true)).is_last_page(5));
+        assert!(page(10, Some(false)).is_last_page(5));
+        // Non-advancing cursor never loops
+        assert!(page(5, Some(true)).is_last_page(5));
+    }
+
+    #[test]
+    fn test_parallel_download_ranges() {
+        assert_eq!(split_ranges(10, 4), vec![0..3, 3..6, 6..9, 9..10]);
+        assert_eq!(split_ranges(8, 4), vec![0..2, 2..4, 4..6, 6..8]);
+        assert_eq!(split_ranges(2, 4), vec![0..1, 1..2]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.bin");
+        std::fs::write(&path, b"abc").unwrap();
+        let sha_abc = "\"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\"";
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime
+            .block_on(verify_content_hash(&path, sha_abc))
+            .unwrap();
+        // Not a content hash, nothing to compare
+        runtime
+            .block_on(verify_content_hash(&path, "\"v7\""))
+            .unwrap();
+        std::fs::write(&path, b"abd").unwrap();
+        assert!(runtime
+            .block_on(verify_content_hash(&path, sha_abc))
+            .is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_probe_candidates_strip_api_suffix() {
+        assert_eq!(
+            probe_candidates(" cloud.example.com/api/ ").unwrap(),
+            ["https://cloud.example.com"]
+        );
+        assert_eq!(
+            probe_candidates("http://localhost:3000/xynoxa/api/trpc?x=1").unwrap(),
+            ["http://localhost:3000/xynoxa", "http://localhost:3000"]
+        );
+        assert!(probe_candidates("ftp://cloud.example.com").is_err());
+    }
+
+    #[test]
+    fn test_session_expiry_warning() {
+        let session: SessionInfo = serde_json::from_str(
+            r#"{"user": {"id": "u1"}, "scopes": ["files"], "expiresAt": "2026-03-10T12:00:00Z"}"#,
+        )
+        .unwrap();
+        let at = |s: &str| s.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+        let warning = chrono::Duration::days(3);
+        assert!(session
+            .expires_within(warning, at("2026-03-01T12:00:00Z"))
+            .is_none());
+        assert_eq!(
+            session.expires_within(warning, at("2026-03-08T12:00:00Z")),
+            Some(chrono::Duration::days(2))
+        );
+    }
+
+    #[test]
+    fn test_pairing_status_decoding() {
+        let status: PairingStatus =
+            serde_json::from_str(r#"{"status": "approved", "token": "xyn-paired"}"#).unwrap();
+        assert_eq!(
+            status,
+            PairingStatus::Approved {
+                token: "xyn-paired".into(),
+                device_id: None,
+            }
+        );
+        let status: PairingStatus = serde_json::from_str(
+            r#"{"status": "approved", "token": "xyn-paired", "deviceId": "d1"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            status,
+            PairingStatus::Approved {
+                token: "xyn-paired".into(),
+                device_id: Some("d1".into()),
+            }
+        );
+        let status: PairingStatus = serde_json::from_str(r#"{"status": "pending"}"#).unwrap();
+        assert_eq!(status, PairingStatus::Pending);
+    }
+
     #[test]
     fn test_file_entry_serialization() {
         let entry = FileEntry {
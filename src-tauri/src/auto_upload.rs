@@ -0,0 +1,404 @@
+//! One-way uploads of new files from [`AutoUploadSource`] directories (camera
+//! roll, screenshots, ...) into a remote folder, mirroring their subfolders.
+//! A ledger remembers what was uploaded; files already in a source when it was
+//! added are left alone.
+
+use crate::api::{ApiClient, XynoxaClient};
+use crate::config::AutoUploadSource;
+use crate::db::{Database, DbKey};
+use crate::error::Result;
+use crate::sync::{compute_hash, SyncOptions};
+use crate::watcher::{self, FolderWatcher};
+use notify::Result as NotifyResult;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+/// A file must keep the same size for this long before it is uploaded, so we
+/// don't pick up photos that are still being copied off a camera.
+const SETTLE_DURATION: Duration = Duration::from_secs(3);
+
+/// Called with (file name, share URL) after a `share_link` source was uploaded.
+pub type ShareCallback = Box<dyn Fn(String, String) + Send>;
+
+/// Default screenshot directory of the current OS.
+pub fn default_screenshot_dir() -> Option<PathBuf> {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()?;
+    let home = PathBuf::from(home);
+    if cfg!(target_os = "macos") {
+        // macOS saves screenshots to the Desktop unless changed
+        Some(home.join("Desktop"))
+    } else {
+        Some(home.join("Pictures").join("Screenshots"))
+    }
+}
+
+enum AutoUploadCommand {
+    FileSystemEvent(PathBuf),
+    Shutdown,
+}
+
+/// One-way upload pipeline for [`AutoUploadSource`] directories. Runs on its own
+/// thread next to the sync worker; dropping the handle stops it.
+pub struct AutoUploadHandle {
+    sender: Sender<AutoUploadCommand>,
+    stopped: Receiver<()>,
+}
+
+impl AutoUploadHandle {
+    pub fn new(
+        token: String,
+        api_url: String,
+        sources: Vec<AutoUploadSource>,
+        ledger_path: PathBuf,
+        options: &SyncOptions,
+        db_key: &DbKey,
+        on_share: ShareCallback,
+    ) -> Result<Self> {
+        let (tx, rx) = channel();
+        let (stopped_tx, stopped_rx) = channel::<()>();
+
+        let mut watchers = Vec::new();
+        for source in &sources {
+            let root = PathBuf::from(&source.path);
+            if !root.is_dir() {
+                tracing::warn!(
+                    "Auto-upload source {:?} is not a directory. Skipping.",
+                    root
+                );
+                continue;
+            }
+            let tx_for_watcher = tx.clone();
+            let handler: watcher::EventHandler =
+                Arc::new(move |res: NotifyResult<notify::Event>| match res {
+                    Ok(event) => {
+                        if matches!(
+                            event.kind,
+                            notify::EventKind::Create(_) | notify::EventKind::Modify(_)
+                        ) {
+                            for path in event.paths {
+                                let _ =
+                                    tx_for_watcher.send(AutoUploadCommand::FileSystemEvent(path));
+                            }
+                        }
+                    }
+                    Err(e) => tracing::warn!("Auto-upload watch error: {:?}", e),
+                });
+            let watcher = FolderWatcher::start(
+                &root,
+                source.watch_mode,
+                options.watcher.poll_interval_secs,
+                handler,
+            )
+            .map_err(|e| e.to_string())?;
+            watchers.push(watcher);
+        }
+
+        let db = Database::open(&ledger_path, db_key)?;
+        let client = XynoxaClient::with_options(token, api_url, &options.client);
+
+        thread::spawn(move || {
+            let mut worker = AutoUploadWorker {
+                client,
+                db,
+                sources,
+                receiver: rx,
+                _watchers: watchers,
+                pending: HashMap::new(),
+                folders: HashMap::new(),
+                on_share,
+                started_at: chrono::Utc::now().timestamp(),
+            };
+            worker.run();
+            // After the worker, which closes the ledger and the watchers
+            drop(worker);
+            drop(stopped_tx);
+        });
+
+        Ok(Self {
+            sender: tx,
+            stopped: stopped_rx,
+        })
+    }
+
+    /// Stops the worker after the running upload. The receiver disconnects
+    /// once it closed its ledger.
+    pub fn stop(mut self) -> Receiver<()> {
+        let _ = self.sender.send(AutoUploadCommand::Shutdown);
+        let (_, placeholder) = channel();
+        std::mem::replace(&mut self.stopped, placeholder)
+    }
+}
+
+impl Drop for AutoUploadHandle {
+    // The watchers hold senders too, so the channel never disconnects on its own
+    fn drop(&mut self) {
+        let _ = self.sender.send(AutoUploadCommand::Shutdown);
+    }
+}
+
+struct AutoUploadWorker {
+    client: XynoxaClient,
+    db: Database,
+    sources: Vec<AutoUploadSource>,
+    receiver: Receiver<AutoUploadCommand>,
+    _watchers: Vec<FolderWatcher>,
+    /// Candidate files with the size seen at the last check
+    pending: HashMap<PathBuf, (u64, Instant)>,
+    /// Remote folder ids of local subfolders, see [`remote_folder`]
+    folders: HashMap<PathBuf, String>,
+    on_share: ShareCallback,
+    /// Unix time the worker started, the share cutoff of sources without one
+    started_at: i64,
+}
+
+impl AutoUploadWorker {
+    fn run(&mut self) {
+        tracing::info!(
+            "Auto-upload worker started for {} sources.",
+            self.sources.len()
+        );
+        let runtime = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(e) => {
+                tracing::error!("Auto-upload runtime failed: {}", e);
+                return;
+            }
+        };
+
+        // Pick up anything added while the client was not running, but not
+        // what was there before the source was added. Sharing sources are
+        // left out: files already there are never shared.
+        for source in self.sources.clone().into_iter().filter(|s| !s.share_link) {
+            for entry in WalkDir::new(&source.path)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .filter(|e| {
+                    source
+                        .added_at
+                        .is_none_or(|since| is_newer(e.path(), since))
+                })
+            {
+                self.enqueue(entry.into_path());
+            }
+        }
+
+        loop {
+            match self.receiver.recv_timeout(SETTLE_DURATION) {
+                Ok(AutoUploadCommand::FileSystemEvent(path)) => self.enqueue(path),
+                Ok(AutoUploadCommand::Shutdown) => {
+                    tracing::info!("Auto-upload shutdown requested. Worker stopping.");
+                    break;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    tracing::info!("Auto-upload channel disconnected. Worker stopping.");
+                    break;
+                }
+            }
+            runtime.block_on(self.flush_settled());
+        }
+    }
+
+    fn enqueue(&mut self, path: PathBuf) {
+        if self.source_for(&path).is_none() {
+            return;
+        }
+        let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+        self.pending.insert(path, (size, Instant::now()));
+    }
+
+    fn source_for(&self, path: &Path) -> Option<&AutoUploadSource> {
+        let source = self.sources.iter().find(|s| path.starts_with(&s.path))?;
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.starts_with('.'))
+            .unwrap_or(true);
+        if hidden || !path.is_file() {
+            return None;
+        }
+        if source.media_only {
+            let mime = mime_guess::from_path(path).first_or_octet_stream();
+            if mime.type_() != mime_guess::mime::IMAGE && mime.type_() != mime_guess::mime::VIDEO {
+                return None;
+            }
+        }
+        Some(source)
+    }
+
+    async fn flush_settled(&mut self) {
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        self.pending.retain(|path, (size, seen)| {
+            if now.duration_since(*seen) < SETTLE_DURATION {
+                return true;
+            }
+            let current = path.metadata().map(|m| m.len()).unwrap_or(0);
+            if current != *size || current == 0 {
+                // Still being written; check again later
+                *size = current;
+                *seen = now;
+                return path.exists();
+            }
+            ready.push(path.clone());
+            false
+        });
+
+        for path in ready {
+            if let Err(e) = self.upload(&path).await {
+                tracing::error!("Auto-upload failed for {:?}: {}", path, e);
+            }
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn upload(&mut self, path: &Path) -> Result<()> {
+        let Some(source) = self.source_for(path).cloned() else {
+            return Ok(());
+        };
+        let key = path.to_string_lossy().to_string();
+        let hash = compute_hash(path)?;
+        if self.db.is_auto_uploaded(&key, &hash)? {
+            return Ok(());
+        }
+
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        tracing::info!("Auto-uploading {} from {}", name, source.path);
+        let folder_id = remote_folder(
+            &self.client,
+            &mut self.folders,
+            Path::new(&source.path),
+            source.remote_folder_id.as_deref(),
+            path,
+        )
+        .await?;
+        let uploaded = self
+            .client
+            .upload_file(path, None, folder_id.as_deref(), &name)
+            .await?;
+        self.db.mark_auto_uploaded(&key, &hash, &uploaded.id)?;
+
+        if source.share_link && is_newer(path, source.share_since.unwrap_or(self.started_at)) {
+            let url = self.client.create_share_link(&uploaded.id).await?;
+            tracing::info!("Share link created for {}", name);
+            (self.on_share)(name, url);
+        }
+        Ok(())
+    }
+}
+
+/// The remote folder for `path` below `root`: `base` for files directly in
+/// `root`, otherwise the matching subfolder of `base`, looked up or created
+/// level by level, so equal names in different subfolders do not collide.
+/// Ids found are kept in `folders`, keyed by local folder.
+async fn remote_folder(
+    client: &dyn ApiClient,
+    folders: &mut HashMap<PathBuf, String>,
+    root: &Path,
+    base: Option<&str>,
+    path: &Path,
+) -> Result<Option<String>> {
+    let relative = path
+        .parent()
+        .and_then(|dir| dir.strip_prefix(root).ok())
+        .unwrap_or(Path::new(""));
+    let mut parent = base.map(str::to_string);
+    let mut local = root.to_path_buf();
+    for component in relative.components() {
+        local.push(component);
+        if let Some(id) = folders.get(&local) {
+            parent = Some(id.clone());
+            continue;
+        }
+        let name = component.as_os_str().to_string_lossy();
+        let id = match client.find_folder(&name, parent.as_deref()).await? {
+            Some(folder) => folder.id,
+            None => client.create_folder(&name, parent.as_deref()).await?.id,
+        };
+        folders.insert(local.clone(), id.clone());
+        parent = Some(id);
+    }
+    Ok(parent)
+}
+
+/// Whether `path` was created (or, where creation times are unknown,
+/// modified) at or after `since`, a Unix time.
+fn is_newer(path: &Path, since: i64) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    metadata
+        .created()
+        .or_else(|_| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .is_some_and(|time| time.as_secs() as i64 >= since)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_api::MockApiClient;
+
+    #[test]
+    fn test_subfolders_are_mirrored() {
+        let client = MockApiClient::new();
+        let mut folders = HashMap::new();
+        let root = Path::new("/media/DCIM");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut folder_of = |path: &str| {
+            runtime
+                .block_on(remote_folder(
+                    &client,
+                    &mut folders,
+                    root,
+                    Some("camera"),
+                    Path::new(path),
+                ))
+                .unwrap()
+        };
+        assert_eq!(
+            folder_of("/media/DCIM/IMG_0001.JPG"),
+            Some("camera".to_string())
+        );
+        let first = folder_of("/media/DCIM/100CANON/IMG_0001.JPG");
+        let second = folder_of("/media/DCIM/101CANON/IMG_0001.JPG");
+        assert!(first.is_some() && second.is_some());
+        assert_ne!(first, second);
+        // Known folders are neither looked up nor created again
+        assert_eq!(folder_of("/media/DCIM/100CANON/IMG_0002.JPG"), first);
+
+        let state = client.state();
+        assert_eq!(state.folder_lookups, 2);
+        assert_eq!(
+            state.created_folders,
+            vec![
+                ("100CANON".to_string(), Some("camera".to_string())),
+                ("101CANON".to_string(), Some("camera".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_files_before_the_cutoff_are_old() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("IMG_0001.JPG");
+        std::fs::write(&path, b"photo").unwrap();
+        let now = chrono::Utc::now().timestamp();
+        assert!(is_newer(&path, now - 60));
+        assert!(!is_newer(&path, now + 60));
+        assert!(!is_newer(&dir.path().join("missing.jpg"), 0));
+    }
+}
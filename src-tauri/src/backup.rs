@@ -0,0 +1,341 @@
+//! Scheduled one-way backups of folders outside the sync root, configured in
+//! [`BackupSource`]. Each run uploads the whole folder into a new remote
+//! folder named after its start time. Nothing is ever downloaded, and the only
+//! deletions are a source's own snapshots beyond its `keep` limit.
+
+use crate::api::XynoxaClient;
+use crate::config::BackupSource;
+use crate::db::{BackupSnapshot, Database, DbKey};
+use crate::error::Result;
+use crate::ignore::IgnoreList;
+use crate::sync::SyncOptions;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+use walkdir::WalkDir;
+
+/// How often the worker checks whether a source is due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Wait after a failed run before trying again, in seconds.
+const RETRY_DELAY: i64 = 60 * 60;
+
+enum BackupCommand {
+    RunNow,
+}
+
+/// Runs [`BackupSource`] snapshots on their own thread; dropping the handle
+/// stops it.
+pub struct BackupHandle {
+    sender: Sender<BackupCommand>,
+    stopped: Receiver<()>,
+}
+
+impl BackupHandle {
+    pub fn new(
+        token: String,
+        api_url: String,
+        sources: Vec<BackupSource>,
+        ledger_path: PathBuf,
+        options: &SyncOptions,
+        db_key: &DbKey,
+    ) -> Result<Self> {
+        let (tx, rx) = channel();
+        let (stopped_tx, stopped_rx) = channel::<()>();
+        let db = Database::open(&ledger_path, db_key)?;
+        let client = XynoxaClient::with_options(token, api_url, &options.client);
+        let ignore = IgnoreList::new(&options.ignore_patterns);
+
+        thread::spawn(move || {
+            let worker = BackupWorker {
+                client,
+                db,
+                sources,
+                ignore,
+                receiver: rx,
+            };
+            worker.run();
+            // After the worker, which closes the ledger
+            drop(worker);
+            drop(stopped_tx);
+        });
+
+        Ok(Self {
+            sender: tx,
+            stopped: stopped_rx,
+        })
+    }
+
+    /// Stops the worker after the running snapshot. The receiver disconnects
+    /// once it closed its ledger.
+    pub fn stop(self) -> Receiver<()> {
+        // Dropping the sender disconnects the worker's channel
+        self.stopped
+    }
+
+    /// Snapshots every source now instead of when it is due.
+    pub fn run_now(&self) {
+        let _ = self.sender.send(BackupCommand::RunNow);
+    }
+}
+
+struct BackupWorker {
+    client: XynoxaClient,
+    db: Database,
+    sources: Vec<BackupSource>,
+    ignore: IgnoreList,
+    receiver: Receiver<BackupCommand>,
+}
+
+impl BackupWorker {
+    fn run(&self) {
+        tracing::info!("Backup worker started for {} sources.", self.sources.len());
+        let runtime = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(e) => {
+                tracing::error!("Backup runtime failed: {}", e);
+                return;
+            }
+        };
+
+        loop {
+            let forced = match self.receiver.recv_timeout(CHECK_INTERVAL) {
+                Ok(BackupCommand::RunNow) => true,
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => {
+                    tracing::info!("Backup channel disconnected. Worker stopping.");
+                    break;
+                }
+            };
+            let now = chrono::Utc::now().timestamp();
+            for source in &self.sources {
+                let due = match self.db.backup_snapshots(&source.path) {
+                    Ok(snapshots) => forced || is_due(&snapshots, source.interval_hours, now),
+                    Err(e) => {
+                        tracing::error!("Backup ledger unreadable: {}", e);
+                        false
+                    }
+                };
+                if due {
+                    if let Err(e) = runtime.block_on(self.snapshot(source)) {
+                        tracing::error!("Backup of {} failed: {}", source.path, e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Uploads `source` into a new snapshot folder, then prunes old snapshots.
+    async fn snapshot(&self, source: &BackupSource) -> Result<()> {
+        let root = Path::new(&source.path);
+        if !root.is_dir() {
+            tracing::warn!("Backup source {:?} is not a directory. Skipping.", root);
+            return Ok(());
+        }
+        let base_id = match &source.remote_folder_id {
+            Some(id) => id.clone(),
+            None => self.base_folder(root).await?,
+        };
+        let started = chrono::Local::now();
+        let mut snapshot = BackupSnapshot {
+            folder_id: String::new(),
+            source: source.path.clone(),
+            name: started.format("%Y-%m-%d_%H%M%S").to_string(),
+            created_at: started.timestamp(),
+            complete: false,
+        };
+        snapshot.folder_id = self
+            .client
+            .create_folder(&snapshot.name, Some(&base_id))
+            .await?
+            .id;
+        // Recorded before uploading, so an interrupted run is pruned later
+        self.db.add_backup_snapshot(&snapshot)?;
+        tracing::info!("Backing up {} to snapshot {}", source.path, snapshot.name);
+
+        let (uploaded, failed) = self.upload_tree(root, &snapshot.folder_id).await;
+        snapshot.complete = failed == 0;
+        self.db.add_backup_snapshot(&snapshot)?;
+        if !snapshot.complete {
+            tracing::warn!(
+                "Backup snapshot {} of {} is incomplete: {} files uploaded, {} failed",
+                snapshot.name,
+                source.path,
+                uploaded,
+                failed
+            );
+            return Ok(());
+        }
+        tracing::info!(
+            "Backup snapshot {} of {} complete: {} files",
+            snapshot.name,
+            source.path,
+            uploaded
+        );
+
+        let snapshots = self.db.backup_snapshots(&source.path)?;
+        for old in expired(&snapshots, source.keep) {
+            tracing::info!(
+                "Deleting old backup snapshot {} of {}",
+                old.name,
+                old.source
+            );
+            match self.client.delete_folder(&old.folder_id).await {
+                Ok(()) => self.db.remove_backup_snapshot(&old.folder_id)?,
+                Err(e) => tracing::warn!("Could not delete snapshot {}: {}", old.name, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// The folder in the server root named after `root`, created if missing.
+    async fn base_folder(&self, root: &Path) -> Result<String> {
+        let name = root
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let existing = self.client.find_folder(&name, None).await.map_err(|e| {
+            format!(
+                "Could not look up backup folder {} ({}); set remote_folder_id",
+                name, e
+            )
+        })?;
+        match existing {
+            Some(folder) => Ok(folder.id),
+            None => Ok(self.client.create_folder(&name, None).await?.id),
+        }
+    }
+
+    /// Uploads every file below `root` not matching the ignore patterns,
+    /// mirroring its folders. Returns the number of uploaded and failed files.
+    async fn upload_tree(&self, root: &Path, snapshot_id: &str) -> (usize, usize) {
+        let mut folders: HashMap<PathBuf, String> = HashMap::new();
+        folders.insert(PathBuf::new(), snapshot_id.to_string());
+        let (mut uploaded, mut failed) = (0, 0);
+        let entries = WalkDir::new(root)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| !self.ignore.matches_name(&e.file_name().to_string_lossy()));
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    tracing::warn!("Backup cannot read {:?}: {}", e.path(), e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            let Ok(relative) = entry.path().strip_prefix(root) else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Missing when creating the parent folder failed
+            let Some(parent_id) = relative.parent().and_then(|p| folders.get(p)).cloned() else {
+                failed += entry.file_type().is_file() as usize;
+                continue;
+            };
+            if entry.file_type().is_dir() {
+                match self.client.create_folder(&name, Some(&parent_id)).await {
+                    Ok(folder) => {
+                        folders.insert(relative.to_path_buf(), folder.id);
+                    }
+                    Err(e) => {
+                        tracing::warn!("Backup could not create folder {:?}: {}", relative, e);
+                        failed += 1;
+                    }
+                }
+            } else if entry.file_type().is_file() {
+                match self
+                    .client
+                    .upload_file(entry.path(), None, Some(&parent_id), &name)
+                    .await
+                {
+                    Ok(_) => uploaded += 1,
+                    Err(e) => {
+                        tracing::warn!("Backup could not upload {:?}: {}", relative, e);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+        (uploaded, failed)
+    }
+}
+
+/// Whether a source with `snapshots` (newest first) should run at `now`:
+/// `interval_hours` after its last complete snapshot, and not sooner than
+/// [`RETRY_DELAY`] after a failed run.
+fn is_due(snapshots: &[BackupSnapshot], interval_hours: u32, now: i64) -> bool {
+    let last_complete = snapshots.iter().find(|s| s.complete);
+    if last_complete.is_some_and(|s| now < s.created_at + interval_hours as i64 * 3600) {
+        return false;
+    }
+    snapshots
+        .first()
+        .is_none_or(|last| last.complete || now >= last.created_at + RETRY_DELAY)
+}
+
+/// Snapshots to delete from `snapshots` (newest first): complete ones beyond
+/// the newest `keep` and incomplete ones older than the newest complete one.
+fn expired(snapshots: &[BackupSnapshot], keep: u32) -> Vec<&BackupSnapshot> {
+    let Some(newest_complete) = snapshots.iter().find(|s| s.complete) else {
+        return Vec::new();
+    };
+    let keep = keep.max(1) as usize;
+    let mut kept = 0;
+    snapshots
+        .iter()
+        .filter(|s| {
+            if s.complete {
+                kept += 1;
+                kept > keep
+            } else {
+                s.created_at < newest_complete.created_at
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(created_at: i64, complete: bool) -> BackupSnapshot {
+        BackupSnapshot {
+            folder_id: format!("s{}", created_at),
+            source: "/home/me/Documents".to_string(),
+            name: created_at.to_string(),
+            created_at,
+            complete,
+        }
+    }
+
+    #[test]
+    fn test_retention_and_schedule() {
+        let hour = 3600;
+        // Newest first
+        let snapshots = vec![
+            snapshot(5 * hour, false),
+            snapshot(4 * hour, true),
+            snapshot(3 * hour, false),
+            snapshot(2 * hour, true),
+            snapshot(hour, true),
+        ];
+        let expired: Vec<i64> = expired(&snapshots, 2)
+            .iter()
+            .map(|s| s.created_at)
+            .collect();
+        // The running or failed newest one stays
+        assert_eq!(expired, vec![3 * hour, hour]);
+        assert!(super::expired(&[snapshot(hour, false)], 1).is_empty());
+
+        assert!(is_due(&[], 24, 0));
+        // Failed an hour after the last complete run: retried after the delay
+        assert!(!is_due(&snapshots, 1, 5 * hour + 10));
+        assert!(is_due(&snapshots, 1, 6 * hour));
+        assert!(!is_due(&snapshots[1..], 24, 5 * hour));
+        assert!(is_due(&snapshots[1..], 24, 28 * hour));
+    }
+}
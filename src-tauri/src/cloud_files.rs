@@ -0,0 +1,457 @@
+//! Files on demand through the Windows Cloud Files API (CfAPI).
+//!
+//! With `files_on_demand`, the sync root is registered as a cloud sync root and
+//! new server files become placeholders instead of being downloaded. Explorer
+//! shows their "online-only / always available" state and status badges, and
+//! the OS asks us for the content (`FETCH_DATA`) when a placeholder is opened.
+//! The sync engine keeps driving everything else. Other platforms get a stub
+//! whose `connect` fails, so the engine falls back to plain downloads.
+
+use crate::api::XynoxaClient;
+use crate::error::{Result, XynoxaError};
+use std::path::Path;
+
+/// Whether `path` is a placeholder whose content is not on disk. Reading such
+/// a file makes the OS download it, so scans must not hash it.
+pub fn is_dehydrated(path: &Path) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        let Ok(metadata) = std::fs::symlink_metadata(path) else {
+            return false;
+        };
+        const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+        const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+        const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+        metadata.file_attributes()
+            & (FILE_ATTRIBUTE_OFFLINE
+                | FILE_ATTRIBUTE_RECALL_ON_OPEN
+                | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS)
+            != 0
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+#[cfg(windows)]
+pub use windows_impl::{disk_space, hydrate, set_always_available, Placeholders};
+
+#[cfg(not(windows))]
+const UNSUPPORTED: &str = "Files on demand are only available on Windows";
+
+#[cfg(not(windows))]
+pub struct Placeholders;
+
+#[cfg(not(windows))]
+impl Placeholders {
+    pub fn connect(_root: &Path, _client: XynoxaClient) -> Result<Self> {
+        Err(XynoxaError::Other(UNSUPPORTED.to_string()))
+    }
+
+    pub fn create(&self, _relative: &str, _file_id: &str, _size: u64) -> Result<()> {
+        Err(XynoxaError::Other(UNSUPPORTED.to_string()))
+    }
+
+    pub fn mark_in_sync(&self, _relative: &str) -> Result<()> {
+        Err(XynoxaError::Other(UNSUPPORTED.to_string()))
+    }
+
+    pub fn free_up(&self, _relative: &str, _file_id: &str) -> Result<()> {
+        Err(XynoxaError::Other(UNSUPPORTED.to_string()))
+    }
+}
+
+/// Pins `path` so the OS downloads and keeps it, or unpins it and frees its
+/// local space ("Always keep on this device" / "Free up space" in Explorer).
+#[cfg(not(windows))]
+pub fn set_always_available(_path: &Path, _always: bool) -> Result<()> {
+    Err(XynoxaError::Other(UNSUPPORTED.to_string()))
+}
+
+/// Downloads the content of placeholder `path`, blocking until it is on disk.
+#[cfg(not(windows))]
+pub fn hydrate(_path: &Path) -> Result<()> {
+    Err(XynoxaError::Other(UNSUPPORTED.to_string()))
+}
+
+/// Total and free bytes of the volume holding `path`.
+#[cfg(unix)]
+pub fn disk_space(path: &Path) -> Result<(u64, u64)> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| XynoxaError::Other(e.to_string()))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let block = stat.f_frsize as u64;
+    Ok((stat.f_blocks as u64 * block, stat.f_bavail as u64 * block))
+}
+
+#[cfg(not(any(windows, unix)))]
+pub fn disk_space(_path: &Path) -> Result<(u64, u64)> {
+    Err(XynoxaError::Other(UNSUPPORTED.to_string()))
+}
+
+#[cfg(windows)]
+mod windows_impl {
+    use super::*;
+    use crate::sync::resolve_local_path;
+    use std::ffi::c_void;
+    use std::io::Read;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::PathBuf;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use windows::core::{GUID, PCWSTR};
+    use windows::Win32::Foundation::{NTSTATUS, STATUS_SUCCESS, STATUS_UNSUCCESSFUL};
+    use windows::Win32::Storage::CloudFilters::*;
+    use windows::Win32::Storage::FileSystem::{
+        GetDiskFreeSpaceExW, FILE_ATTRIBUTE_NORMAL, FILE_BASIC_INFO,
+    };
+
+    /// Identifies this provider to the Cloud Files API; never change it.
+    const PROVIDER_ID: GUID = GUID::from_u128(0x5f1c_7a0e_3b2d_4c8e_9a61_d4e2_b7c3_0f58);
+
+    /// Content is handed to the OS in pieces of this size (a 4 KiB multiple).
+    const TRANSFER_CHUNK: usize = 1024 * 1024;
+
+    /// Seconds between 1601-01-01 (FILETIME epoch) and 1970-01-01.
+    const FILETIME_UNIX_OFFSET: i64 = 11_644_473_600;
+
+    struct FetchContext {
+        client: XynoxaClient,
+        runtime: tokio::runtime::Runtime,
+        scratch_dir: PathBuf,
+    }
+
+    /// A connected sync root; disconnects when dropped. The registration
+    /// stays, so Explorer keeps showing placeholders while the app is closed.
+    pub struct Placeholders {
+        root: PathBuf,
+        key: CF_CONNECTION_KEY,
+        // Referenced by the callbacks through the connection's context pointer
+        _context: Box<FetchContext>,
+    }
+
+    impl Placeholders {
+        /// Registers `root` as a cloud sync root (or updates the registration)
+        /// and starts serving content requests for its placeholders.
+        pub fn connect(root: &Path, client: XynoxaClient) -> Result<Self> {
+            // Outside the sync root so downloads don't trigger the watcher
+            let scratch_dir = std::env::temp_dir().join("xynoxa-hydrate");
+            std::fs::create_dir_all(&scratch_dir)?;
+            let root_wide = wide(root);
+            let name = wide(Path::new("Xynoxa"));
+            let version = wide(Path::new(env!("CARGO_PKG_VERSION")));
+            let registration = CF_SYNC_REGISTRATION {
+                StructSize: std::mem::size_of::<CF_SYNC_REGISTRATION>() as u32,
+                ProviderName: PCWSTR(name.as_ptr()),
+                ProviderVersion: PCWSTR(version.as_ptr()),
+                ProviderId: PROVIDER_ID,
+                ..Default::default()
+            };
+            let policies = CF_SYNC_POLICIES {
+                StructSize: std::mem::size_of::<CF_SYNC_POLICIES>() as u32,
+                Hydration: CF_HYDRATION_POLICY {
+                    Primary: CF_HYDRATION_POLICY_PRIMARY_USHORT {
+                        us: CF_HYDRATION_POLICY_FULL.0 as u16,
+                    },
+                    ..Default::default()
+                },
+                // The sync engine creates every placeholder up front
+                Population: CF_POPULATION_POLICY {
+                    Primary: CF_POPULATION_POLICY_PRIMARY_USHORT {
+                        us: CF_POPULATION_POLICY_ALWAYS_FULL.0 as u16,
+                    },
+                    ..Default::default()
+                },
+                InSync: CF_INSYNC_POLICY_TRACK_ALL,
+                HardLink: CF_HARDLINK_POLICY_NONE,
+                PlaceholderManagement: CF_PLACEHOLDER_MANAGEMENT_POLICY_DEFAULT,
+            };
+            unsafe {
+                CfRegisterSyncRoot(
+                    PCWSTR(root_wide.as_ptr()),
+                    &registration,
+                    &policies,
+                    CF_REGISTER_FLAG_UPDATE,
+                )
+            }
+            .map_err(|e| cf_error("register the sync root", e))?;
+
+            let context = Box::new(FetchContext {
+                client,
+                runtime: tokio::runtime::Runtime::new()?,
+                scratch_dir,
+            });
+            let callbacks = [
+                CF_CALLBACK_REGISTRATION {
+                    Type: CF_CALLBACK_TYPE_FETCH_DATA,
+                    Callback: Some(fetch_data),
+                },
+                CF_CALLBACK_REGISTRATION {
+                    Type: CF_CALLBACK_TYPE_NONE,
+                    Callback: None,
+                },
+            ];
+            let key = unsafe {
+                CfConnectSyncRoot(
+                    PCWSTR(root_wide.as_ptr()),
+                    callbacks.as_ptr(),
+                    Some(&*context as *const FetchContext as *const c_void),
+                    CF_CONNECT_FLAG_REQUIRE_FULL_FILE_PATH,
+                )
+            }
+            .map_err(|e| cf_error("connect the sync root", e))?;
+            tracing::info!("Files on demand enabled for {:?}", root);
+            Ok(Self {
+                root: root.to_path_buf(),
+                key,
+                _context: context,
+            })
+        }
+
+        /// Creates an online-only placeholder for server file `file_id` at
+        /// index path `relative`, marked in sync.
+        pub fn create(&self, relative: &str, file_id: &str, size: u64) -> Result<()> {
+            let path = resolve_local_path(&self.root, relative)?;
+            let parent = path.parent().unwrap_or(&self.root);
+            std::fs::create_dir_all(parent)?;
+            let parent_wide = wide(parent);
+            let name_wide = wide(Path::new(path.file_name().unwrap_or_default()));
+            let now = filetime(SystemTime::now());
+            let mut info = [CF_PLACEHOLDER_CREATE_INFO {
+                RelativeFileName: PCWSTR(name_wide.as_ptr()),
+                FsMetadata: CF_FS_METADATA {
+                    BasicInfo: FILE_BASIC_INFO {
+                        CreationTime: now,
+                        LastAccessTime: now,
+                        LastWriteTime: now,
+                        ChangeTime: now,
+                        FileAttributes: FILE_ATTRIBUTE_NORMAL.0,
+                    },
+                    FileSize: size as i64,
+                },
+                FileIdentity: file_id.as_ptr() as *const c_void,
+                FileIdentityLength: file_id.len() as u32,
+                Flags: CF_PLACEHOLDER_CREATE_FLAG_MARK_IN_SYNC,
+                ..Default::default()
+            }];
+            unsafe {
+                CfCreatePlaceholders(
+                    PCWSTR(parent_wide.as_ptr()),
+                    &mut info,
+                    CF_CREATE_FLAG_NONE,
+                    None,
+                )
+            }
+            .map_err(|e| cf_error("create a placeholder", e))?;
+            info[0]
+                .Result
+                .ok()
+                .map_err(|e| cf_error("create a placeholder", e))
+        }
+
+        /// Makes synced file `relative` online-only, freeing its local space.
+        /// Files the sync downloaded are plain files and become placeholders
+        /// of server file `file_id` first.
+        pub fn free_up(&self, relative: &str, file_id: &str) -> Result<()> {
+            let path = resolve_local_path(&self.root, relative)?;
+            let path_wide = wide(&path);
+            unsafe {
+                let handle = CfOpenFileWithOplock(
+                    PCWSTR(path_wide.as_ptr()),
+                    CF_OPEN_FILE_FLAG_EXCLUSIVE | CF_OPEN_FILE_FLAG_WRITE_ACCESS,
+                )
+                .map_err(|e| cf_error("open the file", e))?;
+                let result = CfConvertToPlaceholder(
+                    handle,
+                    Some(file_id.as_ptr() as *const c_void),
+                    file_id.len() as u32,
+                    CF_CONVERT_FLAG_MARK_IN_SYNC | CF_CONVERT_FLAG_DEHYDRATE,
+                    None,
+                    None,
+                )
+                // Already a placeholder
+                .or_else(|_| CfDehydratePlaceholder(handle, 0, -1, CF_DEHYDRATE_FLAG_NONE, None));
+                CfCloseHandle(handle);
+                result.map_err(|e| cf_error("free up space", e))
+            }
+        }
+
+        /// Shows `relative` as up to date in Explorer, e.g. after an upload.
+        pub fn mark_in_sync(&self, relative: &str) -> Result<()> {
+            let path = resolve_local_path(&self.root, relative)?;
+            let path_wide = wide(&path);
+            unsafe {
+                let handle =
+                    CfOpenFileWithOplock(PCWSTR(path_wide.as_ptr()), CF_OPEN_FILE_FLAG_NONE)
+                        .map_err(|e| cf_error("open the placeholder", e))?;
+                let result = CfSetInSyncState(
+                    handle,
+                    CF_IN_SYNC_STATE_IN_SYNC,
+                    CF_SET_IN_SYNC_FLAG_NONE,
+                    None,
+                );
+                CfCloseHandle(handle);
+                result.map_err(|e| cf_error("mark the file in sync", e))
+            }
+        }
+    }
+
+    /// Pins `path` so the OS downloads and keeps it, or unpins it and frees
+    /// its local space ("Always keep on this device" / "Free up space").
+    pub fn set_always_available(path: &Path, always: bool) -> Result<()> {
+        let path_wide = wide(path);
+        unsafe {
+            let handle = CfOpenFileWithOplock(PCWSTR(path_wide.as_ptr()), CF_OPEN_FILE_FLAG_NONE)
+                .map_err(|e| cf_error("open the placeholder", e))?;
+            let result = if always {
+                CfSetPinState(handle, CF_PIN_STATE_PINNED, CF_SET_PIN_FLAG_NONE, None)
+            } else {
+                CfSetPinState(handle, CF_PIN_STATE_UNPINNED, CF_SET_PIN_FLAG_NONE, None).and_then(
+                    |()| CfDehydratePlaceholder(handle, 0, -1, CF_DEHYDRATE_FLAG_NONE, None),
+                )
+            };
+            CfCloseHandle(handle);
+            result.map_err(|e| cf_error("change the pin state", e))
+        }
+    }
+
+    /// Downloads the content of placeholder `path`, blocking until it is on
+    /// disk. The OS fetches it through [`fetch_data`].
+    pub fn hydrate(path: &Path) -> Result<()> {
+        let path_wide = wide(path);
+        unsafe {
+            let handle = CfOpenFileWithOplock(PCWSTR(path_wide.as_ptr()), CF_OPEN_FILE_FLAG_NONE)
+                .map_err(|e| cf_error("open the placeholder", e))?;
+            let result = CfHydratePlaceholder(handle, 0, -1, CF_HYDRATE_FLAG_NONE, None);
+            CfCloseHandle(handle);
+            result.map_err(|e| cf_error("hydrate the placeholder", e))
+        }
+    }
+
+    /// Total and free bytes of the volume holding `path`.
+    pub fn disk_space(path: &Path) -> Result<(u64, u64)> {
+        let path_wide = wide(path);
+        let (mut total, mut free) = (0u64, 0u64);
+        unsafe {
+            GetDiskFreeSpaceExW(
+                PCWSTR(path_wide.as_ptr()),
+                None,
+                Some(&mut total),
+                Some(&mut free),
+            )
+        }
+        .map_err(|e| XynoxaError::Other(format!("Failed to read disk space: {}", e)))?;
+        Ok((total, free))
+    }
+
+    impl Drop for Placeholders {
+        fn drop(&mut self) {
+            if let Err(e) = unsafe { CfDisconnectSyncRoot(self.key) } {
+                tracing::warn!("Failed to disconnect sync root: {}", e);
+            }
+        }
+    }
+
+    /// The OS needs the content of a placeholder. Downloads the whole file and
+    /// hands it over chunk by chunk.
+    unsafe extern "system" fn fetch_data(
+        info: *const CF_CALLBACK_INFO,
+        params: *const CF_CALLBACK_PARAMETERS,
+    ) {
+        let info = &*info;
+        let context = &*(info.CallbackContext as *const FetchContext);
+        let file_id = String::from_utf8_lossy(std::slice::from_raw_parts(
+            info.FileIdentity as *const u8,
+            info.FileIdentityLength as usize,
+        ))
+        .to_string();
+        let fetch = (*params).Anonymous.FetchData;
+        if let Err(e) = transfer(info, context, &file_id) {
+            tracing::error!("Hydrating {} failed: {}", file_id, e);
+            // Fails the open in the application that triggered it
+            let _ = complete(
+                info,
+                STATUS_UNSUCCESSFUL,
+                &[],
+                fetch.RequiredFileOffset,
+                fetch.RequiredLength,
+            );
+        }
+    }
+
+    fn transfer(info: &CF_CALLBACK_INFO, context: &FetchContext, file_id: &str) -> Result<()> {
+        let scratch = context
+            .scratch_dir
+            .join(format!("{}.hydrate", info.TransferKey));
+        context
+            .runtime
+            .block_on(context.client.download_file(file_id, &scratch, None))?;
+        let result = (|| -> Result<()> {
+            let mut file = std::fs::File::open(&scratch)?;
+            let mut buffer = vec![0u8; TRANSFER_CHUNK];
+            let mut offset = 0i64;
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    return Ok(());
+                }
+                complete(info, STATUS_SUCCESS, &buffer[..read], offset, read as i64)
+                    .map_err(|e| cf_error("transfer data", e))?;
+                offset += read as i64;
+            }
+        })();
+        let _ = std::fs::remove_file(&scratch);
+        result
+    }
+
+    fn complete(
+        info: &CF_CALLBACK_INFO,
+        status: NTSTATUS,
+        data: &[u8],
+        offset: i64,
+        length: i64,
+    ) -> windows::core::Result<()> {
+        let op_info = CF_OPERATION_INFO {
+            StructSize: std::mem::size_of::<CF_OPERATION_INFO>() as u32,
+            Type: CF_OPERATION_TYPE_TRANSFER_DATA,
+            ConnectionKey: info.ConnectionKey,
+            TransferKey: info.TransferKey,
+            CorrelationVector: info.CorrelationVector,
+            RequestKey: info.RequestKey,
+            ..Default::default()
+        };
+        let mut params = CF_OPERATION_PARAMETERS {
+            ParamSize: std::mem::size_of::<CF_OPERATION_PARAMETERS>() as u32,
+            ..Default::default()
+        };
+        unsafe {
+            params.Anonymous.TransferData.CompletionStatus = status;
+            params.Anonymous.TransferData.Buffer = data.as_ptr() as *const c_void;
+            params.Anonymous.TransferData.Offset = offset;
+            params.Anonymous.TransferData.Length = length;
+            CfExecute(&op_info, &mut params)
+        }
+    }
+
+    fn wide(path: &Path) -> Vec<u16> {
+        path.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+
+    fn filetime(time: SystemTime) -> i64 {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        (secs + FILETIME_UNIX_OFFSET) * 10_000_000
+    }
+
+    fn cf_error(action: &str, e: windows::core::Error) -> XynoxaError {
+        XynoxaError::Other(format!("Failed to {}: {}", action, e))
+    }
+}
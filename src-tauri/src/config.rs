@@ -1,34 +1,875 @@
-use serde::{Deserialize, Serialize};
+use crate::error::{FieldError, Result, XynoxaError};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
+/// Layout version written by this build, see [`migrate`].
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Upgrades a config of version `index` to `index + 1` in place.
+type Migration = fn(&mut serde_json::Map<String, serde_json::Value>);
+
+/// One entry per version step; the config layout may only change together with
+/// a new entry here and a bump of [`CONFIG_VERSION`].
+const MIGRATIONS: &[Migration] = &[
+    // 0 -> 1: files written before versioning; same layout
+    |_| {},
+];
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppConfig {
+    /// Layout version; missing in files written before versioning (0).
+    #[serde(default)]
+    pub config_version: u32,
     pub server_url: Option<String>,
     pub sync_path: Option<String>,
+    /// Id of the database in the sync folder, to recognize the folder after
+    /// it was moved. Learned when sync starts; reset when `sync_path` changes.
+    #[serde(default)]
+    pub sync_root_id: Option<i64>,
+    /// Server the sync folder was last synced with. While `server_url`
+    /// differs, syncing waits until the user confirms whether it is the same
+    /// server under a new address. Reset when `sync_path` changes.
+    #[serde(default)]
+    pub sync_server_url: Option<String>,
     pub auth_token: Option<String>,
+    /// Server-side id of the signed-in user, learned after signing in; part
+    /// of [`AppConfig::account_key`].
+    #[serde(default)]
+    pub account_id: Option<String>,
     pub setup_completed: bool,
+    #[serde(default)]
+    pub auto_upload_sources: Vec<AutoUploadSource>,
+    #[serde(default)]
+    pub screenshot_share: ScreenshotShareConfig,
+    /// Folders outside the sync root backed up on a schedule, see `backup`.
+    #[serde(default)]
+    pub backup_sources: Vec<BackupSource>,
+    /// Opt-in anonymous reliability metrics, see `telemetry`.
+    #[serde(default)]
+    pub telemetry_enabled: bool,
+    /// Download and launch the installer when a newer release is found at startup.
+    #[serde(default)]
+    pub auto_install_updates: bool,
+    #[serde(default)]
+    pub timeouts: TimeoutConfig,
+    #[serde(default)]
+    pub connection: ConnectionConfig,
+    /// Shown in the server's session list; `None` uses the host name.
+    #[serde(default)]
+    pub device_name: Option<String>,
+    /// Server-side id from `devices.register`
+    #[serde(default)]
+    pub device_id: Option<String>,
+    /// Encrypt the local databases with SQLCipher, keyed from the OS keyring.
+    #[serde(default)]
+    pub encrypt_database: bool,
+    #[serde(default)]
+    pub window: WindowBehavior,
+    /// UI language tag like `de`; `None` follows the system.
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub watcher: WatcherConfig,
+    #[serde(default)]
+    pub virtual_drive: VirtualDriveConfig,
+    /// Windows only: new server files become online-only placeholders that
+    /// are downloaded when opened (Cloud Files API).
+    #[serde(default)]
+    pub files_on_demand: bool,
+    #[serde(default)]
+    pub reclaim_space: ReclaimConfig,
+    /// Keep the machine awake while large transfers run.
+    #[serde(default = "default_true")]
+    pub prevent_sleep: bool,
+    /// Fetch file content directly from the user's other devices on the same
+    /// network when they have it.
+    #[serde(default = "default_true")]
+    pub lan_transfer: bool,
+    /// Transfer small and recently modified files before large ones instead
+    /// of in queue order.
+    #[serde(default = "default_true")]
+    pub small_files_first: bool,
+    /// Names of local files and folders that are never uploaded, see `ignore`.
+    #[serde(default = "crate::ignore::default_patterns")]
+    pub ignore_patterns: Vec<String>,
+    /// Tell the user when a synced Office document is open on another device,
+    /// derived from lock files other clients put on the server.
+    #[serde(default = "default_true")]
+    pub lock_notifications: bool,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// Megabytes uploaded plus downloaded per calendar month after which only
+    /// small and prioritized transfers run, for capped connections.
+    #[serde(default)]
+    pub bandwidth_cap_mb: Option<u64>,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            config_version: CONFIG_VERSION,
             server_url: None,
             sync_path: None,
+            sync_root_id: None,
+            sync_server_url: None,
             auth_token: None,
+            account_id: None,
             setup_completed: false,
+            auto_upload_sources: Vec::new(),
+            screenshot_share: ScreenshotShareConfig::default(),
+            backup_sources: Vec::new(),
+            telemetry_enabled: false,
+            auto_install_updates: false,
+            timeouts: TimeoutConfig::default(),
+            connection: ConnectionConfig::default(),
+            device_name: None,
+            device_id: None,
+            encrypt_database: false,
+            window: WindowBehavior::default(),
+            locale: None,
+            watcher: WatcherConfig::default(),
+            virtual_drive: VirtualDriveConfig::default(),
+            files_on_demand: false,
+            reclaim_space: ReclaimConfig::default(),
+            prevent_sleep: true,
+            lan_transfer: true,
+            small_files_first: true,
+            ignore_patterns: crate::ignore::default_patterns(),
+            lock_notifications: true,
+            hooks: HooksConfig::default(),
+            bandwidth_cap_mb: None,
+        }
+    }
+}
+
+/// A local directory whose new files are uploaded one-way into a remote folder
+/// (camera roll, screenshots, ...). Not part of the two-way sync root.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AutoUploadSource {
+    pub path: String,
+    /// Target folder on the server; `None` uploads into the root.
+    pub remote_folder_id: Option<String>,
+    /// Only upload images and videos.
+    #[serde(default = "default_true")]
+    pub media_only: bool,
+    /// Create a public share link after upload and copy it to the clipboard.
+    #[serde(default)]
+    pub share_link: bool,
+    /// Unix time `share_link` was switched on; older files are never shared.
+    /// Set by [`ConfigManager::update`].
+    #[serde(default)]
+    pub share_since: Option<i64>,
+    /// Unix time the source was added; files that were already there are not
+    /// uploaded. `None` for sources added before this was recorded. Set by
+    /// [`ConfigManager::update`].
+    #[serde(default)]
+    pub added_at: Option<i64>,
+    #[serde(default)]
+    pub watch_mode: WatchMode,
+}
+
+/// Keeps the time sharing was switched on for sources that already shared and
+/// sets it to `now` for the ones that start sharing. Likewise keeps the time
+/// existing sources were added and sets it to `now` for new ones.
+fn stamp_cutoffs(old: &[AutoUploadSource], sources: &mut [AutoUploadSource], now: i64) {
+    for source in sources {
+        let existing = old.iter().find(|old| old.path == source.path);
+        let since = existing
+            .filter(|old| old.share_link)
+            .and_then(|old| old.share_since);
+        source.share_since = source.share_link.then(|| since.unwrap_or(now));
+        source.added_at = existing.map_or(Some(now), |old| old.added_at);
+    }
+}
+
+/// A local directory uploaded as a whole into a new timestamped remote folder
+/// every `interval_hours`. Backups never download and never delete anything
+/// but their own snapshots beyond `keep`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BackupSource {
+    pub path: String,
+    /// Folder on the server holding the snapshots; `None` creates a folder
+    /// named after `path` in the root.
+    #[serde(default)]
+    pub remote_folder_id: Option<String>,
+    #[serde(default = "default_backup_interval")]
+    pub interval_hours: u32,
+    /// Snapshots kept; older ones are deleted after a complete snapshot
+    #[serde(default = "default_backup_keep")]
+    pub keep: u32,
+}
+
+fn default_backup_interval() -> u32 {
+    24
+}
+
+fn default_backup_keep() -> u32 {
+    7
+}
+
+/// Screenshot-to-share-link workflow: new screenshots are uploaded and their
+/// share link is copied to the clipboard.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ScreenshotShareConfig {
+    pub enabled: bool,
+    /// Screenshot directory; `None` uses the OS default.
+    pub path: Option<String>,
+    pub remote_folder_id: Option<String>,
+    /// Unix time sharing was switched on; older screenshots are never
+    /// shared. Set by [`ConfigManager::update`].
+    #[serde(default)]
+    pub enabled_at: Option<i64>,
+}
+
+/// HTTP timeouts in seconds. Transfers (uploads, upload chunks, downloads) get
+/// their own overall limit since they legitimately take much longer than API calls.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    pub connect_secs: u64,
+    /// Maximum time without receiving any data
+    pub read_secs: u64,
+    /// Overall limit for API requests
+    pub request_secs: u64,
+    /// Overall limit for a single transfer request
+    pub transfer_secs: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            connect_secs: 15,
+            read_secs: 60,
+            request_secs: 120,
+            transfer_secs: 3600,
+        }
+    }
+}
+
+/// HTTP connection reuse. Syncing many small files sends a burst of short
+/// requests, so idle connections are kept to skip the TLS handshake.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ConnectionConfig {
+    /// Idle connections kept open per server
+    pub max_idle_per_host: usize,
+    /// Seconds an idle connection is kept before closing it
+    pub idle_timeout_secs: u64,
+    /// Interval of TCP keep-alive probes in seconds; 0 disables them
+    pub keep_alive_secs: u64,
+    /// Use HTTP/2 with servers that offer it, multiplexing all requests over
+    /// one connection. Off forces HTTP/1.1.
+    pub http2: bool,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: 16,
+            idle_timeout_secs: 90,
+            keep_alive_secs: 60,
+            http2: true,
+        }
+    }
+}
+
+/// What the window's close button does.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CloseAction {
+    /// Keep running in the tray
+    #[default]
+    Hide,
+    Quit,
+}
+
+/// Main window behavior. Hiding options fall back to minimizing when the
+/// desktop has no tray, so the window cannot become unreachable.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct WindowBehavior {
+    pub close_action: CloseAction,
+    /// Hide the window instead of minimizing it to the taskbar
+    pub minimize_to_tray: bool,
+    /// Stay in the tray at startup once setup is complete
+    pub start_minimized: bool,
+}
+
+impl Default for WindowBehavior {
+    fn default() -> Self {
+        Self {
+            close_action: CloseAction::Hide,
+            minimize_to_tray: false,
+            start_minimized: true,
+        }
+    }
+}
+
+/// How changes in a watched folder are noticed, see `watcher`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// Poll on network and FUSE filesystems, use the platform watcher elsewhere
+    #[default]
+    Auto,
+    Native,
+    /// Rescan periodically; for mounts that deliver no change events
+    Polling,
+}
+
+/// How changes in the sync folder are noticed, see `watcher`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct WatcherConfig {
+    pub mode: WatchMode,
+    /// Scan interval when polling instead of using the platform watcher
+    pub poll_interval_secs: u64,
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        Self {
+            mode: WatchMode::Auto,
+            poll_interval_secs: 30,
+        }
+    }
+}
+
+/// The remote storage mounted as a lazily loaded drive, see `vfs`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct VirtualDriveConfig {
+    /// Mount at startup
+    pub enabled: bool,
+    /// `None` uses `~/Xynoxa Drive`
+    pub mount_path: Option<String>,
+    /// Size limit of the read cache in the app data dir
+    pub cache_limit_mb: u64,
+}
+
+impl Default for VirtualDriveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mount_path: None,
+            cache_limit_mb: 1024,
+        }
+    }
+}
+
+/// Makes rarely used files online-only again when the disk fills up. Needs
+/// `files_on_demand`; pinned files are never touched.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ReclaimConfig {
+    pub enabled: bool,
+    /// Files not opened for this many days are candidates
+    pub unused_days: u32,
+    /// Frees space while the disk holding the sync folder is fuller than this
+    pub disk_usage_percent: u8,
+}
+
+impl Default for ReclaimConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            unused_days: 30,
+            disk_usage_percent: 90,
         }
     }
 }
 
+/// Shell commands run on sync events, see `hooks`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// After a sync cycle that transferred files
+    pub on_sync_complete: Option<String>,
+    /// When a conflict backup was created
+    pub on_conflict: Option<String>,
+    /// When a sync cycle failed
+    pub on_error: Option<String>,
+    /// After each downloaded file matching `downloaded_pattern`
+    pub on_file_downloaded: Option<String>,
+    /// Glob for the file's path in the sync folder; `None` matches all
+    pub downloaded_pattern: Option<String>,
+}
+
+/// Host name of this machine, used as the default device name.
+pub fn default_device_name() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| {
+            fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|h| h.trim().to_string())
+        })
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "Desktop".to_string())
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A single field change inside a [`ConfigPatch`].
+///
+/// From JSON, a missing key means `Keep`, an explicit `null` means `Clear`
+/// and any other value means `Set`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum FieldUpdate<T> {
+    #[default]
+    Keep,
+    Set(T),
+    Clear,
+}
+
+impl<T> FieldUpdate<T> {
+    fn apply(self, target: &mut Option<T>) {
+        match self {
+            FieldUpdate::Keep => {}
+            FieldUpdate::Set(v) => *target = Some(v),
+            FieldUpdate::Clear => *target = None,
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for FieldUpdate<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Only reached when the key is present; absent keys use Default (Keep)
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(v) => FieldUpdate::Set(v),
+            None => FieldUpdate::Clear,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ConfigPatch {
+    pub server_url: FieldUpdate<String>,
+    pub sync_path: FieldUpdate<String>,
+    pub sync_root_id: FieldUpdate<i64>,
+    pub sync_server_url: FieldUpdate<String>,
+    pub auth_token: FieldUpdate<String>,
+    pub account_id: FieldUpdate<String>,
+    pub setup_completed: Option<bool>,
+    pub auto_upload_sources: Option<Vec<AutoUploadSource>>,
+    pub screenshot_share: Option<ScreenshotShareConfig>,
+    pub backup_sources: Option<Vec<BackupSource>>,
+    pub telemetry_enabled: Option<bool>,
+    pub auto_install_updates: Option<bool>,
+    pub timeouts: Option<TimeoutConfig>,
+    pub connection: Option<ConnectionConfig>,
+    pub device_name: FieldUpdate<String>,
+    pub device_id: FieldUpdate<String>,
+    pub encrypt_database: Option<bool>,
+    pub window: Option<WindowBehavior>,
+    pub locale: FieldUpdate<String>,
+    pub watcher: Option<WatcherConfig>,
+    pub virtual_drive: Option<VirtualDriveConfig>,
+    pub files_on_demand: Option<bool>,
+    pub reclaim_space: Option<ReclaimConfig>,
+    pub prevent_sleep: Option<bool>,
+    pub lan_transfer: Option<bool>,
+    pub small_files_first: Option<bool>,
+    pub ignore_patterns: Option<Vec<String>>,
+    pub lock_notifications: Option<bool>,
+    pub hooks: Option<HooksConfig>,
+    pub bandwidth_cap_mb: FieldUpdate<u64>,
+}
+
+/// Startup overrides from `XYNOXA_*` environment variables and command-line
+/// flags, for scripted and headless setups. They take precedence over the
+/// config file but are never written to it. Not `Debug`: holds the token.
+#[derive(Clone, Default)]
+pub struct ConfigOverrides {
+    pub server_url: Option<String>,
+    pub sync_path: Option<String>,
+    pub auth_token: Option<String>,
+}
+
+impl ConfigOverrides {
+    /// Reads `XYNOXA_SERVER_URL`, `XYNOXA_SYNC_PATH` and `XYNOXA_TOKEN`, then the
+    /// flags `--server-url`, `--sync-path` and `--token` (as `--flag value` or
+    /// `--flag=value`), which win. Prefer the variable for the token: flags are
+    /// visible in the process list. Demo mode sets the simulated server and
+    /// a folder in [`demo_dir`] instead.
+    pub fn from_env_and_args() -> Self {
+        if let Some(dir) = demo_dir() {
+            return Self {
+                server_url: Some(DEMO_SERVER_URL.to_string()),
+                sync_path: Some(dir.join("Xynoxa Demo").to_string_lossy().to_string()),
+                auth_token: Some("demo".to_string()),
+            };
+        }
+        Self::parse(|name| std::env::var(name).ok(), std::env::args().skip(1))
+    }
+
+    fn parse(env: impl Fn(&str) -> Option<String>, args: impl IntoIterator<Item = String>) -> Self {
+        let mut overrides = Self {
+            server_url: env("XYNOXA_SERVER_URL"),
+            sync_path: env("XYNOXA_SYNC_PATH"),
+            auth_token: env("XYNOXA_TOKEN"),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let slot = match flag.as_str() {
+                "--server-url" => &mut overrides.server_url,
+                "--sync-path" => &mut overrides.sync_path,
+                "--token" => &mut overrides.auth_token,
+                _ => continue,
+            };
+            if let Some(value) = inline.or_else(|| args.next()) {
+                *slot = Some(value);
+            }
+        }
+        // An empty value means "not set", not "override with nothing"
+        for slot in [
+            &mut overrides.server_url,
+            &mut overrides.sync_path,
+            &mut overrides.auth_token,
+        ] {
+            if slot.as_deref().is_some_and(str::is_empty) {
+                *slot = None;
+            }
+        }
+        overrides
+    }
+
+    /// Names of the overridden fields, for logging.
+    pub fn active(&self) -> Vec<&'static str> {
+        [
+            ("server_url", &self.server_url),
+            ("sync_path", &self.sync_path),
+            ("auth_token", &self.auth_token),
+        ]
+        .into_iter()
+        .filter(|(_, value)| value.is_some())
+        .map(|(name, _)| name)
+        .collect()
+    }
+
+    fn apply(&self, mut config: AppConfig) -> AppConfig {
+        if let Some(url) = &self.server_url {
+            config.server_url = Some(url.clone());
+        }
+        if let Some(path) = &self.sync_path {
+            config.sync_path = Some(path.clone());
+        }
+        if let Some(token) = &self.auth_token {
+            config.auth_token = Some(token.clone());
+        }
+        // Everything the wizard asks for is given: skip it (headless start)
+        if self.server_url.is_some() && self.sync_path.is_some() && self.auth_token.is_some() {
+            config.setup_completed = true;
+        }
+        config
+    }
+}
+
+type FieldCheck = fn(&str) -> Result<(), String>;
+
+impl ConfigPatch {
+    /// Problems with the values this patch sets, empty when it is valid.
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        let checks: [(&'static str, Option<&String>, FieldCheck); 3] = [
+            ("serverUrl", set_value(&self.server_url), check_server_url),
+            ("syncPath", set_value(&self.sync_path), check_sync_path),
+            ("authToken", set_value(&self.auth_token), check_token),
+        ];
+        checks
+            .into_iter()
+            .filter_map(|(field, value, check)| {
+                let message = check(value?).err()?;
+                Some(FieldError { field, message })
+            })
+            .collect()
+    }
+
+    /// Fails with [`XynoxaError::Validation`] listing every rejected field.
+    pub fn validate(&self) -> Result<()> {
+        let errors = self.field_errors();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(XynoxaError::Validation(errors))
+        }
+    }
+}
+
+fn set_value<T>(update: &FieldUpdate<T>) -> Option<&T> {
+    match update {
+        FieldUpdate::Set(value) => Some(value),
+        _ => None,
+    }
+}
+
+fn check_server_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url.trim()).map_err(|e| {
+        format!(
+            "Not a valid URL ({}). Example: https://cloud.example.com",
+            e
+        )
+    })?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| "The URL has no host name".to_string())?;
+    match parsed.scheme() {
+        "https" => Ok(()),
+        // Plain HTTP only for a server on this machine
+        "http" if matches!(host, "localhost" | "127.0.0.1" | "[::1]") => Ok(()),
+        "http" => Err("Use https:// - the token would be sent unencrypted".to_string()),
+        scheme => Err(format!("Unsupported scheme {}://, use https://", scheme)),
+    }
+}
+
+fn check_token(token: &str) -> Result<(), String> {
+    let valid_prefix = token.starts_with("xyn-") || token.starts_with("syn-");
+    if !valid_prefix || token.len() <= 4 {
+        return Err("Token must start with 'xyn-'".to_string());
+    }
+    if token.chars().any(char::is_whitespace) {
+        return Err("Token contains spaces; copy it again".to_string());
+    }
+    Ok(())
+}
+
+fn check_sync_path(path: &str) -> Result<(), String> {
+    let expanded = expand_sync_path(path.trim());
+    let path = Path::new(&expanded);
+    if expanded.is_empty() {
+        return Err("Choose a local sync folder".to_string());
+    }
+    if !path.is_absolute() {
+        return Err("Use an absolute path".to_string());
+    }
+    if path.parent().is_none() {
+        return Err("A whole drive cannot be synced; choose a folder".to_string());
+    }
+    if home_dir().is_some_and(|home| home == path) {
+        return Err(
+            "Choose a folder inside your home directory, not the home directory itself".to_string(),
+        );
+    }
+    if is_system_dir(path) {
+        return Err(
+            "System folders cannot be synced; choose a folder in your home directory".to_string(),
+        );
+    }
+    if path.exists() {
+        if !path.is_dir() {
+            return Err("Exists but is not a folder".to_string());
+        }
+        if !is_writable_dir(path) {
+            return Err("The folder is not writable".to_string());
+        }
+        if let Some(client) = foreign_sync_marker(path) {
+            return Err(format!("Already synced by {}", client));
+        }
+    } else {
+        // Must be creatable: the nearest existing ancestor is a writable folder
+        let ancestor = path
+            .ancestors()
+            .find(|a| a.exists())
+            .ok_or_else(|| "No existing parent folder".to_string())?;
+        if !is_writable_dir(ancestor) {
+            return Err(format!("Cannot create a folder in {}", ancestor.display()));
+        }
+    }
+    for ancestor in path.ancestors().skip(1) {
+        if crate::sync::has_database(ancestor) {
+            return Err(format!("Inside the sync folder {}", ancestor.display()));
+        }
+        if let Some(client) = foreign_sync_marker(ancestor) {
+            return Err(format!("Inside a folder synced by {}", client));
+        }
+    }
+    Ok(())
+}
+
+/// Operating system folders (and, for the first group, everything below them).
+fn is_system_dir(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        const TREES: [&str; 12] = [
+            "/bin", "/boot", "/dev", "/etc", "/lib", "/lib64", "/proc", "/sbin", "/sys", "/usr",
+            "/System", "/Library",
+        ];
+        // Their subfolders are fine (e.g. /run/user/1000/doc under Flatpak)
+        const ROOTS: [&str; 9] = [
+            "/home", "/Users", "/media", "/mnt", "/opt", "/run", "/srv", "/tmp", "/var",
+        ];
+        TREES.iter().any(|dir| path.starts_with(dir))
+            || ROOTS.iter().any(|dir| path == Path::new(dir))
+    }
+    #[cfg(windows)]
+    {
+        [
+            "SystemRoot",
+            "ProgramFiles",
+            "ProgramFiles(x86)",
+            "ProgramData",
+        ]
+        .iter()
+        .filter_map(std::env::var_os)
+        .any(|dir| path.starts_with(dir))
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Tries to create a file in `dir`; permission bits alone miss ownership,
+/// ACLs and read-only mounts.
+fn is_writable_dir(dir: &Path) -> bool {
+    if !dir.is_dir() {
+        return false;
+    }
+    let probe = dir.join(format!(".xynoxa-write-test-{}", std::process::id()));
+    match fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Name of another sync client whose metadata sits directly in `dir`.
+fn foreign_sync_marker(dir: &Path) -> Option<&'static str> {
+    if dir.join(".dropbox").exists() {
+        return Some("Dropbox");
+    }
+    let entries = fs::read_dir(dir).ok()?;
+    entries.filter_map(|e| e.ok()).find_map(|entry| {
+        let name = entry.file_name();
+        let name = name.to_str()?;
+        // Sync journals of the Nextcloud/ownCloud desktop clients
+        ((name.starts_with(".sync_") || name.starts_with("._sync_")) && name.ends_with(".db"))
+            .then_some("the Nextcloud/ownCloud client")
+    })
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+impl AppConfig {
+    /// Names the local state (sync database, ledgers, caches) of the
+    /// configured server and account, so ids of different servers or users
+    /// never mix. See [`account_key`].
+    pub fn account_key(&self) -> String {
+        account_key(self.server_url.as_deref(), self.account_id.as_deref())
+    }
+}
+
+/// `<server>-<user>`, short hashes of the server URL and user id, or just
+/// `<server>` while the user id is not known yet.
+pub fn account_key(server_url: Option<&str>, account_id: Option<&str>) -> String {
+    let server = server_key(server_url.unwrap_or_default());
+    match account_id {
+        Some(id) => format!("{}-{}", server, short_hash(id)),
+        None => server,
+    }
+}
+
+/// The server part of [`account_key`]; equal for spellings of the same
+/// address that differ only in case or a trailing slash.
+pub fn server_key(server_url: &str) -> String {
+    short_hash(&server_url.trim().trim_end_matches('/').to_lowercase())
+}
+
+fn short_hash(value: &str) -> String {
+    use sha2::{Digest, Sha256};
+    hex::encode(&Sha256::digest(value.as_bytes())[..6])
+}
+
+/// Expands a leading `~/` to the home directory.
+pub fn expand_sync_path(path: &str) -> String {
+    if path.starts_with("~/") {
+        let home = std::env::var("HOME")
+            .or_else(|_| std::env::var("USERPROFILE"))
+            .unwrap_or_else(|_| ".".to_string());
+        path.replacen("~", &home, 1)
+    } else {
+        path.to_string()
+    }
+}
+
+/// Data directory in portable mode: `xynoxa-data` next to the executable (for
+/// the AppImage, next to the image), holding config, auto-upload ledger and
+/// logs. Enabled by the `--portable` flag or a file named `portable` beside the
+/// executable. The sync database always lives in the sync root. Demo mode
+/// uses [`demo_dir`] instead.
+pub fn portable_dir() -> Option<PathBuf> {
+    if let Some(dir) = demo_dir() {
+        return Some(dir);
+    }
+    let exe = std::env::var_os("APPIMAGE")
+        .map(PathBuf::from)
+        .or_else(|| std::env::current_exe().ok())?;
+    let exe_dir = exe.parent()?;
+    let enabled = std::env::args().skip(1).any(|arg| arg == "--portable")
+        || exe_dir.join("portable").exists();
+    enabled.then(|| exe_dir.join("xynoxa-data"))
+}
+
+/// Server URL of demo mode: the sync engine talks to a simulated server
+/// ([`crate::mock_api::MockApiClient::demo`]) instead of the network.
+pub const DEMO_SERVER_URL: &str = "demo://xynoxa";
+
+/// `identifier` in `tauri.conf.json`, which names the app data directory.
+const APP_IDENTIFIER: &str = "com.xynoxa.desktop-client";
+
+/// Data directory in demo mode, enabled by the `--demo` flag in builds with
+/// the `demo` feature: `demo` in the user's app data directory. Config, logs
+/// and the sync folder live there, so trying the app leaves the real setup
+/// alone.
+pub fn demo_dir() -> Option<PathBuf> {
+    let enabled = cfg!(feature = "demo") && std::env::args().skip(1).any(|arg| arg == "--demo");
+    enabled.then(|| user_data_dir().join(APP_IDENTIFIER).join("demo"))
+}
+
+/// The per-user directory Tauri puts the app data directory in.
+fn user_data_dir() -> PathBuf {
+    let home = PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| ".".to_string()));
+    if cfg!(windows) {
+        std::env::var_os("APPDATA").map_or(home, PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home.join("Library/Application Support")
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .unwrap_or_else(|| home.join(".local/share"))
+    }
+}
+
 pub struct ConfigManager {
     config_path: PathBuf,
+    /// Effective config: the file contents with `overrides` applied
     pub config: Mutex<AppConfig>,
+    /// The config as stored on disk
+    stored: Mutex<AppConfig>,
+    overrides: ConfigOverrides,
 }
 
 impl ConfigManager {
-    pub fn new(_app_data_dir: &Path) -> Self {
+    /// `custom_dir` replaces the per-user config directory (portable mode, see
+    /// [`portable_dir`], or the Flatpak sandbox).
+    pub fn new(custom_dir: Option<&Path>, overrides: ConfigOverrides) -> Self {
         // Use XDG Config Home or fallback.
         // Note: app_data_dir from Tauri is usually ~/.local/share/APP.
         // We want ~/.config/xynoxa/server.conf (with legacy migration from xynoxa)
@@ -36,69 +877,457 @@ impl ConfigManager {
         // but assuming Linux environment:
 
         let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-        let config_dir = PathBuf::from(&home).join(".config").join("xynoxa");
+        let config_dir = custom_dir
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(&home).join(".config").join("xynoxa"));
         let legacy_dir = PathBuf::from(&home).join(".config").join("xynoxa");
         fs::create_dir_all(&config_dir).ok(); // Ensure dir exists
         let config_path = config_dir.join("server.conf"); // Requested filename
         let legacy_path = legacy_dir.join("server.conf");
 
+        if config_path.exists() {
+            restrict_existing(&config_path);
+        }
+
         let config = if config_path.exists() {
-            let content = fs::read_to_string(&config_path).unwrap_or_default();
-            serde_json::from_str(&content).unwrap_or_default()
-        } else if legacy_path.exists() {
-            let content = fs::read_to_string(&legacy_path).unwrap_or_default();
-            let migrated: AppConfig = serde_json::from_str(&content).unwrap_or_default();
-            let _ = fs::write(&config_path, serde_json::to_string_pretty(&migrated).unwrap_or_default());
+            load(&config_path)
+        } else if custom_dir.is_none() && legacy_path.exists() {
+            let migrated = load(&legacy_path);
+            let _ = write_private(
+                &config_path,
+                &serde_json::to_string_pretty(&migrated).unwrap_or_default(),
+            );
             migrated
         } else {
             AppConfig::default()
         };
 
+        let active = overrides.active();
+        if !active.is_empty() {
+            tracing::info!("Config overridden from environment/flags: {:?}", active);
+        }
+
         Self {
             config_path,
-            config: Mutex::new(config),
+            config: Mutex::new(overrides.apply(config.clone())),
+            stored: Mutex::new(config),
+            overrides,
         }
     }
 
-    pub fn save(&self) -> Result<(), String> {
+    pub fn save(&self) -> Result<()> {
         let config = self
-            .config
+            .stored
             .lock()
-            .map_err(|_| "Failed to lock config".to_string())?;
-        let content = serde_json::to_string_pretty(&*config).map_err(|e| e.to_string())?;
-        fs::write(&self.config_path, content).map_err(|e| e.to_string())?;
+            .map_err(|_| XynoxaError::Config("Failed to lock config".to_string()))?;
+        let content = serde_json::to_string_pretty(&*config)?;
+        write_private(&self.config_path, &content)?;
         Ok(())
     }
 
-    pub fn update(
-        &self,
-        url: Option<String>,
-        path: Option<String>,
-        token: Option<String>,
-        completed: Option<bool>,
-    ) -> Result<(), String> {
+    pub fn update(&self, patch: ConfigPatch) -> Result<()> {
         let mut config = self
-            .config
+            .stored
             .lock()
-            .map_err(|_| "Failed to lock config".to_string())?;
+            .map_err(|_| XynoxaError::Config("Failed to lock config".to_string()))?;
 
-        if let Some(u) = url {
-            config.server_url = Some(u);
-        }
-        if let Some(p) = path {
-            config.sync_path = Some(p);
+        let old_token = config.auth_token.clone();
+        patch.server_url.apply(&mut config.server_url);
+        let old_sync_path = config.sync_path.clone();
+        patch.sync_path.apply(&mut config.sync_path);
+        // A different folder; its id is learned when sync starts there
+        if config.sync_path != old_sync_path {
+            config.sync_root_id = None;
+            config.sync_server_url = None;
         }
-        if let Some(t) = token {
-            config.auth_token = Some(t);
+        patch.sync_root_id.apply(&mut config.sync_root_id);
+        patch.sync_server_url.apply(&mut config.sync_server_url);
+        patch.auth_token.apply(&mut config.auth_token);
+        // Possibly another user; the id is learned again after signing in
+        if config.auth_token != old_token {
+            config.account_id = None;
         }
-        if let Some(c) = completed {
+        patch.account_id.apply(&mut config.account_id);
+        if let Some(c) = patch.setup_completed {
             config.setup_completed = c;
         }
+        let now = chrono::Utc::now().timestamp();
+        if let Some(mut sources) = patch.auto_upload_sources {
+            stamp_cutoffs(&config.auto_upload_sources, &mut sources, now);
+            config.auto_upload_sources = sources;
+        }
+        if let Some(mut screenshot_share) = patch.screenshot_share {
+            let old = &config.screenshot_share;
+            let since = old.enabled.then_some(old.enabled_at).flatten();
+            screenshot_share.enabled_at = screenshot_share.enabled.then(|| since.unwrap_or(now));
+            config.screenshot_share = screenshot_share;
+        }
+        if let Some(backup_sources) = patch.backup_sources {
+            config.backup_sources = backup_sources;
+        }
+        if let Some(telemetry_enabled) = patch.telemetry_enabled {
+            config.telemetry_enabled = telemetry_enabled;
+        }
+        if let Some(auto_install_updates) = patch.auto_install_updates {
+            config.auto_install_updates = auto_install_updates;
+        }
+        if let Some(timeouts) = patch.timeouts {
+            config.timeouts = timeouts;
+        }
+        if let Some(connection) = patch.connection {
+            config.connection = connection;
+        }
+        patch.device_name.apply(&mut config.device_name);
+        patch.device_id.apply(&mut config.device_id);
+        if let Some(encrypt_database) = patch.encrypt_database {
+            config.encrypt_database = encrypt_database;
+        }
+        if let Some(window) = patch.window {
+            config.window = window;
+        }
+        patch.locale.apply(&mut config.locale);
+        if let Some(watcher) = patch.watcher {
+            config.watcher = watcher;
+        }
+        if let Some(virtual_drive) = patch.virtual_drive {
+            config.virtual_drive = virtual_drive;
+        }
+        if let Some(files_on_demand) = patch.files_on_demand {
+            config.files_on_demand = files_on_demand;
+        }
+        if let Some(reclaim_space) = patch.reclaim_space {
+            config.reclaim_space = reclaim_space;
+        }
+        if let Some(prevent_sleep) = patch.prevent_sleep {
+            config.prevent_sleep = prevent_sleep;
+        }
+        if let Some(lan_transfer) = patch.lan_transfer {
+            config.lan_transfer = lan_transfer;
+        }
+        if let Some(small_files_first) = patch.small_files_first {
+            config.small_files_first = small_files_first;
+        }
+        if let Some(ignore_patterns) = patch.ignore_patterns {
+            config.ignore_patterns = ignore_patterns;
+        }
+        if let Some(lock_notifications) = patch.lock_notifications {
+            config.lock_notifications = lock_notifications;
+        }
+        if let Some(hooks) = patch.hooks {
+            config.hooks = hooks;
+        }
+        patch.bandwidth_cap_mb.apply(&mut config.bandwidth_cap_mb);
 
         // Save automatically on update
-        let content = serde_json::to_string_pretty(&*config).map_err(|e| e.to_string())?;
-        fs::write(&self.config_path, content).map_err(|e| e.to_string())?;
+        let content = serde_json::to_string_pretty(&*config)?;
+        write_private(&self.config_path, &content)?;
 
+        let effective = self.overrides.apply(config.clone());
+        *self
+            .config
+            .lock()
+            .map_err(|_| XynoxaError::Config("Failed to lock config".to_string()))? = effective;
         Ok(())
     }
 }
+
+/// Reads and migrates a config file. A file that cannot be read or migrated is
+/// kept as `<name>.broken` instead of being overwritten with the defaults.
+fn load(path: &Path) -> AppConfig {
+    let result = fs::read_to_string(path)
+        .map_err(XynoxaError::from)
+        .and_then(|content| Ok(serde_json::from_str(&content)?))
+        .and_then(migrate);
+    match result {
+        Ok((config, from_version)) => {
+            if from_version < config.config_version {
+                tracing::info!(
+                    "Migrated config from version {} to {}",
+                    from_version,
+                    config.config_version
+                );
+                backup(path, &format!("v{}", from_version));
+                if let Ok(content) = serde_json::to_string_pretty(&config) {
+                    let _ = write_private(path, &content);
+                }
+            }
+            config
+        }
+        Err(e) => {
+            tracing::error!("Config {:?} unusable, starting from defaults: {}", path, e);
+            backup(path, "broken");
+            AppConfig::default()
+        }
+    }
+}
+
+/// Writes a file only the current user can read: it holds the token.
+fn write_private(path: &Path, content: &str) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        // `mode` only applies when the file is created
+        restrict_permissions(path)?;
+        file.write_all(content.as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        fs::write(path, content)?;
+        restrict_permissions(path)
+    }
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+}
+
+/// Replaces inherited ACL entries with full access for the current user only.
+#[cfg(windows)]
+fn restrict_permissions(path: &Path) -> std::io::Result<()> {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let user = std::env::var("USERNAME").map_err(std::io::Error::other)?;
+    let status = std::process::Command::new("icacls")
+        .arg(path)
+        .args(["/inheritance:r", "/grant:r"])
+        .arg(format!("{}:F", user))
+        .creation_flags(CREATE_NO_WINDOW)
+        .stdout(std::process::Stdio::null())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!("icacls failed: {}", status)))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn restrict_permissions(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Tightens the permissions of a config file written by an older version.
+fn restrict_existing(path: &Path) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = match fs::metadata(path) {
+            Ok(metadata) => metadata.permissions().mode() & 0o777,
+            Err(_) => return,
+        };
+        if mode & 0o077 == 0 {
+            return;
+        }
+        match restrict_permissions(path) {
+            Ok(()) => tracing::info!(
+                "Restricted permissions of {:?} from {:o} to 600",
+                path,
+                mode
+            ),
+            Err(e) => tracing::warn!("Failed to restrict permissions of {:?}: {}", path, e),
+        }
+    }
+    #[cfg(not(unix))]
+    if let Err(e) = restrict_permissions(path) {
+        tracing::warn!("Failed to restrict permissions of {:?}: {}", path, e);
+    }
+}
+
+fn backup(path: &Path, suffix: &str) {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{}", suffix));
+    if let Err(e) = fs::copy(path, &backup) {
+        tracing::warn!("Failed to back up config to {:?}: {}", backup, e);
+    }
+}
+
+/// Runs the [`MIGRATIONS`] a raw config needs and deserializes it. Returns the
+/// config and the version it was stored with. Configs from a newer client are
+/// read as far as this build understands them and left at their version.
+fn migrate(mut value: serde_json::Value) -> Result<(AppConfig, u32)> {
+    let map = value
+        .as_object_mut()
+        .ok_or_else(|| XynoxaError::Config("Config is not a JSON object".to_string()))?;
+    let from_version = map
+        .get("config_version")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0) as u32;
+
+    if from_version > CONFIG_VERSION {
+        tracing::warn!(
+            "Config version {} is newer than supported {}",
+            from_version,
+            CONFIG_VERSION
+        );
+    }
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from_version as usize) {
+        migration(map);
+        map.insert("config_version".to_string(), (version as u32 + 1).into());
+    }
+
+    Ok((serde_json::from_value(value)?, from_version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_distinguishes_missing_and_null() {
+        let patch: ConfigPatch =
+            serde_json::from_str(r#"{"serverUrl": null, "syncPath": "/data"}"#).unwrap();
+        assert_eq!(patch.server_url, FieldUpdate::Clear);
+        assert_eq!(patch.sync_path, FieldUpdate::Set("/data".to_string()));
+        assert_eq!(patch.auth_token, FieldUpdate::Keep);
+        assert_eq!(patch.setup_completed, None);
+    }
+
+    #[test]
+    fn test_flags_override_environment() {
+        let env = |name: &str| match name {
+            "XYNOXA_SERVER_URL" => Some("https://env.example".to_string()),
+            "XYNOXA_TOKEN" => Some("xyn-env".to_string()),
+            _ => None,
+        };
+        let args = [
+            "--minimized",
+            "--server-url=https://flag.example",
+            "--sync-path",
+            "/srv/sync",
+        ]
+        .map(String::from);
+        let overrides = ConfigOverrides::parse(env, args);
+
+        let config = overrides.apply(AppConfig::default());
+        assert_eq!(config.server_url.as_deref(), Some("https://flag.example"));
+        assert_eq!(config.sync_path.as_deref(), Some("/srv/sync"));
+        assert_eq!(config.auth_token.as_deref(), Some("xyn-env"));
+        assert!(config.setup_completed);
+    }
+
+    #[test]
+    fn test_patch_validation_reports_each_field() {
+        let patch = ConfigPatch {
+            server_url: FieldUpdate::Set("http://cloud.example.com".to_string()),
+            sync_path: FieldUpdate::Set("relative/dir".to_string()),
+            auth_token: FieldUpdate::Set("xyn-ok".to_string()),
+            ..Default::default()
+        };
+        let fields: Vec<&str> = patch.field_errors().iter().map(|e| e.field).collect();
+        assert_eq!(fields, vec!["serverUrl", "syncPath"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".xynoxa.db"), b"").unwrap();
+        let nested = dir.path().join("nested");
+        let patch = ConfigPatch {
+            server_url: FieldUpdate::Set("https://cloud.example.com".to_string()),
+            sync_path: FieldUpdate::Set(nested.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let err = patch.validate().unwrap_err();
+        assert_eq!(
+            serde_json::to_value(&err).unwrap()["fields"][0]["field"],
+            "syncPath"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_system_folders_are_rejected() {
+        assert!(check_sync_path("/etc/xynoxa").is_err());
+        assert!(check_sync_path("/usr/local/share/sync").is_err());
+        assert!(check_sync_path("/var").is_err());
+        assert!(!is_system_dir(Path::new("/var/lib/sync")));
+        assert!(!is_system_dir(Path::new("/home/alex/Xynoxa")));
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_sync_path(&dir.path().join("Xynoxa").to_string_lossy()).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_config_is_written_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.conf");
+        fs::write(&path, "{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, "{\"auth_token\": \"xyn-secret\"}").unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+        assert!(fs::read_to_string(&path).unwrap().contains("xyn-secret"));
+    }
+
+    #[test]
+    fn test_unversioned_config_is_migrated() {
+        let raw = serde_json::json!({
+            "server_url": "https://cloud.example",
+            "sync_path": "/data",
+            "auth_token": null,
+            "setup_completed": true
+        });
+        let (config, from_version) = migrate(raw).unwrap();
+        assert_eq!(from_version, 0);
+        assert_eq!(config.config_version, CONFIG_VERSION);
+        assert_eq!(config.server_url.as_deref(), Some("https://cloud.example"));
+        assert!(config.setup_completed);
+        assert_eq!(config.window, WindowBehavior::default());
+        assert_eq!(config.watcher, WatcherConfig::default());
+    }
+
+    #[test]
+    fn test_share_cutoff_is_kept_while_sharing() {
+        let source = |path: &str, share_link: bool, share_since: Option<i64>| AutoUploadSource {
+            path: path.to_string(),
+            remote_folder_id: None,
+            media_only: true,
+            share_link,
+            share_since,
+            added_at: share_since,
+            watch_mode: WatchMode::Auto,
+        };
+        let old = vec![
+            source("/shots", true, Some(100)),
+            source("/photos", false, None),
+        ];
+        let mut sources = vec![
+            source("/shots", true, None),
+            source("/photos", true, Some(5)),
+            source("/camera", false, Some(5)),
+        ];
+        stamp_cutoffs(&old, &mut sources, 200);
+        let cutoffs: Vec<_> = sources.iter().map(|s| s.share_since).collect();
+        assert_eq!(cutoffs, vec![Some(100), Some(200), None]);
+        // Only /camera is new; /photos was added before this was recorded
+        let added: Vec<_> = sources.iter().map(|s| s.added_at).collect();
+        assert_eq!(added, vec![Some(100), None, Some(200)]);
+    }
+
+    #[test]
+    fn test_account_key() {
+        let server = account_key(Some("https://Cloud.example.com/"), None);
+        assert_eq!(
+            server,
+            account_key(Some(" https://cloud.example.com"), None)
+        );
+        assert_eq!(server.len(), 12);
+        let user = account_key(Some("https://cloud.example.com"), Some("42"));
+        assert!(user.starts_with(&format!("{}-", server)));
+        assert_ne!(
+            user,
+            account_key(Some("https://cloud.example.com"), Some("43"))
+        );
+        assert_ne!(server, account_key(Some("https://other.example.com"), None));
+    }
+}
@@ -0,0 +1,192 @@
+//! Unresolved sync conflicts, for the conflict resolver window.
+//!
+//! When a file changed on both sides, the sync worker keeps the local version
+//! as `<name>.conflict_backup` next to the downloaded server version. Those
+//! backups are the conflict list; resolving one removes its backup.
+
+use crate::error::{Result, XynoxaError};
+use crate::sync::compute_hash;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use walkdir::WalkDir;
+
+pub const BACKUP_EXTENSION: &str = "conflict_backup";
+
+/// One side of a conflict, shown side by side in the resolver.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConflictVersion {
+    /// Relative to the sync root, `/`-separated
+    pub path: String,
+    pub size: u64,
+    /// Seconds since the Unix epoch
+    pub modified: Option<u64>,
+    pub hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conflict {
+    /// The kept local version
+    pub local: ConflictVersion,
+    /// The server version now at the original path, if it still exists
+    pub server: Option<ConflictVersion>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Resolution {
+    /// Drop the local version
+    KeepServer,
+    /// Put the local version back in place; it is uploaded on the next sync
+    KeepLocal,
+    /// Keep the local version as a separate "conflicted copy"
+    KeepBoth,
+}
+
+/// Conflict backups below `root`, sorted by path.
+pub fn find_conflicts(root: &Path) -> Vec<Conflict> {
+    let mut conflicts: Vec<Conflict> = WalkDir::new(root)
+        .follow_links(false)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file() && is_backup(entry.path()))
+        .filter_map(|entry| {
+            let local = describe(root, entry.path())?;
+            let server = original_of(entry.path()).and_then(|path| describe(root, &path));
+            Some(Conflict { local, server })
+        })
+        .collect();
+    conflicts.sort_by(|a, b| a.local.path.cmp(&b.local.path));
+    conflicts
+}
+
+/// Applies `resolution` to the conflict whose backup is at `backup`.
+pub fn resolve(root: &Path, backup: &Path, resolution: Resolution) -> Result<()> {
+    if !backup.starts_with(root) || !is_backup(backup) || !backup.is_file() {
+        return Err(XynoxaError::Other(format!(
+            "{} is not a conflict backup",
+            backup.display()
+        )));
+    }
+    match (resolution, original_of(backup)) {
+        (Resolution::KeepServer, _) => fs::remove_file(backup)?,
+        (Resolution::KeepLocal, Some(original)) => fs::rename(backup, original)?,
+        // Without a server version there is nothing to keep apart from
+        (Resolution::KeepLocal, None) | (Resolution::KeepBoth, None) => {
+            fs::rename(backup, backup.with_extension(""))?
+        }
+        (Resolution::KeepBoth, Some(original)) => {
+            fs::rename(backup, conflicted_copy_path(&original))?
+        }
+    }
+    Ok(())
+}
+
+fn is_backup(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()) == Some(BACKUP_EXTENSION)
+}
+
+/// The file a backup was taken of. The backup replaced its extension, so this
+/// is the sibling with the same stem.
+fn original_of(backup: &Path) -> Option<PathBuf> {
+    let stem = backup.file_stem()?;
+    let bare = backup.with_file_name(stem);
+    if bare.is_file() {
+        return Some(bare);
+    }
+    let mut siblings: Vec<PathBuf> = fs::read_dir(backup.parent()?)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && !is_backup(path) && path.file_stem() == Some(stem))
+        .collect();
+    siblings.sort();
+    siblings.into_iter().next()
+}
+
+fn conflicted_copy_path(original: &Path) -> PathBuf {
+    let stem = original
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = original
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| {
+            let suffix = if n == 1 {
+                String::new()
+            } else {
+                format!(" {}", n)
+            };
+            original.with_file_name(format!("{} (conflicted copy{}){}", stem, suffix, extension))
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+fn describe(root: &Path, path: &Path) -> Option<ConflictVersion> {
+    let metadata = path.metadata().ok()?;
+    let relative = path.strip_prefix(root).ok()?;
+    Some(ConflictVersion {
+        path: relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        size: metadata.len(),
+        modified: metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs()),
+        hash: compute_hash(path).ok(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backups_pair_with_their_original() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("docs")).unwrap();
+        fs::write(root.path().join("docs/report.txt"), b"server").unwrap();
+        fs::write(root.path().join("docs/report.conflict_backup"), b"local!").unwrap();
+
+        let conflicts = find_conflicts(root.path());
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].local.path, "docs/report.conflict_backup");
+        assert_eq!(conflicts[0].local.size, 6);
+        let server = conflicts[0].server.as_ref().unwrap();
+        assert_eq!(server.path, "docs/report.txt");
+        assert_eq!(server.size, 6);
+    }
+
+    #[test]
+    fn test_resolutions() {
+        let root = tempfile::tempdir().unwrap();
+        let original = root.path().join("a.txt");
+        let backup = root.path().join("a.conflict_backup");
+
+        fs::write(&original, b"server").unwrap();
+        fs::write(&backup, b"local").unwrap();
+        resolve(root.path(), &backup, Resolution::KeepBoth).unwrap();
+        assert_eq!(
+            fs::read(root.path().join("a (conflicted copy).txt")).unwrap(),
+            b"local"
+        );
+
+        fs::write(&backup, b"local").unwrap();
+        resolve(root.path(), &backup, Resolution::KeepLocal).unwrap();
+        assert_eq!(fs::read(&original).unwrap(), b"local");
+        assert!(!backup.exists());
+
+        assert!(resolve(root.path(), &original, Resolution::KeepServer).is_err());
+    }
+}
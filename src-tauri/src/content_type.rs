@@ -0,0 +1,170 @@
+//! MIME type of a file about to be uploaded. The extension decides when it is
+//! known; extension-less files and unknown extensions fall back to the first
+//! bytes of the content, so server-side previews and type filters still work.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+const OCTET_STREAM: &str = "application/octet-stream";
+/// Bytes read for sniffing; enough for every signature below.
+const SNIFF_LEN: usize = 512;
+
+/// MIME type for `path`, from its extension or else its content.
+pub fn detect(path: &Path) -> String {
+    if let Some(mime) = mime_guess::from_path(path).first() {
+        return mime.to_string();
+    }
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    let read = File::open(path).and_then(|file| file.take(SNIFF_LEN as u64).read_to_end(&mut head));
+    if let Err(e) = read {
+        tracing::debug!("Cannot sniff MIME type of {:?}: {}", path, e);
+        return OCTET_STREAM.to_string();
+    }
+    sniff(&head).unwrap_or(OCTET_STREAM).to_string()
+}
+
+/// MIME type recognized from the start of a file's content.
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"II*\0", "image/tiff"),
+        (b"MM\0*", "image/tiff"),
+        (b"%PDF-", "application/pdf"),
+        (b"%!PS", "application/postscript"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"BZh", "application/x-bzip2"),
+        (b"\xfd7zXZ\0", "application/x-xz"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"Rar!\x1a\x07", "application/vnd.rar"),
+        (b"SQLite format 3\0", "application/vnd.sqlite3"),
+        (b"\x7fELF", "application/x-executable"),
+        (b"MZ", "application/vnd.microsoft.portable-executable"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"ID3", "audio/mpeg"),
+        (b"\x1a\x45\xdf\xa3", "video/x-matroska"),
+    ];
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(mime);
+    }
+
+    if head.len() >= 12 && head.starts_with(b"RIFF") {
+        return match &head[8..12] {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/wav"),
+            b"AVI " => Some("video/x-msvideo"),
+            _ => None,
+        };
+    }
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return Some(match &head[8..12] {
+            b"heic" | b"heix" | b"mif1" => "image/heic",
+            b"avif" => "image/avif",
+            b"qt  " => "video/quicktime",
+            b"M4A " => "audio/mp4",
+            _ => "video/mp4",
+        });
+    }
+    if head.starts_with(b"#!") {
+        return Some(script(head));
+    }
+    sniff_text(head)
+}
+
+/// MIME type of a script by the interpreter in its shebang line.
+fn script(head: &[u8]) -> &'static str {
+    let line = head.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = String::from_utf8_lossy(line);
+    // "#!/usr/bin/env python3" names the interpreter last, "#!/bin/sh -e" first
+    let interpreter = line[2..]
+        .split_whitespace()
+        .map(|word| word.rsplit('/').next().unwrap_or(word))
+        .find(|word| *word != "env" && !word.starts_with('-'))
+        .unwrap_or_default();
+    match interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "python" => "text/x-python",
+        "perl" => "text/x-perl",
+        "ruby" => "text/x-ruby",
+        "node" | "deno" | "bun" => "text/javascript",
+        "php" => "application/x-httpd-php",
+        _ => "text/x-shellscript",
+    }
+}
+
+/// Markup or plain text, if `head` looks like UTF-8 text.
+fn sniff_text(head: &[u8]) -> Option<&'static str> {
+    if head.is_empty() || head.contains(&0) {
+        return None;
+    }
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // Cut off inside a multi-byte character
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    let start = text.trim_start_matches('\u{feff}').trim_start();
+    let lower: String = start
+        .chars()
+        .take(64)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    Some(
+        if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+            "text/html"
+        } else if lower.starts_with("<svg") {
+            "image/svg+xml"
+        } else if lower.starts_with("<?xml") {
+            if text.contains("<svg") {
+                "image/svg+xml"
+            } else {
+                "application/xml"
+            }
+        } else {
+            "text/plain"
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_without_extension() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some("image/png"));
+        assert_eq!(sniff(b"\0\0\0\x18ftypheic\0\0\0\0"), Some("image/heic"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(
+            sniff(b"#!/usr/bin/env python3\nprint()"),
+            Some("text/x-python")
+        );
+        assert_eq!(sniff(b"#!/bin/bash -e\necho"), Some("text/x-shellscript"));
+        assert_eq!(
+            sniff(b"<?xml version=\"1.0\"?>\n<svg>"),
+            Some("image/svg+xml")
+        );
+        assert_eq!(sniff("Grüße\n".as_bytes()), Some("text/plain"));
+        // A multi-byte character cut off at the end of the sniffed bytes
+        assert_eq!(sniff(&"ü".as_bytes()[..1]), Some("text/plain"));
+        assert_eq!(sniff(b"\x00\x01\x02binary"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn test_extension_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = dir.path().join("notes.txt");
+        std::fs::write(&notes, b"%PDF-1.7").unwrap();
+        assert_eq!(detect(&notes), "text/plain");
+        let scan = dir.path().join("scan");
+        std::fs::write(&scan, b"%PDF-1.7").unwrap();
+        assert_eq!(detect(&scan), "application/pdf");
+        assert_eq!(detect(&dir.path().join("missing")), OCTET_STREAM);
+    }
+}
@@ -0,0 +1,339 @@
+//! Local control API for scripts, third-party tools and the command line:
+//! JSON-RPC 2.0 over a unix socket or, on Windows, a named pipe. Requests and
+//! responses are single lines of JSON. Only the user running the app can
+//! connect.
+//!
+//! Methods mirror the app's commands: `status`, `forceSync`, `pause`,
+//! `resume`, `listFiles`, `search` (`{"query": ...}`), `transfers` and
+//! `report`.
+
+use crate::error::{Result, XynoxaError};
+use crate::sync::SyncHandle;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Failures of the call itself; `data` holds the serialized [`XynoxaError`].
+const CALL_FAILED: i64 = -32000;
+
+/// Where the sync the API controls lives: the app's state or the headless
+/// service's handle.
+pub trait Engine: Send + Sync + 'static {
+    /// Runs `f` on the running sync; fails when there is none.
+    fn with_sync(&self, f: &mut dyn FnMut(&SyncHandle) -> Result<Value>) -> Result<Value>;
+}
+
+impl Engine for Mutex<Option<SyncHandle>> {
+    fn with_sync(&self, f: &mut dyn FnMut(&SyncHandle) -> Result<Value>) -> Result<Value> {
+        let engine_guard = self.lock().map_err(|_| "Failed to lock state")?;
+        f(engine_guard.as_ref().ok_or("Sync is not running")?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    // Absent for notifications, which get no response
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    query: String,
+}
+
+/// Socket of the control API: in `portable_dir`, else the per-user runtime
+/// directory, else `~/.local/share/xynoxa`. Clients find it the same way.
+#[cfg(unix)]
+pub fn endpoint(portable_dir: Option<&Path>) -> PathBuf {
+    let dir = match portable_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+                PathBuf::from(&home).join(".local/share/xynoxa")
+            }
+        },
+    };
+    dir.join("xynoxa-control.sock")
+}
+
+/// Named pipe of the control API, one per user.
+#[cfg(windows)]
+pub fn endpoint(_portable_dir: Option<&Path>) -> PathBuf {
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    PathBuf::from(format!(r"\\.\pipe\xynoxa-control-{}", user))
+}
+
+/// Serves the control API at `endpoint` until the process exits. Fails if
+/// another instance already serves it.
+pub fn start(endpoint: PathBuf, engine: Arc<dyn Engine>) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let listener = runtime.block_on(async { bind(&endpoint) })?;
+    tracing::info!("Control API listening on {:?}", endpoint);
+    std::thread::Builder::new()
+        .name("control-server".to_string())
+        .spawn(move || runtime.block_on(accept(listener, endpoint, engine)))?;
+    Ok(())
+}
+
+#[cfg(unix)]
+type Listener = tokio::net::UnixListener;
+
+#[cfg(unix)]
+fn bind(endpoint: &Path) -> Result<Listener> {
+    use std::os::unix::fs::PermissionsExt;
+    if endpoint.exists() {
+        if std::os::unix::net::UnixStream::connect(endpoint).is_ok() {
+            return Err(XynoxaError::Other(format!(
+                "Another instance serves {:?}",
+                endpoint
+            )));
+        }
+        // Left behind by a crash
+        std::fs::remove_file(endpoint)?;
+    }
+    if let Some(dir) = endpoint.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let listener = tokio::net::UnixListener::bind(endpoint)?;
+    std::fs::set_permissions(endpoint, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+#[cfg(unix)]
+async fn accept(listener: Listener, _endpoint: PathBuf, engine: Arc<dyn Engine>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(serve(stream, Arc::clone(&engine)));
+            }
+            Err(e) => tracing::warn!("Control API accept failed: {}", e),
+        }
+    }
+}
+
+#[cfg(windows)]
+type Listener = tokio::net::windows::named_pipe::NamedPipeServer;
+
+#[cfg(windows)]
+fn bind(endpoint: &Path) -> Result<Listener> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+    // Fails if the pipe exists, i.e. another instance serves it
+    Ok(ServerOptions::new()
+        .first_pipe_instance(true)
+        .reject_remote_clients(true)
+        .create(endpoint)?)
+}
+
+#[cfg(windows)]
+async fn accept(mut listener: Listener, endpoint: PathBuf, engine: Arc<dyn Engine>) {
+    use tokio::net::windows::named_pipe::ServerOptions;
+    loop {
+        if let Err(e) = listener.connect().await {
+            tracing::warn!("Control API accept failed: {}", e);
+            continue;
+        }
+        // The next client connects to a new instance of the pipe
+        let next = match ServerOptions::new()
+            .reject_remote_clients(true)
+            .create(&endpoint)
+        {
+            Ok(next) => next,
+            Err(e) => {
+                tracing::error!("Control API stopped: {}", e);
+                return;
+            }
+        };
+        tokio::spawn(serve(
+            std::mem::replace(&mut listener, next),
+            Arc::clone(&engine),
+        ));
+    }
+}
+
+/// Answers the requests of one connection until it closes.
+async fn serve<S: AsyncRead + AsyncWrite>(stream: S, engine: Arc<dyn Engine>) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let engine = Arc::clone(&engine);
+        // Calls open the database, which blocks
+        let response = tokio::task::spawn_blocking(move || handle_line(&line, &*engine)).await;
+        let Ok(Some(response)) = response else {
+            continue;
+        };
+        if writer
+            .write_all(format!("{}\n", response).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// The response to one request line, `None` for notifications and blank lines.
+fn handle_line(line: &str, engine: &dyn Engine) -> Option<Value> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => {
+            return Some(error_response(
+                Value::Null,
+                PARSE_ERROR,
+                &e.to_string(),
+                None,
+            ))
+        }
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let request = match serde_json::from_value::<Request>(request) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        Ok(_) => {
+            return Some(error_response(
+                id,
+                INVALID_REQUEST,
+                "jsonrpc must be \"2.0\"",
+                None,
+            ))
+        }
+        Err(e) => return Some(error_response(id, INVALID_REQUEST, &e.to_string(), None)),
+    };
+    let response = match call(engine, &request.method, request.params) {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(CallError::Rpc(code, message)) => error_response(id, code, &message, None),
+        Err(CallError::Failed(e)) => {
+            let data = serde_json::to_value(&e).ok();
+            error_response(id, CALL_FAILED, &e.localized_message(), data)
+        }
+    };
+    request.id.map(|_| response)
+}
+
+enum CallError {
+    Rpc(i64, String),
+    Failed(XynoxaError),
+}
+
+impl From<XynoxaError> for CallError {
+    fn from(e: XynoxaError) -> Self {
+        CallError::Failed(e)
+    }
+}
+
+fn call(engine: &dyn Engine, method: &str, params: Value) -> std::result::Result<Value, CallError> {
+    let query = match method {
+        "status" | "forceSync" | "pause" | "resume" | "listFiles" | "transfers" | "report" => None,
+        "search" => match serde_json::from_value::<SearchParams>(params) {
+            Ok(params) => Some(params.query),
+            Err(e) => return Err(CallError::Rpc(INVALID_PARAMS, e.to_string())),
+        },
+        _ => {
+            let message = format!("Unknown method: {}", method);
+            return Err(CallError::Rpc(METHOD_NOT_FOUND, message));
+        }
+    };
+    Ok(engine.with_sync(&mut |sync| {
+        Ok(match method {
+            "status" => json!(sync.status()),
+            "forceSync" => {
+                sync.force_sync();
+                Value::Null
+            }
+            "pause" => {
+                sync.pause();
+                Value::Null
+            }
+            "resume" => {
+                sync.resume();
+                Value::Null
+            }
+            "listFiles" => json!(sync.list_files()?),
+            "transfers" => json!(sync.transfer_queue()),
+            "report" => json!(sync.report()?),
+            _ => json!(sync.search_files(query.as_deref().unwrap_or_default())?),
+        })
+    })?)
+}
+
+fn error_response(id: Value, code: i64, message: &str, data: Option<Value>) -> Value {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_line() {
+        let engine: Mutex<Option<SyncHandle>> = Mutex::new(None);
+        let call = |line: &str| handle_line(line, &engine).unwrap();
+
+        let response = call(r#"{"jsonrpc":"2.0","id":1,"method":"status"}"#);
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["error"]["code"], CALL_FAILED);
+        assert_eq!(response["error"]["data"]["code"], "internal");
+
+        let response = call(r#"{"jsonrpc":"2.0","id":"a","method":"shutdown"}"#);
+        assert_eq!(response["id"], "a");
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+        let response = call(r#"{"jsonrpc":"2.0","id":2,"method":"search","params":{}}"#);
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+        let response = call(r#"{"jsonrpc":"1.0","id":3,"method":"status"}"#);
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+
+        let response = call("{not json");
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+        // Notifications and blank lines are not answered
+        assert!(handle_line(r#"{"jsonrpc":"2.0","method":"forceSync"}"#, &engine).is_none());
+        assert!(handle_line("  ", &engine).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_round_trip() {
+        use std::io::{BufRead, Write};
+        let dir = tempfile::tempdir().unwrap();
+        let endpoint = dir.path().join("control.sock");
+        let engine: Arc<Mutex<Option<SyncHandle>>> = Arc::default();
+        start(endpoint.clone(), Arc::clone(&engine) as Arc<dyn Engine>).unwrap();
+        // A second instance must not take over the socket
+        assert!(start(endpoint.clone(), engine).is_err());
+
+        let mut stream = std::os::unix::net::UnixStream::connect(&endpoint).unwrap();
+        stream
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"nope\"}\n")
+            .unwrap();
+        let mut line = String::new();
+        std::io::BufReader::new(stream)
+            .read_line(&mut line)
+            .unwrap();
+        let response: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
@@ -1,29 +1,341 @@
-use rusqlite::{params, Connection, Result};
+use rusqlite::{ffi, params, Connection, OptionalExtension, Result};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::Read;
 use std::path::Path;
 use std::sync::Mutex;
 
+use crate::api::ResumePoint;
+use crate::conflicts::BACKUP_EXTENSION;
+use crate::transfers::{ChunkProgress, Direction, Transfer, TransferState};
+
 pub struct Database {
     conn: Mutex<Connection>,
 }
 
+/// How a database file is stored on disk. Deliberately not `Debug` so the key
+/// cannot end up in logs.
+#[derive(Clone, Default)]
+pub struct DbKey {
+    /// SQLCipher key. Still needed after encryption was turned off, to decrypt
+    /// existing files.
+    pub key: Option<String>,
+    /// Keep the file encrypted with `key`
+    pub encrypt: bool,
+}
+
+/// Result of [`Database::maintain`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceReport {
+    pub removed_files: usize,
+    pub removed_validators: usize,
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileRecord {
     pub id: Option<String>, // UUID from server
     pub path: String,
+    /// Local content hash as of the last successful sync (the merge base)
     pub hash: String,
     pub modified_at: i64,
+    /// Cursor of the last server event seen for this entry
     pub server_version: i64,
+    /// Content hash the server last reported; differs from `hash` while a
+    /// remote change has not been applied locally yet
+    pub remote_hash: Option<String>,
     pub group_folder_id: Option<String>,
     pub is_group_root: bool,
 }
 
+/// What the server last reported about a file or folder. Mirrored from
+/// `sync.pull` so structural questions (parent ids, duplicates, previous
+/// locations) need no server round trip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteEntry {
+    pub id: String,
+    pub is_folder: bool,
+    pub name: String,
+    pub parent_id: Option<String>,
+    /// Relative to the sync root
+    pub path: String,
+    /// Content hash, `None` for folders
+    pub hash: Option<String>,
+}
+
+/// Entries kept by [`Database::log_activity`].
+pub const ACTIVITY_LIMIT: usize = 1000;
+
+/// Something the sync did, see [`Database::log_activity`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Activity {
+    /// Unix time
+    pub at: i64,
+    /// `upload`, `download`, `conflict`, `warning` or `error`
+    pub kind: String,
+    pub path: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// A local file whose content no longer matches the last synced version
+/// although its modification time is unchanged: bit rot, an interrupted
+/// write or a tool that restored the old timestamp. Its changes are not
+/// uploaded until the user picks a repair.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncIssue {
+    pub path: String,
+    /// Hash as of the last sync
+    pub expected_hash: String,
+    /// Hash of the file on disk
+    pub actual_hash: String,
+    /// Unix time
+    pub detected_at: i64,
+}
+
+/// A failure that keeps coming back, such as an unreadable file or an
+/// upload the server rejects: one entry per problem however often it
+/// happens, removed once the operation succeeds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KnownIssue {
+    /// What failed, e.g. `transfer:docs/a.txt`
+    pub key: String,
+    /// Relative to the sync root, if a file or folder is affected
+    pub path: Option<String>,
+    /// The latest error
+    pub message: String,
+    /// Failures since `first_seen`
+    pub count: u32,
+    /// Unix time
+    pub first_seen: i64,
+    /// Unix time
+    pub last_seen: i64,
+}
+
+/// How long a connection waits for the sync worker's transaction before
+/// failing with SQLITE_BUSY.
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Known issues not seen again for this long are dropped by maintenance.
+const KNOWN_ISSUE_MAX_AGE_SECS: i64 = 30 * 24 * 60 * 60;
+
+const KNOWN_ISSUE_COLUMNS: &str = "key, path, message, count, first_seen, last_seen";
+
+impl KnownIssue {
+    fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
+        Ok(KnownIssue {
+            key: row.get(0)?,
+            path: row.get(1)?,
+            message: row.get(2)?,
+            count: row.get(3)?,
+            first_seen: row.get(4)?,
+            last_seen: row.get(5)?,
+        })
+    }
+}
+
+/// Bytes transferred with the server on one local calendar day. Content
+/// fetched from devices on the LAN is not counted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthDay {
+    /// `YYYY-MM-DD`
+    pub day: String,
+    pub uploaded: u64,
+    pub downloaded: u64,
+}
+
+/// Days of bandwidth usage older than this are dropped by maintenance.
+const BANDWIDTH_MAX_AGE_DAYS: i64 = 400;
+
+/// A remote folder holding one run of a backup source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSnapshot {
+    pub folder_id: String,
+    /// Local path of the backed up folder
+    pub source: String,
+    pub name: String,
+    /// Unix time the run started
+    pub created_at: i64,
+    /// Every file was uploaded
+    pub complete: bool,
+}
+
+/// How a file is organized on the server: its tags and whether it is a
+/// favorite, as set in the web UI. Keyed by file id, so it survives moves.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FileLabels {
+    /// Sorted by name
+    pub tags: Vec<String>,
+    pub favorite: bool,
+}
+
+/// Unsynced entries at or below a folder, see [`Database::folder_health`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FolderHealth {
+    pub path: String,
+    /// Queued or running transfers
+    pub pending: u64,
+    /// Transfers that gave up until the next retry
+    pub failed: u64,
+    /// Tracked conflict backups
+    pub conflicts: u64,
+    /// Direct subfolders contributing to the counts, by path. Always empty
+    /// on the subfolders themselves.
+    pub branches: Vec<FolderHealth>,
+}
+
+impl FolderHealth {
+    fn add(&mut self, pending: u64, failed: u64, conflicts: u64) {
+        self.pending += pending;
+        self.failed += failed;
+        self.conflicts += conflicts;
+    }
+}
+
+const REMOTE_COLUMNS: &str = "id, is_folder, name, parent_id, path, hash";
+
+impl RemoteEntry {
+    fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
+        Ok(RemoteEntry {
+            id: row.get(0)?,
+            is_folder: row.get::<_, i64>(1)? == 1,
+            name: row.get(2)?,
+            parent_id: row.get(3)?,
+            path: row.get(4)?,
+            hash: row.get(5)?,
+        })
+    }
+}
+
+/// Operation journaled before it is sent to the server, see [`Database::begin_ops`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingOpKind {
+    /// Upload of `path`
+    Upload,
+    /// Remote soft delete of file `target`, formerly at `path`
+    DeleteFile,
+    /// Remote delete of folder `target`, formerly at `path`
+    DeleteFolder,
+    /// Local rename of folder `path` to `target` after a remote move
+    MoveFolder,
+}
+
+impl PendingOpKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            PendingOpKind::Upload => "upload",
+            PendingOpKind::DeleteFile => "delete_file",
+            PendingOpKind::DeleteFolder => "delete_folder",
+            PendingOpKind::MoveFolder => "move_folder",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "upload" => Some(PendingOpKind::Upload),
+            "delete_file" => Some(PendingOpKind::DeleteFile),
+            "delete_folder" => Some(PendingOpKind::DeleteFolder),
+            "move_folder" => Some(PendingOpKind::MoveFolder),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PendingOp {
+    pub id: i64,
+    pub kind: PendingOpKind,
+    pub path: String,
+    pub target: Option<String>,
+}
+
+const FILE_COLUMNS: &str =
+    "path, id, hash, modified_at, server_version, group_folder_id, is_group_root, remote_hash";
+
+impl FileRecord {
+    fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
+        Ok(FileRecord {
+            path: row.get(0)?,
+            id: row.get(1)?,
+            hash: row.get(2)?,
+            modified_at: row.get(3)?,
+            server_version: row.get(4)?,
+            group_folder_id: row.get(5)?,
+            is_group_root: row.get::<_, i64>(6)? == 1,
+            remote_hash: row.get(7)?,
+        })
+    }
+}
+
 impl Database {
     pub fn new(db_path: &Path) -> Result<Self> {
-        log::info!("Opening Database at: {:?}", db_path);
+        Self::open(db_path, &DbKey::default())
+    }
+
+    /// Opens `db_path`, encrypted with SQLCipher when `key.encrypt` is set.
+    /// Fails if an existing file is in the other form; [`Database::convert`]
+    /// switches it while nothing else has it open.
+    pub fn open(db_path: &Path, key: &DbKey) -> Result<Self> {
+        tracing::info!("Opening Database at: {:?}", db_path);
+        let wanted = wanted_key(key)?;
+        match (is_plaintext(db_path), wanted) {
+            (Some(true), Some(_)) => {
+                return Err(failure(
+                    ffi::SQLITE_AUTH,
+                    "Database is not encrypted yet; convert it before opening",
+                ));
+            }
+            (Some(false), None) => {
+                return Err(failure(
+                    ffi::SQLITE_AUTH,
+                    "Database is encrypted; convert it before opening",
+                ));
+            }
+            _ => {}
+        }
+
         let conn = Connection::open(db_path)?;
+        if let Some(k) = wanted {
+            apply_key(&conn, k)?;
+        }
+        Self::init(conn)
+    }
 
+    /// Encrypts or decrypts the existing file at `db_path` to match
+    /// `key.encrypt`. Nothing may have it open meanwhile. Returns whether the
+    /// file was rewritten.
+    pub fn convert(db_path: &Path, key: &DbKey) -> Result<bool> {
+        match (is_plaintext(db_path), wanted_key(key)?, key.key.as_deref()) {
+            (Some(true), Some(k), _) => {
+                tracing::info!("Encrypting existing database {:?}", db_path);
+                convert(db_path, None, Some(k))?;
+                Ok(true)
+            }
+            (Some(false), None, Some(k)) => {
+                tracing::info!("Decrypting existing database {:?}", db_path);
+                convert(db_path, Some(k), None)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Database that lives only as long as this handle. Used by tests.
+    pub fn new_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
         // Files table with ID support
         conn.execute(
             "CREATE TABLE IF NOT EXISTS files (
@@ -33,7 +345,10 @@ impl Database {
                 modified_at INTEGER NOT NULL,
                 server_version INTEGER NOT NULL,
                 group_folder_id TEXT,
-                is_group_root INTEGER NOT NULL DEFAULT 0
+                is_group_root INTEGER NOT NULL DEFAULT 0,
+                remote_hash TEXT,
+                synced_at INTEGER,
+                verified_at INTEGER
             )",
             [],
         )?;
@@ -42,6 +357,9 @@ impl Database {
             let mut rows = stmt.query([])?;
             let mut has_group_folder_id = false;
             let mut has_is_group_root = false;
+            let mut has_remote_hash = false;
+            let mut has_synced_at = false;
+            let mut has_verified_at = false;
             while let Some(row) = rows.next()? {
                 let col_name: String = row.get(1)?;
                 if col_name == "group_folder_id" {
@@ -50,6 +368,15 @@ impl Database {
                 if col_name == "is_group_root" {
                     has_is_group_root = true;
                 }
+                if col_name == "remote_hash" {
+                    has_remote_hash = true;
+                }
+                if col_name == "synced_at" {
+                    has_synced_at = true;
+                }
+                if col_name == "verified_at" {
+                    has_verified_at = true;
+                }
             }
             if !has_group_folder_id {
                 let _ = conn.execute("ALTER TABLE files ADD COLUMN group_folder_id TEXT", []);
@@ -60,6 +387,17 @@ impl Database {
                     [],
                 );
             }
+            if !has_remote_hash {
+                let _ = conn.execute("ALTER TABLE files ADD COLUMN remote_hash TEXT", []);
+            }
+            // Unix time the entry was last written by the sync
+            if !has_synced_at {
+                let _ = conn.execute("ALTER TABLE files ADD COLUMN synced_at INTEGER", []);
+            }
+            // Unix time the integrity check last re-hashed the file
+            if !has_verified_at {
+                let _ = conn.execute("ALTER TABLE files ADD COLUMN verified_at INTEGER", []);
+            }
         }
 
         // Global state (cursor)
@@ -71,13 +409,201 @@ impl Database {
             [],
         )?;
 
+        // One-way auto-upload ledger (source file -> uploaded content hash)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS auto_uploads (
+                source_path TEXT PRIMARY KEY,
+                hash TEXT NOT NULL,
+                remote_id TEXT,
+                uploaded_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Snapshots made by the backup worker, see BackupSnapshot
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS backup_snapshots (
+                folder_id TEXT PRIMARY KEY,
+                source TEXT NOT NULL,
+                name TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                complete INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // HTTP validators of downloaded content (file id -> ETag + content hash)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS download_validators (
+                file_id TEXT PRIMARY KEY,
+                etag TEXT NOT NULL,
+                hash TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Journal of server-side operations that may not have completed
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS pending_ops (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                path TEXT NOT NULL,
+                target TEXT,
+                created_at INTEGER NOT NULL,
+                UNIQUE(kind, path)
+            )",
+            [],
+        )?;
+
+        // Persistent transfer queue, see crate::transfers
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS transfers (
+                path TEXT PRIMARY KEY,
+                direction TEXT NOT NULL,
+                size INTEGER,
+                state TEXT NOT NULL,
+                error TEXT,
+                file_id TEXT,
+                version INTEGER NOT NULL DEFAULT 0,
+                seq INTEGER NOT NULL,
+                priority INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+        let has_priority = conn
+            .prepare("SELECT 1 FROM pragma_table_info('transfers') WHERE name = 'priority'")?
+            .exists([])?;
+        if !has_priority {
+            let _ = conn.execute(
+                "ALTER TABLE transfers ADD COLUMN priority INTEGER NOT NULL DEFAULT 0",
+                [],
+            );
+        }
+
+        // Device and inode number of every local file as of the last scan,
+        // used to recognize renames
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS file_identities (
+                path TEXT PRIMARY KEY,
+                device INTEGER NOT NULL,
+                inode INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Confirmed offsets of interrupted transfers, see crate::api::ResumePoint.
+        // `fingerprint` identifies the content the offset belongs to.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS transfer_resume (
+                path TEXT NOT NULL,
+                direction TEXT NOT NULL,
+                token TEXT NOT NULL,
+                confirmed INTEGER NOT NULL,
+                fingerprint TEXT NOT NULL,
+                PRIMARY KEY (path, direction)
+            )",
+            [],
+        )?;
+
+        // What the sync did lately, newest last; see Database::log_activity
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS activity (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                at INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                path TEXT,
+                detail TEXT
+            )",
+            [],
+        )?;
+
+        // Files and folders kept fully downloaded, see Database::pin
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS pinned (path TEXT PRIMARY KEY)",
+            [],
+        )?;
+
+        // Files whose content changed without a write, see SyncIssue
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sync_issues (
+                path TEXT PRIMARY KEY,
+                expected_hash TEXT NOT NULL,
+                actual_hash TEXT NOT NULL,
+                detected_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Recurring failures, see KnownIssue
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS known_issues (
+                key TEXT PRIMARY KEY,
+                path TEXT,
+                message TEXT NOT NULL,
+                count INTEGER NOT NULL,
+                first_seen INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Transfer volume per day, see BandwidthDay
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS bandwidth_usage (
+                day TEXT PRIMARY KEY,
+                uploaded INTEGER NOT NULL DEFAULT 0,
+                downloaded INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+
+        // Tags and favorites from the server, see FileLabels
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS file_tags (
+                file_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (file_id, tag)
+            );
+            CREATE TABLE IF NOT EXISTS favorites (file_id TEXT PRIMARY KEY);",
+        )?;
+
+        // Server-side tree as learned from sync events, see RemoteEntry
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS remote_entries (
+                id TEXT PRIMARY KEY,
+                is_folder INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                parent_id TEXT,
+                path TEXT NOT NULL,
+                hash TEXT
+            );
+            CREATE INDEX IF NOT EXISTS remote_entries_child ON remote_entries (parent_id, name);
+            CREATE INDEX IF NOT EXISTS remote_entries_path ON remote_entries (path);",
+        )?;
+
+        // Unsynced entries per folder, counting only direct children. Kept
+        // current by triggers on `transfers` (queued/active count as pending)
+        // and `files` (conflict backups), see `rebuild_folder_health`.
+        // REPLACE has to fire the delete triggers, or upserts count twice.
+        conn.execute_batch(&format!(
+            "PRAGMA recursive_triggers = ON;
+            CREATE TABLE IF NOT EXISTS folder_health (
+                path TEXT PRIMARY KEY,
+                pending INTEGER NOT NULL DEFAULT 0,
+                failed INTEGER NOT NULL DEFAULT 0,
+                conflicts INTEGER NOT NULL DEFAULT 0
+            );
+            {triggers}",
+            triggers = health_triggers(),
+        ))?;
+
         // Log initial cursor state
         let instance = Self {
             conn: Mutex::new(conn),
         };
 
         let cursor = instance.get_cursor().unwrap_or(0);
-        log::info!("Database initialized. Current Cursor: {}", cursor);
+        tracing::info!("Database initialized. Current Cursor: {}", cursor);
 
         Ok(instance)
     }
@@ -85,7 +611,7 @@ impl Database {
     pub fn insert_or_update(&self, record: &FileRecord) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT OR REPLACE INTO files (path, id, hash, modified_at, server_version, group_folder_id, is_group_root) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT OR REPLACE INTO files (path, id, hash, modified_at, server_version, group_folder_id, is_group_root, remote_hash, synced_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, strftime('%s', 'now'))",
             params![
                 record.path,
                 record.id,
@@ -93,30 +619,72 @@ impl Database {
                 record.modified_at,
                 record.server_version,
                 record.group_folder_id,
-                if record.is_group_root { 1 } else { 0 }
+                if record.is_group_root { 1 } else { 0 },
+                record.remote_hash
             ],
         )?;
         Ok(())
     }
 
+    /// Upserts many records in one transaction (one fsync instead of one per row).
+    pub fn insert_or_update_many(&self, records: &[FileRecord]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.savepoint()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO files (path, id, hash, modified_at, server_version, group_folder_id, is_group_root, remote_hash, synced_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, strftime('%s', 'now'))",
+            )?;
+            for record in records {
+                stmt.execute(params![
+                    record.path,
+                    record.id,
+                    record.hash,
+                    record.modified_at,
+                    record.server_version,
+                    record.group_folder_id,
+                    if record.is_group_root { 1 } else { 0 },
+                    record.remote_hash
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Deletes many records in one transaction.
+    pub fn delete_files(&self, paths: &[String]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.savepoint()?;
+        {
+            let mut stmt = tx.prepare_cached("DELETE FROM files WHERE path = ?1")?;
+            for path in paths {
+                stmt.execute(params![path])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Opens a transaction spanning all writes until the returned guard is
+    /// committed or dropped, e.g. for a whole sync cycle. Dropping the guard
+    /// commits too, so work done before an error is kept as it would be without
+    /// the batch. Nested calls join the outer batch.
+    pub fn begin_batch(&self) -> Result<WriteBatch<'_>> {
+        let conn = self.conn.lock().unwrap();
+        let owner = conn.is_autocommit();
+        if owner {
+            conn.execute_batch("BEGIN")?;
+        }
+        Ok(WriteBatch { db: self, owner })
+    }
+
     pub fn get_file(&self, path: &str) -> Result<Option<FileRecord>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT path, id, hash, modified_at, server_version, group_folder_id, is_group_root FROM files WHERE path = ?1",
-        )?;
+        let mut stmt =
+            conn.prepare(&format!("SELECT {FILE_COLUMNS} FROM files WHERE path = ?1"))?;
 
         let mut rows = stmt.query(params![path])?;
 
         if let Some(row) = rows.next()? {
-            Ok(Some(FileRecord {
-                path: row.get(0)?,
-                id: row.get(1)?,
-                hash: row.get(2)?,
-                modified_at: row.get(3)?,
-                server_version: row.get(4)?,
-                group_folder_id: row.get(5)?,
-                is_group_root: row.get::<_, i64>(6)? == 1,
-            }))
+            Ok(Some(FileRecord::from_row(row)?))
         } else {
             Ok(None)
         }
@@ -124,22 +692,12 @@ impl Database {
 
     pub fn get_file_by_id(&self, id: &str) -> Result<Option<FileRecord>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT path, id, hash, modified_at, server_version, group_folder_id, is_group_root FROM files WHERE id = ?1",
-        )?;
+        let mut stmt = conn.prepare(&format!("SELECT {FILE_COLUMNS} FROM files WHERE id = ?1"))?;
 
         let mut rows = stmt.query(params![id])?;
 
         if let Some(row) = rows.next()? {
-            Ok(Some(FileRecord {
-                path: row.get(0)?,
-                id: row.get(1)?,
-                hash: row.get(2)?,
-                modified_at: row.get(3)?,
-                server_version: row.get(4)?,
-                group_folder_id: row.get(5)?,
-                is_group_root: row.get::<_, i64>(6)? == 1,
-            }))
+            Ok(Some(FileRecord::from_row(row)?))
         } else {
             Ok(None)
         }
@@ -147,74 +705,1243 @@ impl Database {
 
     pub fn get_file_by_hash(&self, hash: &str) -> Result<Option<FileRecord>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT path, id, hash, modified_at, server_version, group_folder_id, is_group_root FROM files WHERE hash = ?1 LIMIT 1",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files WHERE hash = ?1 LIMIT 1"
+        ))?;
 
         let mut rows = stmt.query(params![hash])?;
 
         if let Some(row) = rows.next()? {
-            Ok(Some(FileRecord {
-                path: row.get(0)?,
-                id: row.get(1)?,
-                hash: row.get(2)?,
-                modified_at: row.get(3)?,
-                server_version: row.get(4)?,
-                group_folder_id: row.get(5)?,
-                is_group_root: row.get::<_, i64>(6)? == 1,
-            }))
+            Ok(Some(FileRecord::from_row(row)?))
         } else {
             Ok(None)
         }
     }
 
+    /// Records what the server last reported for `path` without touching the
+    /// local sync state. No-op for unknown paths.
+    pub fn set_remote_state(&self, path: &str, remote_hash: &str, version: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE files SET remote_hash = ?2, server_version = MAX(server_version, ?3) WHERE path = ?1",
+            params![path, remote_hash, version],
+        )?;
+        Ok(())
+    }
+
     pub fn delete_file(&self, path: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute("DELETE FROM files WHERE path = ?1", params![path])?;
         Ok(())
     }
 
-    pub fn get_all_files(&self) -> Result<Vec<FileRecord>> {
+    /// `(device, inode)` of local files by path, as of the last scan.
+    pub fn file_identities(&self) -> Result<HashMap<String, (u64, u64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT path, device, inode FROM file_identities")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                (row.get::<_, i64>(1)? as u64, row.get::<_, i64>(2)? as u64),
+            ))
+        })?;
+        rows.collect()
+    }
+
+    /// Replaces all stored identities with those of the current scan.
+    pub fn replace_file_identities(&self, identities: &HashMap<String, (u64, u64)>) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.savepoint()?;
+        tx.execute("DELETE FROM file_identities", [])?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO file_identities (path, device, inode) VALUES (?1, ?2, ?3)",
+            )?;
+            for (path, (device, inode)) in identities {
+                stmt.execute(params![path, *device as i64, *inode as i64])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Re-roots `old_prefix` and every descendant path under `new_prefix` in one
+    /// transaction. Returns the number of rows moved.
+    pub fn move_path_prefix(&self, old_prefix: &str, new_prefix: &str) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        // Savepoint so this also works inside a `WriteBatch`
+        let tx = conn.savepoint()?;
+        // Replace any stale rows already sitting at the destination paths
+        let moved = tx.execute(
+            "UPDATE OR REPLACE files
+             SET path = ?2 || substr(path, length(?1) + 1)
+             WHERE path = ?1 OR substr(path, 1, length(?1) + 1) = ?1 || '/'",
+            params![old_prefix, new_prefix],
+        )?;
+        tx.execute(
+            "UPDATE OR REPLACE pinned
+             SET path = ?2 || substr(path, length(?1) + 1)
+             WHERE path = ?1 OR substr(path, 1, length(?1) + 1) = ?1 || '/'",
+            params![old_prefix, new_prefix],
+        )?;
+        tx.commit()?;
+        Ok(moved)
+    }
+
+    /// Stores `entry`; when a folder moved, its mirrored descendants move along.
+    pub fn upsert_remote_entry(&self, entry: &RemoteEntry) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.savepoint()?;
+        let old_path: Option<String> = tx
+            .query_row(
+                "SELECT path FROM remote_entries WHERE id = ?1",
+                params![entry.id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(old_path) = old_path.filter(|old| entry.is_folder && *old != entry.path) {
+            tx.execute(
+                "UPDATE remote_entries
+                 SET path = ?2 || substr(path, length(?1) + 1)
+                 WHERE substr(path, 1, length(?1) + 1) = ?1 || '/'",
+                params![old_path, entry.path],
+            )?;
+        }
+        tx.execute(
+            &format!("INSERT OR REPLACE INTO remote_entries ({REMOTE_COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)"),
+            params![
+                entry.id,
+                entry.is_folder as i64,
+                entry.name,
+                entry.parent_id,
+                entry.path,
+                entry.hash
+            ],
+        )?;
+        tx.commit()
+    }
+
+    /// Forgets `id` and, for folders, everything below it.
+    pub fn remove_remote_entry(&self, id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "WITH RECURSIVE doomed(id) AS (
+                 SELECT ?1
+                 UNION SELECT e.id FROM remote_entries e JOIN doomed d ON e.parent_id = d.id
+             )
+             DELETE FROM remote_entries WHERE id IN (SELECT id FROM doomed)",
+            params![id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_remote_entry(&self, id: &str) -> Result<Option<RemoteEntry>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT {REMOTE_COLUMNS} FROM remote_entries WHERE id = ?1"),
+            params![id],
+            RemoteEntry::from_row,
+        )
+        .optional()
+    }
+
+    /// The entry named `name` directly below `parent_id` (root if `None`).
+    pub fn find_remote_child(
+        &self,
+        parent_id: Option<&str>,
+        name: &str,
+    ) -> Result<Option<RemoteEntry>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!(
+                "SELECT {REMOTE_COLUMNS} FROM remote_entries WHERE parent_id IS ?1 AND name = ?2 LIMIT 1"
+            ),
+            params![parent_id, name],
+            RemoteEntry::from_row,
+        )
+        .optional()
+    }
+
+    pub fn find_remote_by_path(&self, path: &str) -> Result<Option<RemoteEntry>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT {REMOTE_COLUMNS} FROM remote_entries WHERE path = ?1 LIMIT 1"),
+            params![path],
+            RemoteEntry::from_row,
+        )
+        .optional()
+    }
+
+    /// Unix time each entry was last written by the sync, by path. Missing for
+    /// entries from before the time was recorded.
+    pub fn sync_times(&self) -> Result<HashMap<String, i64>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt =
-            conn.prepare("SELECT path, id, hash, modified_at, server_version, group_folder_id, is_group_root FROM files")?;
+            conn.prepare("SELECT path, synced_at FROM files WHERE synced_at IS NOT NULL")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
 
-        let file_iter = stmt.query_map([], |row| {
-            Ok(FileRecord {
-                path: row.get(0)?,
-                id: row.get(1)?,
-                hash: row.get(2)?,
-                modified_at: row.get(3)?,
-                server_version: row.get(4)?,
-                group_folder_id: row.get(5)?,
-                is_group_root: row.get::<_, i64>(6)? == 1,
+    /// Records a finished transfer, conflict, warning or error. Only the
+    /// latest [`ACTIVITY_LIMIT`] entries are kept.
+    pub fn log_activity(&self, kind: &str, path: Option<&str>, detail: Option<&str>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO activity (at, kind, path, detail) VALUES (?1, ?2, ?3, ?4)",
+            params![chrono::Utc::now().timestamp(), kind, path, detail],
+        )?;
+        conn.execute(
+            "DELETE FROM activity WHERE id <= last_insert_rowid() - ?1",
+            params![ACTIVITY_LIMIT as i64],
+        )?;
+        Ok(())
+    }
+
+    /// The latest `limit` activity entries, newest first.
+    pub fn recent_activity(&self, limit: usize) -> Result<Vec<Activity>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT at, kind, path, detail FROM activity ORDER BY id DESC LIMIT ?1")?;
+        let rows = stmt.query_map(params![limit as i64], |row| {
+            Ok(Activity {
+                at: row.get(0)?,
+                kind: row.get(1)?,
+                path: row.get(2)?,
+                detail: row.get(3)?,
             })
         })?;
+        rows.collect()
+    }
 
-        let mut files = Vec::new();
-        for file in file_iter {
-            files.push(file?);
+    /// Keeps `path` and, for a folder, everything below it always available
+    /// offline: never a placeholder, downloaded ahead of other files.
+    pub fn pin(&self, path: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO pinned (path) VALUES (?1)",
+            params![path],
+        )?;
+        Ok(())
+    }
+
+    /// Returns false if `path` was not pinned itself.
+    pub fn unpin(&self, path: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM pinned WHERE path = ?1", params![path])? > 0)
+    }
+
+    /// Whether `path` or one of its parent folders is pinned.
+    pub fn is_pinned(&self, path: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pinned
+             WHERE path = ?1 OR substr(?1, 1, length(path) + 1) = path || '/')",
+            params![path],
+            |row| row.get(0),
+        )
+    }
+
+    /// Tracked files at or below a pinned path.
+    pub fn pinned_files(&self) -> Result<Vec<FileRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files WHERE hash != 'directory' AND EXISTS(
+                SELECT 1 FROM pinned WHERE pinned.path = files.path
+                    OR substr(files.path, 1, length(pinned.path) + 1) = pinned.path || '/')"
+        ))?;
+        let rows = stmt.query_map([], FileRecord::from_row)?;
+        rows.collect()
+    }
+
+    /// Replaces the tags of the file or folder with server id `file_id`.
+    pub fn set_tags(&self, file_id: &str, tags: &[String]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.savepoint()?;
+        tx.execute("DELETE FROM file_tags WHERE file_id = ?1", params![file_id])?;
+        for tag in tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO file_tags (file_id, tag) VALUES (?1, ?2)",
+                params![file_id, tag],
+            )?;
         }
-        Ok(files)
+        tx.commit()
     }
 
-    pub fn get_cursor(&self) -> Result<u64> {
+    pub fn set_favorite(&self, file_id: &str, favorite: bool) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT val FROM globals WHERE key = 'cursor'")?;
-        let mut rows = stmt.query([])?;
-        if let Some(row) = rows.next()? {
-            Ok(row.get(0)?)
+        if favorite {
+            conn.execute(
+                "INSERT OR IGNORE INTO favorites (file_id) VALUES (?1)",
+                params![file_id],
+            )?;
         } else {
-            Ok(0)
+            conn.execute("DELETE FROM favorites WHERE file_id = ?1", params![file_id])?;
         }
+        Ok(())
     }
 
-    pub fn set_cursor(&self, cursor: u64) -> Result<()> {
+    /// Forgets the tags and favorite flag of a deleted file or folder.
+    pub fn remove_labels(&self, file_id: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT OR REPLACE INTO globals (key, val) VALUES ('cursor', ?1)",
-            params![cursor],
+        conn.execute("DELETE FROM file_tags WHERE file_id = ?1", params![file_id])?;
+        conn.execute("DELETE FROM favorites WHERE file_id = ?1", params![file_id])?;
+        Ok(())
+    }
+
+    /// Labels of every file or folder with tags or marked as favorite, by id.
+    pub fn labels(&self) -> Result<HashMap<String, FileLabels>> {
+        let conn = self.conn.lock().unwrap();
+        let mut labels: HashMap<String, FileLabels> = HashMap::new();
+        let mut stmt = conn.prepare("SELECT file_id, tag FROM file_tags ORDER BY tag")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (file_id, tag) = row?;
+            labels.entry(file_id).or_default().tags.push(tag);
+        }
+        let mut stmt = conn.prepare("SELECT file_id FROM favorites")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        for file_id in rows {
+            labels.entry(file_id?).or_default().favorite = true;
+        }
+        Ok(labels)
+    }
+
+    /// Recounts `folder_health` from `transfers` and `files` in case the
+    /// triggers drifted. Writes, so only the sync worker calls it.
+    pub fn rebuild_folder_health(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute_batch(&rebuild_health_sql())
+    }
+
+    /// Unsynced entries at or below `path` ("" for the root), split by the
+    /// direct subfolder they are in so the UI can point at the branch that
+    /// is holding things up.
+    pub fn folder_health(&self, path: &str) -> Result<FolderHealth> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT path, pending, failed, conflicts FROM folder_health
+             WHERE (pending > 0 OR failed > 0 OR conflicts > 0)
+                AND (?1 = '' OR path = ?1 OR (path >= ?1 || '/' AND path < ?1 || '0'))
+             ORDER BY path",
+        )?;
+        let rows = stmt.query_map(params![path], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u64>(2)?,
+                row.get::<_, u64>(3)?,
+            ))
+        })?;
+
+        let mut health = FolderHealth {
+            path: path.to_string(),
+            ..Default::default()
+        };
+        // `Docs 2/x` sorts between `Docs` and `Docs/y`, so rows of one
+        // subfolder are not always adjacent
+        let mut branches: BTreeMap<String, FolderHealth> = BTreeMap::new();
+        for row in rows {
+            let (folder, pending, failed, conflicts) = row?;
+            health.add(pending, failed, conflicts);
+            let below = match folder.strip_prefix(path) {
+                Some(rest) if path.is_empty() => rest,
+                Some(rest) => rest.trim_start_matches('/'),
+                None => continue,
+            };
+            let Some(child) = below.split('/').next().filter(|c| !c.is_empty()) else {
+                continue;
+            };
+            let child_path = if path.is_empty() {
+                child.to_string()
+            } else {
+                format!("{}/{}", path, child)
+            };
+            branches
+                .entry(child_path.clone())
+                .or_insert_with(|| FolderHealth {
+                    path: child_path,
+                    ..Default::default()
+                })
+                .add(pending, failed, conflicts);
+        }
+        health.branches = branches.into_values().collect();
+        Ok(health)
+    }
+
+    /// Up to `limit` files, least recently verified first. Entries never
+    /// verified count as verified when they were synced.
+    pub fn files_to_verify(&self, limit: usize) -> Result<Vec<FileRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files WHERE hash != 'directory'
+             ORDER BY COALESCE(verified_at, synced_at, 0), path LIMIT ?1"
+        ))?;
+        let rows = stmt.query_map(params![limit as i64], FileRecord::from_row)?;
+        rows.collect()
+    }
+
+    pub fn mark_verified(&self, paths: &[String]) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let now = chrono::Utc::now().timestamp();
+        for path in paths {
+            conn.execute(
+                "UPDATE files SET verified_at = ?1 WHERE path = ?2",
+                params![now, path],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Unix time the recorded content of `path` was last seen on disk: when
+    /// the sync wrote the entry or the integrity check re-hashed the file.
+    /// 0 if unknown.
+    pub fn confirmed_at(&self, path: &str) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(
+                "SELECT MAX(COALESCE(synced_at, 0), COALESCE(verified_at, 0))
+                 FROM files WHERE path = ?1",
+                params![path],
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or(0))
+    }
+
+    /// Records `issue`, keeping the first detection time. Returns false if
+    /// the path already had an open issue.
+    pub fn add_sync_issue(&self, issue: &SyncIssue) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let known: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sync_issues WHERE path = ?1)",
+            params![issue.path],
+            |row| row.get(0),
+        )?;
+        conn.execute(
+            "INSERT INTO sync_issues (path, expected_hash, actual_hash, detected_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(path) DO UPDATE SET
+                expected_hash = excluded.expected_hash, actual_hash = excluded.actual_hash",
+            params![
+                issue.path,
+                issue.expected_hash,
+                issue.actual_hash,
+                issue.detected_at
+            ],
+        )?;
+        Ok(!known)
+    }
+
+    /// Open issues, oldest first.
+    pub fn sync_issues(&self) -> Result<Vec<SyncIssue>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT path, expected_hash, actual_hash, detected_at FROM sync_issues
+             ORDER BY detected_at, path",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(SyncIssue {
+                path: row.get(0)?,
+                expected_hash: row.get(1)?,
+                actual_hash: row.get(2)?,
+                detected_at: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn remove_sync_issue(&self, path: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM sync_issues WHERE path = ?1", params![path])?;
+        Ok(())
+    }
+
+    /// Counts another failure `key` at `now`. Returns the updated issue.
+    pub fn record_issue(
+        &self,
+        key: &str,
+        path: Option<&str>,
+        message: &str,
+        now: i64,
+    ) -> Result<KnownIssue> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO known_issues (key, path, message, count, first_seen, last_seen)
+             VALUES (?1, ?2, ?3, 1, ?4, ?4)
+             ON CONFLICT(key) DO UPDATE SET
+                message = excluded.message, count = count + 1, last_seen = excluded.last_seen",
+            params![key, path, message, now],
+        )?;
+        conn.query_row(
+            &format!("SELECT {KNOWN_ISSUE_COLUMNS} FROM known_issues WHERE key = ?1"),
+            params![key],
+            KnownIssue::from_row,
+        )
+    }
+
+    /// Forgets the failure `key` after the operation succeeded. Returns
+    /// whether it was known.
+    pub fn resolve_issue(&self, key: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM known_issues WHERE key = ?1", params![key])? > 0)
+    }
+
+    /// Forgets failures with keys starting with `prefix` except `failing`.
+    /// Returns the keys forgotten.
+    pub fn retain_issues(&self, prefix: &str, failing: &HashSet<String>) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT key FROM known_issues WHERE substr(key, 1, ?2) = ?1")?;
+        let keys: Vec<String> = stmt
+            .query_map(params![prefix, prefix.len()], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+        let resolved: Vec<String> = keys
+            .into_iter()
+            .filter(|key| !failing.contains(key))
+            .collect();
+        for key in &resolved {
+            conn.execute("DELETE FROM known_issues WHERE key = ?1", params![key])?;
+        }
+        Ok(resolved)
+    }
+
+    /// Open known issues, most recent first.
+    pub fn known_issues(&self) -> Result<Vec<KnownIssue>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {KNOWN_ISSUE_COLUMNS} FROM known_issues ORDER BY last_seen DESC, key"
+        ))?;
+        let rows = stmt.query_map([], KnownIssue::from_row)?;
+        rows.collect()
+    }
+
+    /// Adds `bytes` transferred in `direction` on `day` (`YYYY-MM-DD`).
+    pub fn add_bandwidth(&self, day: &str, direction: Direction, bytes: u64) -> Result<()> {
+        let (uploaded, downloaded) = match direction {
+            Direction::Upload => (bytes, 0),
+            Direction::Download => (0, bytes),
+        };
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO bandwidth_usage (day, uploaded, downloaded) VALUES (?1, ?2, ?3)
+             ON CONFLICT(day) DO UPDATE SET
+                uploaded = uploaded + excluded.uploaded,
+                downloaded = downloaded + excluded.downloaded",
+            params![day, uploaded, downloaded],
+        )?;
+        Ok(())
+    }
+
+    /// Bandwidth usage from `first_day` (`YYYY-MM-DD`) on, oldest first.
+    pub fn bandwidth_since(&self, first_day: &str) -> Result<Vec<BandwidthDay>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT day, uploaded, downloaded FROM bandwidth_usage WHERE day >= ?1 ORDER BY day",
+        )?;
+        let days = stmt.query_map(params![first_day], |row| {
+            Ok(BandwidthDay {
+                day: row.get(0)?,
+                uploaded: row.get(1)?,
+                downloaded: row.get(2)?,
+            })
+        })?;
+        days.collect()
+    }
+
+    /// All mirrored server files, without folders.
+    pub fn remote_files(&self) -> Result<Vec<RemoteEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {REMOTE_COLUMNS} FROM remote_entries WHERE is_folder = 0"
+        ))?;
+        let entries = stmt.query_map([], RemoteEntry::from_row)?;
+        entries.collect()
+    }
+
+    pub fn get_all_files(&self) -> Result<Vec<FileRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!("SELECT {FILE_COLUMNS} FROM files"))?;
+
+        let file_iter = stmt.query_map([], FileRecord::from_row)?;
+
+        let mut files = Vec::new();
+        for file in file_iter {
+            files.push(file?);
+        }
+        Ok(files)
+    }
+
+    /// Entries whose path contains every whitespace-separated term of `query`
+    /// (case-insensitive for ASCII), shortest paths first.
+    pub fn search_files(&self, query: &str, limit: usize) -> Result<Vec<FileRecord>> {
+        let terms: Vec<String> = query
+            .split_whitespace()
+            .map(|term| {
+                let escaped = term
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                format!("%{}%", escaped)
+            })
+            .collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filter = vec!["path LIKE ? ESCAPE '\\'"; terms.len()].join(" AND ");
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files WHERE {filter} ORDER BY length(path), path LIMIT {limit}"
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(&terms), FileRecord::from_row)?;
+        rows.collect()
+    }
+
+    /// Whether `source_path` was already auto-uploaded with this content hash.
+    pub fn is_auto_uploaded(&self, source_path: &str, hash: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT 1 FROM auto_uploads WHERE source_path = ?1 AND hash = ?2")?;
+        stmt.exists(params![source_path, hash])
+    }
+
+    pub fn mark_auto_uploaded(&self, source_path: &str, hash: &str, remote_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO auto_uploads (source_path, hash, remote_id, uploaded_at) VALUES (?1, ?2, ?3, ?4)",
+            params![source_path, hash, remote_id, chrono::Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    pub fn add_backup_snapshot(&self, snapshot: &BackupSnapshot) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO backup_snapshots (folder_id, source, name, created_at, complete)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                snapshot.folder_id,
+                snapshot.source,
+                snapshot.name,
+                snapshot.created_at,
+                snapshot.complete
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Snapshots of the backup source at `source`, newest first.
+    pub fn backup_snapshots(&self, source: &str) -> Result<Vec<BackupSnapshot>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT folder_id, source, name, created_at, complete FROM backup_snapshots
+             WHERE source = ?1 ORDER BY created_at DESC",
+        )?;
+        let rows = stmt.query_map(params![source], |row| {
+            Ok(BackupSnapshot {
+                folder_id: row.get(0)?,
+                source: row.get(1)?,
+                name: row.get(2)?,
+                created_at: row.get(3)?,
+                complete: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn remove_backup_snapshot(&self, folder_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM backup_snapshots WHERE folder_id = ?1",
+            params![folder_id],
+        )?;
+        Ok(())
+    }
+
+    /// Cached ETag of `file_id`, only if it was stored for content with `hash`.
+    pub fn get_etag(&self, file_id: &str, hash: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT etag FROM download_validators WHERE file_id = ?1 AND hash = ?2")?;
+        let mut rows = stmt.query(params![file_id, hash])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_etag(&self, file_id: &str, etag: &str, hash: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO download_validators (file_id, etag, hash) VALUES (?1, ?2, ?3)",
+            params![file_id, etag, hash],
+        )?;
+        Ok(())
+    }
+
+    /// Journals operations before they are executed and returns their ids for
+    /// [`Database::finish_op`]. The entries are committed right away, also when
+    /// a [`WriteBatch`] is open, so they survive a crash during the server call.
+    /// An unfinished entry for the same kind and path is replaced.
+    pub fn begin_ops(&self, ops: &[(PendingOpKind, &str, Option<&str>)]) -> Result<Vec<i64>> {
+        let mut conn = self.conn.lock().unwrap();
+        let mut ids = Vec::with_capacity(ops.len());
+        {
+            let tx = conn.savepoint()?;
+            {
+                let mut stmt = tx.prepare_cached(
+                    "INSERT OR REPLACE INTO pending_ops (kind, path, target, created_at) VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                let now = chrono::Utc::now().timestamp();
+                for (kind, path, target) in ops {
+                    stmt.execute(params![kind.as_str(), path, target, now])?;
+                    ids.push(tx.last_insert_rowid());
+                }
+            }
+            tx.commit()?;
+        }
+        if !conn.is_autocommit() {
+            conn.execute_batch("COMMIT; BEGIN")?;
+        }
+        Ok(ids)
+    }
+
+    pub fn begin_op(&self, kind: PendingOpKind, path: &str, target: Option<&str>) -> Result<i64> {
+        Ok(self.begin_ops(&[(kind, path, target)])?[0])
+    }
+
+    /// Marks a journaled operation as completed.
+    pub fn finish_op(&self, id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM pending_ops WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Operations that were started but never finished, oldest first.
+    pub fn pending_ops(&self) -> Result<Vec<PendingOp>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id, kind, path, target FROM pending_ops ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?;
+
+        let mut ops = Vec::new();
+        for row in rows {
+            let (id, kind, path, target) = row?;
+            match PendingOpKind::parse(&kind) {
+                Some(kind) => ops.push(PendingOp {
+                    id,
+                    kind,
+                    path,
+                    target,
+                }),
+                None => tracing::warn!("Ignoring unknown pending operation {}", kind),
+            }
+        }
+        Ok(ops)
+    }
+
+    pub fn load_transfers(&self) -> Result<Vec<Transfer>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT t.path, t.direction, t.size, t.state, t.error, t.file_id, t.version, t.seq,
+                    t.priority, r.confirmed
+             FROM transfers t
+             LEFT JOIN transfer_resume r ON r.path = t.path AND r.direction = 'upload'
+                AND t.direction = 'upload'",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let size = row.get::<_, Option<i64>>(2)?.map(|s| s as u64);
+            Ok((
+                Transfer {
+                    path: row.get(0)?,
+                    direction: Direction::Upload,
+                    size,
+                    state: TransferState::Queued,
+                    error: row.get(4)?,
+                    file_id: row.get(5)?,
+                    version: row.get(6)?,
+                    seq: row.get(7)?,
+                    priority: row.get(8)?,
+                    modified: None,
+                    progress: row
+                        .get::<_, Option<i64>>(9)?
+                        .map(|bytes| ChunkProgress::new(bytes as u64, size)),
+                },
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+
+        let mut transfers = Vec::new();
+        for row in rows {
+            let (mut transfer, direction, state) = row?;
+            match (Direction::parse(&direction), TransferState::parse(&state)) {
+                (Some(direction), Some(state)) => {
+                    transfer.direction = direction;
+                    transfer.state = state;
+                    transfers.push(transfer);
+                }
+                _ => tracing::warn!("Ignoring unknown transfer {} ({})", direction, state),
+            }
+        }
+        Ok(transfers)
+    }
+
+    pub fn save_transfers(&self, changed: &[Transfer], removed: &[String]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.savepoint()?;
+        {
+            let mut upsert = tx.prepare_cached(
+                "INSERT OR REPLACE INTO transfers (path, direction, size, state, error, file_id, version, seq, priority)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            for t in changed {
+                upsert.execute(params![
+                    t.path,
+                    t.direction.as_str(),
+                    t.size.map(|s| s as i64),
+                    t.state.as_str(),
+                    t.error,
+                    t.file_id,
+                    t.version,
+                    t.seq,
+                    t.priority
+                ])?;
+            }
+            let mut delete = tx.prepare_cached("DELETE FROM transfers WHERE path = ?1")?;
+            for path in removed {
+                delete.execute(params![path])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Where the interrupted transfer of `path` can continue, if it was for
+    /// the same `fingerprint`.
+    pub fn get_resume(
+        &self,
+        path: &str,
+        direction: Direction,
+        fingerprint: &str,
+    ) -> Result<Option<ResumePoint>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT token, confirmed FROM transfer_resume
+             WHERE path = ?1 AND direction = ?2 AND fingerprint = ?3",
+            params![path, direction.as_str(), fingerprint],
+            |row| {
+                Ok(ResumePoint {
+                    token: row.get(0)?,
+                    offset: row.get::<_, i64>(1)? as u64,
+                })
+            },
+        )
+        .optional()
+    }
+
+    pub fn set_resume(
+        &self,
+        path: &str,
+        direction: Direction,
+        point: &ResumePoint,
+        fingerprint: &str,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO transfer_resume (path, direction, token, confirmed, fingerprint)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                path,
+                direction.as_str(),
+                point.token,
+                point.offset as i64,
+                fingerprint
+            ],
         )?;
         Ok(())
     }
+
+    pub fn clear_resume(&self, path: &str, direction: Direction) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM transfer_resume WHERE path = ?1 AND direction = ?2",
+            params![path, direction.as_str()],
+        )?;
+        Ok(())
+    }
+
+    /// First problem SQLite's `quick_check` finds, `None` if the file is intact.
+    pub fn quick_check(&self) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let result: String = conn.query_row("PRAGMA quick_check(1)", [], |row| row.get(0))?;
+        Ok((result != "ok").then_some(result))
+    }
+
+    /// Prunes file rows for which `keep` returns false plus cached ETags of
+    /// files no longer tracked, recounts folder health, then refreshes the
+    /// planner statistics and vacuums. Must not run inside a [`WriteBatch`].
+    pub fn maintain(&self, keep: impl Fn(&str) -> bool) -> Result<MaintenanceReport> {
+        let conn = self.conn.lock().unwrap();
+        let size_before = db_size(&conn)?;
+
+        let stale: Vec<String> = {
+            let mut stmt = conn.prepare("SELECT path FROM files")?;
+            let paths = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            let mut stale = Vec::new();
+            for path in paths {
+                let path = path?;
+                if !keep(&path) {
+                    stale.push(path);
+                }
+            }
+            stale
+        };
+        for path in &stale {
+            tracing::info!("Pruning stale DB entry {}", path);
+            conn.execute("DELETE FROM files WHERE path = ?1", params![path])?;
+        }
+        let removed_validators = conn.execute(
+            "DELETE FROM download_validators
+             WHERE file_id NOT IN (SELECT id FROM files WHERE id IS NOT NULL)",
+            [],
+        )?;
+        conn.execute(
+            "DELETE FROM sync_issues WHERE path NOT IN (SELECT path FROM files)",
+            [],
+        )?;
+        conn.execute_batch(
+            "DELETE FROM file_tags WHERE file_id NOT IN (SELECT id FROM files WHERE id IS NOT NULL)
+                AND file_id NOT IN (SELECT id FROM remote_entries);
+             DELETE FROM favorites WHERE file_id NOT IN (SELECT id FROM files WHERE id IS NOT NULL)
+                AND file_id NOT IN (SELECT id FROM remote_entries);",
+        )?;
+        conn.execute_batch(&rebuild_health_sql())?;
+        conn.execute(
+            "DELETE FROM known_issues WHERE last_seen < ?1",
+            params![chrono::Utc::now().timestamp() - KNOWN_ISSUE_MAX_AGE_SECS],
+        )?;
+        let oldest_day =
+            chrono::Local::now().date_naive() - chrono::Duration::days(BANDWIDTH_MAX_AGE_DAYS);
+        conn.execute(
+            "DELETE FROM bandwidth_usage WHERE day < ?1",
+            params![oldest_day.format("%Y-%m-%d").to_string()],
+        )?;
+
+        conn.execute_batch("ANALYZE; VACUUM;")?;
+        conn.execute(
+            "INSERT OR REPLACE INTO globals (key, val) VALUES ('last_maintenance', ?1)",
+            params![chrono::Utc::now().timestamp()],
+        )?;
+
+        Ok(MaintenanceReport {
+            removed_files: stale.len(),
+            removed_validators,
+            size_before,
+            size_after: db_size(&conn)?,
+        })
+    }
+
+    /// Unix time of the last [`Database::maintain`] run, 0 if never.
+    pub fn last_maintenance(&self) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(
+                "SELECT val FROM globals WHERE key = 'last_maintenance'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or(0))
+    }
+
+    /// Forgets what the sync learned from the server and the local folder:
+    /// tracked files, the remote mirror, labels, journaled operations, queued
+    /// transfers and the cursor. Pins, activity and backups stay. The next sync
+    /// starts over as a first sync.
+    pub fn reset_index(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute_batch(
+            "BEGIN;
+             DELETE FROM files;
+             DELETE FROM remote_entries;
+             DELETE FROM file_tags;
+             DELETE FROM favorites;
+             DELETE FROM pending_ops;
+             DELETE FROM transfers;
+             DELETE FROM transfer_resume;
+             DELETE FROM download_validators;
+             DELETE FROM file_identities;
+             DELETE FROM sync_issues;
+             DELETE FROM known_issues;
+             INSERT OR REPLACE INTO globals (key, val) VALUES ('cursor', 0);
+             COMMIT;",
+        )
+    }
+
+    pub fn get_cursor(&self) -> Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT val FROM globals WHERE key = 'cursor'")?;
+        let mut rows = stmt.query([])?;
+        if let Some(row) = rows.next()? {
+            Ok(row.get(0)?)
+        } else {
+            Ok(0)
+        }
+    }
+
+    pub fn set_cursor(&self, cursor: u64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO globals (key, val) VALUES ('cursor', ?1)",
+            params![cursor],
+        )?;
+        Ok(())
+    }
+
+    /// Random id of this database, created on first use. Identifies the sync
+    /// folder the database lives in, also after the folder was moved.
+    pub fn root_id(&self) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        let mut bytes = [0u8; 8];
+        getrandom::getrandom(&mut bytes)
+            .map_err(|e| failure(ffi::SQLITE_ERROR, &format!("No random id: {}", e)))?;
+        conn.execute(
+            "INSERT OR IGNORE INTO globals (key, val) VALUES ('root_id', ?1)",
+            params![i64::from_le_bytes(bytes) & i64::MAX],
+        )?;
+        conn.query_row("SELECT val FROM globals WHERE key = 'root_id'", [], |row| {
+            row.get(0)
+        })
+    }
+}
+
+/// Guard returned by [`Database::begin_batch`].
+pub struct WriteBatch<'a> {
+    db: &'a Database,
+    // False when joined an already open batch; only the owner commits
+    owner: bool,
+}
+
+impl WriteBatch<'_> {
+    /// Commits what was written so far and keeps the batch open, bounding how
+    /// much work a crash can lose.
+    pub fn checkpoint(&self) -> Result<()> {
+        if self.owner {
+            self.db
+                .conn
+                .lock()
+                .unwrap()
+                .execute_batch("COMMIT; BEGIN")?;
+        }
+        Ok(())
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if std::mem::take(&mut self.owner) {
+            self.db.conn.lock().unwrap().execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WriteBatch<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            tracing::error!("Failed to commit DB batch: {}", e);
+        }
+    }
+}
+
+/// SQL for the folder containing the entry at `path`, "" for the root: the
+/// path with everything after its last '/' trimmed.
+fn parent_sql(path: &str) -> String {
+    format!("rtrim(rtrim({path}, replace({path}, '/', '')), '/')")
+}
+
+/// SQL that is 1 when `path` is a conflict backup.
+fn conflict_sql(path: &str) -> String {
+    let suffix = format!(".{}", BACKUP_EXTENSION);
+    format!("(substr({path}, -{}) = '{suffix}')", suffix.len())
+}
+
+/// Adds the SQL integer expressions to the counts of `path`'s folder.
+fn health_delta(path: &str, pending: &str, failed: &str, conflicts: &str) -> String {
+    let folder = parent_sql(path);
+    // Not INSERT OR IGNORE: the outer statement's REPLACE would override it
+    format!(
+        "UPDATE folder_health SET pending = pending + ({pending}),
+            failed = failed + ({failed}), conflicts = conflicts + ({conflicts})
+        WHERE path = {folder};
+        INSERT INTO folder_health (path, pending, failed, conflicts)
+            SELECT {folder}, {pending}, {failed}, {conflicts}
+            WHERE NOT EXISTS (SELECT 1 FROM folder_health WHERE path = {folder});"
+    )
+}
+
+/// Recounts `folder_health` from scratch.
+fn rebuild_health_sql() -> String {
+    format!(
+        "DELETE FROM folder_health;
+        INSERT INTO folder_health (path, pending, failed, conflicts)
+            SELECT folder, SUM(pending), SUM(failed), SUM(conflicts) FROM (
+                SELECT {parent} AS folder,
+                    state IN ('queued', 'active') AS pending,
+                    state = 'failed' AS failed, 0 AS conflicts
+                FROM transfers
+                UNION ALL
+                SELECT {parent}, 0, 0, 1 FROM files WHERE {is_conflict}
+            ) GROUP BY folder
+            HAVING SUM(pending) > 0 OR SUM(failed) > 0 OR SUM(conflicts) > 0;",
+        parent = parent_sql("path"),
+        is_conflict = conflict_sql("path"),
+    )
+}
+
+/// Triggers keeping `folder_health` in step with `transfers` and `files`.
+fn health_triggers() -> String {
+    let pending = |row: &str| format!("{row}.state IN ('queued', 'active')");
+    let failed = |row: &str| format!("{row}.state = 'failed'");
+    let transfer_added = health_delta("NEW.path", &pending("NEW"), &failed("NEW"), "0");
+    let transfer_removed = health_delta(
+        "OLD.path",
+        &format!("-({})", pending("OLD")),
+        &format!("-({})", failed("OLD")),
+        "0",
+    );
+    let (new_conflict, old_conflict) = (conflict_sql("NEW.path"), conflict_sql("OLD.path"));
+    let file_added = health_delta("NEW.path", "0", "0", "1");
+    let file_removed = health_delta("OLD.path", "0", "0", "-1");
+    let file_moved = format!(
+        "{}{}",
+        health_delta("OLD.path", "0", "0", &format!("-{old_conflict}")),
+        health_delta("NEW.path", "0", "0", &new_conflict)
+    );
+    format!(
+        "CREATE TRIGGER IF NOT EXISTS transfers_health_insert AFTER INSERT ON transfers
+        BEGIN {transfer_added} END;
+        CREATE TRIGGER IF NOT EXISTS transfers_health_delete AFTER DELETE ON transfers
+        BEGIN {transfer_removed} END;
+        CREATE TRIGGER IF NOT EXISTS transfers_health_update AFTER UPDATE OF path, state ON transfers
+        BEGIN {transfer_removed} {transfer_added} END;
+        CREATE TRIGGER IF NOT EXISTS files_health_insert AFTER INSERT ON files
+        WHEN {new_conflict} BEGIN {file_added} END;
+        CREATE TRIGGER IF NOT EXISTS files_health_delete AFTER DELETE ON files
+        WHEN {old_conflict} BEGIN {file_removed} END;
+        CREATE TRIGGER IF NOT EXISTS files_health_update AFTER UPDATE OF path ON files
+        WHEN {old_conflict} OR {new_conflict} BEGIN {file_moved} END;"
+    )
+}
+
+fn db_size(conn: &Connection) -> Result<u64> {
+    let pages: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+    Ok(pages * page_size)
+}
+
+fn failure(code: std::os::raw::c_int, message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(ffi::Error::new(code), Some(message.to_string()))
+}
+
+/// The SQLCipher key the file should be opened with, `None` for plaintext.
+fn wanted_key(key: &DbKey) -> Result<Option<&str>> {
+    match (key.encrypt, key.key.as_deref()) {
+        (true, Some(k)) => Ok(Some(k)),
+        (true, None) => Err(failure(ffi::SQLITE_AUTH, "Database encryption needs a key")),
+        (false, _) => Ok(None),
+    }
+}
+
+/// `Some(true)` for a plaintext SQLite file, `Some(false)` for anything else
+/// (an encrypted file), `None` if there is no database yet.
+fn is_plaintext(path: &Path) -> Option<bool> {
+    let mut header = [0u8; 16];
+    let mut file = fs::File::open(path).ok()?;
+    file.read_exact(&mut header).ok()?;
+    Some(&header == SQLITE_HEADER)
+}
+
+fn apply_key(conn: &Connection, key: &str) -> Result<()> {
+    // Plain SQLite ignores `PRAGMA key`, which would silently leave the file
+    // unencrypted
+    let cipher: Option<String> = conn
+        .query_row("PRAGMA cipher_version", [], |row| row.get(0))
+        .optional()?;
+    if cipher.is_none() {
+        return Err(failure(
+            ffi::SQLITE_MISUSE,
+            "This build has no SQLCipher support",
+        ));
+    }
+    conn.pragma_update(None, "key", key)
+}
+
+/// Rewrites the database at `path` from key `from` to key `to` (`None` is
+/// plaintext) through a temporary copy, so an interruption leaves the original.
+fn convert(path: &Path, from: Option<&str>, to: Option<&str>) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".converting");
+    let tmp = std::path::PathBuf::from(tmp);
+    let _ = fs::remove_file(&tmp);
+    {
+        let conn = Connection::open(path)?;
+        // Also verifies SQLCipher is available when going from plaintext
+        apply_key(&conn, from.unwrap_or(""))?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS converted KEY ?2",
+            params![tmp.to_string_lossy(), to.unwrap_or("")],
+        )?;
+        conn.query_row("SELECT sqlcipher_export('converted')", [], |_| Ok(()))?;
+        conn.execute("DETACH DATABASE converted", [])?;
+    }
+    fs::rename(&tmp, path).map_err(|e| failure(ffi::SQLITE_CANTOPEN, &e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folder_health_merges_branches_of_one_subfolder() {
+        let db = Database::new_in_memory().unwrap();
+        db.conn
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO folder_health (path, pending, failed, conflicts) VALUES
+                    ('Docs', 1, 0, 0), ('Docs 2/x', 0, 1, 0), ('Docs/y', 0, 0, 1),
+                    ('Photos.bak', 2, 0, 0), ('Photos/2024', 1, 0, 0)",
+            )
+            .unwrap();
+
+        let health = db.folder_health("").unwrap();
+        assert_eq!(health.pending, 4);
+        let branches: Vec<(&str, u64, u64, u64)> = health
+            .branches
+            .iter()
+            .map(|b| (b.path.as_str(), b.pending, b.failed, b.conflicts))
+            .collect();
+        assert_eq!(
+            branches,
+            [
+                ("Docs", 1, 0, 1),
+                ("Docs 2", 0, 1, 0),
+                ("Photos", 1, 0, 0),
+                ("Photos.bak", 2, 0, 0)
+            ]
+        );
+
+        let docs = db.folder_health("Docs").unwrap();
+        assert_eq!((docs.pending, docs.conflicts), (1, 1));
+        assert_eq!(docs.branches.len(), 1);
+        assert_eq!(docs.branches[0].path, "Docs/y");
+    }
 }
@@ -0,0 +1,330 @@
+//! Self-check for support requests: server reachability and sign-in, the sync
+//! folder, free disk space, file watching, the keyring and the sync database.
+//! Every check passes, warns or fails with a translated detail the UI shows
+//! as is; checks that do not apply (e.g. no sync folder yet) are skipped.
+
+use crate::api::XynoxaClient;
+use crate::db::{Database, DbKey};
+use crate::i18n::{tr, tr_args};
+use crate::{sync, watcher};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Free space below this is worth a warning.
+const LOW_DISK_SPACE: u64 = 1024 * 1024 * 1024;
+/// inotify watches available to the user, one per watched folder.
+#[cfg(target_os = "linux")]
+const INOTIFY_WATCHES: &str = "/proc/sys/fs/inotify/max_user_watches";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    Skipped,
+}
+
+/// Outcome of one check. `name` is a stable id the UI labels.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    /// `server`, `auth`, `sync_folder`, `disk_space`, `watcher`, `keyring`
+    /// or `database`
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: String) -> Self {
+        Self {
+            name,
+            status,
+            detail,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsReport {
+    /// Unix time
+    pub created_at: i64,
+    /// No check failed; warnings do not count
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+/// What [`run`] checks.
+pub struct Target {
+    /// `None` while signed out
+    pub client: Option<XynoxaClient>,
+    pub sync_root: Option<PathBuf>,
+    /// See [`crate::config::AppConfig::account_key`]
+    pub account: String,
+    pub db_key: DbKey,
+    /// Keyring service the token is stored under
+    pub keyring_service: &'static str,
+}
+
+/// Runs every check. Local checks run on a blocking thread, as walking a
+/// large sync folder takes a while.
+pub async fn run(target: Target) -> DiagnosticsReport {
+    let mut checks = match &target.client {
+        Some(client) => vec![check_server(client).await, check_auth(client).await],
+        None => vec![
+            Check::new("server", CheckStatus::Skipped, tr("Not signed in")),
+            Check::new("auth", CheckStatus::Fail, tr("Not signed in")),
+        ],
+    };
+    let local = tokio::task::spawn_blocking(move || local_checks(&target)).await;
+    match local {
+        Ok(local) => checks.extend(local),
+        Err(e) => tracing::error!("Local diagnostics failed: {}", e),
+    }
+    DiagnosticsReport {
+        created_at: chrono::Utc::now().timestamp(),
+        passed: checks.iter().all(|check| check.status != CheckStatus::Fail),
+        checks,
+    }
+}
+
+fn local_checks(target: &Target) -> Vec<Check> {
+    let root = target.sync_root.as_deref();
+    vec![
+        check_sync_folder(root),
+        check_disk_space(root),
+        check_watcher(root),
+        check_keyring(target.keyring_service),
+        check_database(root, &target.account, &target.db_key),
+    ]
+}
+
+async fn check_server(client: &XynoxaClient) -> Check {
+    let info = match client.server_info().await {
+        Ok(info) => info,
+        Err(e) => return Check::new("server", CheckStatus::Fail, e.localized_message()),
+    };
+    let version = info.version.as_deref().unwrap_or("?");
+    match info.check_compatibility() {
+        Ok(()) => Check::new(
+            "server",
+            CheckStatus::Pass,
+            tr_args("Reachable, version {0}", &[version]),
+        ),
+        Err(e) => Check::new("server", CheckStatus::Fail, e.localized_message()),
+    }
+}
+
+async fn check_auth(client: &XynoxaClient) -> Check {
+    match client.session_info().await {
+        Ok(session) => {
+            let user = session.user;
+            let name = user.email.or(user.name).unwrap_or(user.id);
+            Check::new(
+                "auth",
+                CheckStatus::Pass,
+                tr_args("Signed in as {0}", &[&name]),
+            )
+        }
+        Err(e) => Check::new("auth", CheckStatus::Fail, e.localized_message()),
+    }
+}
+
+fn check_sync_folder(root: Option<&Path>) -> Check {
+    let name = "sync_folder";
+    let Some(root) = root else {
+        return Check::new(name, CheckStatus::Fail, tr("No sync path configured"));
+    };
+    let shown = root.to_string_lossy();
+    if !root.is_dir() {
+        return Check::new(
+            name,
+            CheckStatus::Fail,
+            tr_args("{0} does not exist", &[&shown]),
+        );
+    }
+    let probe = root.join(sync::WRITE_PROBE);
+    match fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Check::new(
+                name,
+                CheckStatus::Pass,
+                tr_args("{0} is writable", &[&shown]),
+            )
+        }
+        Err(e) => Check::new(
+            name,
+            CheckStatus::Fail,
+            tr_args("Cannot write to {0}: {1}", &[&shown, &e.to_string()]),
+        ),
+    }
+}
+
+fn check_disk_space(root: Option<&Path>) -> Check {
+    let name = "disk_space";
+    let Some(root) = root.filter(|root| root.is_dir()) else {
+        return Check::new(name, CheckStatus::Skipped, tr("No sync folder"));
+    };
+    let (total, free) = match crate::cloud_files::disk_space(root) {
+        Ok(space) => space,
+        Err(e) => return Check::new(name, CheckStatus::Skipped, e.localized_message()),
+    };
+    let detail = tr_args("{0} free of {1}", &[&gigabytes(free), &gigabytes(total)]);
+    let status = if free < LOW_DISK_SPACE {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    };
+    Check::new(name, status, detail)
+}
+
+fn gigabytes(bytes: u64) -> String {
+    format!("{:.1} GB", bytes as f64 / 1e9)
+}
+
+fn check_watcher(root: Option<&Path>) -> Check {
+    let name = "watcher";
+    let Some(root) = root.filter(|root| root.is_dir()) else {
+        return Check::new(name, CheckStatus::Skipped, tr("No sync folder"));
+    };
+    if let Some(fs_type) = watcher::remote_fs_type(root) {
+        return Check::new(
+            name,
+            CheckStatus::Warn,
+            tr_args(
+                "The sync folder is on a {0} network drive; changes made elsewhere are found by polling",
+                &[&fs_type],
+            ),
+        );
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let limit = fs::read_to_string(INOTIFY_WATCHES)
+            .ok()
+            .and_then(|limit| limit.trim().parse::<usize>().ok());
+        if let Some(limit) = limit {
+            let folders = WalkDir::new(root)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_dir())
+                .count();
+            let status = watch_status(folders, limit);
+            let detail = match status {
+                CheckStatus::Pass => tr_args(
+                    "{0} folders, {1} inotify watches",
+                    &[&folders.to_string(), &limit.to_string()],
+                ),
+                _ => tr_args(
+                    "{0} folders but only {1} inotify watches. Raise the limit: {2}",
+                    &[
+                        &folders.to_string(),
+                        &limit.to_string(),
+                        watcher::WATCH_LIMIT_HINT,
+                    ],
+                ),
+            };
+            return Check::new(name, status, detail);
+        }
+    }
+    Check::new(
+        name,
+        CheckStatus::Pass,
+        tr("Changes are reported by the system"),
+    )
+}
+
+/// Watches are shared with other programs, so most of them taken by the
+/// sync folder alone is worth a warning.
+fn watch_status(folders: usize, limit: usize) -> CheckStatus {
+    if folders >= limit {
+        CheckStatus::Fail
+    } else if folders * 10 >= limit * 8 {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    }
+}
+
+fn check_keyring(service: &str) -> Check {
+    let name = "keyring";
+    let result = keyring::Entry::new(service, "auth-token").and_then(|entry| entry.get_password());
+    match result {
+        Ok(_) => Check::new(
+            name,
+            CheckStatus::Pass,
+            tr("The token is stored in the keyring"),
+        ),
+        Err(keyring::Error::NoEntry) => {
+            Check::new(name, CheckStatus::Pass, tr("Available, no token stored"))
+        }
+        Err(e) => Check::new(
+            name,
+            CheckStatus::Warn,
+            tr_args(
+                "Unavailable, the token is only kept in the settings file: {0}",
+                &[&e.to_string()],
+            ),
+        ),
+    }
+}
+
+fn check_database(root: Option<&Path>, account: &str, key: &DbKey) -> Check {
+    let name = "database";
+    let Some(root) = root else {
+        return Check::new(name, CheckStatus::Skipped, tr("No sync folder"));
+    };
+    let Some(path) = sync::existing_db_path(root, account) else {
+        return Check::new(name, CheckStatus::Skipped, tr("Not synced yet"));
+    };
+    let problem = Database::open(&path, key).and_then(|db| db.quick_check());
+    match problem {
+        Ok(None) => Check::new(name, CheckStatus::Pass, tr("Intact")),
+        Ok(Some(problem)) => Check::new(
+            name,
+            CheckStatus::Fail,
+            tr_args("Damaged: {0}. Rebuild the sync database.", &[&problem]),
+        ),
+        Err(e) => Check::new(
+            name,
+            CheckStatus::Fail,
+            tr_args("Cannot open: {0}", &[&e.to_string()]),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Some(dir.path());
+        let key = DbKey::default();
+        let db_path = dir.path().join(".xynoxa-s1-u1.db");
+
+        assert_eq!(check_sync_folder(root).status, CheckStatus::Pass);
+        let missing = dir.path().join("missing");
+        assert_eq!(check_sync_folder(Some(&missing)).status, CheckStatus::Fail);
+        assert_eq!(check_sync_folder(None).status, CheckStatus::Fail);
+        assert_eq!(check_watcher(Some(&missing)).status, CheckStatus::Skipped);
+
+        let database = || check_database(root, "s1-u1", &key).status;
+        assert_eq!(database(), CheckStatus::Skipped);
+        Database::open(&db_path, &key).unwrap();
+        assert_eq!(database(), CheckStatus::Pass);
+        fs::write(&db_path, b"not a database").unwrap();
+        assert_eq!(database(), CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_watch_status() {
+        assert_eq!(watch_status(100, 8192), CheckStatus::Pass);
+        assert_eq!(watch_status(7000, 8192), CheckStatus::Warn);
+        assert_eq!(watch_status(8192, 8192), CheckStatus::Fail);
+    }
+}
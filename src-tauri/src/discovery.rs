@@ -0,0 +1,124 @@
+//! Finds Xynoxa servers announcing themselves on the local network through
+//! mDNS/DNS-SD (`_xynoxa._tcp`), for one-click choices in the setup wizard.
+//!
+//! Optional TXT keys: `name` (display name), `scheme` (default `https`) and
+//! `path` (where the instance is mounted, default `/`).
+
+use crate::error::{Result, XynoxaError};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+pub const SERVICE_TYPE: &str = "_xynoxa._tcp.local.";
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DiscoveredServer {
+    pub name: String,
+    /// Base URL for `probe_server`
+    pub url: String,
+}
+
+/// Browses the network for `timeout` (blocking) and returns each server once.
+pub fn browse(timeout: Duration) -> Result<Vec<DiscoveredServer>> {
+    let mdns = ServiceDaemon::new().map_err(mdns_error)?;
+    let receiver = mdns.browse(SERVICE_TYPE).map_err(mdns_error)?;
+    let deadline = Instant::now() + timeout;
+    let mut servers: Vec<DiscoveredServer> = Vec::new();
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match receiver.recv_timeout(remaining) {
+            Ok(ServiceEvent::ServiceResolved(info)) => {
+                let Some(server) = discovered_server(&info) else {
+                    continue;
+                };
+                tracing::info!("Discovered server {} at {}", server.name, server.url);
+                if !servers.iter().any(|s| s.url == server.url) {
+                    servers.push(server);
+                }
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    let _ = mdns.shutdown();
+    servers.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(servers)
+}
+
+fn discovered_server(info: &ServiceInfo) -> Option<DiscoveredServer> {
+    // The host name keeps TLS certificates valid; addresses are the fallback
+    let host = match info.get_hostname().trim_end_matches('.') {
+        "" => info.get_addresses().iter().next()?.to_string(),
+        host => host.to_string(),
+    };
+    let name = info
+        .get_property_val_str("name")
+        .map(str::to_string)
+        .unwrap_or_else(|| instance_name(info.get_fullname()).to_string());
+    Some(DiscoveredServer {
+        name,
+        url: server_url(
+            &host,
+            info.get_port(),
+            info.get_property_val_str("scheme"),
+            info.get_property_val_str("path"),
+        ),
+    })
+}
+
+/// `Office NAS` from `Office NAS._xynoxa._tcp.local.`
+fn instance_name(fullname: &str) -> &str {
+    fullname
+        .strip_suffix(SERVICE_TYPE)
+        .map(|name| name.trim_end_matches('.'))
+        .unwrap_or(fullname)
+}
+
+fn server_url(host: &str, port: u16, scheme: Option<&str>, path: Option<&str>) -> String {
+    let scheme = scheme.unwrap_or("https");
+    let host = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    };
+    let default_port = matches!((scheme, port), ("https", 443) | ("http", 80));
+    let authority = if default_port {
+        host
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let path = path.unwrap_or("").trim_matches('/');
+    if path.is_empty() {
+        format!("{}://{}", scheme, authority)
+    } else {
+        format!("{}://{}/{}", scheme, authority, path)
+    }
+}
+
+fn mdns_error(e: mdns_sd::Error) -> XynoxaError {
+    XynoxaError::Other(format!("Network discovery failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_url_from_announcement() {
+        assert_eq!(
+            server_url("nas.local", 443, None, None),
+            "https://nas.local"
+        );
+        assert_eq!(
+            server_url("nas.local", 8443, None, Some("/xynoxa/")),
+            "https://nas.local:8443/xynoxa"
+        );
+        assert_eq!(
+            server_url("fd00::2", 80, Some("http"), None),
+            "http://[fd00::2]"
+        );
+        assert_eq!(
+            instance_name("Office NAS._xynoxa._tcp.local."),
+            "Office NAS"
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use crate::i18n::{tr, tr_args};
+use crate::trpc::TwoFactorChallenge;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+/// Error type shared by the API client, sync engine, config and Tauri commands.
+///
+/// Serialized to the frontend as `{ code, message, status }` where `code` is a
+/// stable identifier (see [`XynoxaError::code`]) the UI can match on and
+/// `message` is in the language set with [`crate::i18n::set_locale`].
+#[derive(Debug, thiserror::Error)]
+pub enum XynoxaError {
+    /// The session is gone (revoked or expired token); signs the user out.
+    #[error("Authentication failed: {0}")]
+    Auth(String),
+    /// The session is valid but may not do this one thing, e.g. write to a
+    /// folder shared read-only.
+    #[error("Permission denied: {0}")]
+    Forbidden(String),
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("Server error {status}: {message}")]
+    Server { status: u16, message: String },
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] rusqlite::Error),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Invalid response: {0}")]
+    Decode(String),
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("Incompatible server: {0}")]
+    Incompatible(String),
+    #[error("Invalid settings: {}", describe_fields(.0))]
+    Validation(Vec<FieldError>),
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+    /// The sync root is missing (e.g. an unmounted drive) or read-only.
+    #[error("Sync folder unavailable: {0}")]
+    Unavailable(String),
+    /// `server_url` differs from the server the sync folder was synced with;
+    /// syncing waits until the user says whether it is the same server.
+    #[error("Server address changed from {from} to {to}")]
+    ServerChanged { from: String, to: String },
+    /// The token is only accepted after the challenge is answered.
+    #[error("Second factor required")]
+    TwoFactorRequired(TwoFactorChallenge),
+    #[error("{0}")]
+    Other(String),
+}
+
+pub type Result<T, E = XynoxaError> = std::result::Result<T, E>;
+
+/// A rejected setting. `field` is the camelCase name used in `ConfigPatch`, so
+/// the UI can show `message` next to the matching input.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+fn describe_fields(fields: &[FieldError]) -> String {
+    fields
+        .iter()
+        .map(|f| format!("{}: {}", f.field, f.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn localized_fields(fields: &[FieldError]) -> Vec<FieldError> {
+    fields
+        .iter()
+        .map(|f| FieldError {
+            field: f.field,
+            message: tr(&f.message),
+        })
+        .collect()
+}
+
+impl XynoxaError {
+    /// Stable machine-readable error code. Do not change existing values,
+    /// the frontend matches on them.
+    pub fn code(&self) -> &'static str {
+        match self {
+            XynoxaError::Auth(_) => "auth",
+            XynoxaError::Forbidden(_) => "forbidden",
+            XynoxaError::Network(_) => "network",
+            XynoxaError::Server { .. } => "server",
+            XynoxaError::Io(_) => "io",
+            XynoxaError::Db(_) => "db",
+            XynoxaError::Conflict(_) => "conflict",
+            XynoxaError::Decode(_) => "decode",
+            XynoxaError::Config(_) => "config",
+            XynoxaError::Incompatible(_) => "incompatible",
+            XynoxaError::Validation(_) => "validation",
+            XynoxaError::Cancelled(_) => "cancelled",
+            XynoxaError::Unavailable(_) => "unavailable",
+            XynoxaError::ServerChanged { .. } => "server_changed",
+            XynoxaError::TwoFactorRequired(_) => "two_factor_required",
+            XynoxaError::Other(_) => "internal",
+        }
+    }
+
+    /// The [`Display`](std::fmt::Display) text in the current UI language.
+    /// Details from the server or OS are passed through untranslated.
+    pub fn localized_message(&self) -> String {
+        match self {
+            XynoxaError::Auth(m) => tr_args("Authentication failed: {0}", &[m]),
+            XynoxaError::Forbidden(m) => tr_args("Permission denied: {0}", &[m]),
+            XynoxaError::Network(m) => tr_args("Network error: {0}", &[m]),
+            XynoxaError::Server { status, message } => {
+                tr_args("Server error {0}: {1}", &[&status.to_string(), message])
+            }
+            XynoxaError::Io(e) => tr_args("I/O error: {0}", &[&e.to_string()]),
+            XynoxaError::Db(e) => tr_args("Database error: {0}", &[&e.to_string()]),
+            XynoxaError::Conflict(m) => tr_args("Conflict: {0}", &[m]),
+            XynoxaError::Decode(m) => tr_args("Invalid response: {0}", &[m]),
+            XynoxaError::Config(m) => tr_args("Configuration error: {0}", &[&tr(m)]),
+            XynoxaError::Incompatible(m) => tr_args("Incompatible server: {0}", &[m]),
+            XynoxaError::Validation(fields) => tr_args(
+                "Invalid settings: {0}",
+                &[&describe_fields(&localized_fields(fields))],
+            ),
+            XynoxaError::Cancelled(m) => tr_args("Cancelled: {0}", &[m]),
+            XynoxaError::Unavailable(m) => tr_args("Sync folder unavailable: {0}", &[m]),
+            XynoxaError::ServerChanged { from, to } => tr_args(
+                "The server address changed from {0} to {1}. Confirm whether it is the same server before syncing.",
+                &[from, to],
+            ),
+            XynoxaError::TwoFactorRequired(_) => tr("Second factor required"),
+            XynoxaError::Other(m) => tr(m),
+        }
+    }
+
+    /// Map a non-success HTTP status (plus response body) to the matching variant.
+    pub fn from_status(status: reqwest::StatusCode, message: String) -> Self {
+        match status.as_u16() {
+            401 => XynoxaError::Auth(format!("{}. Body: {}", status, message)),
+            403 => XynoxaError::Forbidden(format!("{}. Body: {}", status, message)),
+            409 => XynoxaError::Conflict(message),
+            code => XynoxaError::Server {
+                status: code,
+                message,
+            },
+        }
+    }
+}
+
+impl Serialize for XynoxaError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("XynoxaError", 5)?;
+        s.serialize_field("code", self.code())?;
+        s.serialize_field("message", &self.localized_message())?;
+        let status = match self {
+            XynoxaError::Server { status, .. } => Some(*status),
+            _ => None,
+        };
+        s.serialize_field("status", &status)?;
+        match self {
+            XynoxaError::Validation(fields) => {
+                s.serialize_field("fields", &localized_fields(fields))?
+            }
+            _ => s.skip_field("fields")?,
+        }
+        match self {
+            XynoxaError::TwoFactorRequired(challenge) => {
+                s.serialize_field("challenge", challenge)?
+            }
+            _ => s.skip_field("challenge")?,
+        }
+        s.end()
+    }
+}
+
+impl From<reqwest::Error> for XynoxaError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            XynoxaError::Decode(e.to_string())
+        } else if let Some(status) = e.status() {
+            XynoxaError::from_status(status, e.to_string())
+        } else {
+            XynoxaError::Network(e.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for XynoxaError {
+    fn from(e: serde_json::Error) -> Self {
+        XynoxaError::Decode(e.to_string())
+    }
+}
+
+impl From<String> for XynoxaError {
+    fn from(msg: String) -> Self {
+        XynoxaError::Other(msg)
+    }
+}
+
+impl From<&str> for XynoxaError {
+    fn from(msg: &str) -> Self {
+        XynoxaError::Other(msg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializes_stable_code() {
+        let err = XynoxaError::from_status(reqwest::StatusCode::UNAUTHORIZED, "nope".into());
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["code"], "auth");
+
+        let err = XynoxaError::from_status(reqwest::StatusCode::FORBIDDEN, "read-only".into());
+        assert_eq!(err.code(), "forbidden");
+
+        let err = XynoxaError::from_status(reqwest::StatusCode::BAD_GATEWAY, "down".into());
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["code"], "server");
+        assert_eq!(json["status"], 502);
+    }
+}
@@ -0,0 +1,186 @@
+//! Estimate shown before the first sync of a folder: what the folder and the
+//! server hold and roughly how long moving it takes. Files present on both
+//! sides are counted twice although the first sync adopts identical ones, so
+//! the duration is an upper bound.
+
+use crate::api::ApiClient;
+use crate::error::Result;
+use crate::ignore::IgnoreList;
+use crate::sync::{is_folder_entity, remote_event_path};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+const PULL_PAGE_SIZE: u32 = 500;
+/// Listing responses smaller than this say more about latency than about
+/// throughput, so no duration is estimated from them.
+const MIN_THROUGHPUT_SAMPLE: u64 = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitialSyncEstimate {
+    /// Files in the sync folder to upload
+    pub local: Totals,
+    /// Files on the server to download
+    pub remote: Totals,
+    /// Measured while listing the server; `None` if the listing was too small
+    pub bytes_per_second: Option<u64>,
+    /// Expected duration at `bytes_per_second`
+    pub seconds: Option<u64>,
+}
+
+/// Walks `root` on a blocking thread and lists the server through `api`.
+pub async fn estimate(
+    api: &dyn ApiClient,
+    root: &Path,
+    ignore_patterns: &[String],
+) -> Result<InitialSyncEstimate> {
+    let local = {
+        let root = root.to_path_buf();
+        let ignore = IgnoreList::new(ignore_patterns);
+        tokio::task::spawn_blocking(move || local_totals(&root, &ignore))
+    };
+    let ignore = IgnoreList::new(ignore_patterns);
+    let (remote, sampled, elapsed) = remote_totals(api, &ignore).await?;
+    let local = local
+        .await
+        .map_err(|e| format!("Scanning the sync folder failed: {}", e))?;
+    let bytes_per_second = throughput(sampled, elapsed);
+    Ok(InitialSyncEstimate {
+        local,
+        remote,
+        bytes_per_second,
+        seconds: bytes_per_second.map(|rate| (local.bytes + remote.bytes).div_ceil(rate)),
+    })
+}
+
+/// Files below `root` the sync would upload.
+fn local_totals(root: &Path, ignore: &IgnoreList) -> Totals {
+    let mut totals = Totals::default();
+    let entries = WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !ignore.matches_name(&e.file_name().to_string_lossy()))
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file());
+    for entry in entries {
+        totals.files += 1;
+        totals.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+    }
+    totals
+}
+
+/// Current server files from the whole event log, plus the listing's size
+/// in bytes and the time it took.
+async fn remote_totals(
+    api: &dyn ApiClient,
+    ignore: &IgnoreList,
+) -> Result<(Totals, u64, Duration)> {
+    let mut sizes: HashMap<String, u64> = HashMap::new();
+    let mut sampled = 0;
+    let mut elapsed = Duration::ZERO;
+    let mut cursor = 0;
+    loop {
+        let started = Instant::now();
+        let page = api.sync_pull(cursor, Some(PULL_PAGE_SIZE)).await?;
+        elapsed += started.elapsed();
+        sampled += serde_json::to_vec(&page.events).map_or(0, |json| json.len() as u64);
+        for event in page
+            .events
+            .iter()
+            .filter(|e| !is_folder_entity(&e.entity_type))
+        {
+            if event.action == "delete" {
+                sizes.remove(&event.entity_id);
+                continue;
+            }
+            let Some(data) = &event.data else {
+                continue;
+            };
+            if ignore.matches_path(&remote_event_path(data, event.owner_id.as_deref())) {
+                sizes.remove(&event.entity_id);
+            } else {
+                let size = data.size.as_deref().and_then(|s| s.parse().ok());
+                sizes.insert(event.entity_id.clone(), size.unwrap_or(0));
+            }
+        }
+        if page.is_last_page(cursor) {
+            break;
+        }
+        cursor = page.next_cursor;
+    }
+    let totals = Totals {
+        files: sizes.len() as u64,
+        bytes: sizes.values().sum(),
+    };
+    Ok((totals, sampled, elapsed))
+}
+
+fn throughput(sampled: u64, elapsed: Duration) -> Option<u64> {
+    if sampled < MIN_THROUGHPUT_SAMPLE || elapsed.is_zero() {
+        return None;
+    }
+    Some((sampled as f64 / elapsed.as_secs_f64()) as u64).filter(|&rate| rate > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::FileData;
+    use crate::mock_api::MockApiClient;
+
+    #[test]
+    fn test_estimate_counts_both_sides() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("docs/node_modules")).unwrap();
+        std::fs::write(dir.path().join("docs/a.txt"), b"hello").unwrap();
+        std::fs::write(dir.path().join("docs/node_modules/x.js"), b"ignored").unwrap();
+        std::fs::write(dir.path().join("b.bin"), vec![0u8; 100]).unwrap();
+
+        let server = MockApiClient::new();
+        server.add_folder("d1", "Photos");
+        server.add_file("f1", "Photos/cat.jpg", &[1; 40]);
+        server.add_file("f2", "old.txt", b"gone");
+        server.add_file("f3", "build.tmp", b"ignored");
+        server.push_event("delete", "file", "f2", FileData::default());
+
+        let patterns = crate::ignore::default_patterns();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let estimate = runtime
+            .block_on(estimate(&server, dir.path(), &patterns))
+            .unwrap();
+        assert_eq!(
+            estimate.local,
+            Totals {
+                files: 2,
+                bytes: 105
+            }
+        );
+        assert_eq!(
+            estimate.remote,
+            Totals {
+                files: 1,
+                bytes: 40
+            }
+        );
+        // A listing this small gives no usable throughput
+        assert_eq!(estimate.seconds, None);
+    }
+
+    #[test]
+    fn test_throughput() {
+        assert_eq!(throughput(1024, Duration::from_secs(1)), None);
+        assert_eq!(
+            throughput(4 * 1024 * 1024, Duration::from_secs(2)),
+            Some(2 * 1024 * 1024)
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! FUSE frontend of the virtual drive (libfuse on Linux, macFUSE on macOS).
+//!
+//! Inode numbers are handed out on first sight of a path and stay valid for
+//! the lifetime of the mount. The mount is read-only; the kernel rejects
+//! writes with `EROFS` before they reach us.
+
+use crate::error::{Result, XynoxaError};
+use crate::vfs::{join, RemoteNode, VirtualDrive};
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry,
+    ReplyOpen, Request,
+};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Keeps the filesystem mounted; unmounts when dropped.
+pub type Session = fuser::BackgroundSession;
+
+/// How long the kernel may cache attributes and lookups.
+const TTL: Duration = Duration::from_secs(5);
+
+const ROOT_INO: u64 = 1;
+const BLOCK_SIZE: u32 = 4096;
+
+pub fn mount(drive: VirtualDrive, mount_point: &Path) -> Result<Session> {
+    let options = [
+        MountOption::RO,
+        MountOption::FSName("xynoxa".to_string()),
+        MountOption::Subtype("xynoxa".to_string()),
+        MountOption::NoExec,
+    ];
+    fuser::spawn_mount2(DriveFs::new(drive), mount_point, &options)
+        .map_err(|e| XynoxaError::Other(format!("Failed to mount virtual drive: {}", e)))
+}
+
+struct DriveFs {
+    drive: VirtualDrive,
+    // Index + 1 is the inode number
+    paths: Vec<String>,
+    inodes: HashMap<String, u64>,
+}
+
+impl DriveFs {
+    fn new(drive: VirtualDrive) -> Self {
+        let mut fs = Self {
+            drive,
+            paths: Vec::new(),
+            inodes: HashMap::new(),
+        };
+        fs.inode(String::new());
+        fs
+    }
+
+    fn inode(&mut self, path: String) -> u64 {
+        if let Some(ino) = self.inodes.get(&path) {
+            return *ino;
+        }
+        self.paths.push(path.clone());
+        let ino = self.paths.len() as u64;
+        self.inodes.insert(path, ino);
+        ino
+    }
+
+    fn path(&self, ino: u64) -> Option<String> {
+        self.paths.get(ino.checked_sub(1)? as usize).cloned()
+    }
+
+    fn attr(&self, req: &Request<'_>, ino: u64, node: &RemoteNode) -> FileAttr {
+        FileAttr {
+            ino,
+            size: node.size,
+            blocks: node.size.div_ceil(BLOCK_SIZE as u64),
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind: if node.is_dir {
+                FileType::Directory
+            } else {
+                FileType::RegularFile
+            },
+            perm: if node.is_dir { 0o555 } else { 0o444 },
+            nlink: if node.is_dir { 2 } else { 1 },
+            uid: req.uid(),
+            gid: req.gid(),
+            rdev: 0,
+            blksize: BLOCK_SIZE,
+            flags: 0,
+        }
+    }
+}
+
+impl Filesystem for DriveFs {
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let (Some(parent), Some(name)) = (self.path(parent), name.to_str()) else {
+            return reply.error(libc::ENOENT);
+        };
+        let path = join(&parent, name);
+        match self.drive.node(&path) {
+            Some(node) => {
+                let ino = self.inode(path);
+                reply.entry(&TTL, &self.attr(req, ino, &node), 0);
+            }
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.path(ino).and_then(|path| self.drive.node(&path)) {
+            Some(node) => reply.attr(&TTL, &self.attr(req, ino, &node)),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        match self.path(ino).and_then(|path| self.drive.node(&path)) {
+            Some(node) if node.is_dir => reply.error(libc::EISDIR),
+            Some(_) => reply.opened(0, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Some(path) = self.path(ino) else {
+            return reply.error(libc::ENOENT);
+        };
+        match self.drive.read(&path, offset.max(0) as u64, size as usize) {
+            Ok(data) => reply.data(&data),
+            Err(e) => {
+                tracing::error!("Virtual drive read of {} failed: {}", path, e);
+                reply.error(libc::EIO);
+            }
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(path) = self.path(ino) else {
+            return reply.error(libc::ENOENT);
+        };
+        // Listing the root is what file managers do on open; a good moment to
+        // pick up remote changes
+        if ino == ROOT_INO && offset == 0 {
+            self.drive.refresh_if_stale();
+        }
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (ino, FileType::Directory, "..".to_string()),
+        ];
+        for (name, node) in self.drive.list(&path) {
+            let child = self.inode(join(&path, &name));
+            let kind = if node.is_dir {
+                FileType::Directory
+            } else {
+                FileType::RegularFile
+            };
+            entries.push((child, kind, name));
+        }
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            // The offset passed back is that of the next entry
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
@@ -0,0 +1,181 @@
+//! User commands run on sync events, configured in [`HooksConfig`]. A hook
+//! runs through the shell with the event's details in `XYNOXA_*` environment
+//! variables, e.g. to re-index a photo library after downloads. Hooks are not
+//! waited for, so a slow script never holds up the sync.
+
+use crate::config::HooksConfig;
+use crate::sync::{SyncEvent, SyncState};
+use crate::transfers::Direction;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+pub struct Hooks {
+    config: HooksConfig,
+    root: PathBuf,
+    // Transfers of the running cycle, from its last progress event
+    transfers: Mutex<usize>,
+}
+
+/// A hook due for an event: its name, command and environment.
+type Invocation<'a> = (&'static str, &'a str, Vec<(&'static str, String)>);
+
+impl Hooks {
+    pub fn new(config: HooksConfig, root: PathBuf) -> Self {
+        Self {
+            config,
+            root,
+            transfers: Mutex::new(0),
+        }
+    }
+
+    /// Runs the hook configured for `event`, if any.
+    pub fn fire(&self, event: &SyncEvent) {
+        let Some((name, command, vars)) = self.invocation(event) else {
+            return;
+        };
+        tracing::info!("Running {} hook", name);
+        let mut child = shell(command);
+        child
+            .env("XYNOXA_EVENT", name)
+            .env("XYNOXA_SYNC_ROOT", &self.root)
+            .envs(vars)
+            .current_dir(&self.root)
+            .stdin(Stdio::null());
+        match child.spawn() {
+            Ok(mut child) => {
+                // Reaped in the background
+                std::thread::spawn(move || match child.wait() {
+                    Ok(status) if !status.success() => {
+                        tracing::warn!("{} hook exited with {}", name, status)
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("{} hook failed: {}", name, e),
+                });
+            }
+            Err(e) => tracing::warn!("Could not run {} hook: {}", name, e),
+        }
+    }
+
+    fn invocation(&self, event: &SyncEvent) -> Option<Invocation<'_>> {
+        match event {
+            SyncEvent::Progress { total, .. } => {
+                *self.transfers.lock().unwrap() = *total;
+                None
+            }
+            // Only cycles that transferred something, not every idle poll
+            SyncEvent::State {
+                state: SyncState::Idle,
+            } => {
+                let transfers = std::mem::take(&mut *self.transfers.lock().unwrap());
+                let command = hook(&self.config.on_sync_complete).filter(|_| transfers > 0)?;
+                let vars = vec![("XYNOXA_TRANSFERS", transfers.to_string())];
+                Some(("sync-complete", command, vars))
+            }
+            SyncEvent::Conflict { path, backup } => {
+                let vars = vec![
+                    ("XYNOXA_PATH", path.clone()),
+                    ("XYNOXA_BACKUP", backup.clone()),
+                ];
+                Some(("conflict", hook(&self.config.on_conflict)?, vars))
+            }
+            SyncEvent::Error { code, message } => {
+                let vars = vec![
+                    ("XYNOXA_ERROR_CODE", code.to_string()),
+                    ("XYNOXA_ERROR_MESSAGE", message.clone()),
+                ];
+                Some(("error", hook(&self.config.on_error)?, vars))
+            }
+            SyncEvent::FileFinished {
+                path,
+                direction: Direction::Download,
+                error: None,
+            } => {
+                let command = hook(&self.config.on_file_downloaded)?;
+                let pattern = self.config.downloaded_pattern.as_deref();
+                if !pattern.is_none_or(|p| crate::ignore::glob_matches(p, path)) {
+                    return None;
+                }
+                let file = self.root.join(path).to_string_lossy().to_string();
+                let vars = vec![("XYNOXA_PATH", path.clone()), ("XYNOXA_FILE", file)];
+                Some(("file-downloaded", command, vars))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The command of a hook, unless it is blank.
+fn hook(command: &Option<String>) -> Option<&str> {
+    command.as_deref().map(str::trim).filter(|c| !c.is_empty())
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let mut shell = Command::new("cmd");
+    shell
+        .arg("/C")
+        .raw_arg(command)
+        .creation_flags(CREATE_NO_WINDOW);
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hooks_match_events() {
+        let hooks = Hooks::new(
+            HooksConfig {
+                on_sync_complete: Some("notify-send done".to_string()),
+                on_conflict: Some(" ".to_string()),
+                on_error: None,
+                on_file_downloaded: Some("reindex".to_string()),
+                downloaded_pattern: Some("Photos/*.jpg".to_string()),
+            },
+            PathBuf::from("/home/me/Xynoxa"),
+        );
+        let idle = SyncEvent::State {
+            state: SyncState::Idle,
+        };
+        // Nothing transferred
+        assert!(hooks.invocation(&idle).is_none());
+        hooks.invocation(&SyncEvent::Progress { done: 2, total: 3 });
+        let (name, command, vars) = hooks.invocation(&idle).unwrap();
+        assert_eq!((name, command), ("sync-complete", "notify-send done"));
+        assert_eq!(vars, vec![("XYNOXA_TRANSFERS", "3".to_string())]);
+        assert!(hooks.invocation(&idle).is_none());
+
+        let downloaded = |path: &str| SyncEvent::FileFinished {
+            path: path.to_string(),
+            direction: Direction::Download,
+            error: None,
+        };
+        let (name, _, vars) = hooks.invocation(&downloaded("Photos/IMG_1.JPG")).unwrap();
+        assert_eq!(name, "file-downloaded");
+        assert_eq!(vars[0], ("XYNOXA_PATH", "Photos/IMG_1.JPG".to_string()));
+        assert!(hooks.invocation(&downloaded("Docs/a.jpg")).is_none());
+
+        // Blank and missing commands are skipped
+        let conflict = SyncEvent::Conflict {
+            path: "a.txt".to_string(),
+            backup: "a.conflict_backup".to_string(),
+        };
+        assert!(hooks.invocation(&conflict).is_none());
+        let error = SyncEvent::Error {
+            code: "network",
+            message: "offline".to_string(),
+        };
+        assert!(hooks.invocation(&error).is_none());
+    }
+}
@@ -0,0 +1,123 @@
+//! Translation of messages returned to the UI.
+//!
+//! Messages are looked up by their English text, so anything missing from a
+//! catalog still reads fine. Catalogs live in `locales/<language>.json` and map
+//! the English text to its translation; `{0}`, `{1}`, ... mark arguments.
+
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+}
+
+static CURRENT: RwLock<Locale> = RwLock::new(Locale::En);
+
+impl Locale {
+    /// Language of a tag like `de`, `de-AT` or `de_DE.UTF-8`. Unsupported
+    /// languages fall back to English.
+    pub fn parse(tag: &str) -> Locale {
+        let language = tag
+            .split(['-', '_', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "de" => Locale::De,
+            _ => Locale::En,
+        }
+    }
+
+    /// Language of the user session, from the POSIX locale variables.
+    pub fn system() -> Locale {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .map(|value| Locale::parse(&value))
+            .unwrap_or_default()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+        }
+    }
+
+    fn catalog(self) -> Option<&'static HashMap<String, String>> {
+        static DE: OnceLock<HashMap<String, String>> = OnceLock::new();
+        match self {
+            Locale::En => None,
+            Locale::De => {
+                Some(DE.get_or_init(|| parse_catalog(include_str!("../locales/de.json"))))
+            }
+        }
+    }
+}
+
+fn parse_catalog(json: &str) -> HashMap<String, String> {
+    serde_json::from_str(json).unwrap_or_else(|e| {
+        tracing::error!("Invalid message catalog: {}", e);
+        HashMap::new()
+    })
+}
+
+pub fn set_locale(locale: Locale) {
+    *CURRENT.write().unwrap() = locale;
+}
+
+pub fn current() -> Locale {
+    *CURRENT.read().unwrap()
+}
+
+/// `message` in the current language.
+pub fn tr(message: &str) -> String {
+    translate(current(), message)
+}
+
+/// `template` in the current language, with `{n}` replaced by `args[n]`.
+pub fn tr_args(template: &str, args: &[&str]) -> String {
+    let mut message = tr(template);
+    for (i, arg) in args.iter().enumerate() {
+        message = message.replace(&format!("{{{}}}", i), arg);
+    }
+    message
+}
+
+fn translate(locale: Locale, message: &str) -> String {
+    locale
+        .catalog()
+        .and_then(|catalog| catalog.get(message))
+        .cloned()
+        .unwrap_or_else(|| message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_tags() {
+        assert_eq!(Locale::parse("de_DE.UTF-8"), Locale::De);
+        assert_eq!(Locale::parse("de-AT"), Locale::De);
+        assert_eq!(Locale::parse("fr-FR"), Locale::En);
+        assert_eq!(Locale::parse(""), Locale::En);
+    }
+
+    #[test]
+    fn test_missing_translation_falls_back_to_english() {
+        assert_eq!(
+            translate(Locale::De, "Sync is not running"),
+            "Sync läuft nicht"
+        );
+        assert_eq!(translate(Locale::De, "Something new"), "Something new");
+        assert_eq!(
+            translate(Locale::En, "Sync is not running"),
+            "Sync is not running"
+        );
+    }
+}
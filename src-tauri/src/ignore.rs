@@ -0,0 +1,186 @@
+//! Files and folders the sync leaves alone: user-editable name patterns
+//! (editor temp files, `node_modules`, ...) plus Office lock files and the
+//! client's own files in the sync root, which are always skipped.
+
+use std::sync::RwLock;
+
+/// Patterns of a fresh install. Matched against file and folder names,
+/// ignoring case; `*` matches any run of characters and `?` a single one.
+pub const DEFAULT_PATTERNS: &[&str] = &[
+    ".git",
+    "node_modules",
+    "*.swp",
+    "*.tmp",
+    ".DS_Store",
+    "Thumbs.db",
+    "*.part",
+    "*.crdownload",
+    "*.partial",
+];
+
+pub fn default_patterns() -> Vec<String> {
+    DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect()
+}
+
+/// Trims the patterns and drops empty entries and duplicates.
+pub fn clean_patterns(patterns: Vec<String>) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for pattern in patterns {
+        let pattern = pattern.trim();
+        if !pattern.is_empty() && !cleaned.iter().any(|p| p == pattern) {
+            cleaned.push(pattern.to_string());
+        }
+    }
+    cleaned
+}
+
+/// The ignore patterns in use, shared by the scanner and the watcher.
+/// Replaced with [`IgnoreList::set`] while the sync runs.
+#[derive(Debug)]
+pub struct IgnoreList {
+    patterns: RwLock<Vec<Vec<char>>>,
+}
+
+impl Default for IgnoreList {
+    fn default() -> Self {
+        Self::new(&default_patterns())
+    }
+}
+
+impl IgnoreList {
+    pub fn new(patterns: &[String]) -> Self {
+        Self {
+            patterns: RwLock::new(compile(patterns)),
+        }
+    }
+
+    pub fn set(&self, patterns: &[String]) {
+        *self.patterns.write().unwrap() = compile(patterns);
+    }
+
+    /// Whether a file or folder called `name` is skipped.
+    pub fn matches_name(&self, name: &str) -> bool {
+        if is_internal(name) {
+            return true;
+        }
+        let name: Vec<char> = name.chars().flat_map(char::to_lowercase).collect();
+        self.patterns
+            .read()
+            .unwrap()
+            .iter()
+            .any(|pattern| glob_match(pattern, &name))
+    }
+
+    /// Whether relative `path` or one of its parent folders is skipped.
+    pub fn matches_path(&self, path: &str) -> bool {
+        path.split('/').any(|name| self.matches_name(name))
+    }
+}
+
+/// Name of the document an Office lock file belongs to. Microsoft Office
+/// (`~$port.docx`) may replace the start of the name, so the document's name
+/// ends with the result; LibreOffice (`.~lock.Report.docx#`) keeps it whole.
+pub fn lock_file_target(name: &str) -> Option<&str> {
+    let target = match name.strip_prefix(".~lock.") {
+        Some(rest) => rest.strip_suffix('#')?,
+        None => name.strip_prefix("~$")?,
+    };
+    (!target.is_empty()).then_some(target)
+}
+
+/// Lock files, the client's database, its write probe and partially
+/// transferred files.
+fn is_internal(name: &str) -> bool {
+    lock_file_target(name).is_some()
+        || crate::sync::is_database_file(name)
+        || name == crate::sync::WRITE_PROBE
+        || name.ends_with(crate::api::PARTIAL_SUFFIX)
+        || name.ends_with(crate::lan::PARTIAL_SUFFIX)
+}
+
+/// Whether `text` matches glob `pattern`, ignoring case. `*` also matches
+/// `/`, so `Photos/*.jpg` covers subfolders.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let lower = |s: &str| -> Vec<char> { s.chars().flat_map(char::to_lowercase).collect() };
+    glob_match(&lower(pattern), &lower(text))
+}
+
+fn compile(patterns: &[String]) -> Vec<Vec<char>> {
+    patterns
+        .iter()
+        .map(|p| p.chars().flat_map(char::to_lowercase).collect())
+        .collect()
+}
+
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it currently covers
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_patterns() {
+        let ignore = IgnoreList::default();
+        for name in [
+            "~$Report.docx",
+            ".~lock.Report.odt#",
+            ".report.txt.swp",
+            "thumbs.db",
+            ".DS_Store",
+            "video.mp4.crdownload",
+            "a.pdf.xynoxa-part",
+            ".xynoxa.db-wal",
+            ".xynoxa-3f2a9c01b7e4-77d0e1a2c3b4.db",
+        ] {
+            assert!(ignore.matches_name(name), "{}", name);
+        }
+        for name in ["Report.docx", "tmp", "swp.txt", "partial.txt"] {
+            assert!(!ignore.matches_name(name), "{}", name);
+        }
+        assert!(ignore.matches_path("web/node_modules/react/index.js"));
+        assert!(!ignore.matches_path("web/src/index.js"));
+
+        ignore.set(&["build".to_string(), "*.o".to_string()]);
+        assert!(!ignore.matches_name("node_modules"));
+        assert!(ignore.matches_path("build/main.o"));
+        assert!(ignore.matches_name("main.O"));
+        // Never editable
+        assert!(ignore.matches_name(".xynoxa.db"));
+        assert!(ignore.matches_name("~$port.docx"));
+    }
+
+    #[test]
+    fn test_lock_file_target() {
+        assert_eq!(lock_file_target("~$port.docx"), Some("port.docx"));
+        assert_eq!(lock_file_target(".~lock.Report.odt#"), Some("Report.odt"));
+        assert_eq!(lock_file_target(".~lock.Report.odt"), None);
+        assert_eq!(lock_file_target("~$"), None);
+        assert_eq!(lock_file_target("Report.docx"), None);
+    }
+}
@@ -0,0 +1,159 @@
+//! Shares the disk between hashing and transfers. Hashing a whole tree
+//! (scans, integrity checks, auto-upload) reads as fast as the disk allows,
+//! so at most [`MAX_CONCURRENT_HASHES`] files are hashed at once, only one
+//! while a transfer runs, and hash reads pause briefly to let transfers in.
+
+use std::io::Read;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+pub const MAX_CONCURRENT_HASHES: usize = 2;
+/// Bytes hashed between pauses while transfers are active.
+const YIELD_BYTES: u64 = 4 * 1024 * 1024;
+const YIELD_PAUSE: Duration = Duration::from_millis(10);
+
+static SCHEDULER: IoScheduler = IoScheduler::new(MAX_CONCURRENT_HASHES);
+
+/// Waits for a hash slot of the process-wide scheduler.
+pub fn hash_permit() -> HashPermit<'static> {
+    SCHEDULER.hash_permit()
+}
+
+/// Marks a transfer as running until the guard is dropped.
+pub fn transfer() -> TransferGuard<'static> {
+    SCHEDULER.transfer()
+}
+
+/// `reader` pausing every [`YIELD_BYTES`] while transfers are active.
+pub fn throttled<R: Read>(reader: R) -> Throttled<'static, R> {
+    Throttled {
+        reader,
+        scheduler: &SCHEDULER,
+        since_yield: 0,
+    }
+}
+
+struct State {
+    hashing: usize,
+    transfers: usize,
+}
+
+pub struct IoScheduler {
+    state: Mutex<State>,
+    changed: Condvar,
+    max_hashes: usize,
+}
+
+impl IoScheduler {
+    pub const fn new(max_hashes: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                hashing: 0,
+                transfers: 0,
+            }),
+            changed: Condvar::new(),
+            max_hashes,
+        }
+    }
+
+    pub fn hash_permit(&self) -> HashPermit<'_> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let limit = if state.transfers > 0 {
+                1
+            } else {
+                self.max_hashes
+            };
+            if state.hashing < limit {
+                break;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+        state.hashing += 1;
+        HashPermit { scheduler: self }
+    }
+
+    pub fn transfer(&self) -> TransferGuard<'_> {
+        self.state.lock().unwrap().transfers += 1;
+        TransferGuard { scheduler: self }
+    }
+
+    pub fn transfers_active(&self) -> bool {
+        self.state.lock().unwrap().transfers > 0
+    }
+
+    fn release(&self, update: impl FnOnce(&mut State)) {
+        update(&mut self.state.lock().unwrap());
+        self.changed.notify_all();
+    }
+}
+
+pub struct HashPermit<'a> {
+    scheduler: &'a IoScheduler,
+}
+
+impl Drop for HashPermit<'_> {
+    fn drop(&mut self) {
+        self.scheduler.release(|state| state.hashing -= 1);
+    }
+}
+
+pub struct TransferGuard<'a> {
+    scheduler: &'a IoScheduler,
+}
+
+impl Drop for TransferGuard<'_> {
+    fn drop(&mut self) {
+        self.scheduler.release(|state| state.transfers -= 1);
+    }
+}
+
+pub struct Throttled<'a, R> {
+    reader: R,
+    scheduler: &'a IoScheduler,
+    since_yield: u64,
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.since_yield >= YIELD_BYTES {
+            self.since_yield = 0;
+            if self.scheduler.transfers_active() {
+                std::thread::sleep(YIELD_PAUSE);
+            }
+        }
+        let read = self.reader.read(buf)?;
+        self.since_yield += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_one_hash_at_a_time_during_transfers() {
+        let scheduler = IoScheduler::new(2);
+        let acquired = AtomicBool::new(false);
+        let first = scheduler.hash_permit();
+        // Without transfers a second hash may run
+        drop(scheduler.hash_permit());
+
+        let transfer = scheduler.transfer();
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                let _permit = scheduler.hash_permit();
+                acquired.store(true, Ordering::SeqCst);
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!acquired.load(Ordering::SeqCst));
+            drop(first);
+            waiter.join().unwrap();
+        });
+        assert!(acquired.load(Ordering::SeqCst));
+        drop(transfer);
+        assert!(!scheduler.transfers_active());
+    }
+}
@@ -0,0 +1,436 @@
+//! Direct transfer of file content between the user's devices on the same
+//! network. The server only coordinates: it hands every device of the account
+//! the same LAN key (`devices.lanKey`) and announces content hashes through
+//! `sync.pull`. Devices announce themselves via mDNS as `_xynoxa-peer._tcp`
+//! and serve files they hold by content hash; the downloading side verifies
+//! the hash, so a peer can neither substitute content nor learn anything
+//! without the key.
+//!
+//! Connection: both sides send a random 32-byte nonce, HKDF-SHA256 derives one
+//! ChaCha20-Poly1305 key per direction from the LAN key and both nonces. Every
+//! frame is a big-endian u32 length followed by the sealed payload, nonces are
+//! frame counters. The client sends the hash, the server answers with the
+//! size (empty if it does not have the file) followed by data frames.
+
+use crate::error::{Result, XynoxaError};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const SERVICE_TYPE: &str = "_xynoxa-peer._tcp.local.";
+/// Appended to the name of a file while it is received from a peer.
+pub const PARTIAL_SUFFIX: &str = ".xynoxa-lan";
+
+const NONCE_LEN: usize = 32;
+/// Plaintext bytes per data frame
+const CHUNK_SIZE: usize = 256 * 1024;
+/// Sealed frames are at most a chunk plus the Poly1305 tag
+const MAX_FRAME: usize = CHUNK_SIZE + 16;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const IO_TIMEOUT: Duration = Duration::from_secs(15);
+/// Peers served at the same time; further connections are closed right away
+const MAX_CONNECTIONS: usize = 8;
+
+/// Local path of a fully synced file with the given content hash.
+pub type ContentLookup = Box<dyn Fn(&str) -> Option<PathBuf> + Send + Sync>;
+
+/// Advertises this device, serves its files to peers and fetches from them.
+/// Stops announcing and serving when dropped.
+pub struct LanTransfer {
+    key: [u8; 32],
+    instance: String,
+    mdns: ServiceDaemon,
+    port: u16,
+    shutdown: Arc<AtomicBool>,
+    /// Peers of the same account by mDNS full name
+    peers: Arc<Mutex<HashMap<String, Vec<SocketAddr>>>>,
+}
+
+impl LanTransfer {
+    /// `key_hex` is the account's LAN key as handed out by the server.
+    pub fn start(key_hex: &str, lookup: ContentLookup) -> Result<Self> {
+        let key: [u8; 32] = hex::decode(key_hex.trim())
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| {
+                XynoxaError::Decode("LAN key must be 32 hex-encoded bytes".to_string())
+            })?;
+        let tag = account_tag(&key);
+
+        let listener = TcpListener::bind("0.0.0.0:0")?;
+        let port = listener.local_addr()?.port();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        spawn_server(listener, key, lookup, Arc::clone(&shutdown))?;
+
+        let mut random = [0u8; 8];
+        getrandom::getrandom(&mut random)
+            .map_err(|e| XynoxaError::Other(format!("No randomness: {}", e)))?;
+        let instance = format!("xynoxa-{}", hex::encode(random));
+        let mdns = ServiceDaemon::new().map_err(mdns_error)?;
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            &instance,
+            &format!("{}.local.", instance),
+            "",
+            port,
+            &[("tag", tag.as_str())][..],
+        )
+        .map_err(mdns_error)?
+        .enable_addr_auto();
+        mdns.register(info).map_err(mdns_error)?;
+
+        let peers: Arc<Mutex<HashMap<String, Vec<SocketAddr>>>> = Arc::default();
+        let receiver = mdns.browse(SERVICE_TYPE).map_err(mdns_error)?;
+        let own_name = format!("{}.{}", instance, SERVICE_TYPE);
+        let known = Arc::clone(&peers);
+        std::thread::Builder::new()
+            .name("lan-browser".to_string())
+            .spawn(move || {
+                while let Ok(event) = receiver.recv() {
+                    match event {
+                        ServiceEvent::ServiceResolved(info)
+                            if info.get_fullname() != own_name
+                                && info.get_property_val_str("tag") == Some(tag.as_str()) =>
+                        {
+                            let addrs: Vec<SocketAddr> = info
+                                .get_addresses()
+                                .iter()
+                                .map(|ip| SocketAddr::new(*ip, info.get_port()))
+                                .collect();
+                            tracing::info!("LAN peer {} at {:?}", info.get_fullname(), addrs);
+                            known
+                                .lock()
+                                .unwrap()
+                                .insert(info.get_fullname().to_string(), addrs);
+                        }
+                        ServiceEvent::ServiceRemoved(_, fullname) => {
+                            known.lock().unwrap().remove(&fullname);
+                        }
+                        _ => {}
+                    }
+                }
+            })?;
+
+        tracing::info!("LAN transfer listening on port {}", port);
+        Ok(Self {
+            key,
+            instance,
+            mdns,
+            port,
+            shutdown,
+            peers,
+        })
+    }
+
+    pub fn has_peers(&self) -> bool {
+        !self.peers.lock().unwrap().is_empty()
+    }
+
+    /// Fetches the content with `hash` from any peer into `dest`. Returns false
+    /// if no peer had it; `dest` is only replaced by verified content.
+    pub fn fetch(&self, hash: &str, dest: &Path) -> Result<bool> {
+        let addrs: Vec<SocketAddr> = self
+            .peers
+            .lock()
+            .unwrap()
+            .values()
+            .flatten()
+            .copied()
+            .collect();
+        for addr in addrs {
+            match self.fetch_from(addr, hash, dest) {
+                Ok(true) => {
+                    tracing::info!("Fetched {} from LAN peer {}", hash, addr);
+                    return Ok(true);
+                }
+                Ok(false) => {}
+                Err(e) => tracing::debug!("LAN peer {} failed: {}", addr, e),
+            }
+        }
+        Ok(false)
+    }
+
+    fn fetch_from(&self, addr: SocketAddr, hash: &str, dest: &Path) -> Result<bool> {
+        let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut session = Session::handshake(&mut stream, &self.key, Role::Client)?;
+        session.send(&mut stream, hash.as_bytes())?;
+        let header = session.receive(&mut stream)?;
+        let Ok(size) = <[u8; 8]>::try_from(header.as_slice()).map(u64::from_be_bytes) else {
+            return Ok(false);
+        };
+
+        let mut partial = dest.as_os_str().to_owned();
+        partial.push(PARTIAL_SUFFIX);
+        let partial = PathBuf::from(partial);
+        let result = (|| -> Result<bool> {
+            let mut file = std::fs::File::create(&partial)?;
+            let mut hasher = Sha256::new();
+            let mut received = 0u64;
+            while received < size {
+                let chunk = session.receive(&mut stream)?;
+                if chunk.is_empty() {
+                    return Err(XynoxaError::Decode("LAN transfer ended early".to_string()));
+                }
+                received += chunk.len() as u64;
+                hasher.update(&chunk);
+                file.write_all(&chunk)?;
+            }
+            file.sync_all()?;
+            Ok(received == size && hex::encode(hasher.finalize()) == hash)
+        })();
+        match result {
+            Ok(true) => {
+                std::fs::rename(&partial, dest)?;
+                Ok(true)
+            }
+            other => {
+                let _ = std::fs::remove_file(&partial);
+                other
+            }
+        }
+    }
+}
+
+impl Drop for LanTransfer {
+    fn drop(&mut self) {
+        let _ = self
+            .mdns
+            .unregister(&format!("{}.{}", self.instance, SERVICE_TYPE));
+        let _ = self.mdns.shutdown();
+        // The server thread checks the flag once `accept` returns
+        self.shutdown.store(true, Ordering::Relaxed);
+        let _ = TcpStream::connect_timeout(
+            &SocketAddr::from(([127, 0, 0, 1], self.port)),
+            CONNECT_TIMEOUT,
+        );
+    }
+}
+
+/// Accepts peers on `listener` until `shutdown` is set and one more
+/// connection arrives, serving up to [`MAX_CONNECTIONS`] at a time.
+fn spawn_server(
+    listener: TcpListener,
+    key: [u8; 32],
+    lookup: ContentLookup,
+    shutdown: Arc<AtomicBool>,
+) -> Result<std::thread::JoinHandle<()>> {
+    let lookup = Arc::new(lookup);
+    let active = Arc::new(AtomicUsize::new(0));
+    let server = std::thread::Builder::new()
+        .name("lan-server".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                if shutdown.load(Ordering::Relaxed) {
+                    break;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                if active.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                    active.fetch_sub(1, Ordering::Relaxed);
+                    tracing::debug!("Too many LAN peers, refusing {:?}", stream.peer_addr());
+                    continue;
+                }
+                let lookup = Arc::clone(&lookup);
+                let active = Arc::clone(&active);
+                std::thread::spawn(move || {
+                    let peer = stream.peer_addr().ok();
+                    if let Err(e) = serve(stream, &key, &lookup) {
+                        tracing::debug!("LAN transfer to {:?} failed: {}", peer, e);
+                    }
+                    active.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+            tracing::info!("LAN transfer stopped listening");
+        })?;
+    Ok(server)
+}
+
+/// Answers one request; files are served only by their content hash.
+fn serve(mut stream: TcpStream, key: &[u8; 32], lookup: &ContentLookup) -> Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut session = Session::handshake(&mut stream, key, Role::Server)?;
+    let request = session.receive(&mut stream)?;
+    let hash = String::from_utf8_lossy(&request).to_string();
+    let file = (hash.len() == 64)
+        .then(|| lookup(&hash))
+        .flatten()
+        .and_then(|path| std::fs::File::open(path).ok());
+    let Some(mut file) = file else {
+        return session.send(&mut stream, &[]);
+    };
+    let size = file.metadata()?.len();
+    let _transfer = crate::io_scheduler::transfer();
+    session.send(&mut stream, &size.to_be_bytes())?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut sent = 0u64;
+    while sent < size {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        session.send(&mut stream, &buffer[..read])?;
+        sent += read as u64;
+    }
+    // Truncated while serving; the client notices the short read
+    session.send(&mut stream, &[])
+}
+
+/// Identifies peers of the same account without revealing the key.
+fn account_tag(key: &[u8; 32]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"xynoxa-lan-tag");
+    hasher.update(key);
+    hex::encode(&hasher.finalize()[..8])
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    Client,
+    Server,
+}
+
+/// Encrypted channel of one connection.
+struct Session {
+    sealer: ChaCha20Poly1305,
+    opener: ChaCha20Poly1305,
+    sent: u64,
+    received: u64,
+}
+
+impl Session {
+    fn handshake(stream: &mut TcpStream, key: &[u8; 32], role: Role) -> Result<Self> {
+        let mut own = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut own)
+            .map_err(|e| XynoxaError::Other(format!("No randomness: {}", e)))?;
+        stream.write_all(&own)?;
+        let mut other = [0u8; NONCE_LEN];
+        stream.read_exact(&mut other)?;
+        let (client, server) = match role {
+            Role::Client => (own, other),
+            Role::Server => (other, own),
+        };
+        Ok(Self::derive(key, &client, &server, role))
+    }
+
+    fn derive(key: &[u8; 32], client: &[u8], server: &[u8], role: Role) -> Self {
+        let salt = [client, server].concat();
+        let hkdf = Hkdf::<Sha256>::new(Some(&salt), key);
+        let mut to_server = [0u8; 32];
+        let mut to_client = [0u8; 32];
+        hkdf.expand(b"xynoxa-lan client", &mut to_server)
+            .expect("32 bytes is a valid HKDF length");
+        hkdf.expand(b"xynoxa-lan server", &mut to_client)
+            .expect("32 bytes is a valid HKDF length");
+        let (seal, open) = match role {
+            Role::Client => (to_server, to_client),
+            Role::Server => (to_client, to_server),
+        };
+        Self {
+            sealer: ChaCha20Poly1305::new(Key::from_slice(&seal)),
+            opener: ChaCha20Poly1305::new(Key::from_slice(&open)),
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    fn send(&mut self, stream: &mut impl Write, plaintext: &[u8]) -> Result<()> {
+        let sealed = self
+            .sealer
+            .encrypt(&frame_nonce(self.sent), plaintext)
+            .map_err(|_| XynoxaError::Other("LAN encryption failed".to_string()))?;
+        self.sent += 1;
+        stream.write_all(&(sealed.len() as u32).to_be_bytes())?;
+        stream.write_all(&sealed)?;
+        Ok(())
+    }
+
+    fn receive(&mut self, stream: &mut impl Read) -> Result<Vec<u8>> {
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_FRAME {
+            return Err(XynoxaError::Decode("LAN frame too large".to_string()));
+        }
+        let mut sealed = vec![0u8; len];
+        stream.read_exact(&mut sealed)?;
+        let plaintext = self
+            .opener
+            .decrypt(&frame_nonce(self.received), sealed.as_slice())
+            .map_err(|_| {
+                XynoxaError::Decode(
+                    "LAN frame could not be opened; the peer may not share the account key"
+                        .to_string(),
+                )
+            })?;
+        self.received += 1;
+        Ok(plaintext)
+    }
+}
+
+fn frame_nonce(counter: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    *Nonce::from_slice(&nonce)
+}
+
+fn mdns_error(e: mdns_sd::Error) -> XynoxaError {
+    XynoxaError::Other(format!("LAN announcement failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_only_open_with_the_same_key() {
+        let key = [7u8; 32];
+        let mut client = Session::derive(&key, &[1; 32], &[2; 32], Role::Client);
+        let mut server = Session::derive(&key, &[1; 32], &[2; 32], Role::Server);
+        let mut wire = Vec::new();
+        client.send(&mut wire, b"hello").unwrap();
+        client.send(&mut wire, b"again").unwrap();
+        let mut reader = wire.as_slice();
+        assert_eq!(server.receive(&mut reader).unwrap(), b"hello");
+        assert_eq!(server.receive(&mut reader).unwrap(), b"again");
+
+        let mut stranger = Session::derive(&[8u8; 32], &[1; 32], &[2; 32], Role::Server);
+        let mut wire = Vec::new();
+        client.send(&mut wire, b"secret").unwrap();
+        assert!(matches!(
+            stranger.receive(&mut wire.as_slice()),
+            Err(XynoxaError::Decode(_))
+        ));
+        assert_ne!(account_tag(&key), account_tag(&[8u8; 32]));
+    }
+
+    #[test]
+    fn test_server_stops_on_shutdown() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let server = spawn_server(
+            listener,
+            [7u8; 32],
+            Box::new(|_| None),
+            Arc::clone(&shutdown),
+        )
+        .unwrap();
+
+        shutdown.store(true, Ordering::Relaxed);
+        TcpStream::connect(addr).unwrap();
+        server.join().unwrap();
+    }
+}
@@ -1,25 +1,107 @@
 pub mod api;
+pub mod auto_upload;
+pub mod backup;
+pub mod cloud_files;
 pub mod config;
+pub mod conflicts;
+pub mod content_type;
+pub mod control;
 pub mod db;
+pub mod diagnostics;
+pub mod discovery;
+pub mod error;
+pub mod estimate;
+#[cfg(all(feature = "virtual-drive", unix))]
+mod fuse;
+pub mod hooks;
+pub mod i18n;
+pub mod ignore;
+pub mod io_scheduler;
+pub mod lan;
+pub mod logging;
+pub mod migration;
+#[cfg(any(test, feature = "demo"))]
+pub mod mock_api;
+pub mod network;
+pub mod pairing;
+pub mod power;
+pub mod report;
+pub mod sandbox;
+pub mod service;
 pub mod sync;
+pub mod telemetry;
+pub mod thumbnails;
+pub mod transfers;
+pub mod trpc;
+pub mod updater;
+pub mod uploads;
+pub mod vfs;
+pub mod watcher;
 
 use keyring::Entry;
-use std::path::PathBuf;
-use std::sync::Mutex;
-use sync::SyncHandle;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use sync::{
+    BandwidthUsage, RemoteSearchResult, RepairAction, SearchResult, SyncEvent, SyncHandle,
+    SyncOptions, SyncState, SyncStatus,
+};
 use tauri::State;
 
-use crate::config::{AppConfig, ConfigManager};
+use crate::api::{
+    ClientOptions, Device, FileVersion, ServerInfo, ServerProbe, SessionInfo, TrashedFile,
+    XynoxaClient,
+};
+use crate::auto_upload::AutoUploadHandle;
+use crate::backup::BackupHandle;
+use crate::config::{
+    expand_sync_path, AppConfig, AutoUploadSource, CloseAction, ConfigManager, ConfigOverrides,
+    ConfigPatch, FieldUpdate, VirtualDriveConfig, WatchMode,
+};
+use crate::conflicts::{Conflict, Resolution};
+use crate::db::{BackupSnapshot, DbKey, FolderHealth, KnownIssue, MaintenanceReport, SyncIssue};
+use crate::diagnostics::DiagnosticsReport;
+use crate::error::{FieldError, Result, XynoxaError};
+use crate::estimate::InitialSyncEstimate;
+use crate::i18n::Locale;
+use crate::pairing::{PairingFinished, PairingPrompt};
+use crate::telemetry::TelemetryReport;
+use crate::thumbnails::ThumbnailCache;
+use crate::transfers::Transfer;
+use crate::updater::UpdateInfo;
+use crate::uploads::UploadProgress;
+use crate::vfs::{MountedDrive, VirtualDrive};
+use tauri::image::Image;
 use tauri::menu::{Menu, MenuItem};
 use tauri::tray::TrayIconBuilder;
-use tauri::{Manager, WindowEvent};
+use tauri::window::{ProgressBarState, ProgressBarStatus};
+use tauri::{Emitter, Manager, Theme, WebviewUrl, WebviewWindowBuilder, WindowEvent};
+use tauri_plugin_autostart::ManagerExt;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_dialog::DialogExt;
+use tauri_plugin_notification::NotificationExt;
+use tauri_plugin_opener::OpenerExt;
 
 const KEYRING_SERVICE_NEW: &str = "xynoxa-desktop-client";
 const KEYRING_SERVICE_LEGACY: &str = "xynoxa-desktop-client";
+/// Users are warned this many days before their token expires.
+const TOKEN_EXPIRY_WARNING_DAYS: i64 = 3;
+/// Maximum number of `search_remote` results.
+const REMOTE_SEARCH_LIMIT: u32 = 100;
+const SESSION_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(12 * 60 * 60);
 
 struct AppState {
     sync_engine: Mutex<Option<SyncHandle>>, // Renamed type
     config_manager: Mutex<Option<ConfigManager>>,
+    auto_upload: Mutex<Option<AutoUploadHandle>>,
+    backup: Mutex<Option<BackupHandle>>,
+    // Without a tray, hiding the window would leave no way back to it
+    tray_available: AtomicBool,
+    /// Id of the pairing the setup wizard is waiting for
+    pairing: Mutex<Option<String>>,
+    /// Token waiting for its second factor; saved once that is answered
+    pending_login: Mutex<Option<String>>,
+    virtual_drive: Mutex<Option<MountedDrive>>,
 }
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
@@ -29,11 +111,112 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-fn login(state: State<AppState>, token: String) -> Result<String, String> {
+async fn login(app: tauri::AppHandle, state: State<'_, AppState>, token: String) -> Result<String> {
     if !(token.starts_with("xyn-") || token.starts_with("syn-")) {
-        return Err("Invalid token format. Token must start with 'xyn-'.".to_string());
+        return Err(XynoxaError::Auth(
+            "Invalid token format. Token must start with 'xyn-'.".to_string(),
+        ));
     }
 
+    verify_login(&app, &state, token, None).await?;
+    Ok(i18n::tr("Login successful"))
+}
+
+/// Answers the challenge sent as `auth://two-factor` and finishes signing in.
+#[tauri::command]
+async fn submit_second_factor(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    challenge_id: String,
+    method: String,
+    response: serde_json::Value,
+) -> Result<String> {
+    let pending = state
+        .pending_login
+        .lock()
+        .map_err(|_| "Lock fail")?
+        .clone()
+        .ok_or_else(|| {
+            XynoxaError::Auth("No sign-in is waiting for a second factor".to_string())
+        })?;
+    let token = token_client(&state, pending.clone())?
+        .verify_second_factor(&challenge_id, &method, response)
+        .await?
+        .unwrap_or(pending);
+    verify_login(&app, &state, token, None).await?;
+    Ok(i18n::tr("Login successful"))
+}
+
+/// Registers this device, the first authenticated call with a new token, and
+/// learns who signed in. A pairing already registered the device, so its
+/// `device_id` is only stored. The token is only saved once it is accepted:
+/// after a second-factor challenge, which is passed to the UI as
+/// `auth://two-factor`, it waits for [`submit_second_factor`]. Other failures
+/// do not block signing in.
+async fn verify_login(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    token: String,
+    device_id: Option<String>,
+) -> Result<()> {
+    let client = token_client(state, token.clone())?;
+    let registered = match device_id {
+        Some(device_id) => store_device_id(state, device_id),
+        None => register_device(state, &client).await.map(|_| ()),
+    };
+    match registered {
+        Ok(()) => {
+            finish_login(state, token)?;
+            match client.session_info().await {
+                Ok(session) => remember_account(state, &session)?,
+                Err(e) => tracing::warn!("Could not look up the signed-in user: {}", e),
+            }
+            Ok(())
+        }
+        Err(XynoxaError::TwoFactorRequired(challenge)) => {
+            tracing::info!("Second factor required: {:?}", challenge.methods);
+            *state.pending_login.lock().map_err(|_| "Lock fail")? = Some(token);
+            if let Err(e) = app.emit("auth://two-factor", &challenge) {
+                tracing::warn!("Failed to emit auth://two-factor: {}", e);
+            }
+            Err(XynoxaError::TwoFactorRequired(challenge))
+        }
+        Err(e) => {
+            tracing::warn!("Device registration failed: {}", e);
+            finish_login(state, token)
+        }
+    }
+}
+
+/// Saves the accepted `token` and forgets one waiting for a second factor.
+fn finish_login(state: &AppState, token: String) -> Result<()> {
+    *state.pending_login.lock().map_err(|_| "Lock fail")? = None;
+    store_token(state, token)
+}
+
+/// Stores the id of the signed-in user, which names the account's local
+/// state from now on, see [`AppConfig::account_key`].
+fn remember_account(state: &AppState, session: &SessionInfo) -> Result<()> {
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    let known = cm
+        .config
+        .lock()
+        .map_err(|_| "Lock fail")?
+        .account_id
+        .clone();
+    if known.as_deref() == Some(session.user.id.as_str()) {
+        return Ok(());
+    }
+    tracing::info!("Signed in as user {}", session.user.id);
+    cm.update(ConfigPatch {
+        account_id: FieldUpdate::Set(session.user.id.clone()),
+        ..Default::default()
+    })
+}
+
+/// Saves a new auth token.
+fn store_token(state: &AppState, token: String) -> Result<()> {
     // Save to Keyring (Best Effort)
     if let Ok(entry) = Entry::new(KEYRING_SERVICE_NEW, "auth-token") {
         let _ = entry.set_password(&token);
@@ -45,185 +228,2414 @@ fn login(state: State<AppState>, token: String) -> Result<String, String> {
     // Save to Config (User Request)
     let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
     let cm = raw.as_ref().ok_or("Config not init")?;
-    cm.update(None, None, Some(token), None)?;
+    cm.update(ConfigPatch {
+        auth_token: FieldUpdate::Set(token),
+        ..Default::default()
+    })
+}
+
+/// Starts signing in by approving a code in the browser. The outcome arrives
+/// as a `pairing://finished` event.
+#[tauri::command]
+async fn start_pairing(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    server_url: String,
+) -> Result<PairingPrompt> {
+    let config = current_config(&state)?;
+    let client = XynoxaClient::anonymous(server_url, &ClientOptions::from_config(&config));
+    let name = config
+        .device_name
+        .unwrap_or_else(config::default_device_name);
+    let request = client.start_pairing(&name).await?;
+    tracing::info!("Pairing started, code {}", request.code);
+    *state.pairing.lock().map_err(|_| "Lock fail")? = Some(request.pairing_id.clone());
+
+    let prompt = PairingPrompt::new(request.clone());
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AppState>();
+        let is_active = || {
+            state
+                .pairing
+                .lock()
+                .map(|active| active.as_deref() == Some(request.pairing_id.as_str()))
+                .unwrap_or(false)
+        };
+        let Some(result) = pairing::wait_for_approval(&client, &request, is_active).await else {
+            tracing::info!("Pairing cancelled.");
+            return;
+        };
+        if let Ok(mut active) = state.pairing.lock() {
+            *active = None;
+        }
+        let result = match result {
+            Ok(approval) => verify_login(&app, &state, approval.token, approval.device_id).await,
+            Err(e) => Err(e),
+        };
+        let finished = PairingFinished {
+            approved: result.is_ok(),
+            error: result.err().map(|e| e.localized_message()),
+        };
+        tracing::info!("Pairing finished: {:?}", finished);
+        if let Err(e) = app.emit("pairing://finished", &finished) {
+            tracing::warn!("Failed to emit pairing://finished: {}", e);
+        }
+    });
+    Ok(prompt)
+}
+
+#[tauri::command]
+fn cancel_pairing(state: State<AppState>) -> Result<()> {
+    *state.pairing.lock().map_err(|_| "Lock fail")? = None;
+    Ok(())
+}
+
+#[tauri::command]
+fn logout(state: State<AppState>) -> Result<()> {
+    clear_credentials(&state)
+}
+
+/// Removes the token from keyring and config and forgets the device id.
+fn clear_credentials(state: &AppState) -> Result<()> {
+    // Clear Keyring, unless in demo mode: that holds the real account's token
+    if config::demo_dir().is_none() {
+        if let Ok(entry) = Entry::new(KEYRING_SERVICE_NEW, "auth-token") {
+            let _ = entry.delete_credential();
+        }
+        if let Ok(entry) = Entry::new(KEYRING_SERVICE_LEGACY, "auth-token") {
+            let _ = entry.delete_credential();
+        }
+    }
+
+    *state.pending_login.lock().map_err(|_| "Lock fail")? = None;
+
+    // Clear Config
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    cm.update(ConfigPatch {
+        auth_token: FieldUpdate::Clear,
+        device_id: FieldUpdate::Clear,
+        ..Default::default()
+    })?;
+
+    Ok(())
+}
+
+/// Registers this client under its device name and stores the returned id.
+async fn register_device(state: &AppState, client: &XynoxaClient) -> Result<Device> {
+    let name = current_config(state)?
+        .device_name
+        .unwrap_or_else(config::default_device_name);
+    let device = client.register_device(&name).await?;
+    tracing::info!("Registered device {} as {}", name, device.id);
+    store_device_id(state, device.id.clone())?;
+    Ok(device)
+}
+
+fn store_device_id(state: &AppState, device_id: String) -> Result<()> {
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    cm.update(ConfigPatch {
+        device_id: FieldUpdate::Set(device_id),
+        ..Default::default()
+    })
+}
+
+#[tauri::command]
+async fn list_devices(state: State<'_, AppState>) -> Result<Vec<Device>> {
+    api_client(&state)?.list_devices().await
+}
+
+/// Revokes a device of the account. Revoking this device also signs it out.
+#[tauri::command]
+async fn revoke_device(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    device_id: String,
+) -> Result<()> {
+    api_client(&state)?.revoke_device(&device_id).await?;
+    if current_config(&state)?.device_id.as_deref() == Some(device_id.as_str()) {
+        handle_session_revoked(&app);
+    }
+    Ok(())
+}
+
+/// The server no longer accepts our token (device revoked, token deleted):
+/// stop syncing, sign out, and tell the user.
+fn handle_session_revoked(app: &tauri::AppHandle) {
+    tracing::warn!("Session revoked by server. Signing out.");
+    let state = app.state::<AppState>();
+    if let Ok(mut guard) = state.auto_upload.lock() {
+        *guard = None;
+    }
+    if let Ok(mut guard) = state.backup.lock() {
+        *guard = None;
+    }
+    if let Ok(mut guard) = state.virtual_drive.lock() {
+        *guard = None;
+    }
+    if let Ok(mut guard) = state.sync_engine.lock() {
+        *guard = None;
+    }
+    if let Err(e) = clear_credentials(&state) {
+        tracing::error!("Failed to clear credentials: {}", e);
+    }
+
+    if let Err(e) = app.emit("session://revoked", ()) {
+        tracing::warn!("Failed to emit session://revoked: {}", e);
+    }
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title(i18n::tr("Signed out"))
+        .body(i18n::tr(
+            "This device's access was revoked. Please sign in again.",
+        ))
+        .show()
+    {
+        tracing::warn!("Failed to show notification: {}", e);
+    }
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// SQLCipher settings for the local databases. The key is created in the OS
+/// keyring on first use and kept when encryption is turned off again, so the
+/// existing files can still be decrypted.
+fn database_key(config: &AppConfig) -> Result<DbKey> {
+    let entry = Entry::new(KEYRING_SERVICE_NEW, "db-key")
+        .map_err(|e| XynoxaError::Config(format!("Keyring unavailable: {}", e)));
+    let mut key = entry.as_ref().ok().and_then(|e| e.get_password().ok());
+    if config.encrypt_database && key.is_none() {
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes)
+            .map_err(|e| XynoxaError::Config(format!("No randomness for database key: {}", e)))?;
+        let new_key = hex::encode(bytes);
+        entry?
+            .set_password(&new_key)
+            .map_err(|e| XynoxaError::Config(format!("Failed to store database key: {}", e)))?;
+        key = Some(new_key);
+    }
+    Ok(DbKey {
+        key,
+        encrypt: config.encrypt_database,
+    })
+}
+
+/// Stops the sync, auto-upload and backup workers and waits until they closed
+/// their databases and let go of the sync folder.
+async fn stop_workers(state: &AppState) -> Result<()> {
+    let mut stopped = Vec::new();
+    if let Some(handle) = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?
+        .take()
+    {
+        stopped.push(handle.stop());
+    }
+    if let Some(handle) = state.auto_upload.lock().map_err(|_| "Lock fail")?.take() {
+        stopped.push(handle.stop());
+    }
+    if let Some(handle) = state.backup.lock().map_err(|_| "Lock fail")?.take() {
+        stopped.push(handle.stop());
+    }
+    // Each waits for its running cycle, upload or snapshot to finish
+    tauri::async_runtime::spawn_blocking(move || {
+        for receiver in stopped {
+            let _ = receiver.recv();
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Turns encryption of the local databases on or off and converts the
+/// existing files. A database must not be open while it is rewritten, so the
+/// workers are stopped first and started again afterwards.
+#[tauri::command]
+async fn set_database_encryption(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<()> {
+    let config = current_config(&state)?;
+    if config.encrypt_database == enabled {
+        return Ok(());
+    }
+    let key = database_key(&AppConfig {
+        encrypt_database: enabled,
+        ..config.clone()
+    })?;
+    let previous_key = database_key(&config)?;
+    let base = app_data_dir(&app)?;
+    let mut ledger_dirs = vec![base.clone()];
+    if let Ok(entries) = std::fs::read_dir(base.join("accounts")) {
+        ledger_dirs.extend(entries.flatten().map(|entry| entry.path()));
+    }
+    let mut paths: Vec<PathBuf> = ledger_dirs
+        .iter()
+        .flat_map(|dir| [dir.join("auto_upload.db"), dir.join("backup.db")])
+        .filter(|path| path.is_file())
+        .collect();
+    if let Some(path) = config.sync_path.as_deref() {
+        paths.extend(sync::database_files(Path::new(&expand_sync_path(path))));
+    }
+
+    let syncing = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?
+        .is_some();
+    stop_workers(&state).await?;
+    let converted = tauri::async_runtime::spawn_blocking(move || {
+        for (i, path) in paths.iter().enumerate() {
+            if let Err(e) = crate::db::Database::convert(path, &key) {
+                // Back to the form the config still asks for
+                for done in &paths[..i] {
+                    if let Err(e) = crate::db::Database::convert(done, &previous_key) {
+                        tracing::error!("Failed to restore database {:?}: {}", done, e);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    if converted.is_ok() {
+        let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+        raw.as_ref().ok_or("Config not init")?.update(ConfigPatch {
+            encrypt_database: Some(enabled),
+            ..Default::default()
+        })?;
+    }
+
+    // Resumes with whichever setting the files match now
+    if let Err(e) = start_auto_upload(&app) {
+        tracing::error!("Failed to restart auto-upload: {}", e);
+    }
+    if let Err(e) = start_backup(&app) {
+        tracing::error!("Failed to restart backup: {}", e);
+    }
+    if syncing {
+        start_sync(app.clone(), state, None)?;
+    }
+    Ok(converted?)
+}
+
+/// Callback for [`SyncHandle::new`] that signs out when sync hits an auth error.
+fn auth_lost_callback(app: &tauri::AppHandle) -> sync::AuthLostCallback {
+    let app = app.clone();
+    Box::new(move |e| {
+        tracing::error!("Sync stopped: {}", e);
+        handle_session_revoked(&app);
+    })
+}
+
+/// Callback for [`SyncHandle::new`] that forwards worker events to the webview.
+fn sync_event_callback(app: &tauri::AppHandle) -> sync::EventCallback {
+    let app = app.clone();
+    Box::new(move |event| {
+        update_taskbar(&app, &event);
+        let notification = match &event {
+            SyncEvent::Warning { message, .. } => Some((i18n::tr("Sync warning"), message.clone())),
+            SyncEvent::DocumentLock { path, open: true } => Some((
+                i18n::tr("Document in use"),
+                i18n::tr_args(
+                    "{0} is open on another device. Edits made here may conflict.",
+                    &[path.as_str()],
+                ),
+            )),
+            _ => None,
+        };
+        if let Some((title, body)) = notification {
+            if let Err(e) = app.notification().builder().title(title).body(body).show() {
+                tracing::warn!("Failed to show notification: {}", e);
+            }
+        }
+        if let Err(e) = app.emit(event.name(), &event) {
+            tracing::warn!("Failed to emit {}: {}", event.name(), e);
+        }
+    })
+}
+
+/// Mirrors sync progress on the taskbar or dock icon: percent of the current
+/// cycle as a progress bar, outstanding transfers as a badge.
+fn update_taskbar(app: &tauri::AppHandle, event: &SyncEvent) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    let (status, progress, badge) = match event {
+        SyncEvent::Progress { done, total } if *total > 0 => (
+            ProgressBarStatus::Normal,
+            Some((done * 100 / total) as u64),
+            Some((total - done) as i64),
+        ),
+        SyncEvent::State {
+            state: SyncState::Error | SyncState::Unavailable,
+        } => (ProgressBarStatus::Error, None, None),
+        SyncEvent::State {
+            state: SyncState::Paused,
+        } => (ProgressBarStatus::Paused, None, None),
+        SyncEvent::State {
+            state: SyncState::Idle | SyncState::Stopped,
+        } => (ProgressBarStatus::None, None, None),
+        _ => return,
+    };
+    let state = ProgressBarState {
+        status: Some(status),
+        progress,
+    };
+    if let Err(e) = window.set_progress_bar(state) {
+        tracing::debug!("Taskbar progress unavailable: {}", e);
+    }
+    // Unsupported on Windows, where the progress bar has to do
+    if let Err(e) = window.set_badge_count(badge.filter(|count| *count > 0)) {
+        tracing::debug!("Taskbar badge unavailable: {}", e);
+    }
+}
+
+#[tauri::command]
+fn check_auth(state: State<AppState>) -> bool {
+    // Check Config first
+    if let Ok(raw) = state.config_manager.lock() {
+        if let Some(cm) = raw.as_ref() {
+            if let Ok(conf) = cm.config.lock() {
+                if conf.auth_token.is_some() {
+                    return true;
+                }
+            }
+        }
+    }
+
+    // Fallback to Keyring
+    if let Ok(entry) = Entry::new(KEYRING_SERVICE_NEW, "auth-token") {
+        return entry.get_password().is_ok();
+    }
+    if let Ok(entry) = Entry::new(KEYRING_SERVICE_LEGACY, "auth-token") {
+        return entry.get_password().is_ok();
+    }
+    false
+}
+
+#[tauri::command]
+fn get_config(state: State<AppState>) -> Result<AppConfig> {
+    current_config(&state)
+}
+
+/// Snapshot of the loaded config.
+fn current_config(state: &AppState) -> Result<AppConfig> {
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    let conf = cm.config.lock().map_err(|_| "Lock fail")?;
+    Ok(conf.clone())
+}
+
+/// Field errors `save_config` would reject `patch` with; empty when valid.
+#[tauri::command]
+fn validate_config(patch: ConfigPatch) -> Vec<FieldError> {
+    patch.field_errors()
+}
+
+/// Shows the native folder picker (the desktop portal under Flatpak) and
+/// checks the choice like `save_config` would. `None` if the user cancelled;
+/// a rejected folder fails with a `syncPath` validation error.
+#[tauri::command]
+async fn pick_sync_folder(app: tauri::AppHandle) -> Result<Option<String>> {
+    let title = i18n::tr("Select Sync Folder");
+    #[cfg(target_os = "linux")]
+    if sandbox::is_flatpak() {
+        return validated_sync_folder(sandbox::pick_folder(&title).await?);
+    }
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .set_title(title)
+        .pick_folder(move |folder| {
+            let _ = tx.send(folder);
+        });
+    let folder = rx
+        .await
+        .map_err(|_| XynoxaError::Other("Folder dialog closed unexpectedly".to_string()))?;
+    let path = folder
+        .map(|f| f.into_path())
+        .transpose()
+        .map_err(|e| XynoxaError::Other(e.to_string()))?;
+    validated_sync_folder(path)
+}
+
+fn validated_sync_folder(path: Option<PathBuf>) -> Result<Option<String>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let path = path.to_string_lossy().to_string();
+    ConfigPatch {
+        sync_path: FieldUpdate::Set(path.clone()),
+        ..Default::default()
+    }
+    .validate()?;
+    Ok(Some(path))
+}
+
+/// Folders the Nextcloud or ownCloud client syncs on this machine, offered
+/// during setup.
+#[tauri::command]
+fn find_foreign_sync_folders() -> Vec<migration::ForeignFolder> {
+    migration::find_foreign_folders()
+}
+
+/// Makes a folder of another client the sync folder and ignores that client's
+/// journal files in it. Its files are adopted on the first sync.
+#[tauri::command]
+fn import_foreign_folder(state: State<AppState>, path: String) -> Result<()> {
+    let patch = ConfigPatch {
+        sync_path: FieldUpdate::Set(path),
+        ..Default::default()
+    };
+    patch.validate()?;
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    let patterns = cm
+        .config
+        .lock()
+        .map_err(|_| "Lock fail")?
+        .ignore_patterns
+        .clone();
+    cm.update(ConfigPatch {
+        ignore_patterns: Some(migration::with_journal_patterns(patterns)),
+        ..patch
+    })
+}
+
+#[tauri::command]
+fn save_config(app: tauri::AppHandle, state: State<AppState>, patch: ConfigPatch) -> Result<()> {
+    patch.validate()?;
+    let telemetry_enabled = patch.telemetry_enabled;
+    {
+        let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+        let cm = raw.as_ref().ok_or("Config not init")?;
+        cm.update(patch)?;
+    }
+    if let Some(enabled) = telemetry_enabled {
+        let now = chrono::Utc::now().timestamp();
+        telemetry::set_enabled(enabled, &telemetry_path(&app)?, now);
+    }
+    Ok(())
+}
+
+/// Switches the language of messages returned to the UI. `None` follows the
+/// system language.
+#[tauri::command]
+fn set_locale(state: State<AppState>, locale: Option<String>) -> Result<()> {
+    let locale = locale.filter(|tag| !tag.trim().is_empty());
+    i18n::set_locale(match &locale {
+        Some(tag) => Locale::parse(tag),
+        None => Locale::system(),
+    });
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    cm.update(ConfigPatch {
+        locale: match locale {
+            Some(tag) => FieldUpdate::Set(tag),
+            None => FieldUpdate::Clear,
+        },
+        ..Default::default()
+    })
+}
+
+/// Sets the name shown for this client in the server's session list. An empty
+/// name resets it to the host name. Applies to connections opened afterwards.
+#[tauri::command]
+fn set_device_name(state: State<AppState>, name: String) -> Result<()> {
+    let name = name.trim().to_string();
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    cm.update(ConfigPatch {
+        device_name: if name.is_empty() {
+            FieldUpdate::Clear
+        } else {
+            FieldUpdate::Set(name)
+        },
+        ..Default::default()
+    })
+}
+
+/// Checks the sync root, creating it if missing. A missing folder that was
+/// synced before (`known`) is not recreated: it was unmounted or moved, see
+/// [`find_moved_sync_folder`], and syncing the empty folder would download
+/// everything again.
+fn validate_sync_root(path: &PathBuf, known: bool) -> Result<()> {
+    if path.as_os_str().is_empty() {
+        return Err(XynoxaError::Config("Sync path is empty".to_string()));
+    }
+    if !path.is_absolute() {
+        return Err(XynoxaError::Config(
+            "Sync path must be absolute".to_string(),
+        ));
+    }
+    if path.exists() {
+        if path.is_dir() {
+            return Ok(());
+        }
+        return Err(XynoxaError::Config(
+            "Sync path is not a directory".to_string(),
+        ));
+    }
+    if known {
+        return Err(XynoxaError::Unavailable(i18n::tr_args(
+            "The sync folder {0} is not available",
+            &[&path.to_string_lossy()],
+        )));
+    }
+    std::fs::create_dir_all(path)?;
+    if !path.is_dir() {
+        return Err(XynoxaError::Config(
+            "Failed to create sync directory".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn start_sync(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    token: Option<String>,
+) -> Result<String> {
+    // Load config
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    let conf = cm.config.lock().map_err(|_| "Lock fail")?;
+
+    let path_str = conf.sync_path.clone().ok_or("No sync path configured")?;
+    let config_token = conf.auth_token.clone();
+
+    // Expand ~ for cross-platform safety
+    let path_str = expand_sync_path(&path_str);
+
+    let api_url = conf.server_url.clone(); // Clone before drop? yes.
+    let options = SyncOptions::from_config(&conf);
+    let db_key = database_key(&conf)?;
+    let known_root = conf.sync_root_id.is_some();
+    let synced_server = conf.sync_server_url.clone();
+
+    drop(conf); // Unlock early
+    drop(raw);
+
+    // Load token
+    let auth_token = if let Some(t) = token {
+        t
+    } else if let Some(t) = config_token {
+        t
+    } else {
+        if let Ok(entry) = Entry::new(KEYRING_SERVICE_NEW, "auth-token") {
+            entry
+                .get_password()
+                .map_err(|_| XynoxaError::Auth("Not logged in".to_string()))?
+        } else if let Ok(entry) = Entry::new(KEYRING_SERVICE_LEGACY, "auth-token") {
+            entry
+                .get_password()
+                .map_err(|_| XynoxaError::Auth("Not logged in".to_string()))?
+        } else {
+            return Err(XynoxaError::Auth("Not logged in".to_string()));
+        }
+    };
+
+    // Init Handle
+    let mut engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+
+    // Prevent parallel worker instances (prevents duplicate folder creates/uploads)
+    if engine_guard.is_some() {
+        tracing::info!("Sync already running - skipping second start");
+        return Ok("Sync already running".to_string());
+    }
+    // The service syncs the real folder, never the demo one
+    if service::status().active && config::demo_dir().is_none() {
+        return Ok(i18n::tr("Sync runs in the background service"));
+    }
+
+    // Create Handle (which spawns Worker)
+    let root = PathBuf::from(path_str);
+    validate_sync_root(&root, known_root)?;
+    check_sync_server(&state, &root, synced_server.as_deref(), api_url.as_deref())?;
+    remember_sync_root(&state, &root, &options.account, &db_key);
+    let handle = SyncHandle::new(
+        auth_token,
+        root,
+        api_url,
+        options,
+        db_key,
+        auth_lost_callback(&app),
+        sync_event_callback(&app),
+    );
+
+    *engine_guard = Some(handle);
+    Ok(i18n::tr("Sync started"))
+}
+
+/// Records the id of the sync folder at `root` so it can be recognized after
+/// a move. Fresh folders get theirs on the next start.
+fn remember_sync_root(state: &AppState, root: &Path, account: &str, db_key: &DbKey) {
+    let id = match sync::root_id(root, account, db_key) {
+        Ok(Some(id)) => id,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::warn!("Cannot read the id of sync folder {:?}: {}", root, e);
+            return;
+        }
+    };
+    let result = state
+        .config_manager
+        .lock()
+        .map_err(|_| XynoxaError::from("Lock fail"))
+        .and_then(|raw| {
+            let cm = raw.as_ref().ok_or("Config not init")?;
+            if cm.config.lock().map_err(|_| "Lock fail")?.sync_root_id == Some(id) {
+                return Ok(());
+            }
+            cm.update(ConfigPatch {
+                sync_root_id: FieldUpdate::Set(id),
+                ..Default::default()
+            })
+        });
+    if let Err(e) = result {
+        tracing::warn!("Cannot remember the sync folder id: {}", e);
+    }
+}
+
+/// Refuses to sync the folder at `root` with the server `current` while it
+/// holds the state of the differently addressed server `synced`, until
+/// [`confirm_server_change`]. Otherwise records `current` as its server.
+fn check_sync_server(
+    state: &AppState,
+    root: &Path,
+    synced: Option<&str>,
+    current: Option<&str>,
+) -> Result<()> {
+    let Some(current) = current else {
+        return Ok(());
+    };
+    if let Some(synced) = synced {
+        if config::server_key(synced) == config::server_key(current) {
+            return Ok(());
+        }
+        if sync::has_database(root) {
+            return Err(XynoxaError::ServerChanged {
+                from: synced.to_string(),
+                to: current.to_string(),
+            });
+        }
+    }
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    cm.update(ConfigPatch {
+        sync_server_url: FieldUpdate::Set(current.to_string()),
+        ..Default::default()
+    })
+}
+
+/// Answers [`XynoxaError::ServerChanged`]. The same server under a new
+/// address keeps the sync state and caches; a different server starts over
+/// from the local files, so no ids or deletions of the old one reach it.
+/// Sync is started again by the caller.
+#[tauri::command]
+async fn confirm_server_change(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    same_server: bool,
+) -> Result<()> {
+    let config = current_config(&state)?;
+    let (Some(path), Some(synced), Some(current)) = (
+        &config.sync_path,
+        &config.sync_server_url,
+        &config.server_url,
+    ) else {
+        return Ok(());
+    };
+    let root = PathBuf::from(expand_sync_path(path));
+    let (from, to) = (config::server_key(synced), config::server_key(current));
+    // Nothing may have the databases open while they are renamed or deleted
+    stop_workers(&state).await?;
+    let mut patch = ConfigPatch {
+        sync_server_url: FieldUpdate::Set(current.clone()),
+        ..Default::default()
+    };
+    if same_server {
+        tracing::info!("{} is the new address of {}", current, synced);
+        sync::rekey_databases(&root, &from, &to)?;
+        let accounts = app_data_dir(&app)?.join("accounts");
+        for entry in std::fs::read_dir(&accounts).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(rest) = name.strip_prefix(&from) else {
+                continue;
+            };
+            let renamed = accounts.join(format!("{}{}", to, rest));
+            if let Err(e) = std::fs::rename(entry.path(), renamed) {
+                tracing::warn!("Could not move the caches in {:?}: {}", entry.path(), e);
+            }
+        }
+    } else {
+        tracing::info!("Syncing {:?} with the different server {}", root, current);
+        sync::remove_databases(&root, &from)?;
+        // It was kept in the removed database
+        patch.sync_root_id = FieldUpdate::Clear;
+    }
+    {
+        let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+        let cm = raw.as_ref().ok_or("Config not init")?;
+        cm.update(patch)?;
+    }
+    if let Err(e) = start_auto_upload(&app) {
+        tracing::error!("Failed to restart auto-upload: {}", e);
+    }
+    if let Err(e) = start_backup(&app) {
+        tracing::error!("Failed to restart backup: {}", e);
+    }
+    Ok(())
+}
+
+/// The sync folder's new location if it is missing because it was renamed or
+/// moved, e.g. by renaming a parent folder.
+#[tauri::command]
+fn find_moved_sync_folder(state: State<AppState>) -> Result<Option<String>> {
+    let config = current_config(&state)?;
+    let (Some(path), Some(id)) = (&config.sync_path, config.sync_root_id) else {
+        return Ok(None);
+    };
+    let old_root = PathBuf::from(expand_sync_path(path));
+    let moved = sync::find_moved_root(
+        &old_root,
+        id,
+        &config.account_key(),
+        &database_key(&config)?,
+    );
+    Ok(moved.map(|root| root.to_string_lossy().to_string()))
+}
+
+/// Points the config at the sync folder's new location and restarts sync.
+/// Paths in the database are relative to the folder, so nothing is synced
+/// again.
+#[tauri::command]
+fn relink_sync_folder(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    new_path: String,
+) -> Result<String> {
+    let config = current_config(&state)?;
+    let root = PathBuf::from(expand_sync_path(&new_path));
+    let found = sync::root_id(&root, &config.account_key(), &database_key(&config)?)?;
+    if found.is_none() || config.sync_root_id.is_some_and(|id| found != Some(id)) {
+        return Err(XynoxaError::Config(i18n::tr_args(
+            "{0} is not the moved sync folder",
+            &[&new_path],
+        )));
+    }
+    tracing::info!("Relinking sync folder to {:?}", root);
+    state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?
+        .take();
+    {
+        let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+        let cm = raw.as_ref().ok_or("Config not init")?;
+        let patch = ConfigPatch {
+            sync_path: FieldUpdate::Set(root.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        patch.validate()?;
+        cm.update(patch)?;
+    }
+    start_sync(app, state, None)
+}
+
+/// Moves the sync folder to `new_path` and continues syncing there if sync was
+/// running, with sync stopped while the files move. If the user already moved
+/// the folder there, only the config changes.
+#[tauri::command]
+async fn move_sync_folder(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    new_path: String,
+) -> Result<String> {
+    let config = current_config(&state)?;
+    let old_path = config
+        .sync_path
+        .as_deref()
+        .ok_or("No sync path configured")?;
+    let old_root = PathBuf::from(expand_sync_path(old_path));
+    let new_root = PathBuf::from(expand_sync_path(&new_path));
+    let patch = ConfigPatch {
+        sync_path: FieldUpdate::Set(new_root.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    patch.validate()?;
+    let moved_by_hand = !old_root.exists()
+        && config.sync_root_id.is_some()
+        && sync::root_id(&new_root, &config.account_key(), &database_key(&config)?)?
+            == config.sync_root_id;
+
+    let running = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?
+        .take();
+    let was_running = running.is_some();
+    if let Some(handle) = running {
+        // Waits for the running cycle to finish
+        let stopped = handle.stop();
+        let _ = tauri::async_runtime::spawn_blocking(move || stopped.recv())
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let moved = if moved_by_hand {
+        Ok(())
+    } else {
+        let (from, to) = (old_root, new_root);
+        tauri::async_runtime::spawn_blocking(move || sync::move_root(&from, &to))
+            .await
+            .map_err(|e| e.to_string())?
+    };
+    if moved.is_ok() {
+        let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+        raw.as_ref().ok_or("Config not init")?.update(patch)?;
+    }
+    // Resumes in whichever place holds the folder now
+    let started = if was_running {
+        start_sync(app, state, None)
+    } else {
+        Ok("Sync folder moved".to_string())
+    };
+    moved?;
+    started
+}
+
+/// Vacuums the sync database and prunes stale rows; also runs monthly on its own.
+#[tauri::command]
+async fn maintain_db(state: State<'_, AppState>) -> Result<MaintenanceReport> {
+    let reply = {
+        let engine_guard = state
+            .sync_engine
+            .lock()
+            .map_err(|_| "Failed to lock state")?;
+        engine_guard
+            .as_ref()
+            .ok_or("Sync is not running")?
+            .request_maintenance()?
+    };
+    // The worker may be in the middle of a cycle
+    tauri::async_runtime::spawn_blocking(move || reply.recv())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|_| "Sync worker stopped")?
+}
+
+/// Rebuilds a damaged or lost sync database from the server's event log and
+/// the local files. Returns the number of entries tracked afterwards.
+#[tauri::command]
+async fn rebuild_index(state: State<'_, AppState>) -> Result<usize> {
+    let reply = {
+        let engine_guard = state
+            .sync_engine
+            .lock()
+            .map_err(|_| "Failed to lock state")?;
+        engine_guard
+            .as_ref()
+            .ok_or("Sync is not running")?
+            .request_rebuild()?
+    };
+    // Runs a full sync cycle
+    tauri::async_runtime::spawn_blocking(move || reply.recv())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|_| "Sync worker stopped")?
+}
+
+#[tauri::command]
+fn get_file_list(state: State<AppState>) -> Result<Vec<crate::sync::FileListEntry>> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+
+    if let Some(handle) = &*engine_guard {
+        handle.list_files()
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// Pending, failed and conflicted entries below a folder of the sync root,
+/// by subfolder.
+#[tauri::command]
+fn get_folder_health(state: State<AppState>, path: String) -> Result<FolderHealth> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard
+        .as_ref()
+        .ok_or("Sync is not running")?
+        .folder_health(&path)
+}
+
+/// Finds synced files by name without walking the sync folder.
+#[tauri::command]
+fn search_files(state: State<AppState>, query: String) -> Result<Vec<SearchResult>> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+
+    if let Some(handle) = &*engine_guard {
+        handle.search_files(&query)
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// Searches the files on the server, including ones this device does not
+/// have, and tells which of them are synced here.
+#[tauri::command]
+async fn search_remote(
+    state: State<'_, AppState>,
+    query: String,
+) -> Result<Vec<RemoteSearchResult>> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let client = api_client(&state)?;
+    let hits = client.search_files(query, REMOTE_SEARCH_LIMIT).await?;
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    match &*engine_guard {
+        Some(handle) => handle.annotate_remote_hits(hits),
+        None => Ok(hits
+            .into_iter()
+            .map(|hit| RemoteSearchResult {
+                hit,
+                local_path: None,
+                status: None,
+            })
+            .collect()),
+    }
+}
+
+#[tauri::command]
+fn get_transfer_queue(state: State<AppState>) -> Result<Vec<Transfer>> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+
+    Ok(engine_guard
+        .as_ref()
+        .map(|handle| handle.transfer_queue())
+        .unwrap_or_default())
+}
+
+/// Uploads deferred because other programs are still writing the files, and
+/// whether the sync is paused.
+#[tauri::command]
+fn get_sync_status(state: State<AppState>) -> Result<SyncStatus> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    Ok(engine_guard
+        .as_ref()
+        .map(|handle| handle.status())
+        .unwrap_or_default())
+}
+
+/// Replaces the name patterns the sync skips; `None` restores the defaults.
+/// Applies to the running sync right away.
+#[tauri::command]
+fn set_ignore_patterns(state: State<AppState>, patterns: Option<Vec<String>>) -> Result<()> {
+    let patterns = match patterns {
+        Some(patterns) => ignore::clean_patterns(patterns),
+        None => ignore::default_patterns(),
+    };
+    {
+        let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+        let cm = raw.as_ref().ok_or("Config not init")?;
+        cm.update(ConfigPatch {
+            ignore_patterns: Some(patterns.clone()),
+            ..Default::default()
+        })?;
+    }
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    if let Some(engine) = engine_guard.as_ref() {
+        engine.set_ignore_patterns(&patterns);
+    }
+    Ok(())
+}
+
+/// Bytes transferred per day and this month, against the monthly cap.
+#[tauri::command]
+fn get_bandwidth_usage(state: State<AppState>) -> Result<BandwidthUsage> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard
+        .as_ref()
+        .ok_or("Sync is not running")?
+        .bandwidth_usage()
+}
+
+/// Sets the monthly transfer cap in MB; `None` or 0 removes it. Applies to
+/// the running sync right away.
+#[tauri::command]
+fn set_bandwidth_cap(state: State<AppState>, cap_mb: Option<u64>) -> Result<()> {
+    let cap_mb = cap_mb.filter(|&mb| mb > 0);
+    {
+        let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+        let cm = raw.as_ref().ok_or("Config not init")?;
+        cm.update(ConfigPatch {
+            bandwidth_cap_mb: match cap_mb {
+                Some(mb) => FieldUpdate::Set(mb),
+                None => FieldUpdate::Clear,
+            },
+            ..Default::default()
+        })?;
+    }
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    if let Some(engine) = engine_guard.as_ref() {
+        engine.set_bandwidth_cap(sync::bandwidth_cap(cap_mb));
+    }
+    Ok(())
+}
+
+/// Skips the transfer of `path` (e.g. a stuck large upload) until it is retried.
+#[tauri::command]
+fn cancel_transfer(state: State<AppState>, path: String) -> Result<()> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard
+        .as_ref()
+        .ok_or("Sync is not running")?
+        .cancel_transfer(&path)
+}
+
+/// Moves `path` to the front of the transfer queue, e.g. during a large initial sync.
+#[tauri::command]
+fn prioritize(state: State<AppState>, path: String) -> Result<()> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard
+        .as_ref()
+        .ok_or("Sync is not running")?
+        .prioritize(&path)
+}
+
+#[tauri::command]
+fn retry_transfer(state: State<AppState>, path: String) -> Result<()> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard
+        .as_ref()
+        .ok_or("Sync is not running")?
+        .retry_transfer(&path)
+}
+
+/// Saves a report of all tracked files and the recent activity where the user
+/// chooses. `None` if the dialog was cancelled.
+#[tauri::command]
+async fn export_report(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    format: report::ReportFormat,
+) -> Result<Option<String>> {
+    let report = {
+        let engine_guard = state
+            .sync_engine
+            .lock()
+            .map_err(|_| "Failed to lock state")?;
+        engine_guard
+            .as_ref()
+            .ok_or("Sync is not running")?
+            .report()?
+    };
+    let name = format!(
+        "xynoxa-report-{}.{}",
+        chrono::Local::now().format("%Y-%m-%d"),
+        format.extension()
+    );
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .set_title(i18n::tr("Export Sync Report"))
+        .set_file_name(name)
+        .add_filter(format.extension(), &[format.extension()])
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+    let path = rx
+        .await
+        .map_err(|_| XynoxaError::Other("File dialog closed unexpectedly".to_string()))?;
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let path = path
+        .into_path()
+        .map_err(|e| XynoxaError::Other(e.to_string()))?;
+    std::fs::write(&path, report.render(format)?)?;
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+/// Stops syncing after the running cycle until `resume_sync`.
+#[tauri::command]
+fn pause_sync(state: State<AppState>) -> Result<()> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard.as_ref().ok_or("Sync is not running")?.pause();
+    Ok(())
+}
+
+#[tauri::command]
+fn resume_sync(state: State<AppState>) -> Result<()> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard.as_ref().ok_or("Sync is not running")?.resume();
+    Ok(())
+}
+
+/// Files the integrity check or a scan found changed without being written.
+#[tauri::command]
+fn list_sync_issues(state: State<AppState>) -> Result<Vec<SyncIssue>> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard
+        .as_ref()
+        .ok_or("Sync is not running")?
+        .sync_issues()
+}
+
+/// Failures that keep recurring, one entry per problem with how often and
+/// since when it happens.
+#[tauri::command]
+fn list_known_issues(state: State<AppState>) -> Result<Vec<KnownIssue>> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard
+        .as_ref()
+        .ok_or("Sync is not running")?
+        .known_issues()
+}
+
+#[tauri::command]
+fn repair_sync_issue(state: State<AppState>, path: String, action: RepairAction) -> Result<()> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard
+        .as_ref()
+        .ok_or("Sync is not running")?
+        .repair_sync_issue(&path, action)
+}
+
+// The control API acts on the sync the app runs
+impl control::Engine for tauri::AppHandle {
+    fn with_sync(
+        &self,
+        f: &mut dyn FnMut(&SyncHandle) -> Result<serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        control::Engine::with_sync(&self.state::<AppState>().sync_engine, f)
+    }
+}
+
+/// Directory for app-managed data (ledger, caches); next to the executable in
+/// portable mode.
+fn app_data_dir(app: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = match config::portable_dir() {
+        Some(dir) => dir,
+        None => app
+            .path()
+            .app_data_dir()
+            .map_err(|e| XynoxaError::Config(e.to_string()))?,
+    };
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Entries of the app data dir kept per account, with their SQLite sidecars.
+const ACCOUNT_STATE: [&str; 3] = ["thumbnails", "auto_upload.db", "backup.db"];
+
+/// Ledgers and caches of the configured account, see
+/// [`AppConfig::account_key`]. A new account adopts what was kept before its
+/// user id was known, or directly in the app data dir by older versions.
+fn account_dir(app: &tauri::AppHandle, config: &AppConfig) -> Result<PathBuf> {
+    let base = app_data_dir(app)?;
+    let key = config.account_key();
+    let dir = base.join("accounts").join(&key);
+    if dir.is_dir() {
+        return Ok(dir);
+    }
+    std::fs::create_dir_all(&dir)?;
+    let server = key.split('-').next().unwrap_or(&key);
+    let earlier = [base.join("accounts").join(server), base];
+    let owned = |entry: &std::fs::DirEntry| {
+        let name = entry.file_name().to_string_lossy().to_string();
+        ACCOUNT_STATE.iter().any(|state| name.starts_with(state))
+    };
+    for earlier in earlier.iter().filter(|d| **d != dir) {
+        let Ok(entries) = std::fs::read_dir(earlier) else {
+            continue;
+        };
+        let entries: Vec<_> = entries.flatten().filter(owned).collect();
+        if entries.is_empty() {
+            continue;
+        }
+        tracing::info!("Adopting account data from {:?}", earlier);
+        for entry in entries {
+            if let Err(e) = std::fs::rename(entry.path(), dir.join(entry.file_name())) {
+                tracing::warn!("Could not move {:?}: {}", entry.path(), e);
+            }
+        }
+        break;
+    }
+    Ok(dir)
+}
+
+/// Preview image of `file_id` for file listings: the server thumbnail (cached
+/// per account), or the local file for small images.
+#[tauri::command]
+async fn get_thumbnail(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    file_id: String,
+) -> Result<tauri::ipc::Response> {
+    let record = {
+        let engine_guard = state
+            .sync_engine
+            .lock()
+            .map_err(|_| "Failed to lock state")?;
+        match &*engine_guard {
+            Some(handle) => handle.get_file_by_id(&file_id)?,
+            None => None,
+        }
+    };
+    let hash = record.as_ref().map(|r| r.hash.as_str()).unwrap_or("remote");
+    let cache = ThumbnailCache::new(
+        account_dir(&app, &current_config(&state)?)?.join("thumbnails"),
+        thumbnails::MAX_CACHE_BYTES,
+    );
+    if let Some(bytes) = cache.get(&file_id, hash) {
+        return Ok(tauri::ipc::Response::new(bytes));
+    }
+
+    let fetched = api_client(&state)?
+        .fetch_thumbnail(&file_id, thumbnails::THUMBNAIL_SIZE)
+        .await;
+    if let Ok(Some(bytes)) = &fetched {
+        if let Err(e) = cache.put(&file_id, hash, bytes) {
+            tracing::warn!("Failed to cache thumbnail: {}", e);
+        }
+    }
+    match fetched {
+        Ok(Some(bytes)) => Ok(tauri::ipc::Response::new(bytes)),
+        result => record
+            .and_then(|r| synced_file_path(&state, &r.path).ok())
+            .and_then(|path| thumbnails::local_preview(&path))
+            .map(tauri::ipc::Response::new)
+            .ok_or_else(|| {
+                result
+                    .err()
+                    .unwrap_or_else(|| XynoxaError::Other("No preview available".to_string()))
+            }),
+    }
+}
+
+/// Uploads dropped files and folders into remote folder `remote_folder_id`
+/// (the root if `None`). Synced folders receive a local copy the sync worker
+/// uploads; other folders are uploaded to directly. Reports each item as an
+/// `upload://progress` event.
+#[tauri::command]
+async fn upload_paths(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    paths: Vec<String>,
+    remote_folder_id: Option<String>,
+) -> Result<()> {
+    let conf = current_config(&state)?;
+    let root = PathBuf::from(expand_sync_path(
+        conf.sync_path.as_deref().ok_or("No sync path configured")?,
+    ));
+    let local_dir = match &remote_folder_id {
+        None => Some(root.clone()),
+        Some(folder_id) => {
+            let engine_guard = state
+                .sync_engine
+                .lock()
+                .map_err(|_| "Failed to lock state")?;
+            match engine_guard
+                .as_ref()
+                .map(|handle| handle.get_file_by_id(folder_id))
+                .transpose()?
+                .flatten()
+            {
+                Some(record) if record.hash == "directory" => {
+                    Some(sync::resolve_local_path(&root, &record.path)?)
+                }
+                _ => None,
+            }
+        }
+    };
+    let client = api_client(&state)?;
+
+    let total = paths.len();
+    for (i, path) in paths.into_iter().enumerate() {
+        let source = PathBuf::from(&path);
+        let result = match &local_dir {
+            Some(dir) => uploads::copy_into(&source, dir).map(|_| ()),
+            None => uploads::upload_tree(&client, &source, remote_folder_id.as_deref()).await,
+        };
+        if let Err(e) = &result {
+            tracing::error!("Upload of dropped {} failed: {}", path, e);
+        }
+        let progress = UploadProgress {
+            path,
+            done: i + 1,
+            total,
+            error: result.err().map(|e| e.to_string()),
+        };
+        if let Err(e) = app.emit("upload://progress", &progress) {
+            tracing::warn!("Failed to emit upload://progress: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn sync_root(state: &AppState) -> Result<PathBuf> {
+    let conf = current_config(state)?;
+    let root = expand_sync_path(conf.sync_path.as_deref().ok_or("No sync path configured")?);
+    Ok(PathBuf::from(root))
+}
+
+/// Absolute location of a synced file given by its index path.
+fn synced_file_path(state: &AppState, path: &str) -> Result<PathBuf> {
+    let local = sync::resolve_local_path(&sync_root(state)?, path)?;
+    if !local.exists() {
+        return Err(XynoxaError::Other(format!(
+            "{} is not available locally",
+            path
+        )));
+    }
+    Ok(local)
+}
+
+/// Shows the auxiliary window `label`, creating it on first use. The frontend
+/// picks the page to render from the window label.
+fn open_aux_window(
+    app: &tauri::AppHandle,
+    label: &str,
+    title: &str,
+    (width, height): (f64, f64),
+) -> Result<()> {
+    if let Some(window) = app.get_webview_window(label) {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+        return Ok(());
+    }
+    WebviewWindowBuilder::new(app, label, WebviewUrl::default())
+        .title(title)
+        .inner_size(width, height)
+        .min_inner_size(width / 2.0, height / 2.0)
+        .build()
+        .map_err(|e| XynoxaError::Other(format!("Failed to open window: {}", e)))?;
+    Ok(())
+}
+
+// Window creation deadlocks in synchronous commands on Windows, hence async
+#[tauri::command]
+async fn open_conflict_resolver(app: tauri::AppHandle) -> Result<()> {
+    open_aux_window(
+        &app,
+        "conflicts",
+        &i18n::tr("Resolve conflicts"),
+        (760.0, 480.0),
+    )
+}
+
+#[tauri::command]
+async fn open_transfer_monitor(app: tauri::AppHandle) -> Result<()> {
+    open_aux_window(&app, "transfers", &i18n::tr("Transfers"), (640.0, 520.0))
+}
+
+#[tauri::command]
+async fn open_trash(app: tauri::AppHandle) -> Result<()> {
+    open_aux_window(&app, "trash", &i18n::tr("Trash"), (640.0, 520.0))
+}
+
+#[tauri::command]
+fn list_conflicts(state: State<AppState>) -> Result<Vec<Conflict>> {
+    Ok(conflicts::find_conflicts(&sync_root(&state)?))
+}
+
+/// Resolves the conflict whose backup is at `path` (relative to the sync root).
+#[tauri::command]
+fn resolve_conflict(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    path: String,
+    resolution: Resolution,
+) -> Result<()> {
+    let backup = synced_file_path(&state, &path)?;
+    conflicts::resolve(&sync_root(&state)?, &backup, resolution)?;
+    tracing::info!("Resolved conflict {} ({:?})", path, resolution);
+    // Lets the main window update its conflict count
+    if let Err(e) = app.emit("conflicts://resolved", &path) {
+        tracing::warn!("Failed to emit conflicts://resolved: {}", e);
+    }
+    Ok(())
+}
+
+/// Opens the file manager with `path` selected. Falls back to opening the
+/// containing folder where selecting is not supported (e.g. Linux file
+/// managers without the FileManager1 D-Bus interface).
+#[tauri::command]
+fn reveal_in_file_manager(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    path: String,
+) -> Result<()> {
+    let local = synced_file_path(&state, &path)?;
+    if let Err(e) = app.opener().reveal_item_in_dir(&local) {
+        tracing::warn!("Reveal failed, opening the folder instead: {}", e);
+        let folder = local.parent().unwrap_or(&local);
+        app.opener()
+            .open_path(folder.to_string_lossy(), None::<&str>)
+            .map_err(|e| XynoxaError::Other(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Opens `path` with the default application.
+#[tauri::command]
+fn open_file(app: tauri::AppHandle, state: State<AppState>, path: String) -> Result<()> {
+    let local = synced_file_path(&state, &path)?;
+    app.opener()
+        .open_path(local.to_string_lossy(), None::<&str>)
+        .map_err(|e| XynoxaError::Other(e.to_string()))
+}
+
+/// Keeps `path` downloaded ("Always keep on this device") or frees its local
+/// space, leaving an online-only placeholder. Needs files on demand (Windows).
+#[tauri::command]
+fn set_file_availability(
+    state: State<AppState>,
+    path: String,
+    always_available: bool,
+) -> Result<()> {
+    let local = synced_file_path(&state, &path)?;
+    cloud_files::set_always_available(&local, always_available)
+}
+
+/// Keeps a synced file or folder always available offline: downloaded, never
+/// an online-only placeholder, and ahead of other downloads.
+#[tauri::command]
+fn pin_path(state: State<AppState>, path: String) -> Result<()> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard
+        .as_ref()
+        .ok_or("Sync is not running")?
+        .pin_path(&path)
+}
+
+#[tauri::command]
+fn unpin_path(state: State<AppState>, path: String) -> Result<()> {
+    let engine_guard = state
+        .sync_engine
+        .lock()
+        .map_err(|_| "Failed to lock state")?;
+    engine_guard
+        .as_ref()
+        .ok_or("Sync is not running")?
+        .unpin_path(&path)
+}
+
+/// Starts the app at login. Flatpak apps ask the background portal instead of
+/// writing an autostart entry.
+#[tauri::command]
+async fn set_autostart(app: tauri::AppHandle, enabled: bool) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    if sandbox::is_flatpak() {
+        return sandbox::request_autostart(enabled).await;
+    }
+    let autolaunch = app.autolaunch();
+    let result = if enabled {
+        autolaunch.enable()
+    } else {
+        autolaunch.disable()
+    };
+    result.map_err(|e| XynoxaError::Other(e.to_string()))
+}
+
+/// Stored server URL and token (config first, keyring as fallback).
+fn server_credentials(state: &AppState) -> Result<(String, String)> {
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    let conf = cm.config.lock().map_err(|_| "Lock fail")?;
+    let server_url = conf
+        .server_url
+        .clone()
+        .ok_or_else(|| XynoxaError::Config("No server URL configured".to_string()))?;
+    let token = conf.auth_token.clone();
+    drop(conf);
+    drop(raw);
+
+    let token = token
+        .or_else(|| {
+            Entry::new(KEYRING_SERVICE_NEW, "auth-token")
+                .and_then(|entry| entry.get_password())
+                .ok()
+        })
+        .ok_or_else(|| XynoxaError::Auth("Not logged in".to_string()))?;
+
+    Ok((token, server_url))
+}
+
+/// Builds an API client from the stored server URL and token, for commands that
+/// talk to the server directly instead of going through the sync worker.
+fn api_client(state: &AppState) -> Result<XynoxaClient> {
+    let (token, server_url) = server_credentials(state)?;
+    let options = ClientOptions::from_config(&current_config(state)?);
+    Ok(XynoxaClient::with_options(token, server_url, &options))
+}
+
+/// [`api_client`] with a `token` that is not saved yet, while signing in.
+fn token_client(state: &AppState, token: String) -> Result<XynoxaClient> {
+    let config = current_config(state)?;
+    let server_url = config
+        .server_url
+        .clone()
+        .ok_or_else(|| XynoxaError::Config("No server URL configured".to_string()))?;
+    let options = ClientOptions::from_config(&config);
+    Ok(XynoxaClient::with_options(token, server_url, &options))
+}
+
+/// (Re)mounts the virtual drive from the current config. Blocks until the
+/// remote tree is loaded.
+fn start_virtual_drive(app: &tauri::AppHandle) -> Result<PathBuf> {
+    let state = app.state::<AppState>();
+    let config = current_config(&state)?.virtual_drive;
+    let mount_point = match &config.mount_path {
+        Some(path) => PathBuf::from(expand_sync_path(path)),
+        None => PathBuf::from(expand_sync_path("~/Xynoxa Drive")),
+    };
+
+    let mut guard = state.virtual_drive.lock().map_err(|_| "Lock fail")?;
+    *guard = None; // Unmounts a previous mount first
+
+    let drive = VirtualDrive::new(
+        Arc::new(api_client(&state)?),
+        app_data_dir(app)?.join("drive-cache"),
+        config.cache_limit_mb * 1024 * 1024,
+    )?;
+    *guard = Some(vfs::mount(drive, &mount_point)?);
+    tracing::info!("Virtual drive mounted at {:?}", mount_point);
+    Ok(mount_point)
+}
+
+/// Mounts the remote storage as a drive and keeps mounting it at startup.
+/// Returns the mount point.
+#[tauri::command]
+async fn mount_virtual_drive(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    mount_path: Option<String>,
+) -> Result<String> {
+    {
+        let current = current_config(&state)?.virtual_drive;
+        let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+        let cm = raw.as_ref().ok_or("Config not init")?;
+        cm.update(ConfigPatch {
+            virtual_drive: Some(VirtualDriveConfig {
+                enabled: true,
+                mount_path: mount_path.filter(|p| !p.trim().is_empty()),
+                ..current
+            }),
+            ..Default::default()
+        })?;
+    }
+    // Loading the tree blocks on its own runtime
+    let mount_point = tauri::async_runtime::spawn_blocking(move || start_virtual_drive(&app))
+        .await
+        .map_err(|e| XynoxaError::Other(e.to_string()))??;
+    Ok(mount_point.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+fn unmount_virtual_drive(state: State<AppState>) -> Result<()> {
+    *state.virtual_drive.lock().map_err(|_| "Lock fail")? = None;
+    let current = current_config(&state)?.virtual_drive;
+    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
+    let cm = raw.as_ref().ok_or("Config not init")?;
+    cm.update(ConfigPatch {
+        virtual_drive: Some(VirtualDriveConfig {
+            enabled: false,
+            ..current
+        }),
+        ..Default::default()
+    })
+}
+
+/// (Re)starts the one-way auto-upload pipeline from the current config, including
+/// the screenshot share source. Stops it when no sources are configured.
+fn start_auto_upload(app: &tauri::AppHandle) -> Result<()> {
+    let state = app.state::<AppState>();
+    let config = current_config(&state)?;
+    let options = SyncOptions::from_config(&config);
+    let db_key = database_key(&config)?;
+    let ledger_path = account_dir(app, &config)?.join("auto_upload.db");
+    let AppConfig {
+        auto_upload_sources: mut sources,
+        screenshot_share,
+        ..
+    } = config;
+
+    if screenshot_share.enabled {
+        let dir = screenshot_share
+            .path
+            .map(PathBuf::from)
+            .or_else(auto_upload::default_screenshot_dir);
+        match dir {
+            Some(dir) => sources.push(AutoUploadSource {
+                path: dir.to_string_lossy().to_string(),
+                remote_folder_id: screenshot_share.remote_folder_id,
+                media_only: true,
+                share_link: true,
+                share_since: screenshot_share.enabled_at,
+                added_at: screenshot_share.enabled_at,
+                watch_mode: WatchMode::Auto,
+            }),
+            None => tracing::warn!("No screenshot directory found. Screenshot sharing disabled."),
+        }
+    }
+
+    let mut guard = state.auto_upload.lock().map_err(|_| "Lock fail")?;
+    *guard = None; // Dropping the handle stops the previous worker
+
+    if sources.is_empty() {
+        return Ok(());
+    }
+
+    let (token, server_url) = server_credentials(&state)?;
+
+    let share_app = app.clone();
+    let on_share = Box::new(move |name: String, url: String| {
+        if let Err(e) = share_app.clipboard().write_text(url.clone()) {
+            tracing::warn!("Failed to copy share link to clipboard: {}", e);
+        }
+        if let Err(e) = share_app
+            .notification()
+            .builder()
+            .title(i18n::tr("Share link copied"))
+            .body(format!("{} - {}", name, url))
+            .show()
+        {
+            tracing::warn!("Failed to show notification: {}", e);
+        }
+    });
+
+    *guard = Some(AutoUploadHandle::new(
+        token,
+        server_url,
+        sources,
+        ledger_path,
+        &options,
+        &db_key,
+        on_share,
+    )?);
+    Ok(())
+}
+
+#[tauri::command]
+fn apply_auto_upload(app: tauri::AppHandle) -> Result<()> {
+    start_auto_upload(&app)
+}
+
+/// (Re)starts the backup worker from the current config. Stops it when no
+/// sources are configured; sources overlapping the sync folder are skipped.
+fn start_backup(app: &tauri::AppHandle) -> Result<()> {
+    let state = app.state::<AppState>();
+    let config = current_config(&state)?;
+    let options = SyncOptions::from_config(&config);
+    let db_key = database_key(&config)?;
+    let ledger_path = account_dir(app, &config)?.join("backup.db");
+    let sync_root = config
+        .sync_path
+        .as_deref()
+        .map(|path| PathBuf::from(expand_sync_path(path)));
+    let sources: Vec<_> = config
+        .backup_sources
+        .into_iter()
+        .filter(|source| {
+            let path = Path::new(&source.path);
+            let overlaps = sync_root
+                .as_ref()
+                .is_some_and(|root| path.starts_with(root) || root.starts_with(path));
+            if overlaps {
+                tracing::warn!(
+                    "Backup source {} overlaps the sync folder. Skipping.",
+                    source.path
+                );
+            }
+            !overlaps
+        })
+        .collect();
+
+    let mut guard = state.backup.lock().map_err(|_| "Lock fail")?;
+    *guard = None; // Dropping the handle stops the previous worker
+
+    if sources.is_empty() {
+        return Ok(());
+    }
+
+    let (token, server_url) = server_credentials(&state)?;
+    *guard = Some(BackupHandle::new(
+        token,
+        server_url,
+        sources,
+        ledger_path,
+        &options,
+        &db_key,
+    )?);
+    Ok(())
+}
 
-    Ok("Login successful".to_string())
+#[tauri::command]
+fn apply_backup(app: tauri::AppHandle) -> Result<()> {
+    start_backup(&app)
 }
 
+/// Snapshots every backup source now instead of when it is due.
 #[tauri::command]
-fn logout(state: State<AppState>) -> Result<(), String> {
-    // Clear Keyring
-    if let Ok(entry) = Entry::new(KEYRING_SERVICE_NEW, "auth-token") {
-        let _ = entry.delete_credential();
-    }
-    if let Ok(entry) = Entry::new(KEYRING_SERVICE_LEGACY, "auth-token") {
-        let _ = entry.delete_credential();
-    }
+fn backup_now(state: State<AppState>) -> Result<()> {
+    let guard = state.backup.lock().map_err(|_| "Lock fail")?;
+    guard
+        .as_ref()
+        .ok_or("No backup sources configured")?
+        .run_now();
+    Ok(())
+}
 
-    // Clear Config
-    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
-    let cm = raw.as_ref().ok_or("Config not init")?;
-    // To clear, we can pass empty string or handle logic in update.
-    // update takes Option<String>. If we pass explicit None it ignores.
-    // Ideally update should take Option<Option<String>> for unset?
-    // For now, let's just make sure we interpret empty string as none or just overwrite.
-    // Actually, `update` logic: `if let Some(t) = token { config.auth_token = Some(t); }`.
-    // It doesn't allow clearing. We'll fix `update` or just hack it with empty string for now if usage allows,
-    // but better to manually lock and clear.
-
-    let mut config = cm.config.lock().map_err(|_| "Lock fail")?;
-    config.auth_token = None;
-    drop(config);
-    cm.save()?;
+/// Snapshots of the backup source at `path`, newest first.
+#[tauri::command]
+fn list_backup_snapshots(app: tauri::AppHandle, path: String) -> Result<Vec<BackupSnapshot>> {
+    let state = app.state::<AppState>();
+    let config = current_config(&state)?;
+    let ledger_path = account_dir(&app, &config)?.join("backup.db");
+    let db = crate::db::Database::open(&ledger_path, &database_key(&config)?)?;
+    Ok(db.backup_snapshots(&path)?)
+}
 
-    Ok(())
+/// Shows exactly what the next telemetry report would contain.
+#[tauri::command]
+fn get_telemetry_preview() -> TelemetryReport {
+    telemetry::report()
 }
 
+/// User, scopes and expiry of the current token.
 #[tauri::command]
-fn check_auth(state: State<AppState>) -> bool {
-    // Check Config first
-    if let Ok(raw) = state.config_manager.lock() {
-        if let Some(cm) = raw.as_ref() {
-            if let Ok(conf) = cm.config.lock() {
-                if conf.auth_token.is_some() {
-                    return true;
-                }
+async fn get_session_info(state: State<'_, AppState>) -> Result<SessionInfo> {
+    let session = api_client(&state)?.session_info().await?;
+    // Signed in before user ids were stored
+    if current_config(&state)?.account_id.is_none() {
+        remember_account(&state, &session)?;
+    }
+    Ok(session)
+}
+
+/// Emits `session://expiring` with the [`SessionInfo`] while the token is about
+/// to expire, and notifies once per token.
+async fn run_session_expiry_check(app: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(SESSION_CHECK_INTERVAL);
+    let mut notified_for = None;
+    loop {
+        interval.tick().await;
+        let state = app.state::<AppState>();
+        let client = match api_client(&state) {
+            Ok(client) => client,
+            Err(_) => continue,
+        };
+        let session = match client.session_info().await {
+            Ok(session) => session,
+            Err(e) => {
+                tracing::debug!("Session check failed: {}", e);
+                continue;
             }
+        };
+        let warning = chrono::Duration::days(TOKEN_EXPIRY_WARNING_DAYS);
+        let Some(left) = session.expires_within(warning, chrono::Utc::now()) else {
+            continue;
+        };
+        tracing::warn!("Auth token expires at {:?}", session.expires_at);
+        if let Err(e) = app.emit("session://expiring", &session) {
+            tracing::warn!("Failed to emit session://expiring: {}", e);
+        }
+        if notified_for == session.expires_at {
+            continue;
+        }
+        notified_for = session.expires_at;
+        if let Err(e) = app
+            .notification()
+            .builder()
+            .title(i18n::tr("Sign-in expires soon"))
+            .body(i18n::tr_args(
+                "Your token expires in {0} day(s). Create a new one to keep syncing.",
+                &[&left.num_days().max(0).to_string()],
+            ))
+            .show()
+        {
+            tracing::warn!("Failed to show notification: {}", e);
         }
     }
+}
 
-    // Fallback to Keyring
-    if let Ok(entry) = Entry::new(KEYRING_SERVICE_NEW, "auth-token") {
-        return entry.get_password().is_ok();
-    }
-    if let Ok(entry) = Entry::new(KEYRING_SERVICE_LEGACY, "auth-token") {
-        return entry.get_password().is_ok();
+/// Where the telemetry counters are kept between runs.
+fn telemetry_path(app: &tauri::AppHandle) -> Result<PathBuf> {
+    Ok(app_data_dir(app)?.join("telemetry.json"))
+}
+
+/// Sends the aggregated telemetry report once per interval, only while the user
+/// has opted in. Counters are saved between runs, so a report that fell due
+/// while the app was closed is sent right after the next start, and kept when
+/// sending fails.
+async fn run_telemetry_reporter(app: tauri::AppHandle) {
+    let path = match telemetry_path(&app) {
+        Ok(path) => path,
+        Err(e) => {
+            tracing::warn!("Telemetry disabled, no data dir: {}", e);
+            return;
+        }
+    };
+    let enabled = current_config(&app.state::<AppState>()).is_ok_and(|c| c.telemetry_enabled);
+    telemetry::start(&path, enabled, chrono::Utc::now().timestamp());
+    // The first tick completes immediately
+    let mut interval = tokio::time::interval(telemetry::CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let state = app.state::<AppState>();
+        let Ok(config) = current_config(&state) else {
+            continue;
+        };
+        if !config.telemetry_enabled {
+            continue;
+        }
+        let now = chrono::Utc::now().timestamp();
+        if let (true, Some(server_url)) = (telemetry::is_due(now), &config.server_url) {
+            let options = ClientOptions::from_config(&config);
+            match api::send_telemetry(server_url, &options, &telemetry::report()).await {
+                Ok(()) => telemetry::reset(now),
+                Err(e) => tracing::debug!("Telemetry report not sent: {}", e),
+            }
+        }
+        if let Err(e) = telemetry::save(&path) {
+            tracing::debug!("Failed to save telemetry counters: {}", e);
+        }
     }
-    false
 }
 
+/// Reports the server API version, failing with an `incompatible` error when
+/// this client cannot sync with it.
 #[tauri::command]
-fn get_config(state: State<AppState>) -> Result<AppConfig, String> {
-    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
-    let cm = raw.as_ref().ok_or("Config not init")?;
-    let conf = cm.config.lock().map_err(|_| "Lock fail")?;
-    Ok(conf.clone())
+async fn check_server_compatibility(state: State<'_, AppState>) -> Result<ServerInfo> {
+    let client = api_client(&state)?;
+    let info = client.server_info().await?;
+    info.check_compatibility()?;
+    Ok(info)
 }
 
+/// Checks what support asks about first: server, sign-in, sync folder, disk
+/// space, file watching, keyring and sync database. Problems found are part
+/// of the report, not an error.
 #[tauri::command]
-fn save_config(
-    state: State<AppState>,
-    url: Option<String>,
-    path: Option<String>,
-    token: Option<String>,
-    completed: Option<bool>,
-) -> Result<(), String> {
-    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
-    let cm = raw.as_ref().ok_or("Config not init")?;
-    cm.update(url, path, token, completed)
+async fn run_diagnostics(state: State<'_, AppState>) -> Result<DiagnosticsReport> {
+    let config = current_config(&state)?;
+    let target = diagnostics::Target {
+        client: api_client(&state).ok(),
+        sync_root: config
+            .sync_path
+            .as_deref()
+            .map(|path| PathBuf::from(expand_sync_path(path))),
+        account: config.account_key(),
+        // Without the key the database check reports it cannot open the file
+        db_key: database_key(&config).unwrap_or_default(),
+        keyring_service: KEYRING_SERVICE_NEW,
+    };
+    Ok(diagnostics::run(target).await)
 }
 
+/// What the first sync of `path` moves: files and bytes in the folder and on
+/// the server, and the expected duration at the throughput seen while
+/// listing the server.
 #[tauri::command]
-fn expand_sync_path(path: &str) -> String {
-    if path.starts_with("~/") {
-        let home = std::env::var("HOME")
-            .or_else(|_| std::env::var("USERPROFILE"))
-            .unwrap_or_else(|_| ".".to_string());
-        path.replacen("~", &home, 1)
-    } else {
-        path.to_string()
+async fn estimate_initial_sync(
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<InitialSyncEstimate> {
+    let config = current_config(&state)?;
+    let api = sync::worker_api(api_client(&state)?, config.server_url.as_deref());
+    let root = PathBuf::from(expand_sync_path(&path));
+    estimate::estimate(api.as_ref(), &root, &config.ignore_patterns).await
+}
+
+/// Xynoxa servers announcing themselves on the local network (mDNS).
+#[tauri::command]
+async fn discover_servers() -> Result<Vec<discovery::DiscoveredServer>> {
+    tauri::async_runtime::spawn_blocking(|| discovery::browse(std::time::Duration::from_secs(3)))
+        .await
+        .map_err(|e| XynoxaError::Other(e.to_string()))?
+}
+
+/// Locates the Xynoxa API behind a URL entered in the setup wizard.
+#[tauri::command]
+async fn probe_server(state: State<'_, AppState>, url: String) -> Result<ServerProbe> {
+    let options = current_config(&state)
+        .map(|config| ClientOptions::from_config(&config))
+        .unwrap_or_default();
+    api::probe_server(&url, &options).await
+}
+
+#[tauri::command]
+async fn check_for_updates() -> Result<UpdateInfo> {
+    updater::check_for_updates().await
+}
+
+/// Downloads the installer of the latest release and launches it.
+#[tauri::command]
+async fn install_update(app: tauri::AppHandle) -> Result<()> {
+    let info = updater::check_for_updates().await?;
+    if !info.available {
+        return Ok(());
     }
+    let url = info.download_url.ok_or_else(|| {
+        XynoxaError::Other(format!(
+            "Release {} has no installer for this platform",
+            info.latest_version
+        ))
+    })?;
+    // Never launch an installer that could not be verified
+    let (Some(checksum_url), Some(signature_url)) = (info.checksum_url, info.signature_url) else {
+        return Err(XynoxaError::Other(format!(
+            "Release {} publishes no checksum or signature for its installer",
+            info.latest_version
+        )));
+    };
+    let path =
+        updater::download_installer(&url, &checksum_url, &signature_url, &std::env::temp_dir())
+            .await?;
+    tracing::info!("Launching installer {:?}", path);
+    app.opener()
+        .open_path(path.to_string_lossy(), None::<&str>)
+        .map_err(|e| XynoxaError::Other(e.to_string()))
 }
 
-fn validate_sync_root(path: &PathBuf) -> Result<(), String> {
-    if path.as_os_str().is_empty() {
-        return Err("Sync path is empty".to_string());
+/// Startup update check: notifies about new releases and installs them when
+/// `auto_install_updates` is enabled.
+async fn run_startup_update_check(app: tauri::AppHandle) {
+    // Installers launched by the previous run have finished by now
+    updater::remove_stale_downloads(&std::env::temp_dir());
+    let info = match updater::check_for_updates().await {
+        Ok(info) => info,
+        Err(e) => {
+            tracing::warn!("Update check failed: {}", e);
+            return;
+        }
+    };
+    if !info.available {
+        tracing::info!("Client is up to date ({})", info.current_version);
+        return;
     }
-    if !path.is_absolute() {
-        return Err("Sync path must be absolute".to_string());
+    tracing::info!("Update available: {}", info.latest_version);
+
+    let auto_install = current_config(&app.state::<AppState>())
+        .map(|c| c.auto_install_updates)
+        .unwrap_or(false);
+
+    let body = if auto_install {
+        i18n::tr_args("Installing version {0}...", &[&info.latest_version])
+    } else {
+        i18n::tr_args(
+            "Version {0} is available (installed: {1}).",
+            &[&info.latest_version, &info.current_version],
+        )
+    };
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title(i18n::tr("Xynoxa update"))
+        .body(body)
+        .show()
+    {
+        tracing::warn!("Failed to show notification: {}", e);
     }
-    if path.exists() {
-        if path.is_dir() {
-            return Ok(());
+
+    if auto_install {
+        if let Err(e) = install_update(app.clone()).await {
+            tracing::error!("Automatic update failed: {}", e);
         }
-        return Err("Sync path is not a directory".to_string());
     }
-    std::fs::create_dir_all(path).map_err(|e| e.to_string())?;
-    if !path.is_dir() {
-        return Err("Failed to create sync directory".to_string());
+}
+
+#[tauri::command]
+async fn list_trashed_files(state: State<'_, AppState>) -> Result<Vec<TrashedFile>> {
+    let client = api_client(&state)?;
+    client.list_trashed_files().await
+}
+
+#[tauri::command]
+async fn restore_file(state: State<'_, AppState>, file_id: String) -> Result<()> {
+    let client = api_client(&state)?;
+    client.restore_file(&file_id).await?;
+
+    // Pull the restore event right away so the file reappears locally
+    if let Ok(engine_guard) = state.sync_engine.lock() {
+        if let Some(handle) = &*engine_guard {
+            handle.force_sync();
+        }
     }
     Ok(())
 }
 
-fn start_sync(state: State<AppState>, token: Option<String>) -> Result<String, String> {
-    // Load config
-    let raw = state.config_manager.lock().map_err(|_| "Lock fail")?;
-    let cm = raw.as_ref().ok_or("Config not init")?;
-    let conf = cm.config.lock().map_err(|_| "Lock fail")?;
+/// The server's stored versions of `file_id`, newest first.
+#[tauri::command]
+async fn get_file_versions(
+    state: State<'_, AppState>,
+    file_id: String,
+) -> Result<Vec<FileVersion>> {
+    let client = api_client(&state)?;
+    client.list_versions(&file_id).await
+}
 
-    let path_str = conf.sync_path.clone().ok_or("No sync path configured")?;
-    let config_token = conf.auth_token.clone();
+/// Rolls the synced file `file_id` back to `version`: the old content
+/// replaces the local file and is uploaded as a new version, so the newer
+/// versions stay in the history.
+#[tauri::command]
+async fn restore_version(state: State<'_, AppState>, file_id: String, version: i64) -> Result<()> {
+    let path = {
+        let engine_guard = state
+            .sync_engine
+            .lock()
+            .map_err(|_| "Failed to lock state")?;
+        engine_guard
+            .as_ref()
+            .ok_or("Sync is not running")?
+            .get_file_by_id(&file_id)?
+            .ok_or_else(|| format!("{} is not synced to this device", file_id))?
+            .path
+    };
+    let local = sync::resolve_local_path(&sync_root(&state)?, &path)?;
+    let client = api_client(&state)?;
+    client.download_version(&file_id, version, &local).await?;
 
-    // Expand ~ for cross-platform safety
-    let path_str = expand_sync_path(&path_str);
+    if let Ok(engine_guard) = state.sync_engine.lock() {
+        if let Some(handle) = &*engine_guard {
+            handle.force_sync();
+        }
+    }
+    Ok(())
+}
 
-    let api_url = conf.server_url.clone(); // Clone before drop? yes.
+/// Deletes a trashed file for good.
+#[tauri::command]
+async fn purge_trashed_file(state: State<'_, AppState>, file_id: String) -> Result<()> {
+    let client = api_client(&state)?;
+    client.permanent_delete_file(&file_id).await
+}
 
-    drop(conf); // Unlock early
-    drop(raw);
+#[tauri::command]
+async fn empty_trash(state: State<'_, AppState>) -> Result<()> {
+    let client = api_client(&state)?;
+    client.empty_trash().await?;
+    tracing::info!("Emptied the trash");
+    Ok(())
+}
 
-    // Load token
-    let auth_token = if let Some(t) = token {
-        t
-    } else if let Some(t) = config_token {
-        t
-    } else {
-        if let Ok(entry) = Entry::new(KEYRING_SERVICE_NEW, "auth-token") {
-            entry
-                .get_password()
-                .map_err(|_| "Not logged in".to_string())?
-        } else if let Ok(entry) = Entry::new(KEYRING_SERVICE_LEGACY, "auth-token") {
-            entry
-                .get_password()
-                .map_err(|_| "Not logged in".to_string())?
-        } else {
-            return Err("Not logged in".to_string());
+const TRAY_ID: &str = "main";
+
+/// Tray icon variant that stays visible on a panel with `theme`.
+fn tray_icon(theme: Theme) -> Option<Image<'static>> {
+    let bytes: &[u8] = match theme {
+        Theme::Dark => include_bytes!("../icons/tray-dark.png"),
+        _ => include_bytes!("../icons/tray-light.png"),
+    };
+    match Image::from_bytes(bytes) {
+        Ok(icon) => Some(icon),
+        Err(e) => {
+            tracing::warn!("Tray icon for {:?} theme unavailable: {}", theme, e);
+            None
         }
+    }
+}
+
+/// Switches the tray icon after the desktop theme changed.
+fn update_tray_theme(app: &tauri::AppHandle, theme: Theme) {
+    let (Some(tray), Some(icon)) = (app.tray_by_id(TRAY_ID), tray_icon(theme)) else {
+        return;
     };
+    if let Err(e) = tray.set_icon(Some(icon)) {
+        tracing::warn!("Failed to update tray icon: {}", e);
+    }
+}
 
-    // Init Handle
-    let mut engine_guard = state
-        .sync_engine
-        .lock()
-        .map_err(|_| "Failed to lock state".to_string())?;
+/// Builds the tray icon and menu. Returns false if the desktop has no tray.
+fn setup_tray(app: &tauri::App) -> bool {
+    // Optional; never crash app if unavailable
+    let quit_i = match MenuItem::with_id(app, "quit", i18n::tr("Quit"), true, None::<&str>) {
+        Ok(item) => item,
+        Err(e) => {
+            tracing::warn!("Tray menu item 'quit' unavailable: {}", e);
+            return false;
+        }
+    };
+    let show_i = match MenuItem::with_id(app, "show", i18n::tr("Show"), true, None::<&str>) {
+        Ok(item) => item,
+        Err(e) => {
+            tracing::warn!("Tray menu item 'show' unavailable: {}", e);
+            return false;
+        }
+    };
+    let menu = match Menu::with_items(app, &[&show_i, &quit_i]) {
+        Ok(menu) => menu,
+        Err(e) => {
+            tracing::warn!("Tray menu unavailable: {}", e);
+            return false;
+        }
+    };
 
-    // Prevent parallel worker instances (prevents duplicate folder creates/uploads)
-    if engine_guard.is_some() {
-        log::info!("Sync already running - skipping second start");
-        return Ok("Sync already running".to_string());
+    let theme = app
+        .get_webview_window("main")
+        .and_then(|window| window.theme().ok())
+        .unwrap_or(Theme::Light);
+    let Some(icon) = tray_icon(theme).or_else(|| app.default_window_icon().cloned()) else {
+        tracing::warn!("Tray icon unavailable. Skipping tray initialization.");
+        return false;
+    };
+    let result = TrayIconBuilder::with_id(TRAY_ID)
+        .icon(icon)
+        // macOS recolors template icons to match the menu bar
+        .icon_as_template(true)
+        .menu(&menu)
+        .on_menu_event(move |app, event| match event.id().as_ref() {
+            "quit" => {
+                app.exit(0);
+            }
+            "show" => {
+                if let Some(window) = app.get_webview_window("main") {
+                    if let Err(e) = window.show() {
+                        tracing::error!("Failed to show window: {}", e);
+                    }
+                    if let Err(e) = window.set_focus() {
+                        tracing::error!("Failed to focus window: {}", e);
+                    }
+                }
+            }
+            _ => {}
+        })
+        .build(app);
+    if let Err(e) = result {
+        tracing::warn!("Tray initialization failed: {}", e);
+        return false;
     }
+    true
+}
 
-    // Create Handle (which spawns Worker)
-    let root = PathBuf::from(path_str);
-    validate_sync_root(&root)?;
-    let handle = SyncHandle::new(auth_token, root, api_url);
+fn hide_to_tray(window: &tauri::Window) {
+    match window.hide() {
+        Ok(_) => {
+            tracing::info!("Window hidden successfully.");
+            // On Linux Wayland, sometimes hide() alone isn't enough or is ignored visually
+            // if the window thinks it's being closed.
+            // Force minimize as well to ensure it leaves the workspace.
+            #[cfg(target_os = "linux")]
+            {
+                let _ = window.minimize();
+            }
+        }
+        Err(e) => {
+            tracing::error!("Failed to hide window: {}", e);
+            // Fallback to minimize if hide fails
+            let _ = window.minimize();
+        }
+    }
+}
 
-    *engine_guard = Some(handle);
-    Ok("Sync started".to_string())
+/// `xynoxa.log` next to the portable data, in the Flatpak sandbox (which
+/// cannot write to ~/.local/share) or in `~/.local/share/xynoxa/logs`.
+fn log_path(portable_dir: Option<&Path>) -> PathBuf {
+    let custom_dir = portable_dir
+        .map(Path::to_path_buf)
+        .or_else(sandbox::data_dir);
+    let log_dir = match custom_dir {
+        Some(dir) => dir.join("logs"),
+        None => {
+            let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+            PathBuf::from(&home).join(".local/share/xynoxa/logs")
+        }
+    };
+    std::fs::create_dir_all(&log_dir).ok();
+    log_dir.join("xynoxa.log")
 }
 
-#[tauri::command]
-fn get_file_list(state: State<AppState>) -> Result<Vec<crate::db::FileRecord>, String> {
-    let engine_guard = state
-        .sync_engine
+fn config_manager(portable_dir: Option<&Path>) -> ConfigManager {
+    let custom_dir = portable_dir
+        .map(Path::to_path_buf)
+        .or_else(sandbox::config_dir);
+    ConfigManager::new(custom_dir.as_deref(), ConfigOverrides::from_env_and_args())
+}
+
+/// Syncs without window or tray (`--headless`), e.g. as the systemd user
+/// service. Needs a completed setup; stops on Ctrl+C or SIGTERM.
+pub fn run_headless() {
+    let portable_dir = config::portable_dir();
+    logging::init(&log_path(portable_dir.as_deref()));
+    tracing::info!("Headless sync started");
+
+    let cm = config_manager(portable_dir.as_deref());
+    let handle = cm
+        .config
         .lock()
-        .map_err(|_| "Failed to lock state".to_string())?;
+        .map_err(|_| XynoxaError::from("Lock fail"))
+        .and_then(|conf| start_headless_sync(&conf));
+    let handle = match handle {
+        Ok(handle) => handle,
+        Err(e) => {
+            tracing::error!("Headless sync failed to start: {}", e);
+            eprintln!("{}", e.localized_message());
+            std::process::exit(service::EXIT_NOT_CONFIGURED);
+        }
+    };
 
-    if let Some(handle) = &*engine_guard {
-        handle.list_files()
-    } else {
-        Ok(vec![])
+    let engine = Arc::new(Mutex::new(Some(handle)));
+    if let Err(e) = control::start(
+        control::endpoint(portable_dir.as_deref()),
+        Arc::clone(&engine),
+    ) {
+        tracing::warn!("Control API unavailable: {}", e);
+    }
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
+    runtime.block_on(shutdown_signal());
+    tracing::info!("Headless sync stopping");
+    if let Ok(mut handle) = engine.lock() {
+        handle.take();
+    }
+}
+
+fn start_headless_sync(conf: &AppConfig) -> Result<SyncHandle> {
+    if !conf.setup_completed {
+        return Err(XynoxaError::Config(
+            "Setup is not complete; run the app once or pass --server-url, --sync-path and XYNOXA_TOKEN".to_string(),
+        ));
+    }
+    let root = PathBuf::from(expand_sync_path(
+        conf.sync_path.as_deref().ok_or("No sync path configured")?,
+    ));
+    validate_sync_root(&root, conf.sync_root_id.is_some())?;
+    let token = conf
+        .auth_token
+        .clone()
+        .or_else(|| {
+            [KEYRING_SERVICE_NEW, KEYRING_SERVICE_LEGACY]
+                .into_iter()
+                .find_map(|service| Entry::new(service, "auth-token").ok()?.get_password().ok())
+        })
+        .ok_or_else(|| XynoxaError::Auth("Not logged in".to_string()))?;
+
+    Ok(SyncHandle::new(
+        token,
+        root,
+        conf.server_url.clone(),
+        SyncOptions::from_config(conf),
+        database_key(conf)?,
+        // Nothing to sign out of; systemd must not restart into the same error
+        Box::new(|e| {
+            tracing::error!("Sync stopped: {}", e);
+            std::process::exit(service::EXIT_NOT_CONFIGURED);
+        }),
+        Box::new(|event| match event {
+            SyncEvent::Warning { message, .. } => tracing::warn!("{}", message),
+            SyncEvent::Conflict { .. } => tracing::warn!("Sync conflict: {:?}", event),
+            _ => {}
+        }),
+    ))
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
     }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Installs and starts the headless sync service (`systemd --user`). The app
+/// stops its own sync first; both must not sync the same folder.
+#[tauri::command]
+fn install_service(state: State<AppState>) -> Result<service::ServiceStatus> {
+    *state.sync_engine.lock().map_err(|_| "Lock fail")? = None;
+    service::install()
+}
+
+#[tauri::command]
+fn uninstall_service() -> Result<()> {
+    service::uninstall()
+}
+
+#[tauri::command]
+fn service_status() -> service::ServiceStatus {
+    service::status()
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -235,52 +2647,38 @@ pub fn run() {
         ))
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(tauri_plugin_notification::init())
         .manage(AppState {
             sync_engine: Mutex::new(None),
             config_manager: Mutex::new(None),
+            auto_upload: Mutex::new(None),
+            backup: Mutex::new(None),
+            tray_available: AtomicBool::new(false),
+            pairing: Mutex::new(None),
+            pending_login: Mutex::new(None),
+            virtual_drive: Mutex::new(None),
         })
         .setup(|app| {
-            // 1. Setup Logging
-            use simplelog::*;
-            use std::fs::File;
+            // Portable mode keeps config, ledger and logs next to the executable
+            let portable_dir = config::portable_dir();
 
-            let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-            let log_dir = PathBuf::from(&home).join(".local/share/xynoxa/logs");
-            std::fs::create_dir_all(&log_dir).ok();
-            let log_path = log_dir.join("xynoxa.log");
-
-            let _ = CombinedLogger::init(vec![
-                TermLogger::new(
-                    LevelFilter::Info,
-                    Config::default(),
-                    TerminalMode::Mixed,
-                    ColorChoice::Auto,
-                ),
-                WriteLogger::new(
-                    LevelFilter::Debug,
-                    Config::default(),
-                    File::create(&log_path).unwrap(),
-                ),
-            ]);
+            // 1. Setup Logging
+            logging::init(&log_path(portable_dir.as_deref()));
 
-            log::info!("Application started");
+            tracing::info!("Application started");
 
             // Panics to log
             std::panic::set_hook(Box::new(move |info| {
-                log::error!("Panic: {:?}", info);
+                tracing::error!("Panic: {:?}", info);
             }));
 
-            let _handle = app.handle();
-            let app_data_dir = match app.path().app_data_dir() {
-                Ok(dir) => dir,
-                Err(e) => {
-                    log::error!("Failed to resolve app_data_dir: {}", e);
-                    return Ok(());
-                }
-            };
+            if let Some(dir) = &portable_dir {
+                tracing::info!("Portable mode. Data directory: {:?}", dir);
+            }
 
             // 2. Init Config
-            let cm = ConfigManager::new(&app_data_dir);
+            let cm = config_manager(portable_dir.as_deref());
             let state = app.state::<AppState>();
 
             // Acquire lock to check config status
@@ -294,14 +2692,31 @@ pub fn run() {
                 false
             };
             drop(conf_guard); // Release lock
+
+            let locale = current_config(&state)
+                .ok()
+                .and_then(|config| config.locale)
+                .map(|tag| Locale::parse(&tag))
+                .unwrap_or_else(Locale::system);
+            i18n::set_locale(locale);
+            tracing::info!("UI language: {}", locale.as_str());
+
+            tauri::async_runtime::spawn(run_telemetry_reporter(app.handle().clone()));
+            tauri::async_runtime::spawn(run_session_expiry_check(app.handle().clone()));
+            tauri::async_runtime::spawn(run_startup_update_check(app.handle().clone()));
+            let endpoint = control::endpoint(portable_dir.as_deref());
+            if let Err(e) = control::start(endpoint, Arc::new(app.handle().clone())) {
+                tracing::warn!("Control API unavailable: {}", e);
+            }
             let window = match app.get_webview_window("main") {
                 Some(w) => w,
                 None => {
-                    log::error!("Main window not found. Skipping UI setup.");
+                    tracing::error!("Main window not found. Skipping UI setup.");
                     return Ok(());
                 }
             };
 
+            let mut started_in_background = false;
             if setup_completed {
                 // Try Config Token First
                 let mut token_found = None;
@@ -332,7 +2747,8 @@ pub fn run() {
                 }
 
                 if let Some(token) = token_found {
-                    log::info!("Setup complete and auth valid. Starting minimized.");
+                    tracing::info!("Setup complete and auth valid. Starting sync.");
+                    started_in_background = true;
 
                     // Clone handle for background thread
                     let app_handle = app.handle().clone();
@@ -347,117 +2763,160 @@ pub fn run() {
                         let path_str = conf.sync_path.clone().unwrap_or_default();
                         let path_str = expand_sync_path(&path_str);
                         let api_url = conf.server_url.clone();
+                        let options = SyncOptions::from_config(&conf);
+                        let db_key = database_key(&conf);
+                        let known_root = conf.sync_root_id.is_some();
+                        let synced_server = conf.sync_server_url.clone();
                         drop(conf);
                         drop(raw);
+                        let db_key = match db_key {
+                            Ok(db_key) => db_key,
+                            Err(e) => {
+                                tracing::error!(
+                                    "Database key unavailable, aborting autostart: {}",
+                                    e
+                                );
+                                return;
+                            }
+                        };
 
                         let root = PathBuf::from(path_str);
-                        if let Err(e) = validate_sync_root(&root) {
-                            log::error!("Sync root invalid, aborting autostart: {}", e);
+                        if let Err(e) = validate_sync_root(&root, known_root) {
+                            tracing::error!("Sync root invalid, aborting autostart: {}", e);
+                            return;
+                        }
+                        if let Err(e) = check_sync_server(
+                            &state,
+                            &root,
+                            synced_server.as_deref(),
+                            api_url.as_deref(),
+                        ) {
+                            tracing::error!("Not starting sync: {}", e);
+                            return;
+                        }
+                        remember_sync_root(&state, &root, &options.account, &db_key);
+                        if service::status().active && config::demo_dir().is_none() {
+                            tracing::info!("Sync runs in the background service. Not starting.");
                             return;
                         }
 
                         // SyncHandle::new starts the thread and watcher internally
-                        let handle = SyncHandle::new(token, root, api_url);
+                        let handle = SyncHandle::new(
+                            token,
+                            root,
+                            api_url,
+                            options,
+                            db_key,
+                            auth_lost_callback(&app_handle),
+                            sync_event_callback(&app_handle),
+                        );
                         *state.sync_engine.lock().unwrap() = Some(handle);
-                        log::info!("Sync engine auto-started in background.");
+                        tracing::info!("Sync engine auto-started in background.");
+
+                        if let Err(e) = start_auto_upload(&app_handle) {
+                            tracing::error!("Auto-upload failed to start: {}", e);
+                        }
+                        if let Err(e) = start_backup(&app_handle) {
+                            tracing::error!("Backup failed to start: {}", e);
+                        }
+                        let drive = current_config(&state).map(|c| c.virtual_drive);
+                        if drive.is_ok_and(|drive| drive.enabled) {
+                            if let Err(e) = start_virtual_drive(&app_handle) {
+                                tracing::error!("Virtual drive failed to mount: {}", e);
+                            }
+                        }
                     });
                 } else {
-                    log::warn!("Auth token missing despite setup_completed. Showing wizard.");
+                    tracing::warn!("Auth token missing despite setup_completed. Showing wizard.");
                     if let Err(e) = window.show() {
-                        log::error!("Failed to show window: {}", e);
+                        tracing::error!("Failed to show window: {}", e);
                     }
                     if let Err(e) = window.set_focus() {
-                        log::error!("Failed to focus window: {}", e);
+                        tracing::error!("Failed to focus window: {}", e);
                     }
                 }
             } else {
-                log::info!("Setup not complete. Showing wizard.");
+                tracing::info!("Setup not complete. Showing wizard.");
                 if let Err(e) = window.show() {
-                    log::error!("Failed to show window: {}", e);
+                    tracing::error!("Failed to show window: {}", e);
                 }
                 if let Err(e) = window.set_focus() {
-                    log::error!("Failed to focus window: {}", e);
+                    tracing::error!("Failed to focus window: {}", e);
                 }
             }
 
-            // Setup Tray (optional; never crash app if unavailable)
-            let quit_i = match MenuItem::with_id(app, "quit", "Quit", true, None::<&str>) {
-                Ok(item) => item,
-                Err(e) => {
-                    log::warn!("Tray menu item 'quit' unavailable: {}", e);
-                    return Ok(());
-                }
-            };
-            let show_i = match MenuItem::with_id(app, "show", "Show", true, None::<&str>) {
-                Ok(item) => item,
-                Err(e) => {
-                    log::warn!("Tray menu item 'show' unavailable: {}", e);
-                    return Ok(());
-                }
-            };
-            let menu = match Menu::with_items(app, &[&show_i, &quit_i]) {
-                Ok(menu) => menu,
-                Err(e) => {
-                    log::warn!("Tray menu unavailable: {}", e);
-                    return Ok(());
-                }
-            };
+            let tray_available = setup_tray(app);
+            state
+                .tray_available
+                .store(tray_available, Ordering::Relaxed);
 
-            if let Some(icon) = app.default_window_icon().cloned() {
-                if let Err(e) = TrayIconBuilder::new()
-                    .icon(icon)
-                    .menu(&menu)
-                    .on_menu_event(move |app, event| match event.id().as_ref() {
-                        "quit" => {
-                            app.exit(0);
-                        }
-                        "show" => {
-                            if let Some(window) = app.get_webview_window("main") {
-                                if let Err(e) = window.show() {
-                                    log::error!("Failed to show window: {}", e);
-                                }
-                                if let Err(e) = window.set_focus() {
-                                    log::error!("Failed to focus window: {}", e);
-                                }
-                            }
-                        }
-                        _ => {}
-                    })
-                    .build(app)
-                {
-                    log::warn!("Tray initialization failed: {}", e);
+            if started_in_background {
+                let behavior = current_config(&state)
+                    .map(|config| config.window)
+                    .unwrap_or_default();
+                if !behavior.start_minimized || !tray_available {
+                    tracing::info!("Showing window (tray available: {}).", tray_available);
+                    if let Err(e) = window.show() {
+                        tracing::error!("Failed to show window: {}", e);
+                    }
+                } else {
+                    tracing::info!("Starting minimized to tray.");
                 }
-            } else {
-                log::warn!("Tray icon unavailable. Skipping tray initialization.");
             }
 
             Ok(())
         })
         .on_window_event(|window, event| {
-            if let WindowEvent::CloseRequested { api, .. } = event {
-                log::info!("Window Close Requested. Intercepting...");
-
-                // Prevent close first to satisfy any OS constraints
-                api.prevent_close();
-
-                // Try to hide
-                match window.hide() {
-                    Ok(_) => {
-                        log::info!("Window hidden successfully.");
-                        // On Linux Wayland, sometimes hide() alone isn't enough or is ignored visually
-                        // if the window thinks it's being closed.
-                        // Force minimize as well to ensure it leaves the workspace.
-                        #[cfg(target_os = "linux")]
-                        {
-                            let _ = window.minimize();
-                        }
+            let state = window.state::<AppState>();
+            let tray_available = state.tray_available.load(Ordering::Relaxed);
+            let behavior = current_config(&state)
+                .map(|config| config.window)
+                .unwrap_or_default();
+
+            match event {
+                WindowEvent::CloseRequested { api, .. } => {
+                    if behavior.close_action == CloseAction::Quit {
+                        tracing::info!("Window Close Requested. Quitting.");
+                        window.app_handle().exit(0);
+                        return;
                     }
-                    Err(e) => {
-                        log::error!("Failed to hide window: {}", e);
-                        // Fallback to minimize if hide fails
+
+                    tracing::info!("Window Close Requested. Intercepting...");
+
+                    // Prevent close first to satisfy any OS constraints
+                    api.prevent_close();
+
+                    if !tray_available {
+                        // Hiding would make the app unreachable; keep it in the taskbar
                         let _ = window.minimize();
+                        return;
+                    }
+
+                    hide_to_tray(window);
+                }
+                WindowEvent::Resized(_) => {
+                    // Minimizing shows up as a resize
+                    if behavior.minimize_to_tray
+                        && tray_available
+                        && window.is_minimized().unwrap_or(false)
+                    {
+                        tracing::info!("Window minimized. Hiding to tray.");
+                        hide_to_tray(window);
                     }
                 }
+                WindowEvent::Focused(true) => {
+                    // Poll the server at the normal rate while the user is around
+                    if let Ok(engine) = state.sync_engine.lock() {
+                        if let Some(handle) = &*engine {
+                            handle.notify_activity();
+                        }
+                    }
+                }
+                WindowEvent::ThemeChanged(theme) => {
+                    tracing::info!("Desktop theme changed to {:?}.", theme);
+                    update_tray_theme(window.app_handle(), *theme);
+                }
+                _ => {}
             }
         })
         .invoke_handler(tauri::generate_handler![
@@ -467,8 +2926,80 @@ pub fn run() {
             check_auth,
             start_sync,
             get_file_list,
+            get_folder_health,
+            find_moved_sync_folder,
+            relink_sync_folder,
+            move_sync_folder,
+            confirm_server_change,
+            search_files,
+            search_remote,
+            get_transfer_queue,
+            cancel_transfer,
+            retry_transfer,
+            pause_sync,
+            resume_sync,
+            export_report,
+            list_sync_issues,
+            list_known_issues,
+            repair_sync_issue,
+            prioritize,
+            reveal_in_file_manager,
+            open_file,
+            pin_path,
+            unpin_path,
+            get_thumbnail,
+            upload_paths,
             get_config,
-            save_config
+            validate_config,
+            pick_sync_folder,
+            find_foreign_sync_folders,
+            import_foreign_folder,
+            discover_servers,
+            set_autostart,
+            install_service,
+            uninstall_service,
+            service_status,
+            save_config,
+            list_trashed_files,
+            restore_file,
+            get_file_versions,
+            restore_version,
+            purge_trashed_file,
+            empty_trash,
+            apply_auto_upload,
+            apply_backup,
+            backup_now,
+            list_backup_snapshots,
+            get_telemetry_preview,
+            check_for_updates,
+            install_update,
+            check_server_compatibility,
+            run_diagnostics,
+            estimate_initial_sync,
+            probe_server,
+            set_device_name,
+            set_ignore_patterns,
+            get_bandwidth_usage,
+            set_bandwidth_cap,
+            set_database_encryption,
+            get_sync_status,
+            set_locale,
+            submit_second_factor,
+            get_session_info,
+            open_conflict_resolver,
+            open_transfer_monitor,
+            open_trash,
+            list_conflicts,
+            resolve_conflict,
+            mount_virtual_drive,
+            unmount_virtual_drive,
+            set_file_availability,
+            start_pairing,
+            cancel_pairing,
+            list_devices,
+            revoke_device,
+            maintain_db,
+            rebuild_index
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,231 @@
+use regex::{Captures, Regex};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+/// Set `XYNOXA_LOG_FORMAT=json` to write the log file as JSON lines (one object per
+/// event, including the active span stack) for diagnostics.
+const LOG_FORMAT_ENV: &str = "XYNOXA_LOG_FORMAT";
+
+/// Set `XYNOXA_LOG_HASH_PATHS=1` to replace file paths with short hashes, e.g.
+/// before attaching the log to a support request. Tokens are always masked.
+const HASH_PATHS_ENV: &str = "XYNOXA_LOG_HASH_PATHS";
+
+/// Installs the global tracing subscriber: terminal output at INFO (overridable via
+/// `RUST_LOG`) and a debug log file at `log_path`. Also bridges `log` records from
+/// dependencies into tracing.
+pub fn init(log_path: &Path) {
+    let json = std::env::var(LOG_FORMAT_ENV)
+        .map(|v| v.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+
+    let hash_paths = std::env::var(HASH_PATHS_ENV)
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+
+    let term_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let term_layer = tracing_subscriber::fmt::layer()
+        .with_writer(Redacting::new(io::stderr, hash_paths))
+        .with_filter(term_filter);
+
+    // Span close events carry timing, which makes it easy to see which
+    // sync cycle / transfer / API call a log line belongs to.
+    let file_layer = match File::create(log_path) {
+        Ok(file) => {
+            let layer = tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_span_events(FmtSpan::CLOSE)
+                .with_writer(Redacting::new(Mutex::new(file), hash_paths));
+            let filter = EnvFilter::new("info,tauri_app_lib=debug");
+            Some(if json {
+                layer.json().with_filter(filter).boxed()
+            } else {
+                layer.with_filter(filter).boxed()
+            })
+        }
+        Err(e) => {
+            eprintln!("Failed to create log file {:?}: {}", log_path, e);
+            None
+        }
+    };
+
+    let _ = tracing_subscriber::registry()
+        .with(term_layer)
+        .with(file_layer)
+        .try_init();
+}
+
+/// Wraps a writer so every formatted log line passes through [`redact`].
+struct Redacting<M> {
+    inner: M,
+    hash_paths: bool,
+}
+
+impl<M> Redacting<M> {
+    fn new(inner: M, hash_paths: bool) -> Self {
+        Self { inner, hash_paths }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            hash_paths: self.hash_paths,
+        }
+    }
+}
+
+struct RedactingWriter<W> {
+    inner: W,
+    hash_paths: bool,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    // The fmt layer writes each event with a single call, so lines arrive whole
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.inner
+            .write_all(redact(&text, self.hash_paths).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn secret_patterns() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            (r"(?i)(bearer\s+)[A-Za-z0-9._~+/=-]+", "${1}[REDACTED]"),
+            (r"\b(xyn|syn)-[A-Za-z0-9_-]{4,}", "${1}-[REDACTED]"),
+            (
+                r#"(?i)((?:auth_?token|token|password|secret)"?\s*[:=]\s*"?)[^"\s,&}]+"#,
+                "${1}[REDACTED]",
+            ),
+        ]
+        .into_iter()
+        .map(|(re, rep)| (Regex::new(re).expect("valid redaction pattern"), rep))
+        .collect()
+    })
+}
+
+fn path_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r#"[^\s"'()\[\]{},;]*[/\\][^\s"'()\[\]{},;]*"#).unwrap())
+}
+
+/// Masks credentials and, with `hash_paths`, replaces anything path-like
+/// (except URLs) with `<path:hash>` so identical paths stay correlatable.
+pub fn redact(text: &str, hash_paths: bool) -> String {
+    let mut out = text.to_string();
+    for (re, rep) in secret_patterns() {
+        out = re.replace_all(&out, *rep).into_owned();
+    }
+    if hash_paths {
+        out = path_pattern()
+            .replace_all(&out, |caps: &Captures| {
+                let m = &caps[0];
+                if m.contains("://") {
+                    m.to_string()
+                } else {
+                    let digest = hex::encode(Sha256::digest(m.as_bytes()));
+                    format!("<path:{}>", &digest[..8])
+                }
+            })
+            .into_owned();
+    }
+    out
+}
+
+/// Lets a recurring failure be logged the first time and then at most once
+/// per interval, so one unreadable file does not fill the log every cycle.
+pub struct LogThrottle {
+    interval: Duration,
+    // Per failure key: when it was last logged and how often it was not since
+    seen: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl LogThrottle {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// Whether the failure `key` is logged now, with the number of repeats
+    /// left out since it last was.
+    pub fn check(&self, key: &str) -> Option<u32> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Option<u32> {
+        let mut seen = self.seen.lock().unwrap();
+        match seen.get_mut(key) {
+            Some((logged, skipped)) if now.duration_since(*logged) < self.interval => {
+                *skipped += 1;
+                None
+            }
+            Some((logged, skipped)) => {
+                *logged = now;
+                Some(std::mem::take(skipped))
+            }
+            None => {
+                seen.insert(key.to_string(), (now, 0));
+                Some(0)
+            }
+        }
+    }
+
+    /// Forgets `key` once the failure is gone, so a relapse is logged at once.
+    pub fn reset(&self, key: &str) {
+        self.seen.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle_logs_repeats_once_per_interval() {
+        let throttle = LogThrottle::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(throttle.check_at("a", at(0)), Some(0));
+        assert_eq!(throttle.check_at("a", at(10)), None);
+        assert_eq!(throttle.check_at("a", at(20)), None);
+        assert_eq!(throttle.check_at("b", at(20)), Some(0));
+        assert_eq!(throttle.check_at("a", at(61)), Some(2));
+        throttle.reset("a");
+        assert_eq!(throttle.check_at("a", at(62)), Some(0));
+    }
+
+    #[test]
+    fn test_redacts_tokens_and_paths() {
+        let line = r#"Authorization: Bearer abc.def auth_token: "xyn-12345678" at /home/me/Sync/a.txt via https://cloud.example/api"#;
+
+        let masked = redact(line, false);
+        assert!(!masked.contains("abc.def"));
+        assert!(!masked.contains("12345678"));
+        assert!(masked.contains("/home/me/Sync/a.txt"));
+
+        let hashed = redact(line, true);
+        assert!(!hashed.contains("/home/me"));
+        assert!(hashed.contains("<path:"));
+        assert!(hashed.contains("https://cloud.example/api"));
+    }
+}
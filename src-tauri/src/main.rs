@@ -2,5 +2,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
-    tauri_app_lib::run()
+    if std::env::args().skip(1).any(|arg| arg == "--headless") {
+        tauri_app_lib::run_headless()
+    } else {
+        tauri_app_lib::run()
+    }
 }
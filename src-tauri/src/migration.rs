@@ -0,0 +1,222 @@
+//! Moving from the Nextcloud or ownCloud desktop client: finds the folders
+//! those clients sync (their `nextcloud.cfg` / `owncloud.cfg`) so setup can
+//! offer one as the Xynoxa folder. Files already in it are adopted by hash on
+//! the first sync instead of downloaded again, like in any folder with content.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Files the other clients keep in their sync folders: the sync journal,
+/// logs and the exclude list. Added to the ignore patterns on import.
+pub const JOURNAL_PATTERNS: &[&str] = &[
+    ".sync_*.db*",
+    "._sync_*.db*",
+    ".csync_journal.db*",
+    ".owncloudsync.log*",
+    ".nextcloudsync.log*",
+    ".sync-exclude.lst",
+];
+
+/// A folder pairing of another desktop client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForeignFolder {
+    /// "Nextcloud" or "ownCloud"
+    pub client: &'static str,
+    pub server_url: String,
+    pub user: Option<String>,
+    pub local_path: String,
+}
+
+/// Folders of the Nextcloud and ownCloud clients on this machine that exist
+/// and mirror a whole account. Pairings of a server subfolder and folders
+/// with virtual files are left out: the first would not match the Xynoxa
+/// folder's layout, the second holds mostly placeholders.
+pub fn find_foreign_folders() -> Vec<ForeignFolder> {
+    let mut found: Vec<ForeignFolder> = Vec::new();
+    for (client, path) in config_files() {
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        tracing::info!("Found {} client configuration at {:?}", client, path);
+        for folder in parse_config(client, &text) {
+            if Path::new(&folder.local_path).is_dir()
+                && !found.iter().any(|f| f.local_path == folder.local_path)
+            {
+                found.push(folder);
+            }
+        }
+    }
+    found
+}
+
+/// `patterns` plus the journal patterns it lacks.
+pub fn with_journal_patterns(mut patterns: Vec<String>) -> Vec<String> {
+    for pattern in JOURNAL_PATTERNS {
+        if !patterns.iter().any(|p| p == pattern) {
+            patterns.push(pattern.to_string());
+        }
+    }
+    patterns
+}
+
+#[cfg(target_os = "linux")]
+fn config_files() -> Vec<(&'static str, PathBuf)> {
+    let home = PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| ".".to_string()));
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"));
+    vec![
+        ("Nextcloud", config.join("Nextcloud/nextcloud.cfg")),
+        (
+            "Nextcloud",
+            home.join(
+                ".var/app/com.nextcloud.desktopclient.nextcloud/config/Nextcloud/nextcloud.cfg",
+            ),
+        ),
+        ("ownCloud", config.join("ownCloud/owncloud.cfg")),
+    ]
+}
+
+#[cfg(target_os = "macos")]
+fn config_files() -> Vec<(&'static str, PathBuf)> {
+    let home = PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| ".".to_string()));
+    let preferences = home.join("Library/Preferences");
+    vec![
+        ("Nextcloud", preferences.join("Nextcloud/nextcloud.cfg")),
+        ("ownCloud", preferences.join("ownCloud/owncloud.cfg")),
+    ]
+}
+
+#[cfg(windows)]
+fn config_files() -> Vec<(&'static str, PathBuf)> {
+    let Some(app_data) = std::env::var_os("APPDATA").map(PathBuf::from) else {
+        return Vec::new();
+    };
+    vec![
+        ("Nextcloud", app_data.join("Nextcloud\\nextcloud.cfg")),
+        ("ownCloud", app_data.join("ownCloud\\owncloud.cfg")),
+    ]
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn config_files() -> Vec<(&'static str, PathBuf)> {
+    Vec::new()
+}
+
+/// Folder pairings in the `[Accounts]` section of a client's INI file
+/// (`0\url=...`, `0\Folders\1\localPath=...`).
+fn parse_config(client: &'static str, text: &str) -> Vec<ForeignFolder> {
+    let mut in_accounts = false;
+    let mut values: Vec<(Vec<&str>, String)> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_accounts = line == "[Accounts]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=').filter(|_| in_accounts) else {
+            continue;
+        };
+        values.push((key.trim().split('\\').collect(), unquote(value.trim())));
+    }
+    let value = |wanted: &[&str]| {
+        values
+            .iter()
+            .find(|(key, _)| key.as_slice() == wanted)
+            .map(|(_, value)| value.clone())
+            .filter(|value| !value.is_empty())
+    };
+
+    let mut folders = Vec::new();
+    for (key, local_path) in &values {
+        // <account>\Folders\<n>\localPath; Multifolders are synced the same way
+        let [account, group, n, "localPath"] = key.as_slice() else {
+            continue;
+        };
+        if !matches!(*group, "Folders" | "Multifolders") {
+            continue;
+        }
+        let target = value(&[*account, *group, *n, "targetPath"]).unwrap_or_default();
+        if !target.trim_matches('/').is_empty() {
+            continue;
+        }
+        let Some(server_url) = value(&[*account, "url"]) else {
+            continue;
+        };
+        let user = ["dav_user", "webflow_user", "http_user", "user"]
+            .iter()
+            .find_map(|field| value(&[*account, field]));
+        folders.push(ForeignFolder {
+            client,
+            server_url,
+            user,
+            local_path: local_path.trim_end_matches(['/', '\\']).to_string(),
+        });
+    }
+    folders
+}
+
+/// A value as Qt writes it: quoted when it contains special characters.
+fn unquote(value: &str) -> String {
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+        None => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let text = r#"[General]
+clientVersion=3.13.0
+
+[Accounts]
+0\Folders\1\localPath=/home/me/Nextcloud/
+0\Folders\1\targetPath=/
+0\Folders\2\localPath=/home/me/Work/
+0\Folders\2\targetPath=/Work
+0\FoldersWithPlaceholders\3\localPath=/home/me/Virtual/
+0\FoldersWithPlaceholders\3\targetPath=/
+0\dav_user=me
+0\url=https://cloud.example.com
+1\Multifolders\1\localPath="C:/Users/me/My \"Cloud\"/"
+1\Multifolders\1\targetPath=
+1\url=https://other.example.com
+version=13
+"#;
+        let folders = parse_config("Nextcloud", text);
+        assert_eq!(
+            folders,
+            vec![
+                ForeignFolder {
+                    client: "Nextcloud",
+                    server_url: "https://cloud.example.com".to_string(),
+                    user: Some("me".to_string()),
+                    local_path: "/home/me/Nextcloud".to_string(),
+                },
+                ForeignFolder {
+                    client: "Nextcloud",
+                    server_url: "https://other.example.com".to_string(),
+                    user: None,
+                    local_path: "C:/Users/me/My \"Cloud\"".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_journal_patterns() {
+        let patterns = with_journal_patterns(vec![".git".to_string(), ".sync_*.db*".to_string()]);
+        assert_eq!(patterns.len(), JOURNAL_PATTERNS.len() + 1);
+        let ignore = crate::ignore::IgnoreList::new(&patterns);
+        assert!(ignore.matches_name("._sync_4f1a2b.db-wal"));
+        assert!(ignore.matches_name(".nextcloudsync.log"));
+        assert!(!ignore.matches_name("notes.txt"));
+    }
+}
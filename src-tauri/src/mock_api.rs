@@ -0,0 +1,305 @@
+//! In-process stand-in for the Xynoxa server so the sync engine can be tested
+//! without a live instance. Keeps an ordered event log for `sync.pull`, file
+//! contents by id, and records every mutation the client sends. With the
+//! `demo` feature it also backs demo mode (see [`crate::config::demo_dir`]),
+//! seeded by [`MockApiClient::demo`].
+
+use crate::api::{
+    ApiClient, DownloadOutcome, FileData, FolderEntry, MutationBatch, ResumePoint, ResumeSink,
+    ServerInfo, SyncEvent, SyncResponse, UploadedFile,
+};
+use crate::error::{Result, XynoxaError};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Default)]
+pub struct MockState {
+    /// Reported by `server_info`; 0 means the default of 1.
+    pub api_version: u32,
+    /// Server event log; an event's position (1-based) is its cursor.
+    pub events: Vec<SyncEvent>,
+    /// File contents served by `download_file`, keyed by file id.
+    pub contents: HashMap<String, Vec<u8>>,
+    /// Every call sent through `execute_batch`, except folder creations,
+    /// which land in `created_folders`.
+    pub batch_calls: Vec<(String, serde_json::Value)>,
+    /// `(name, parent_id)` of every created folder.
+    pub created_folders: Vec<(String, Option<String>)>,
+    /// `(original_name, folder_id)` of every upload.
+    pub uploads: Vec<(String, Option<String>)>,
+    /// File ids whose content was actually transferred by `download_file`.
+    pub downloads: Vec<String>,
+    /// `(file_id, offset)` of every download continued from a resume point.
+    pub resumed_downloads: Vec<(String, u64)>,
+    /// Number of `find_folder` calls.
+    pub folder_lookups: usize,
+    next_id: u64,
+}
+
+impl MockState {
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}-{}", prefix, self.next_id)
+    }
+
+    fn create_folder(&mut self, name: &str, parent_id: Option<&str>) -> Result<FolderEntry> {
+        // Like the server, refuse duplicate names in a folder
+        if self.folder_id(name, parent_id).is_some() {
+            return Err(XynoxaError::Conflict(format!(
+                "Folder {} already exists",
+                name
+            )));
+        }
+        self.created_folders
+            .push((name.to_string(), parent_id.map(str::to_string)));
+        Ok(FolderEntry {
+            id: self.next_id("folder"),
+            name: Some(name.to_string()),
+        })
+    }
+
+    /// Id of the logged folder named `name` below `parent_id`.
+    fn folder_id(&self, name: &str, parent_id: Option<&str>) -> Option<String> {
+        self.events.iter().find_map(|e| {
+            let data = e.data.as_ref()?;
+            let parent = data.folder_id.as_deref().or(data.parent_id.as_deref());
+            (e.action == "create"
+                && e.entity_type == "folder"
+                && data.name.as_deref() == Some(name)
+                && parent == parent_id)
+                .then(|| e.entity_id.clone())
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct MockApiClient {
+    state: Mutex<MockState>,
+}
+
+impl MockApiClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Locks the server state for setup or assertions.
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Appends an event to the server log.
+    pub fn push_event(&self, action: &str, entity_type: &str, entity_id: &str, data: FileData) {
+        let mut state = self.state();
+        let id = state.events.len() as u64 + 1;
+        state.events.push(SyncEvent {
+            id,
+            owner_id: None,
+            action: action.to_string(),
+            entity_type: entity_type.to_string(),
+            entity_id: entity_id.to_string(),
+            data: Some(data),
+        });
+    }
+
+    /// Stores a remote folder and emits its `create` event.
+    pub fn add_folder(&self, id: &str, path: &str) {
+        let data = FileData {
+            path: Some(path.to_string()),
+            name: path.rsplit('/').next().map(str::to_string),
+            ..Default::default()
+        };
+        self.push_event("create", "folder", id, data);
+    }
+
+    /// Stores a remote file and emits its `create` event.
+    pub fn add_file(&self, id: &str, path: &str, content: &[u8]) {
+        self.state()
+            .contents
+            .insert(id.to_string(), content.to_vec());
+        let data = FileData {
+            path: Some(path.to_string()),
+            name: path.rsplit('/').next().map(str::to_string),
+            hash: Some(hex::encode(Sha256::digest(content))),
+            size: Some(content.len().to_string()),
+            ..Default::default()
+        };
+        self.push_event("create", "file", id, data);
+    }
+
+    /// A server with a few sample folders and files to explore the app with.
+    pub fn demo() -> Self {
+        let server = Self::new();
+        server.add_folder("demo-documents", "Documents");
+        server.add_folder("demo-photos", "Photos");
+        server.add_folder("demo-projects", "Projects");
+        server.add_folder("demo-website", "Projects/Website");
+        server.add_file(
+            "demo-welcome",
+            "Welcome.md",
+            b"# Welcome to Xynoxa\n\nThis is demo mode: the files come from a simulated \
+              server that only lives while the app runs. Add, change or delete files \
+              in this folder to see how they sync.\n",
+        );
+        server.add_file(
+            "demo-notes",
+            "Documents/Notes.txt",
+            b"Shopping list\n- Coffee\n- Bread\n",
+        );
+        server.add_file(
+            "demo-budget",
+            "Documents/Budget.csv",
+            b"Month,Income,Expenses\nJanuary,3200,2750\nFebruary,3200,2980\n",
+        );
+        server.add_file(
+            "demo-logo",
+            "Photos/Logo.svg",
+            b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"64\" height=\"64\">\
+              <circle cx=\"32\" cy=\"32\" r=\"28\" fill=\"#06b6d4\"/></svg>\n",
+        );
+        server.add_file(
+            "demo-index",
+            "Projects/Website/index.html",
+            b"<!doctype html>\n<title>Hello</title>\n<h1>Hello from Xynoxa</h1>\n",
+        );
+        server
+    }
+}
+
+#[async_trait]
+impl ApiClient for MockApiClient {
+    async fn server_info(&self) -> Result<ServerInfo> {
+        Ok(ServerInfo {
+            api_version: self.state().api_version.max(1),
+            version: Some("mock".to_string()),
+        })
+    }
+
+    async fn sync_pull(&self, cursor: u64, limit: Option<u32>) -> Result<SyncResponse> {
+        let state = self.state();
+        let limit = limit.unwrap_or(u32::MAX) as usize;
+        let events: Vec<SyncEvent> = state
+            .events
+            .iter()
+            .filter(|e| e.id > cursor)
+            .take(limit)
+            .cloned()
+            .collect();
+        let next_cursor = events.last().map(|e| e.id).unwrap_or(cursor);
+        Ok(SyncResponse {
+            has_more: Some(next_cursor < state.events.len() as u64),
+            events,
+            next_cursor,
+        })
+    }
+
+    async fn execute_batch(&self, batch: MutationBatch) -> Result<Vec<Result<serde_json::Value>>> {
+        let mut state = self.state();
+        Ok(batch
+            .calls()
+            .iter()
+            .map(|(procedure, input)| {
+                if procedure != "folders.create" {
+                    state.batch_calls.push((procedure.clone(), input.clone()));
+                    return Ok(serde_json::Value::Null);
+                }
+                let name = input["name"].as_str().unwrap_or_default();
+                let folder = state.create_folder(name, input["parentId"].as_str())?;
+                Ok(serde_json::to_value(folder)?)
+            })
+            .collect())
+    }
+
+    async fn create_folder(&self, name: &str, parent_id: Option<&str>) -> Result<FolderEntry> {
+        self.state().create_folder(name, parent_id)
+    }
+
+    async fn find_folder(
+        &self,
+        name: &str,
+        parent_id: Option<&str>,
+    ) -> Result<Option<FolderEntry>> {
+        let mut state = self.state();
+        state.folder_lookups += 1;
+        Ok(state.folder_id(name, parent_id).map(|id| FolderEntry {
+            id,
+            name: Some(name.to_string()),
+        }))
+    }
+
+    async fn upload_file(
+        &self,
+        local_path: &Path,
+        file_id: Option<&str>,
+        folder_id: Option<&str>,
+        original_name: &str,
+    ) -> Result<UploadedFile> {
+        let content = std::fs::read(local_path)?;
+        let mut state = self.state();
+        let id = match file_id {
+            Some(id) => id.to_string(),
+            None => state.next_id("file"),
+        };
+        state
+            .uploads
+            .push((original_name.to_string(), folder_id.map(str::to_string)));
+        let uploaded = UploadedFile {
+            id: id.clone(),
+            path: original_name.to_string(),
+            size: content.len().to_string(),
+            mime: "application/octet-stream".to_string(),
+            hash: hex::encode(Sha256::digest(&content)),
+            storage_path: None,
+        };
+        state.contents.insert(id, content);
+        Ok(uploaded)
+    }
+
+    async fn download_file(
+        &self,
+        file_id: &str,
+        local_path: &Path,
+        if_none_match: Option<&str>,
+    ) -> Result<DownloadOutcome> {
+        let mut state = self.state();
+        let content = state
+            .contents
+            .get(file_id)
+            .cloned()
+            .ok_or_else(|| XynoxaError::Server {
+                status: 404,
+                message: format!("File {} not found", file_id),
+            })?;
+        // The mock uses the quoted content hash as ETag
+        let etag = format!("\"{}\"", hex::encode(Sha256::digest(&content)));
+        if if_none_match.is_some_and(|tags| tags.split(',').any(|t| t.trim() == etag)) {
+            return Ok(DownloadOutcome::NotModified);
+        }
+        state.downloads.push(file_id.to_string());
+        drop(state);
+
+        if let Some(parent) = local_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(local_path, content)?;
+        Ok(DownloadOutcome::Downloaded { etag: Some(etag) })
+    }
+
+    async fn download_file_resumable(
+        &self,
+        file_id: &str,
+        local_path: &Path,
+        if_none_match: Option<&str>,
+        resume: Option<ResumePoint>,
+        _on_progress: ResumeSink<'_>,
+    ) -> Result<DownloadOutcome> {
+        if let Some(point) = resume {
+            self.state()
+                .resumed_downloads
+                .push((file_id.to_string(), point.offset));
+        }
+        self.download_file(file_id, local_path, if_none_match).await
+    }
+}
@@ -0,0 +1,78 @@
+//! Network change notifications (netlink on Linux, SystemConfiguration on
+//! macOS, the IP helper API on Windows). An address appearing or vanishing
+//! means Wi-Fi reconnected, a cable was plugged in or a VPN went up or down,
+//! so the sync worker reconnects right away instead of at its next periodic
+//! check.
+
+use if_watch::IfEvent;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// The watcher first reports every existing address; those are not changes.
+const STARTUP_QUIET: Duration = Duration::from_secs(2);
+
+/// Calls `on_change` from a background thread whenever a routable address is
+/// added or removed. The thread ends once `on_change` returns false.
+pub fn watch(on_change: impl Fn() -> bool + Send + 'static) -> std::io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    std::thread::Builder::new()
+        .name("network-monitor".to_string())
+        .spawn(move || {
+            runtime.block_on(async move {
+                let mut watcher = match if_watch::tokio::IfWatcher::new() {
+                    Ok(watcher) => watcher,
+                    Err(e) => {
+                        tracing::warn!("Network change monitor unavailable: {}", e);
+                        return;
+                    }
+                };
+                let started = Instant::now();
+                loop {
+                    let event = match std::future::poll_fn(|cx| watcher.poll_if_event(cx)).await {
+                        Ok(event) => event,
+                        Err(e) => {
+                            tracing::warn!("Network change monitor failed: {}", e);
+                            return;
+                        }
+                    };
+                    if started.elapsed() < STARTUP_QUIET || !is_relevant(&event) {
+                        continue;
+                    }
+                    tracing::info!("Network changed: {:?}", event);
+                    if !on_change() {
+                        return;
+                    }
+                }
+            })
+        })?;
+    Ok(())
+}
+
+/// Loopback and IPv6 link-local addresses come and go without connectivity
+/// changing.
+fn is_relevant(event: &IfEvent) -> bool {
+    let (IfEvent::Up(net) | IfEvent::Down(net)) = event;
+    match net.addr() {
+        IpAddr::V4(addr) => !addr.is_loopback() && !addr.is_link_local(),
+        IpAddr::V6(addr) => !addr.is_loopback() && (addr.segments()[0] & 0xffc0) != 0xfe80,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_only_addresses_are_ignored() {
+        let up = |net: &str| IfEvent::Up(net.parse().unwrap());
+        assert!(is_relevant(&up("192.168.1.20/24")));
+        assert!(is_relevant(&IfEvent::Down("10.8.0.2/32".parse().unwrap())));
+        assert!(is_relevant(&up("2001:db8::5/64")));
+        assert!(!is_relevant(&up("127.0.0.1/8")));
+        assert!(!is_relevant(&up("169.254.3.1/16")));
+        assert!(!is_relevant(&up("fe80::1/64")));
+        assert!(!is_relevant(&up("::1/128")));
+    }
+}
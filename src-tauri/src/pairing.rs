@@ -0,0 +1,86 @@
+//! Signing in by pairing with a browser session instead of pasting a token.
+//!
+//! The client shows a short code and a QR code of the approval page. Once the
+//! user approves the pairing in the web UI, `devices.pairing.poll` hands out a
+//! token scoped to this device.
+
+use crate::api::{PairingRequest, PairingStatus, XynoxaClient};
+use crate::error::{Result, XynoxaError};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// What the setup wizard shows while waiting for approval.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PairingPrompt {
+    #[serde(flatten)]
+    pub request: PairingRequest,
+    /// `verification_url` as an SVG QR code, `None` if it does not fit one
+    pub qr_svg: Option<String>,
+}
+
+impl PairingPrompt {
+    pub fn new(request: PairingRequest) -> Self {
+        let qr_svg = QrCode::new(request.verification_url.as_bytes())
+            .map(|code| code.render::<svg::Color>().min_dimensions(192, 192).build())
+            .ok();
+        Self { request, qr_svg }
+    }
+}
+
+/// What an approved pairing hands out.
+#[derive(Debug, Clone)]
+pub struct Approval {
+    /// Token scoped to this device
+    pub token: String,
+    /// Id the server registered this device under; older servers leave the
+    /// registration to the client
+    pub device_id: Option<String>,
+}
+
+/// Payload of the `pairing://finished` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct PairingFinished {
+    pub approved: bool,
+    pub error: Option<String>,
+}
+
+/// Polls until the pairing is decided and returns the approval. Returns
+/// `None` as soon as `is_active` turns false, i.e. the user cancelled.
+pub async fn wait_for_approval(
+    client: &XynoxaClient,
+    request: &PairingRequest,
+    is_active: impl Fn() -> bool,
+) -> Option<Result<Approval>> {
+    let deadline = Instant::now() + Duration::from_secs(request.expires_in);
+    let interval = Duration::from_secs(request.interval.max(1));
+    loop {
+        tokio::time::sleep(interval).await;
+        if !is_active() {
+            return None;
+        }
+        if Instant::now() >= deadline {
+            return Some(Err(expired()));
+        }
+        match client.poll_pairing(&request.pairing_id).await {
+            Ok(PairingStatus::Pending) => {}
+            Ok(PairingStatus::Approved { token, device_id }) => {
+                return Some(Ok(Approval { token, device_id }))
+            }
+            Ok(PairingStatus::Denied) => {
+                return Some(Err(XynoxaError::Auth(
+                    "Pairing was denied in the browser".to_string(),
+                )))
+            }
+            Ok(PairingStatus::Expired) => return Some(Err(expired())),
+            // Keep polling through network hiccups until the deadline
+            Err(e) => tracing::warn!("Pairing poll failed: {}", e),
+        }
+    }
+}
+
+fn expired() -> XynoxaError {
+    XynoxaError::Auth("Pairing code expired".to_string())
+}
@@ -0,0 +1,77 @@
+//! Keeps the machine from sleeping while long transfers run, so an overnight
+//! initial sync actually finishes. Linux asks logind through `systemd-inhibit`,
+//! macOS takes a power assertion through `caffeinate`, Windows sets the
+//! thread's execution state.
+
+/// Held while transfers run; sleep is allowed again when dropped. On Windows
+/// it must be dropped on the thread that acquired it.
+pub struct SleepInhibitor {
+    #[cfg(not(windows))]
+    helper: std::process::Child,
+}
+
+impl SleepInhibitor {
+    /// `None` if the platform offers no inhibitor (e.g. no logind, sandboxed).
+    pub fn acquire(reason: &str) -> Option<Self> {
+        match Self::platform_acquire(reason) {
+            Ok(inhibitor) => {
+                tracing::info!("Preventing sleep: {}", reason);
+                Some(inhibitor)
+            }
+            Err(e) => {
+                tracing::debug!("Sleep inhibitor unavailable: {}", e);
+                None
+            }
+        }
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn platform_acquire(reason: &str) -> std::io::Result<Self> {
+        // Holds the lock until killed
+        let helper = std::process::Command::new("systemd-inhibit")
+            .args(["--what=sleep", "--who=Xynoxa", "--mode=block"])
+            .arg(format!("--why={}", reason))
+            .args(["sleep", "infinity"])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()?;
+        Ok(Self { helper })
+    }
+
+    #[cfg(target_os = "macos")]
+    fn platform_acquire(_reason: &str) -> std::io::Result<Self> {
+        // -w ends the assertion with our process, even after a crash
+        let helper = std::process::Command::new("caffeinate")
+            .args(["-i", "-w", &std::process::id().to_string()])
+            .spawn()?;
+        Ok(Self { helper })
+    }
+
+    #[cfg(windows)]
+    fn platform_acquire(_reason: &str) -> std::io::Result<Self> {
+        use windows::Win32::System::Power::{
+            SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED,
+        };
+        if unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) }.0 == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self {})
+    }
+}
+
+impl Drop for SleepInhibitor {
+    fn drop(&mut self) {
+        #[cfg(not(windows))]
+        {
+            let _ = self.helper.kill();
+            let _ = self.helper.wait();
+        }
+        #[cfg(windows)]
+        unsafe {
+            windows::Win32::System::Power::SetThreadExecutionState(
+                windows::Win32::System::Power::ES_CONTINUOUS,
+            );
+        }
+        tracing::info!("Sleep allowed again");
+    }
+}
@@ -0,0 +1,81 @@
+//! Sandboxed installs (Flatpak). Inside the sandbox, files outside the app's
+//! own directories are only reachable through the XDG desktop portals, and
+//! config, logs and autostart have to stay within the app's own directories.
+
+#[cfg(target_os = "linux")]
+use crate::error::{Result, XynoxaError};
+use std::path::{Path, PathBuf};
+
+/// Whether the app runs inside a Flatpak sandbox.
+pub fn is_flatpak() -> bool {
+    std::env::var_os("FLATPAK_ID").is_some() || Path::new("/.flatpak-info").exists()
+}
+
+/// Asks the file chooser portal for a folder; `None` if the user cancelled.
+/// The returned path is exported into the sandbox by the document portal.
+#[cfg(target_os = "linux")]
+pub async fn pick_folder(title: &str) -> Result<Option<PathBuf>> {
+    use ashpd::desktop::file_chooser::SelectedFiles;
+    use ashpd::desktop::ResponseError;
+
+    let request = SelectedFiles::open_file()
+        .title(title)
+        .directory(true)
+        .multiple(false)
+        .modal(true);
+    let files = match request.send().await.and_then(|r| r.response()) {
+        Ok(files) => files,
+        Err(ashpd::Error::Response(ResponseError::Cancelled)) => return Ok(None),
+        Err(e) => {
+            return Err(XynoxaError::Other(format!(
+                "Folder selection failed: {}",
+                e
+            )))
+        }
+    };
+    Ok(files.uris().first().and_then(|uri| uri.to_file_path().ok()))
+}
+
+/// Per-app config directory of the sandbox (`~/.var/app/<id>/config/xynoxa`);
+/// `None` outside Flatpak. `~/.config` is not writable from inside.
+pub fn config_dir() -> Option<PathBuf> {
+    sandbox_dir("XDG_CONFIG_HOME")
+}
+
+/// Per-app data directory of the sandbox, for logs; `None` outside Flatpak.
+pub fn data_dir() -> Option<PathBuf> {
+    sandbox_dir("XDG_DATA_HOME")
+}
+
+fn sandbox_dir(var: &str) -> Option<PathBuf> {
+    if !is_flatpak() {
+        return None;
+    }
+    std::env::var_os(var).map(|dir| PathBuf::from(dir).join("xynoxa"))
+}
+
+/// Asks the background portal to start the app minimized at login (or not);
+/// autostart entries written from inside the sandbox are never run.
+#[cfg(target_os = "linux")]
+pub async fn request_autostart(enabled: bool) -> Result<()> {
+    use ashpd::desktop::background::Background;
+
+    let binary = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "xynoxa-desktop-client".to_string());
+    let response = Background::request()
+        .reason("Keep your files in sync after login")
+        .auto_start(enabled)
+        .command([binary.as_str(), "--minimized"])
+        .send()
+        .await
+        .and_then(|r| r.response())
+        .map_err(|e| XynoxaError::Other(format!("Background portal request failed: {}", e)))?;
+    if enabled && !response.auto_start() {
+        return Err(XynoxaError::Other(
+            "Starting at login was not allowed".to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+//! `systemd --user` unit that runs the sync engine headless (`--headless`)
+//! at login, for machines without a desktop session or tray.
+
+use crate::error::{Result, XynoxaError};
+use serde::Serialize;
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+
+pub const UNIT_NAME: &str = "xynoxa-sync.service";
+
+/// Exit code of `--headless` when setup or login is missing; the unit does not
+/// restart on it, since retrying cannot help.
+pub const EXIT_NOT_CONFIGURED: i32 = 78;
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct ServiceStatus {
+    /// The unit file exists
+    pub installed: bool,
+    /// Started at login
+    pub enabled: bool,
+    /// Running right now; the app must not sync the same folder in parallel
+    pub active: bool,
+}
+
+/// Unit running `exe --headless`, restarted after crashes.
+pub fn unit_file(exe: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Xynoxa file sync\n\
+         After=network-online.target\n\
+         Wants=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=simple\n\
+         ExecStart=\"{}\" --headless\n\
+         Restart=on-failure\n\
+         RestartSec=30\n\
+         RestartPreventExitStatus={}\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        // systemd unquotes with C-style escapes and expands % specifiers
+        exe.to_string_lossy()
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%"),
+        EXIT_NOT_CONFIGURED
+    )
+}
+
+/// `~/.config/systemd/user/xynoxa-sync.service`
+#[cfg(target_os = "linux")]
+fn unit_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("systemd/user").join(UNIT_NAME))
+}
+
+/// Writes the unit for the running executable, enables and starts it.
+#[cfg(target_os = "linux")]
+pub fn install() -> Result<ServiceStatus> {
+    let path = unit_path().ok_or_else(|| XynoxaError::Config("No home directory".to_string()))?;
+    let exe = std::env::var_os("APPIMAGE")
+        .map(PathBuf::from)
+        .map_or_else(std::env::current_exe, Ok)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, unit_file(&exe))?;
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", UNIT_NAME])?;
+    tracing::info!("Installed sync service {:?}", path);
+    Ok(status())
+}
+
+/// Stops and disables the unit and removes its file.
+#[cfg(target_os = "linux")]
+pub fn uninstall() -> Result<()> {
+    let Some(path) = unit_path().filter(|p| p.exists()) else {
+        return Ok(());
+    };
+    if let Err(e) = systemctl(&["disable", "--now", UNIT_NAME]) {
+        tracing::warn!("Failed to disable sync service: {}", e);
+    }
+    std::fs::remove_file(&path)?;
+    systemctl(&["daemon-reload"])?;
+    tracing::info!("Removed sync service {:?}", path);
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn status() -> ServiceStatus {
+    let installed = unit_path().is_some_and(|p| p.exists());
+    if !installed {
+        return ServiceStatus::default();
+    }
+    ServiceStatus {
+        installed,
+        enabled: systemctl(&["is-enabled", "--quiet", UNIT_NAME]).is_ok(),
+        active: systemctl(&["is-active", "--quiet", UNIT_NAME]).is_ok(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn systemctl(args: &[&str]) -> Result<()> {
+    let output = std::process::Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .map_err(|e| XynoxaError::Other(format!("systemctl unavailable: {}", e)))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(XynoxaError::Other(format!(
+            "systemctl --user {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn install() -> Result<ServiceStatus> {
+    Err(XynoxaError::Other(
+        "The background service needs systemd (Linux)".to_string(),
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn uninstall() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn status() -> ServiceStatus {
+    ServiceStatus::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_file_quotes_executable() {
+        let unit = unit_file(Path::new("/opt/My Apps/xynoxa"));
+        assert!(unit.contains("ExecStart=\"/opt/My Apps/xynoxa\" --headless\n"));
+        assert!(unit.contains("RestartPreventExitStatus=78\n"));
+        assert!(unit.contains("WantedBy=default.target"));
+        assert!(unit_file(Path::new("/opt/a\"b")).contains("ExecStart=\"/opt/a\\\"b\""));
+    }
+}
@@ -1,36 +1,396 @@
-use crate::api::XynoxaClient;
-use crate::db::{Database, FileRecord};
-use notify::{RecursiveMode, Result as NotifyResult, Watcher};
+use crate::api::{
+    ApiClient, ClientOptions, DownloadOutcome, FileData, FolderEntry, MutationBatch,
+    RemoteSearchHit, ResumePoint, XynoxaClient,
+};
+use crate::cloud_files::{self, is_dehydrated, Placeholders};
+use crate::db::{
+    BandwidthDay, Database, DbKey, FileLabels, FileRecord, FolderHealth, KnownIssue,
+    MaintenanceReport, PendingOpKind, RemoteEntry, SyncIssue, WriteBatch, ACTIVITY_LIMIT,
+};
+use crate::config::{AppConfig, HooksConfig, ReclaimConfig, WatcherConfig};
+use crate::error::{Result, XynoxaError};
+use crate::hooks::Hooks;
+use crate::ignore::IgnoreList;
+use crate::io_scheduler;
+use crate::lan::{ContentLookup, LanTransfer};
+use crate::logging::LogThrottle;
+use crate::network;
+use crate::power::SleepInhibitor;
+use crate::report::{ReportFile, SyncReport};
+use crate::transfers::{
+    self, ChunkProgress, Direction, Scheduling, Transfer, TransferQueue, TransferState,
+};
+use crate::watcher::{self, FolderWatcher, OwnChanges, PendingSaves};
+use crate::{i18n, telemetry};
+use notify::Result as NotifyResult;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
-use std::sync::atomic::{AtomicBool, Ordering};
+/// Page size requested from `sync.pull`. The server may clamp it.
+const PULL_PAGE_SIZE: u32 = 200;
+
+/// Maximum number of `search_files` results.
+const SEARCH_LIMIT: usize = 200;
+
+/// Automatic `Database::maintain` interval.
+const MAINTENANCE_INTERVAL_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Background integrity check: how often it runs while the sync is idle and
+/// how much it re-hashes per run. Every file comes up in turn.
+const VERIFY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const VERIFY_BATCH_FILES: usize = 200;
+const VERIFY_BATCH_BYTES: u64 = 256 * 1024 * 1024;
+
+/// How often the disk is checked for `ReclaimConfig::disk_usage_percent`.
+const RECLAIM_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Local items processed per DB commit during the PUSH phase.
+const DB_CHECKPOINT_INTERVAL: usize = 500;
+
+/// How often a running transfer checks whether the user cancelled it.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Periodic pull interval while things change; doubled after every pull that
+/// brought nothing new, up to `MAX_POLL_INTERVAL`.
+const PERIODIC_SYNC_INTERVAL: Duration = Duration::from_secs(20);
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Pending transfer volume from which sleep is inhibited (`prevent_sleep`).
+const SLEEP_INHIBIT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Rescan interval while uploads wait for files other programs are writing.
+const BUSY_RETRY_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Created and removed in the sync root to find out if it is writable.
+pub const WRITE_PROBE: &str = ".xynoxa-probe";
+
+/// Files at least this large are checked for being mostly holes.
+const SPARSE_MIN_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A failure that keeps recurring is logged again at most this often; it
+/// stays listed as a [`KnownIssue`] meanwhile.
+const ERROR_LOG_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// [`KnownIssue`] key of failed sync cycles; other keys are a kind and a path.
+const CYCLE_ISSUE: &str = "sync";
+
+/// Over the monthly bandwidth cap only transfers up to this size run, besides
+/// the ones the user prioritized.
+const ESSENTIAL_TRANSFER_BYTES: u64 = 1024 * 1024;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
+use tracing::Instrument;
+
+/// Called from the worker thread when the server rejects the token during sync
+/// (e.g. the device was revoked). The worker stops afterwards.
+pub type AuthLostCallback = Box<dyn Fn(&XynoxaError) + Send>;
+
+/// Receives [`SyncEvent`]s from the worker thread.
+pub type EventCallback = Box<dyn Fn(SyncEvent) + Send + Sync>;
+
+/// Overall worker state, carried by `sync://state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncState {
+    Idle,
+    Syncing,
+    Error,
+    Stopped,
+    /// The sync root is missing or read-only; retried until it is back
+    Unavailable,
+    /// Paused by the user; changes are picked up on resume
+    Paused,
+}
+
+/// Progress reported by the worker. The webview receives each variant as the
+/// event named by [`SyncEvent::name`] with the fields as payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum SyncEvent {
+    State {
+        state: SyncState,
+    },
+    FileStarted {
+        path: String,
+        direction: Direction,
+    },
+    FileFinished {
+        path: String,
+        direction: Direction,
+        error: Option<String>,
+    },
+    Error {
+        code: &'static str,
+        message: String,
+    },
+    Conflict {
+        path: String,
+        backup: String,
+    },
+    /// Transfers finished so far in the current sync cycle
+    Progress {
+        done: usize,
+        total: usize,
+    },
+    /// The server confirmed another chunk of a large upload
+    ChunkProgress {
+        path: String,
+        #[serde(flatten)]
+        progress: ChunkProgress,
+    },
+    /// Sync keeps running, but the user should know (e.g. degraded watching)
+    Warning {
+        code: &'static str,
+        message: String,
+    },
+    /// A document is open in Office on another device (`open`), or no longer
+    DocumentLock {
+        path: String,
+        open: bool,
+    },
+}
+
+impl SyncEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyncEvent::State { .. } => "sync://state",
+            SyncEvent::FileStarted { .. } => "sync://file-started",
+            SyncEvent::FileFinished { .. } => "sync://file-finished",
+            SyncEvent::Error { .. } => "sync://error",
+            SyncEvent::Conflict { .. } => "sync://conflict",
+            SyncEvent::Progress { .. } => "sync://progress",
+            SyncEvent::ChunkProgress { .. } => "sync://chunk-progress",
+            SyncEvent::Warning { .. } => "sync://warning",
+            SyncEvent::DocumentLock { .. } => "sync://document-lock",
+        }
+    }
+}
+
+/// A server search hit with what this device has of it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteSearchResult {
+    #[serde(flatten)]
+    pub hit: RemoteSearchHit,
+    /// Path in the sync folder; `None` if the file is not synced here
+    pub local_path: Option<String>,
+    pub status: Option<FileStatus>,
+}
+
+/// Sync state of a single index entry, as shown next to search results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Synced,
+    /// Not uploaded yet, or a remote change is not applied yet
+    Pending,
+    Queued,
+    Transferring,
+    Failed,
+    /// Transfer cancelled by the user
+    Skipped,
+}
+
+/// Why the upload of a local file waits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BusyReason {
+    /// Opened for writing by another program (Windows)
+    Locked,
+    /// Size or modification time changed since the file was queued
+    Changing,
+}
+
+/// A local file whose upload is deferred because it is still being written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BusyFile {
+    pub path: String,
+    pub reason: BusyReason,
+    /// Unix time the upload was first deferred
+    pub since: i64,
+    /// Sync cycle that last found the file busy
+    #[serde(skip)]
+    cycle: u64,
+}
+
+/// Returned by the `get_sync_status` command.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    pub busy_files: Vec<BusyFile>,
+    pub paused: bool,
+}
+
+/// Returned by the `get_bandwidth_usage` command.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthUsage {
+    /// Days of the current month with transfers, oldest first
+    pub days: Vec<BandwidthDay>,
+    pub month_uploaded: u64,
+    pub month_downloaded: u64,
+    /// Monthly cap in bytes
+    pub cap: Option<u64>,
+    /// The cap is reached: large transfers wait until next month
+    pub capped: bool,
+}
+
+impl BandwidthUsage {
+    fn load(db: &Database, cap: Option<u64>) -> Result<Self> {
+        let month_start = chrono::Local::now().format("%Y-%m-01").to_string();
+        let days = db.bandwidth_since(&month_start)?;
+        let month_uploaded = days.iter().map(|d| d.uploaded).sum();
+        let month_downloaded = days.iter().map(|d| d.downloaded).sum();
+        Ok(Self {
+            days,
+            month_uploaded,
+            month_downloaded,
+            cap,
+            capped: cap.is_some_and(|cap| month_uploaded + month_downloaded >= cap),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub record: FileRecord,
+    pub status: FileStatus,
+    /// Kept available offline, itself or through a parent folder
+    pub pinned: bool,
+}
+
+/// A tracked file with its tags and favorite flag from the server.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileListEntry {
+    #[serde(flatten)]
+    pub record: FileRecord,
+    #[serde(flatten)]
+    pub labels: FileLabels,
+}
+
+fn file_status(record: &FileRecord, transfers: &HashMap<String, TransferState>) -> FileStatus {
+    match transfers.get(&record.path) {
+        Some(TransferState::Queued) => FileStatus::Queued,
+        Some(TransferState::Active) => FileStatus::Transferring,
+        Some(TransferState::Failed) => FileStatus::Failed,
+        Some(TransferState::Cancelled) => FileStatus::Skipped,
+        None if record.id.is_none() => FileStatus::Pending,
+        None if record
+            .remote_hash
+            .as_ref()
+            .is_some_and(|remote| *remote != record.hash) =>
+        {
+            FileStatus::Pending
+        }
+        None => FileStatus::Synced,
+    }
+}
+
+/// Settings for [`SyncHandle::new`], taken from `AppConfig`.
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    pub client: ClientOptions,
+    pub watcher: WatcherConfig,
+    pub files_on_demand: bool,
+    pub reclaim_space: ReclaimConfig,
+    pub prevent_sleep: bool,
+    pub lan_transfer: bool,
+    pub small_files_first: bool,
+    pub ignore_patterns: Vec<String>,
+    pub lock_notifications: bool,
+    pub hooks: HooksConfig,
+    /// Names the sync database, see [`AppConfig::account_key`]
+    pub account: String,
+    /// Monthly cap in bytes, see [`AppConfig::bandwidth_cap_mb`]
+    pub bandwidth_cap: Option<u64>,
+}
+
+impl SyncOptions {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            client: ClientOptions::from_config(config),
+            watcher: config.watcher.clone(),
+            files_on_demand: config.files_on_demand,
+            reclaim_space: config.reclaim_space.clone(),
+            prevent_sleep: config.prevent_sleep,
+            lan_transfer: config.lan_transfer,
+            small_files_first: config.small_files_first,
+            ignore_patterns: config.ignore_patterns.clone(),
+            lock_notifications: config.lock_notifications,
+            hooks: config.hooks.clone(),
+            account: config.account_key(),
+            bandwidth_cap: bandwidth_cap(config.bandwidth_cap_mb),
+        }
+    }
+}
 
 #[allow(dead_code)]
 pub struct SyncHandle {
     sender: Sender<SyncCommand>,
+    /// Disconnected when the worker thread has ended
+    stopped: Receiver<()>,
     local_root: PathBuf,
+    db_path: PathBuf,
+    db_key: DbKey,
+    transfers: Arc<TransferQueue>,
+    ignore: Arc<IgnoreList>,
+    busy: Arc<Mutex<HashMap<String, BusyFile>>>,
+    paused: Arc<AtomicBool>,
+    bandwidth_cap: Arc<Mutex<Option<u64>>>,
 }
 
 impl SyncHandle {
-    pub fn new(token: String, local_root: PathBuf, api_url: Option<String>) -> Self {
+    pub fn new(
+        token: String,
+        local_root: PathBuf,
+        api_url: Option<String>,
+        options: SyncOptions,
+        db_key: DbKey,
+        on_auth_lost: AuthLostCallback,
+        on_event: EventCallback,
+    ) -> Self {
         let (tx, rx) = channel();
+        let (stopped_tx, stopped_rx) = channel::<()>();
+        let hooks = Hooks::new(options.hooks.clone(), local_root.clone());
+        let on_event: EventCallback = Box::new(move |event| {
+            hooks.fire(&event);
+            on_event(event)
+        });
 
         let worker_token = token.clone();
         let worker_root = local_root.clone();
         let worker_url = api_url.clone();
+        let worker_db_key = db_key.clone();
+        let transfers = Arc::new(TransferQueue::default());
+        if options.small_files_first {
+            transfers.set_scheduling(Scheduling::SmallFirst);
+        }
+        let worker_transfers = Arc::clone(&transfers);
+        let ignore = Arc::new(IgnoreList::new(&options.ignore_patterns));
+        let worker_ignore = Arc::clone(&ignore);
+        let ignore_for_watcher = Arc::clone(&ignore);
+        let busy = Arc::new(Mutex::new(HashMap::new()));
+        let worker_busy = Arc::clone(&busy);
+        let paused = Arc::new(AtomicBool::new(false));
+        let worker_paused = Arc::clone(&paused);
+        let bandwidth_cap = Arc::new(Mutex::new(options.bandwidth_cap));
+        let worker_bandwidth_cap = Arc::clone(&bandwidth_cap);
 
         // Ensure root exists before watching
         if let Err(e) = ensure_sync_root(&local_root) {
-            log::error!("Failed to initialize sync root {:?}: {}", local_root, e);
+            tracing::error!("Failed to initialize sync root {:?}: {}", local_root, e);
         }
+        let db_path = resolve_db_path(&local_root, &options.account);
+        let worker_db_path = db_path.clone();
 
         // Channel for watcher to communicate with worker
         // Actually, easiest is to pipe watcher events to the SAME channel 'tx'.
@@ -40,25 +400,20 @@ impl SyncHandle {
         let tx_for_watcher = tx.clone();
         let worker_root_clone_for_watcher = local_root.clone();
 
-        // Shared flag to suppress watcher events during active sync
-        // This prevents the debounce timer from being reset by sync-created files
-        let sync_active = Arc::new(AtomicBool::new(false));
-        let sync_active_for_watcher = Arc::clone(&sync_active);
+        // Paths the worker changes; their events would only restart the
+        // debounce timer, while the user's changes still get through
+        let own_changes = Arc::new(OwnChanges::default());
+        let own_changes_for_watcher = Arc::clone(&own_changes);
 
-        let mut watcher =
-            notify::recommended_watcher(move |res: NotifyResult<notify::Event>| match res {
+        let handler: watcher::EventHandler =
+            Arc::new(move |res: NotifyResult<notify::Event>| match res {
                 Ok(event) => {
-                    // Skip all events while sync is in progress (prevents debounce reset)
-                    if sync_active_for_watcher.load(Ordering::Relaxed) {
-                        return;
-                    }
-
                     // Ignore read-only access events
                     if let notify::EventKind::Access(_) = event.kind {
                         return;
                     }
 
-                    log::debug!("Watcher Event: {:?}", event);
+                    tracing::debug!("Watcher Event: {:?}", event);
 
                         // Filter out .xynoxa.db/.xynoxa.db, hidden files, and the root directory itself
                     let is_relevant = event.paths.iter().any(|p| {
@@ -67,15 +422,16 @@ impl SyncHandle {
                             return false;
                         }
 
+                        // Caused by the sync itself, e.g. a download
+                        if own_changes_for_watcher.is_own(p) {
+                            return false;
+                        }
+
                         // Check every component to ensure no parent is ignored (specifically .git)
                         if let Ok(rel) = p.strip_prefix(&worker_root_clone_for_watcher) {
                             for component in rel.components() {
                                 if let Some(os_str) = component.as_os_str().to_str() {
-                                    if os_str == ".git"
-                                        || os_str == "node_modules"
-                                        || os_str == ".xynoxa.db"
-                                        || os_str == ".xynoxa.db"
-                                    {
+                                    if ignore_for_watcher.matches_name(os_str) {
                                         return false;
                                     }
                                 }
@@ -87,19 +443,33 @@ impl SyncHandle {
                     });
 
                     if is_relevant {
-                        log::info!("FS Event triggered by relevant paths: {:?}", event.paths);
+                        tracing::info!("FS Event triggered by relevant paths: {:?}", event.paths);
                         let _ = tx_for_watcher.send(SyncCommand::FileSystemEvent(event));
                     } else {
-                        log::debug!("FS Event ignored (hidden/irrelevant): {:?}", event.paths);
+                        tracing::debug!("FS Event ignored (hidden/irrelevant): {:?}", event.paths);
                     }
                 }
-                Err(e) => println!("Watch error: {:?}", e),
-            })
-            .expect("Failed to create watcher");
+                // A directory created later could not be watched
+                Err(e) if watcher::is_watch_limit(&e) => {
+                    let _ = tx_for_watcher.send(SyncCommand::WatchLimitReached);
+                }
+                Err(e) => tracing::warn!("Watch error: {:?}", e),
+            });
 
-        watcher
-            .watch(&local_root, RecursiveMode::Recursive)
-            .expect("Failed to watch root");
+        let watcher = FolderWatcher::start(
+            &local_root,
+            options.watcher.mode,
+            options.watcher.poll_interval_secs,
+            handler,
+        )
+        .expect("Failed to watch root");
+
+        let tx_for_network = tx.clone();
+        if let Err(e) = network::watch(move || {
+            tx_for_network.send(SyncCommand::NetworkChanged).is_ok()
+        }) {
+            tracing::warn!("Failed to start network change monitor: {}", e);
+        }
 
         thread::spawn(move || {
             // Worker takes ownership of watcher to keep it alive?
@@ -108,29 +478,398 @@ impl SyncHandle {
             // If we move watcher to thread, it stays alive as long as thread.
             // Let's move watcher to worker.
 
-            let mut worker = SyncWorker::new(
+            let client = XynoxaClient::with_options(
                 worker_token,
+                worker_url.clone().unwrap_or_default(),
+                &options.client,
+            );
+            let placeholder_client = client.clone();
+            let lan_client = client.clone();
+            let worker = SyncWorker::new(
+                worker_api(client, worker_url.as_deref()),
                 worker_root,
-                worker_url,
+                &worker_db_path,
+                &worker_db_key,
                 rx,
-                Some(Box::new(watcher)),
-                sync_active,
+                Some(watcher),
+                own_changes,
             );
+            let mut worker = match worker {
+                Ok(worker) => worker,
+                Err(e) => {
+                    tracing::error!("Sync database unavailable, worker not started: {}", e);
+                    on_event(SyncEvent::Error {
+                        code: e.code(),
+                        message: e.to_string(),
+                    });
+                    on_event(SyncEvent::State {
+                        state: SyncState::Error,
+                    });
+                    return;
+                }
+            };
+            worker.on_auth_lost = Some(on_auth_lost);
+            worker.on_event = Some(on_event);
+            worker.transfers = worker_transfers;
+            worker.ignore = worker_ignore;
+            worker.lock_notifications = options.lock_notifications;
+            worker.busy = worker_busy;
+            worker.paused = worker_paused;
+            worker.bandwidth_cap = worker_bandwidth_cap;
+            worker.prevent_sleep = options.prevent_sleep;
+            worker.reclaim = options.reclaim_space.clone();
+            if options.files_on_demand {
+                match Placeholders::connect(&worker.local_root, placeholder_client) {
+                    Ok(placeholders) => worker.placeholders = Some(placeholders),
+                    Err(e) => tracing::error!("Files on demand unavailable, downloading files: {}", e),
+                }
+            }
+            if worker.reclaim.enabled && worker.placeholders.is_none() {
+                tracing::warn!("Freeing up space needs files on demand; leaving local files alone");
+            }
+            if options.lan_transfer {
+                match worker.runtime.block_on(lan_client.lan_key()) {
+                    Ok(Some(key)) => {
+                        let lookup = content_lookup(
+                            worker.local_root.clone(),
+                            worker_db_path.clone(),
+                            worker_db_key.clone(),
+                        );
+                        match LanTransfer::start(&key, lookup) {
+                            Ok(lan) => worker.lan = Some(Arc::new(lan)),
+                            Err(e) => tracing::warn!("LAN transfer unavailable: {}", e),
+                        }
+                    }
+                    Ok(None) => tracing::debug!("Server does not coordinate LAN transfers"),
+                    Err(e) => tracing::warn!("Failed to get LAN key: {}", e),
+                }
+            }
             if let Err(e) = worker.run() {
-                log::error!("Sync Worker crashed: {}", e);
+                tracing::error!("Sync Worker crashed: {}", e);
             }
+            // After the worker, which closes the database and the watcher
+            drop(worker);
+            drop(stopped_tx);
         });
 
         Self {
             sender: tx,
+            stopped: stopped_rx,
             local_root,
+            db_path,
+            db_key,
+            transfers,
+            ignore,
+            busy,
+            paused,
+            bandwidth_cap,
+        }
+    }
+
+    /// Uploads waiting for other programs to finish writing, and whether the
+    /// sync is paused.
+    pub fn status(&self) -> SyncStatus {
+        let mut busy_files: Vec<BusyFile> = self.busy.lock().unwrap().values().cloned().collect();
+        busy_files.sort_by(|a, b| a.path.cmp(&b.path));
+        SyncStatus {
+            busy_files,
+            paused: self.paused.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Stops the worker after the running cycle. The receiver disconnects
+    /// once it let go of the sync folder, e.g. before moving it.
+    pub fn stop(mut self) -> Receiver<()> {
+        let _ = self.sender.send(SyncCommand::Shutdown);
+        let (_, placeholder) = channel();
+        std::mem::replace(&mut self.stopped, placeholder)
+    }
+
+    fn open_db(&self) -> Result<Database> {
+        Ok(Database::open(&self.db_path, &self.db_key)?)
+    }
+
+    /// Stops syncing after the running cycle until [`SyncHandle::resume`].
+    /// Local changes are still recorded.
+    pub fn pause(&self) {
+        if !self.paused.swap(true, Ordering::Relaxed) {
+            let _ = self.sender.send(SyncCommand::Pause);
+        }
+    }
+
+    /// Syncs again, starting with a full scan for what changed meanwhile.
+    pub fn resume(&self) {
+        if self.paused.swap(false, Ordering::Relaxed) {
+            self.force_sync();
+        }
+    }
+
+    /// Replaces the ignore patterns and rescans, so files that are no longer
+    /// ignored get uploaded.
+    pub fn set_ignore_patterns(&self, patterns: &[String]) {
+        self.ignore.set(patterns);
+        self.force_sync();
+    }
+
+    /// Uploads and downloads that are running, waiting, failed or skipped.
+    pub fn transfer_queue(&self) -> Vec<Transfer> {
+        self.transfers.snapshot()
+    }
+
+    /// Skips the transfer of `path` (aborting it if running) until it is retried.
+    pub fn cancel_transfer(&self, path: &str) -> Result<()> {
+        self.transfers.cancel(path)
+    }
+
+    /// Runs the transfer of `path` before all other waiting transfers.
+    pub fn prioritize(&self, path: &str) -> Result<()> {
+        self.transfers.prioritize(path)
+    }
+
+    /// Keeps `path` (a file, or a folder with everything below it) always
+    /// available offline. Online-only copies are downloaded with the next cycle.
+    pub fn pin_path(&self, path: &str) -> Result<()> {
+        let db = self.open_db()?;
+        if db.get_file(path)?.is_none() {
+            return Err(format!("{} is not synced", path).into());
+        }
+        db.pin(path)?;
+        self.force_sync();
+        Ok(())
+    }
+
+    /// Undoes [`SyncHandle::pin_path`]. Downloaded content stays on disk.
+    pub fn unpin_path(&self, path: &str) -> Result<()> {
+        let db = self.open_db()?;
+        if !db.unpin(path)? {
+            return Err(format!("{} is not pinned", path).into());
+        }
+        Ok(())
+    }
+
+    /// Queues a failed or cancelled transfer again and starts a sync cycle.
+    pub fn retry_transfer(&self, path: &str) -> Result<()> {
+        self.transfers.retry(path)?;
+        self.force_sync();
+        Ok(())
+    }
+
+    /// Asks the worker to vacuum and prune the sync database between cycles.
+    /// The report arrives on the returned channel.
+    pub fn request_maintenance(&self) -> Result<Receiver<Result<MaintenanceReport>>> {
+        let (tx, rx) = channel();
+        self.sender
+            .send(SyncCommand::Maintain(tx))
+            .map_err(|_| "Sync worker is not running")?;
+        Ok(rx)
+    }
+
+    /// Asks the worker to rebuild the sync database from the server's event
+    /// log and the local files, see [`Database::reset_index`]. The number of
+    /// entries tracked afterwards arrives on the returned channel.
+    pub fn request_rebuild(&self) -> Result<Receiver<Result<usize>>> {
+        let (tx, rx) = channel();
+        self.sender
+            .send(SyncCommand::RebuildIndex(tx))
+            .map_err(|_| "Sync worker is not running")?;
+        Ok(rx)
+    }
+
+    /// The user is looking at the app; idle polling returns to its base interval.
+    pub fn notify_activity(&self) {
+        let _ = self.sender.send(SyncCommand::UserActive);
+    }
+
+    /// Requests an immediate full sync cycle (e.g. after restoring files remotely).
+    pub fn force_sync(&self) {
+        let _ = self.sender.send(SyncCommand::ForceSync);
+    }
+
+    /// Files whose content changed without being written, see [`SyncIssue`].
+    pub fn sync_issues(&self) -> Result<Vec<SyncIssue>> {
+        let db = self.open_db()?;
+        Ok(db.sync_issues()?)
+    }
+
+    /// Recurring failures, one entry per problem, see [`KnownIssue`].
+    pub fn known_issues(&self) -> Result<Vec<KnownIssue>> {
+        Ok(self.open_db()?.known_issues()?)
+    }
+
+    /// This month's transfers with the server and the cap, see
+    /// [`BandwidthUsage`].
+    pub fn bandwidth_usage(&self) -> Result<BandwidthUsage> {
+        let cap = *self.bandwidth_cap.lock().unwrap();
+        BandwidthUsage::load(&self.open_db()?, cap)
+    }
+
+    /// Replaces the monthly cap in bytes. Transfers it held back run with the
+    /// next sync.
+    pub fn set_bandwidth_cap(&self, cap: Option<u64>) {
+        *self.bandwidth_cap.lock().unwrap() = cap;
+        self.force_sync();
+    }
+
+    /// Resolves the issue of `path`: restores the server copy, or uploads
+    /// the local content as a deliberate edit. Runs with the next cycle.
+    pub fn repair_sync_issue(&self, path: &str, action: RepairAction) -> Result<()> {
+        let db = self.open_db()?;
+        let record = db.get_file(path)?;
+        match (action, record) {
+            (RepairAction::Download, Some(FileRecord { id: Some(id), .. })) => {
+                self.transfers
+                    .enqueue(path, Direction::Download, None, Some(&id), 0);
+            }
+            (RepairAction::Download, _) => {
+                return Err(format!("{} is not on the server", path).into());
+            }
+            (RepairAction::Upload, Some(record)) => {
+                // Without a known modification time the change counts as an edit
+                db.insert_or_update(&FileRecord {
+                    modified_at: 0,
+                    ..record
+                })?;
+            }
+            (RepairAction::Upload, None) => {}
         }
+        db.remove_sync_issue(path)?;
+        self.force_sync();
+        Ok(())
+    }
+
+    /// Index entries whose path contains all words of `query`.
+    pub fn search_files(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let db = self.open_db()?;
+        search_index(&db, &self.transfers, query)
+    }
+
+    /// Adds the local sync state to results of the server's search.
+    pub fn annotate_remote_hits(
+        &self,
+        hits: Vec<RemoteSearchHit>,
+    ) -> Result<Vec<RemoteSearchResult>> {
+        let db = self.open_db()?;
+        annotate_remote_hits(&db, &self.transfers, hits)
+    }
+
+    pub fn get_file_by_id(&self, file_id: &str) -> Result<Option<FileRecord>> {
+        let db = self.open_db()?;
+        Ok(db.get_file_by_id(file_id)?)
+    }
+
+    pub fn list_files(&self) -> Result<Vec<FileListEntry>> {
+        let db = self.open_db()?;
+        let mut labels = db.labels()?;
+        Ok(db
+            .get_all_files()?
+            .into_iter()
+            .map(|record| FileListEntry {
+                labels: record
+                    .id
+                    .as_ref()
+                    .and_then(|id| labels.remove(id))
+                    .unwrap_or_default(),
+                record,
+            })
+            .collect())
+    }
+
+    /// Unsynced transfers and conflicts at or below the folder `path`,
+    /// relative to the sync root.
+    pub fn folder_health(&self, path: &str) -> Result<FolderHealth> {
+        let db = self.open_db()?;
+        Ok(db.folder_health(path.trim_matches('/'))?)
+    }
+
+    /// Every tracked file with its status, and what the sync did lately.
+    pub fn report(&self) -> Result<SyncReport> {
+        let db = self.open_db()?;
+        let transfers = transfer_states(&self.transfers);
+        let sync_times = db.sync_times()?;
+        let mut files: Vec<ReportFile> = db
+            .get_all_files()?
+            .into_iter()
+            .map(|record| {
+                let folder = record.hash == "directory";
+                let size = fs::metadata(local_path_from_relative(&self.local_root, &record.path))
+                    .ok()
+                    .filter(|metadata| metadata.is_file())
+                    .map(|metadata| metadata.len());
+                ReportFile {
+                    status: file_status(&record, &transfers),
+                    synced_at: sync_times.get(&record.path).copied(),
+                    hash: (!folder).then(|| record.hash.clone()),
+                    path: record.path,
+                    id: record.id,
+                    folder,
+                    size,
+                }
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(SyncReport {
+            generated_at: chrono::Utc::now().timestamp(),
+            sync_root: self.local_root.to_string_lossy().to_string(),
+            files,
+            activity: db.recent_activity(ACTIVITY_LIMIT)?,
+        })
     }
+}
+
+/// How the user resolves a [`SyncIssue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepairAction {
+    /// Replace the local file with the server copy
+    Download,
+    /// Keep the local content and upload it
+    Upload,
+}
+
+fn transfer_states(queue: &TransferQueue) -> HashMap<String, TransferState> {
+    queue
+        .snapshot()
+        .into_iter()
+        .map(|t| (t.path, t.state))
+        .collect()
+}
+
+fn search_index(db: &Database, queue: &TransferQueue, query: &str) -> Result<Vec<SearchResult>> {
+    let transfers = transfer_states(queue);
+    db.search_files(query, SEARCH_LIMIT)?
+        .into_iter()
+        .map(|record| {
+            Ok(SearchResult {
+                status: file_status(&record, &transfers),
+                pinned: db.is_pinned(&record.path)?,
+                record,
+            })
+        })
+        .collect()
+}
+
+fn annotate_remote_hits(
+    db: &Database,
+    queue: &TransferQueue,
+    hits: Vec<RemoteSearchHit>,
+) -> Result<Vec<RemoteSearchResult>> {
+    let transfers = transfer_states(queue);
+    hits.into_iter()
+        .map(|hit| {
+            let record = db.get_file_by_id(&hit.id)?;
+            Ok(RemoteSearchResult {
+                status: record.as_ref().map(|record| file_status(record, &transfers)),
+                local_path: record.map(|record| record.path),
+                hit,
+            })
+        })
+        .collect()
+}
 
-    pub fn list_files(&self) -> Result<Vec<FileRecord>, String> {
-        let db_path = resolve_db_path(&self.local_root);
-        let db = Database::new(&db_path).map_err(|e| e.to_string())?;
-        db.get_all_files().map_err(|e| e.to_string())
+// The watcher holds a sender clone, so the channel never disconnects on its own
+impl Drop for SyncHandle {
+    fn drop(&mut self) {
+        let _ = self.sender.send(SyncCommand::Shutdown);
     }
 }
 
@@ -138,70 +877,202 @@ impl SyncHandle {
 enum SyncCommand {
     ForceSync,
     FileSystemEvent(notify::Event),
+    /// The platform watcher ran out of watches
+    WatchLimitReached,
+    /// An address was added or removed (reconnect, VPN up/down)
+    NetworkChanged,
+    /// The window gained focus
+    UserActive,
+    Maintain(Sender<Result<MaintenanceReport>>),
+    RebuildIndex(Sender<Result<usize>>),
+    /// [`SyncHandle::pause`] set the shared flag
+    Pause,
+    Shutdown,
 }
 
 struct SyncWorker {
-    client: XynoxaClient,
+    client: Arc<dyn ApiClient>,
     local_root: PathBuf,
     db: Database,
     receiver: Receiver<SyncCommand>,
-    #[allow(dead_code)] // Watcher is kept alive by being held here
-    watcher: Option<Box<dyn Watcher + Send>>,
-    sync_active: Arc<AtomicBool>,
+    // Also keeps the watcher alive
+    watcher: Option<FolderWatcher>,
+    // Paths being changed by the worker, shared with the watcher
+    own_changes: Arc<OwnChanges>,
     runtime: tokio::runtime::Runtime,
+    // Incremented per scan_and_sync; tags the sync_cycle span
+    cycle: AtomicU64,
+    // Set once the server passed the API version check
+    server_compatible: AtomicBool,
+    on_auth_lost: Option<AuthLostCallback>,
+    on_event: Option<EventCallback>,
+    transfers: Arc<TransferQueue>,
+    // Set when the token was rejected; the worker stops instead of retrying
+    auth_lost: AtomicBool,
+    // Files on demand: new server files become placeholders instead of downloads
+    placeholders: Option<Placeholders>,
+    // Turns rarely used files back into placeholders when the disk fills up
+    reclaim: ReclaimConfig,
+    prevent_sleep: bool,
+    // Direct transfers from the user's other devices on the same network
+    lan: Option<Arc<LanTransfer>>,
+    // Content hash announced for each queued download, by file id
+    expected_hashes: Mutex<HashMap<String, String>>,
+    // Local names that are never uploaded, shared with the watcher
+    ignore: Arc<IgnoreList>,
+    lock_notifications: bool,
+    // Office lock files on the server by id, with the document they lock
+    remote_locks: Mutex<HashMap<String, String>>,
+    // Deferred uploads of files still being written, shared with the handle
+    busy: Arc<Mutex<HashMap<String, BusyFile>>>,
+    // Temp files of editor saves, never uploaded
+    saves: PendingSaves,
+    // Pipes, devices and sparse files seen by the last scan, warned about once
+    special_files: Mutex<HashSet<String>>,
+    // Folders the last scan could not read; their files are not deleted remotely
+    unreadable: Mutex<Vec<String>>,
+    // Recurring failures are logged once per ERROR_LOG_INTERVAL
+    log_throttle: LogThrottle,
+    // The sync database in the root; `None` for an in-memory database
+    db_path: Option<PathBuf>,
+    // Filesystem the root was on when the worker opened its database
+    root_device: Option<u64>,
+    // Set while the sync root is missing or read-only
+    root_unavailable: AtomicBool,
+    // The root is back and has to be watched and scanned again
+    root_recovered: AtomicBool,
+    // Set by the handle; no cycles run while it is
+    paused: Arc<AtomicBool>,
+    // Monthly cap in bytes, set by the handle
+    bandwidth_cap: Arc<Mutex<Option<u64>>>,
+    // The cap was reached when the queue last started a transfer
+    capped: AtomicBool,
 }
 
 impl SyncWorker {
     fn new(
-        token: String,
+        client: Arc<dyn ApiClient>,
         local_root: PathBuf,
-        api_url: Option<String>,
+        db_path: &Path,
+        db_key: &DbKey,
         receiver: Receiver<SyncCommand>,
-        watcher: Option<Box<dyn Watcher + Send>>,
-        sync_active: Arc<AtomicBool>,
-    ) -> Self {
+        watcher: Option<FolderWatcher>,
+        own_changes: Arc<OwnChanges>,
+    ) -> Result<Self> {
         // Create DB
-        let db_path = resolve_db_path(&local_root);
         let _ = ensure_sync_root(&local_root);
-        let db = Database::new(&db_path).expect("Failed to initialize database");
+        let db = Database::open(db_path, db_key)?;
+        let root_device = root_device(&local_root);
+
+        Ok(Self {
+            db_path: Some(db_path.to_path_buf()),
+            root_device,
+            ..Self::with_client(client, local_root, db, receiver, watcher, own_changes)
+        })
+    }
 
+    /// Builds a worker around an injected API client and database (tests use
+    /// `MockApiClient` and `Database::new_in_memory`).
+    fn with_client(
+        client: Arc<dyn ApiClient>,
+        local_root: PathBuf,
+        db: Database,
+        receiver: Receiver<SyncCommand>,
+        watcher: Option<FolderWatcher>,
+        own_changes: Arc<OwnChanges>,
+    ) -> Self {
         // Create reusable runtime - avoids expensive runtime creation on every sync
         let runtime = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
 
         Self {
-            client: XynoxaClient::new(token, api_url.unwrap_or_default()),
+            client,
             local_root,
             db,
             receiver,
             watcher,
-            sync_active,
+            own_changes,
             runtime,
+            cycle: AtomicU64::new(0),
+            server_compatible: AtomicBool::new(false),
+            on_auth_lost: None,
+            on_event: None,
+            transfers: Arc::new(TransferQueue::default()),
+            auth_lost: AtomicBool::new(false),
+            placeholders: None,
+            reclaim: ReclaimConfig::default(),
+            prevent_sleep: false,
+            lan: None,
+            expected_hashes: Mutex::default(),
+            ignore: Arc::new(IgnoreList::default()),
+            lock_notifications: false,
+            remote_locks: Mutex::default(),
+            busy: Arc::default(),
+            saves: PendingSaves::default(),
+            special_files: Mutex::default(),
+            unreadable: Mutex::default(),
+            log_throttle: LogThrottle::new(ERROR_LOG_INTERVAL),
+            db_path: None,
+            root_device: None,
+            root_unavailable: AtomicBool::new(false),
+            root_recovered: AtomicBool::new(false),
+            paused: Arc::default(),
+            bandwidth_cap: Arc::default(),
+            capped: AtomicBool::new(false),
         }
     }
 
     #[allow(unused_assignments)] // sync_in_progress IS read in next loop iteration
-    fn run(&mut self) -> Result<(), String> {
-        log::info!("Sync Worker started.");
+    fn run(&mut self) -> Result<()> {
+        tracing::info!("Sync Worker started.");
+        if let Err(e) = self.db.rebuild_folder_health() {
+            tracing::warn!("Failed to recount folder health: {}", e);
+        }
+        match self.db.load_transfers() {
+            Ok(transfers) => self.transfers.restore(transfers),
+            Err(e) => tracing::error!("Failed to load transfer queue: {}", e),
+        }
+        if self.watcher.as_ref().is_some_and(|w| w.watch_limit_hit()) {
+            self.warn_watch_limit();
+        }
 
         // Initial Sync - suppress watcher events during initial sync
-        self.sync_active.store(true, Ordering::Relaxed);
-        if let Err(e) = self.scan_and_sync(true) {
-            // Full sync on startup
-            log::error!("Initial sync failed: {}", e);
-        }
-        self.sync_active.store(false, Ordering::Relaxed);
+        self.run_cycle(true, "Initial");
 
         // Debounce configuration: wait 4 seconds after last FS event before syncing
         const DEBOUNCE_DURATION: Duration = Duration::from_secs(4);
-        const PERIODIC_SYNC_INTERVAL: Duration = Duration::from_secs(20); // Check for server changes
 
         let mut last_fs_event: Option<std::time::Instant> = None;
         let mut pending_sync = false;
+        let mut poll_interval = PERIODIC_SYNC_INTERVAL;
+        let mut last_verification = std::time::Instant::now();
+        let mut last_reclaim = std::time::Instant::now();
 
         loop {
+            if self.auth_lost.load(Ordering::Relaxed) {
+                tracing::warn!("Authentication lost. Worker stopping.");
+                break;
+            }
+            if self.root_recovered.swap(false, Ordering::Relaxed) {
+                // Watches end when the folder is unmounted
+                if let Some(watcher) = self.watcher.as_mut() {
+                    if let Err(e) = watcher.restart() {
+                        tracing::error!("Failed to watch {:?} again: {}", self.local_root, e);
+                    }
+                }
+                // Changes made while it was away are picked up by a full scan
+                last_fs_event = Some(std::time::Instant::now());
+                pending_sync = true;
+            }
+
             // Calculate timeout: if we have pending events, use remaining debounce time
             // Otherwise, use periodic sync interval
-            let timeout = if pending_sync {
+            // Deferred uploads are retried with a local scan
+            let retry_busy = !self.busy.lock().unwrap().is_empty();
+            let paused = self.paused.load(Ordering::Relaxed);
+            let timeout = if paused {
+                // Only commands matter until resumed
+                PERIODIC_SYNC_INTERVAL
+            } else if pending_sync {
                 if let Some(last_event) = last_fs_event {
                     let elapsed = last_event.elapsed();
                     if elapsed >= DEBOUNCE_DURATION {
@@ -213,133 +1084,230 @@ impl SyncWorker {
                 } else {
                     DEBOUNCE_DURATION
                 }
+            } else if retry_busy {
+                poll_interval.min(BUSY_RETRY_INTERVAL)
             } else {
-                PERIODIC_SYNC_INTERVAL
+                poll_interval
             };
 
             match self.receiver.recv_timeout(timeout) {
                 Ok(cmd) => match cmd {
+                    SyncCommand::ForceSync if self.paused.load(Ordering::Relaxed) => {
+                        tracing::debug!("Sync paused, ignoring force sync");
+                    }
                     SyncCommand::ForceSync => {
-                        log::info!("Force sync requested");
+                        tracing::info!("Force sync requested");
+                        poll_interval = PERIODIC_SYNC_INTERVAL;
                         pending_sync = false;
                         last_fs_event = None;
-                        self.sync_active.store(true, Ordering::Relaxed);
-                        if let Err(e) = self.scan_and_sync(true) {
-                            // Full sync
-                            log::error!("Force sync failed: {}", e);
+                        self.run_cycle(true, "Force");
+                    }
+                    SyncCommand::WatchLimitReached => {
+                        if self.fall_back_to_polling() {
+                            // Changes in the unwatched directory may be missed
+                            last_fs_event = Some(std::time::Instant::now());
+                            pending_sync = true;
+                        }
+                    }
+                    SyncCommand::NetworkChanged => {
+                        // Sync once the burst of address changes is over and
+                        // DHCP/DNS have settled, like after a file change
+                        last_fs_event = Some(std::time::Instant::now());
+                        pending_sync = true;
+                        poll_interval = PERIODIC_SYNC_INTERVAL;
+                    }
+                    SyncCommand::UserActive => {
+                        if poll_interval > PERIODIC_SYNC_INTERVAL {
+                            tracing::debug!("User active, polling every {:?} again", PERIODIC_SYNC_INTERVAL);
+                            poll_interval = PERIODIC_SYNC_INTERVAL;
                         }
-                        self.sync_active.store(false, Ordering::Relaxed);
                     }
-                    SyncCommand::FileSystemEvent(_event) => {
-                        // FS events during sync are already filtered by the watcher
+                    SyncCommand::Maintain(reply) => {
+                        let _ = reply.send(self.maintain_db());
+                    }
+                    SyncCommand::RebuildIndex(reply) => {
+                        let _ = reply.send(self.rebuild_index());
+                    }
+                    SyncCommand::Pause => {
+                        tracing::info!("Sync paused");
+                        self.emit(SyncEvent::State {
+                            state: SyncState::Paused,
+                        });
+                    }
+                    SyncCommand::Shutdown => {
+                        tracing::info!("Shutdown requested. Worker stopping.");
+                        break;
+                    }
+                    SyncCommand::FileSystemEvent(event) => {
+                        // Events of the worker's own changes are already filtered by the watcher
+                        self.saves.record(&event);
                         // Reset debounce timer on each FS event
                         last_fs_event = Some(std::time::Instant::now());
                         pending_sync = true;
-                        log::debug!("FS Event received, debounce timer reset (4s)");
+                        poll_interval = PERIODIC_SYNC_INTERVAL;
+                        tracing::debug!("FS Event received, debounce timer reset (4s)");
                     }
                 },
                 Err(RecvTimeoutError::Timeout) => {
-                    if pending_sync {
+                    if paused {
+                        // Pending changes stay pending until resumed
+                    } else if pending_sync && self.saves.in_progress() {
+                        // An editor wrote its temp file and is about to rename
+                        // it over the document, upload only the result
+                        tracing::debug!("Editor save in progress, waiting for the rename");
+                        last_fs_event = Some(std::time::Instant::now());
+                    } else if pending_sync || retry_busy {
                         // Debounce period completed, now sync
-                        log::info!("Debounce complete (4s), starting sync...");
+                        tracing::info!("Debounce complete (4s), starting sync...");
                         pending_sync = false;
                         last_fs_event = None;
-                        self.sync_active.store(true, Ordering::Relaxed);
-                        if let Err(e) = self.scan_and_sync(true) {
-                            // Has local changes
-                            log::error!("Event sync failed: {}", e);
-                        }
-                        self.sync_active.store(false, Ordering::Relaxed);
+                        self.run_cycle(true, "Event");
                     } else {
                         // Periodic sync - only pull, no local scan
-                        log::debug!("Periodic sync check");
-                        self.sync_active.store(true, Ordering::Relaxed);
-                        if let Err(e) = self.scan_and_sync(false) {
-                            // No local changes
-                            log::error!("Periodic sync failed: {}", e);
+                        tracing::debug!("Periodic sync check");
+                        let cursor = self.db.get_cursor().unwrap_or(0);
+                        // No local changes
+                        self.run_cycle(false, "Periodic");
+                        // A moved cursor means the server had new events
+                        let changed = self.db.get_cursor().unwrap_or(0) != cursor;
+                        poll_interval = next_poll_interval(poll_interval, changed);
+
+                        let last = self.db.last_maintenance().unwrap_or(0);
+                        if chrono::Utc::now().timestamp() - last >= MAINTENANCE_INTERVAL_SECS {
+                            if let Err(e) = self.maintain_db() {
+                                tracing::error!("Scheduled DB maintenance failed: {}", e);
+                            }
+                        }
+                        // Only between idle polls, so it never delays a sync
+                        if last_verification.elapsed() >= VERIFY_INTERVAL {
+                            last_verification = std::time::Instant::now();
+                            if let Err(e) = self.verify_integrity() {
+                                tracing::error!("Integrity check failed: {}", e);
+                            }
+                        }
+                        if last_reclaim.elapsed() >= RECLAIM_INTERVAL {
+                            last_reclaim = std::time::Instant::now();
+                            if let Err(e) = self.reclaim_space() {
+                                tracing::error!("Freeing up space failed: {}", e);
+                            }
                         }
-                        self.sync_active.store(false, Ordering::Relaxed);
                     }
                 }
                 Err(RecvTimeoutError::Disconnected) => {
-                    log::info!("Channel disconnected. Worker stopping.");
+                    tracing::info!("Channel disconnected. Worker stopping.");
                     break;
                 }
             }
         }
+        self.emit(SyncEvent::State {
+            state: SyncState::Stopped,
+        });
         Ok(())
     }
 
-    fn scan_and_sync(&self, has_local_changes: bool) -> Result<(), String> {
-        log::debug!("Sync check starting...");
+    fn scan_and_sync(&self, has_local_changes: bool) -> Result<()> {
+        let cycle = self.cycle.fetch_add(1, Ordering::Relaxed) + 1;
+        let span = tracing::info_span!("sync_cycle", cycle, local_changes = has_local_changes);
+        let _enter = span.enter();
+        tracing::debug!("Sync check starting...");
 
-        self.runtime.block_on(async {
+        let started = std::time::Instant::now();
+        self.emit(SyncEvent::State {
+            state: SyncState::Syncing,
+        });
+        let result = self.runtime.block_on(async {
             // Safety: Ensure sync root is valid and accessible before doing anything
-            ensure_sync_root(&self.local_root)?;
+            self.check_sync_root()?;
+
+            // One transaction per cycle instead of one per row, committed after
+            // every pull page and every DB_CHECKPOINT_INTERVAL pushed items
+            let batch = self.db.begin_batch()?;
             normalize_db_paths(&self.db)?;
+            self.transfers.requeue_failed();
+            self.transfers.reset_progress();
+
+            // Refuse to sync against servers we cannot talk to, rather than
+            // failing later with decode errors. Re-checked until it passes.
+            if !self.server_compatible.load(Ordering::Relaxed) {
+                let info = self.client.server_info().await?;
+                info.check_compatibility()?;
+                tracing::info!("Server API version {} is compatible", info.api_version);
+                self.server_compatible.store(true, Ordering::Relaxed);
+            }
+
+            // Finish what a crash interrupted before new events build on it
+            self.recover_folder_moves()?;
+
+            // First sync into a folder that already has content (restored
+            // backup, another client's folder): keep what already matches
+            if self.db.get_cursor()? == 0
+                && self.db.get_all_files()?.is_empty()
+                && self.has_local_content()
+            {
+                self.seed_index().await?;
+            }
 
             // A. PULL Phase (Server -> Client)
             // Loop until all server events are processed
             let mut processed_any = false;
             loop {
-                let cursor = self.db.get_cursor().map_err(|e| e.to_string())?;
-                log::debug!("Checking for changes from cursor: {}", cursor);
+                let cursor = self.db.get_cursor()?;
+                tracing::debug!("Checking for changes from cursor: {}", cursor);
 
                 let sync_response = self
                     .client
-                    .sync_pull(cursor)
-                    .await
-                    .map_err(|e| e.to_string())?;
+                    .sync_pull(cursor, Some(PULL_PAGE_SIZE))
+                    .await?;
 
                 // If no events, we're done with PULL phase
                 if sync_response.events.is_empty() {
                     if processed_any {
-                        log::info!("All server events processed.");
+                        tracing::info!("All server events processed.");
                     }
                     break;
                 }
 
                 processed_any = true;
+                let last_page = sync_response.is_last_page(cursor);
 
-                log::info!("Processing {} events...", sync_response.events.len());
+                tracing::info!("Processing {} events...", sync_response.events.len());
 
                 for event in sync_response.events {
-                    log::info!(
+                    tracing::info!(
                         "Processing event: {} ({}) for {}",
                         event.id,
                         event.action,
                         event.entity_id
                     );
 
+                    // Already reflected in the index, e.g. adopted by seed_index
+                    let known = self.db.get_file_by_id(&event.entity_id)?;
+                    if known.is_some_and(|record| record.server_version >= event.id as i64) {
+                        continue;
+                    }
+
+                    // Where a moved entry was before, for files the index lost track of
+                    let previous_remote = if event.action == "move" {
+                        self.db.get_remote_entry(&event.entity_id)?
+                    } else {
+                        None
+                    };
+                    self.mirror_remote_event(&event)?;
+
                     match event.action.as_str() {
-                        "create" | "update" | "copy" => {
+                        "create" | "update" | "copy" | "restore" => {
                             if let Some(data) = event.data {
                                 let file_id = event.entity_id.clone();
 
-                                // Determine effective path
-                                // API now provides "path" field for ALL entity types (files AND folders)
-                                let effective_path_str = if let Some(p) = &data.path {
-                                    // Primary path source - server provides full path for all entities
-                                    normalize_remote_path(p)
-                                } else if let Some(sp) = &data.storage_path {
-                                    // Fallback: strip owner prefix if available
-                                    if let Some(owner) = &event.owner_id {
-                                        let prefix = format!("{}/", owner);
-                                        normalize_remote_path(sp.strip_prefix(&prefix).unwrap_or(sp))
-                                    } else {
-                                        normalize_remote_path(sp)
-                                    }
-                                } else {
-                                    // Last resort: use name only (for backward compatibility)
-                                    normalize_remote_path(&data.name.clone().unwrap_or_default())
-                                };
+                                let effective_path_str =
+                                    remote_event_path(&data, event.owner_id.as_deref());
 
                                 if effective_path_str.is_empty() {
                                     continue;
                                 }
 
                                 if !is_safe_relative_path(&effective_path_str) {
-                                    log::error!(
+                                    tracing::error!(
                                         "Skipping unsafe path from server: {}",
                                         effective_path_str
                                     );
@@ -349,12 +1317,27 @@ impl SyncWorker {
                                 let local_path =
                                     local_path_from_relative(&self.local_root, &effective_path_str);
 
+                                // Put there by other clients; never downloaded
+                                if event.entity_type == "file" && self.note_remote_lock(&file_id, &effective_path_str) {
+                                    continue;
+                                }
 
-
-                                if event.entity_type == "folder" || event.entity_type == "group" || event.entity_type == "group_folder" {
-                                    log::info!("Creating folder (type: {}): {}", event.entity_type, effective_path_str);
-                                    if let Err(e) = fs::create_dir_all(&local_path) {
-                                        log::error!("Failed to create folder {}: {}", effective_path_str, e);
+                                if is_folder_entity(&event.entity_type) {
+                                    // Renames can arrive as updates carrying the new path
+                                    if let Some(existing) = self.db.get_file_by_id(&file_id).unwrap_or(None) {
+                                        if existing.path != effective_path_str {
+                                            self.apply_remote_folder_move(
+                                                &file_id,
+                                                Some(&existing),
+                                                &effective_path_str,
+                                                &data,
+                                            )?;
+                                            continue;
+                                        }
+                                    }
+                                    tracing::info!("Creating folder (type: {}): {}", event.entity_type, effective_path_str);
+                                    if let Err(e) = self.create_dirs(&local_path) {
+                                        tracing::error!("Failed to create folder {}: {}", effective_path_str, e);
                                     }
                                     let is_group_root = data
                                         .group_folder_id
@@ -369,148 +1352,154 @@ impl SyncWorker {
                                         hash: "directory".to_string(),
                                         modified_at: 0,
                                         server_version: 0,
+                                        remote_hash: None,
                                         group_folder_id: data.group_folder_id.clone(),
                                         is_group_root,
-                                    }).map_err(|e| e.to_string())?;
+                                    })?;
                                 } else if event.entity_type == "file" {
                                     let remote_hash = data.hash.unwrap_or_default();
 
                                     // Check local
-                                    let local_hash = compute_hash(&local_path).unwrap_or_default();
-
-                                    if local_hash != remote_hash {
-                                        // Need to download
-                                        if local_hash.is_empty() {
-                                            log::info!("New file from server: {}", effective_path_str);
-                                            if let Err(e) = self.download_file(&file_id, &effective_path_str).await {
-                                                log::error!("Download failed for {}: {}", effective_path_str, e);
+                                    let db_rec = self.db.get_file(&effective_path_str).unwrap_or(None);
+                                    let local_hash = self.local_hash(&local_path, db_rec.as_ref());
+
+                                    let size = data.size.as_deref().and_then(|s| s.parse().ok());
+                                    if local_hash.is_empty() {
+                                        tracing::info!("New file from server: {}", effective_path_str);
+                                        if !self.create_placeholder(&effective_path_str, &file_id, size, &remote_hash, event.id as i64, data.group_folder_id.as_deref()) {
+                                            self.enqueue_download(&effective_path_str, size, &file_id, event.id as i64, &remote_hash);
+                                        }
+                                    } else {
+                                        match classify_change(db_rec.as_ref(), &local_hash, &remote_hash) {
+                                            Divergence::InSync => {
+                                                // Update DB with correct metadata
+                                                self.db.insert_or_update(&FileRecord {
+                                                    path: effective_path_str.clone(),
+                                                    id: Some(file_id),
+                                                    hash: remote_hash.clone(),
+                                                    modified_at: db_rec.as_ref().map(|r| r.modified_at).unwrap_or(0),
+                                                    server_version: event.id as i64,
+                                                    remote_hash: Some(remote_hash.clone()),
+                                                    group_folder_id: data.group_folder_id.clone(),
+                                                    is_group_root: false,
+                                                })?;
                                             }
-                                        } else {
-                                            // Conflict check: file exists locally WITH different hash
-                                            // Basic strategy: Server wins (for now)
-                                            let local_mtime = local_path
-                                                .metadata()
-                                                .ok()
-                                                .and_then(|m| m.modified().ok())
-                                                .and_then(|t| {
-                                                    t.duration_since(std::time::UNIX_EPOCH).ok()
-                                                })
-                                                .map(|d| d.as_secs() as i64)
-                                                .unwrap_or(0);
-
-                                            let db_rec =
-                                                self.db.get_file(&effective_path_str).unwrap_or(None);
-                                            let db_mtime = db_rec.as_ref().map(|r| r.modified_at).unwrap_or(0);
-
-                                            if local_mtime > db_mtime {
-                                                // Local is newer: conflict. For now, backup and overwrite
-                                                log::warn!(
-                                                    "Conflict detected for {}. Local newer. Backing up...",
-                                                    effective_path_str
-                                                );
-                                                let backup_path =
-                                                    local_path.with_extension("conflict_backup");
-                                                let _ = fs::rename(&local_path, &backup_path);
-                                                if let Err(e) = self.download_file(&file_id, &effective_path_str).await {
-                                                    log::error!("Download failed for {}: {}", effective_path_str, e);
-                                                }
-                                            } else {
-                                                log::info!("Downloading updated content for {}", effective_path_str);
-                                                match self.download_file(&file_id, &effective_path_str).await {
-                                                    Ok(_) => log::info!("Download complete for {}", effective_path_str),
-                                                    Err(e) => {
-                                                        log::error!("Download failed for {}: {}", effective_path_str, e)
-                                                    }
+                                            Divergence::Remote => {
+                                                // An online-only copy is replaced instead of hydrated
+                                                let _own = self.own_changes.change(&local_path);
+                                                let replaced = is_dehydrated(&local_path)
+                                                    && fs::remove_file(&local_path).is_ok()
+                                                    && self.create_placeholder(&effective_path_str, &file_id, size, &remote_hash, event.id as i64, data.group_folder_id.as_deref());
+                                                if !replaced {
+                                                    tracing::info!("Queueing updated content for {}", effective_path_str);
+                                                    self.enqueue_download(&effective_path_str, size, &file_id, event.id as i64, &remote_hash);
                                                 }
                                             }
+                                            Divergence::Local => {
+                                                // Server still has the merge base; PUSH uploads the local edit
+                                                tracing::info!("Keeping local change to {} (server copy unchanged)", effective_path_str);
+                                            }
+                                            Divergence::Both => {
+                                                self.resolve_conflict(&file_id, &effective_path_str).await;
+                                            }
                                         }
-                                    } else {
-                                        // Update DB with correct metadata
-                                            self.db
-                                            .insert_or_update(&FileRecord {
-                                                path: effective_path_str.clone(),
-                                                id: Some(file_id),
-                                                hash: remote_hash,
-                                                modified_at: 0,
-                                                server_version: 0,
-                                                group_folder_id: data.group_folder_id.clone(),
-                                                is_group_root: false,
-                                            })
-                                            .map_err(|e| e.to_string())?;
+                                    }
+                                    if !remote_hash.is_empty() {
+                                        self.db.set_remote_state(&effective_path_str, &remote_hash, event.id as i64)?;
                                     }
                                 }
                             }
                         }
                         "delete" => {
+                            self.release_remote_lock(&event.entity_id);
+                            self.transfers.remove_download(&event.entity_id);
                             if let Some(record) =
                                 self.db.get_file_by_id(&event.entity_id).unwrap_or(None)
                             {
-                                log::info!("Deleting local: {}", record.path);
+                                tracing::info!("Deleting local: {}", record.path);
                                 let full_path =
                                     local_path_from_relative(&self.local_root, &record.path);
+                                let _own = self.own_changes.change(&full_path);
 
                                 // Check if it's a directory
                                 if full_path.is_dir() {
                                     if let Err(e) = fs::remove_dir_all(&full_path) {
-                                         log::error!("Failed to remove directory {}: {}", record.path, e);
+                                         tracing::error!("Failed to remove directory {}: {}", record.path, e);
                                     }
                                 } else {
                                     if let Err(e) = fs::remove_file(&full_path) {
-                                        log::error!("Failed to remove file {}: {}", record.path, e);
+                                        tracing::error!("Failed to remove file {}: {}", record.path, e);
                                     }
                                 }
                                 // Cleanup DB
                                 let _ = self.db.delete_file(&record.path);
+                                self.transfers.remove(&record.path);
                             }
                         }
                         "move" => {
                             if let Some(data) = event.data {
                                 let file_id = event.entity_id.clone();
-                                // Determine new path (reuse logic)
-                                let new_path_str = if let Some(p) = &data.path {
-                                    normalize_remote_path(p)
-                                } else if let Some(sp) = &data.storage_path {
-                                    if let Some(owner) = &event.owner_id {
-                                        let prefix = format!("{}/", owner);
-                                        normalize_remote_path(sp.strip_prefix(&prefix).unwrap_or(sp))
-                                    } else {
-                                        normalize_remote_path(sp)
-                                    }
-                                } else {
-                                    normalize_remote_path(&data.name.clone().unwrap_or_default())
-                                };
+                                let new_path_str =
+                                    remote_event_path(&data, event.owner_id.as_deref());
 
                                 if new_path_str.is_empty() {
                                     continue;
                                 }
 
                                 if !is_safe_relative_path(&new_path_str) {
-                                    log::error!("Skipping unsafe move path: {}", new_path_str);
+                                    tracing::error!("Skipping unsafe move path: {}", new_path_str);
                                     continue;
                                 }
 
                                 // 1. Find old path in DB by ID
                                 let old_record_opt = self.db.get_file_by_id(&file_id).unwrap_or(None);
+                                // A download queued for the old path must not recreate it
+                                let queued = self.transfers.remove_download(&file_id);
 
-                                if let Some(old_record) = old_record_opt {
-                                    let old_local =
-                                        local_path_from_relative(&self.local_root, &old_record.path);
+                                let is_folder = is_folder_entity(&event.entity_type)
+                                    || old_record_opt
+                                        .as_ref()
+                                        .map(|r| r.hash == "directory")
+                                        .unwrap_or(false);
+
+                                if is_folder {
+                                    self.apply_remote_folder_move(
+                                        &file_id,
+                                        old_record_opt.as_ref(),
+                                        &new_path_str,
+                                        &data,
+                                    )?;
+                                } else if let Some(old_record) =
+                                    old_record_opt.as_ref().filter(|r| r.path == new_path_str)
+                                {
+                                    // Already there, e.g. the echo of a local rename
+                                    if let Some(queued) = queued {
+                                        self.transfers.enqueue(&new_path_str, Direction::Download, queued.size, Some(&file_id), queued.version);
+                                    }
+                                    tracing::debug!("{} is already at {}", file_id, old_record.path);
+                                } else if let Some(old_record) = old_record_opt {
+                                    if let Some(queued) = queued {
+                                        self.transfers.enqueue(&new_path_str, Direction::Download, queued.size, Some(&file_id), queued.version);
+                                    }
+                                    let old_local =
+                                        local_path_from_relative(&self.local_root, &old_record.path);
                                     let new_local =
                                         local_path_from_relative(&self.local_root, &new_path_str);
 
-                                    log::info!("Moving {} -> {}", old_record.path, new_path_str);
+                                    tracing::info!("Moving {} -> {}", old_record.path, new_path_str);
+                                    let _own = (self.own_changes.change(&old_local), self.own_changes.change(&new_local));
 
                                     // Ensure parent dirs exist
                                     if let Some(parent) = new_local.parent() {
-                                        let _ = fs::create_dir_all(parent);
+                                        let _ = self.create_dirs(parent);
                                     }
 
                                     // Actually move
                                     if let Err(e) = fs::rename(&old_local, &new_local) {
-                                        log::warn!("Move failed ({}). Falling back to download.", e);
+                                        tracing::warn!("Move failed ({}). Falling back to download.", e);
                                         // Fallback: delete old, download new
                                         if let Err(e) = self.download_file(&file_id, &new_path_str).await {
-                                            log::error!("Move fallback failed: {}", e);
+                                            tracing::error!("Move fallback failed: {}", e);
                                         } else {
                                             // If download worked, remove old file if it still exists
                                             let _ = fs::remove_file(old_local);
@@ -526,7 +1515,7 @@ impl SyncWorker {
                                         let file_size = metadata.as_ref().map(|m| m.len()).unwrap_or(0);
                                         
                                         if file_size == 0 || (new_hash != expected_hash && !expected_hash.is_empty()) {
-                                            log::warn!(
+                                            tracing::warn!(
                                                 "Move corrupted file {} (size: {}, hash mismatch: {}). Re-downloading...",
                                                 new_path_str,
                                                 file_size,
@@ -538,7 +1527,7 @@ impl SyncWorker {
                                             let _ = self.db.delete_file(&old_record.path);
                                             
                                             if let Err(e) = self.download_file(&file_id, &new_path_str).await {
-                                                log::error!("Re-download after corrupted move failed: {}", e);
+                                                tracing::error!("Re-download after corrupted move failed: {}", e);
                                             }
                                         } else {
                                             // Move succeeded and file is intact: Update DB with verified hash
@@ -563,22 +1552,40 @@ impl SyncWorker {
                                                     hash: new_hash, // Use newly computed hash!
                                                     modified_at: modified,
                                                     server_version: old_record.server_version,
+                                                    remote_hash: data.hash.clone().or(old_record.remote_hash.clone()),
                                                     group_folder_id: data.group_folder_id.clone(),
                                                     is_group_root,
-                                                })
-                                                .map_err(|e| e.to_string())?;
+                                                })?;
                                             
-                                            log::info!("Move completed successfully: {} -> {}", old_record.path, new_path_str);
+                                            tracing::info!("Move completed successfully: {} -> {}", old_record.path, new_path_str);
                                         }
                                     }
                                 } else {
+                                    // A local copy at the mirrored old location only needs to move;
+                                    // the conditional download below then answers 304
+                                    if let Some(previous) = previous_remote.filter(|p| p.path != new_path_str) {
+                                        let old_local = local_path_from_relative(&self.local_root, &previous.path);
+                                        let new_local = local_path_from_relative(&self.local_root, &new_path_str);
+                                        if previous.hash.is_some()
+                                            && compute_hash(&old_local).ok() == previous.hash
+                                            && !new_local.exists()
+                                        {
+                                            let _own = (self.own_changes.change(&old_local), self.own_changes.change(&new_local));
+                                            if let Some(parent) = new_local.parent() {
+                                                let _ = self.create_dirs(parent);
+                                            }
+                                            if fs::rename(&old_local, &new_local).is_ok() {
+                                                tracing::info!("Moved untracked {} -> {}", previous.path, new_path_str);
+                                            }
+                                        }
+                                    }
                                     // Not found in DB? Treat as new download (create)
-                                    log::warn!(
+                                    tracing::warn!(
                                         "Move event for unknown file {}. Treating as create.",
                                         file_id
                                     );
                                     if let Err(e) = self.download_file(&file_id, &new_path_str).await {
-                                        log::error!("Move (as create) failed: {}", e);
+                                        tracing::error!("Move (as create) failed: {}", e);
                                     }
                                 }
                             }
@@ -590,61 +1597,166 @@ impl SyncWorker {
                 // Update Cursor after processing this batch
                 if sync_response.next_cursor > cursor {
                     self.db
-                        .set_cursor(sync_response.next_cursor)
-                        .map_err(|e| e.to_string())?;
+                        .set_cursor(sync_response.next_cursor)?;
                 }
+                self.checkpoint(&batch)?;
 
-                // Continue loop to check for more events
+                if last_page {
+                    tracing::info!("All server events processed.");
+                    break;
+                }
+                // Continue loop to fetch the next page
             }
+            self.hydrate_pinned();
+            self.run_transfers(&batch).await?;
+            self.recover_pending_ops().await?;
 
             // B. PUSH Phase (Client -> Server)
             // Skip expensive local scan if no local changes (periodic check only pulls)
             if !has_local_changes {
-                log::debug!("Skipping PUSH phase (no local changes)");
-                log::debug!("Sync check completed.");
+                tracing::debug!("Skipping PUSH phase (no local changes)");
+                self.transfers.persist(&self.db)?;
+                tracing::debug!("Sync check completed.");
                 return Ok(());
             }
 
-            let local_files = self.scan_local_files();
+            let (local_files, identities) = self.scan_local_files();
             let db_records = self.db.get_all_files().unwrap_or_default();
+            let known_identities = self.db.file_identities()?;
+            let by_identity: HashMap<(u64, u64), &String> =
+                identities.iter().map(|(path, identity)| (*identity, path)).collect();
 
             // Safety: refuse destructive deletes if the root looks empty or invalid
             if local_files.is_empty() && !db_records.is_empty() && is_effectively_empty_root(&self.local_root)? {
-                return Err("Local sync root appears empty or inaccessible; refusing to delete remote files.".to_string());
+                return Err(XynoxaError::Conflict("Local sync root appears empty or inaccessible; refusing to delete remote files.".to_string()));
             }
 
             // 1. Check for Deletions
+            // Remote deletes are queued and flushed as one batched request
+            let mut deletions = MutationBatch::new();
+            let mut deleted_paths: Vec<String> = Vec::new();
+            let mut removed_records: Vec<String> = Vec::new();
+            let mut journal: Vec<(PendingOpKind, &str, Option<&str>)> = Vec::new();
+            // Renamed files are moved on the server instead of deleted and uploaded again
+            let mut moves = MutationBatch::new();
+            let mut moved: Vec<(&FileRecord, &String, std::ops::Range<usize>)> = Vec::new();
             for db_rec in &db_records {
                     if !local_files.contains_key(&db_rec.path) {
-                        log::info!("Local delete detected for {}. Pushing...", db_rec.path);
+                        // Newly ignored, not deleted: the server copy stays
+                        if self.ignore.matches_path(&db_rec.path)
+                            || self.special_files.lock().unwrap().contains(&db_rec.path)
+                            || self.in_unreadable_folder(&db_rec.path)
+                        {
+                            continue;
+                        }
+                        if let Some(new_path) =
+                            self.local_move_target(db_rec, &local_files, &known_identities, &by_identity)
+                        {
+                            if let Some(calls) = self.queue_local_move(&mut moves, db_rec, new_path)? {
+                                tracing::info!("Local move detected: {} -> {}", db_rec.path, new_path);
+                                moved.push((db_rec, new_path, calls));
+                                continue;
+                            }
+                        }
+                        tracing::info!("Local delete detected for {}. Pushing...", db_rec.path);
 
                     if let Some(fid) = &db_rec.id {
                         if db_rec.hash == "directory" {
                             if db_rec.is_group_root {
                                 let full_path = self.local_root.join(&db_rec.path);
-                                let _ = fs::create_dir_all(&full_path);
-                                log::info!("Group root restore: {}", db_rec.path);
+                                let _ = self.create_dirs(&full_path);
+                                tracing::info!("Group root restore: {}", db_rec.path);
                                 continue;
-                            } else if let Err(e) = self.client.delete_folder(fid).await {
-                                log::error!("Failed remote folder delete {}: {}", db_rec.path, e);
                             }
+                            deletions.delete_folder(fid)?;
+                            journal.push((PendingOpKind::DeleteFolder, &db_rec.path, Some(fid)));
                         } else {
-                            if let Err(e) = self.client.soft_delete_file(fid).await {
-                                log::error!("Failed remote delete {}: {}", db_rec.path, e);
-                            }
+                            deletions.soft_delete_file(fid)?;
+                            journal.push((PendingOpKind::DeleteFile, &db_rec.path, Some(fid)));
                         }
+                        deleted_paths.push(db_rec.path.clone());
                     }
                     // Always remove from DB if locally gone
-                    let _ = self.db.delete_file(&db_rec.path);
+                    removed_records.push(db_rec.path.clone());
                 }
             }
+            // Journal first: the records below are the only trace of the remote ids
+            let op_ids = self.db.begin_ops(&journal)?;
+            if let Err(e) = self.db.delete_files(&removed_records) {
+                tracing::error!("Failed to remove {} DB records: {}", removed_records.len(), e);
+            }
+
+            if !deletions.is_empty() {
+                tracing::info!("Pushing {} remote deletes...", deletions.len());
+                match self.client.execute_batch(deletions).await {
+                    Ok(results) => {
+                        for (path, result) in deleted_paths.iter().zip(results) {
+                            if let Err(e) = result {
+                                telemetry::record_error(&e);
+                                tracing::error!("Failed remote delete {}: {}", path, e);
+                            }
+                        }
+                        for id in op_ids {
+                            self.db.finish_op(id)?;
+                        }
+                    }
+                    // Left in the journal and retried next cycle
+                    Err(e) => tracing::error!("Remote delete batch failed: {}", e),
+                }
+            }
+
+            // Paths whose move failed are left alone until the next cycle
+            let mut move_targets: Vec<&String> = Vec::new();
+            // Their old identity stays, so the move is detected again
+            let mut kept_identities: Vec<(String, (u64, u64))> = Vec::new();
+            if !moves.is_empty() {
+                tracing::info!("Pushing {} local moves...", moved.len());
+                let results = self.client.execute_batch(moves).await;
+                for (record, new_path, calls) in moved {
+                    let failure = match &results {
+                        Ok(results) => results[calls].iter().find_map(|r| r.as_ref().err()),
+                        Err(e) => Some(e),
+                    };
+                    if let Some(e) = failure {
+                        telemetry::record_error(e);
+                        tracing::error!("Failed remote move {} -> {}: {}", record.path, new_path, e);
+                        move_targets.push(new_path);
+                        if let Some(identity) = known_identities.get(&record.path) {
+                            kept_identities.push((record.path.clone(), *identity));
+                        }
+                        continue;
+                    }
+                    // The old content stays the merge base, so an edit made
+                    // along with the rename is uploaded below
+                    self.db.delete_file(&record.path)?;
+                    self.db.insert_or_update(&FileRecord {
+                        path: new_path.clone(),
+                        ..record.clone()
+                    })?;
+                }
+            }
+            if kept_identities.is_empty() {
+                self.db.replace_file_identities(&identities)?;
+            } else {
+                let mut stored = identities.clone();
+                stored.extend(kept_identities);
+                self.db.replace_file_identities(&stored)?;
+            }
 
             // 2. Check for Updates/Creations
-            // Sort keys to ensure parents are processed before children (for folder creation)
-            let mut sorted_paths: Vec<String> = local_files.keys().cloned().collect();
+            let mut sorted_paths: Vec<String> = local_files
+                .keys()
+                .filter(|path| !move_targets.contains(path))
+                .cloned()
+                .collect();
             sorted_paths.sort();
+            // Created after the scan, parents before children
+            let mut new_folders: Vec<String> = Vec::new();
 
-            for path in sorted_paths {
+            for (i, path) in sorted_paths.into_iter().enumerate() {
+                if i > 0 && i % DB_CHECKPOINT_INTERVAL == 0 {
+                    self.checkpoint(&batch)?;
+                }
                 let record = local_files.get(&path).unwrap();
                 let db_entry = self.db.get_file(&path).unwrap_or(None);
 
@@ -655,59 +1767,817 @@ impl SyncWorker {
                         // or DB has hash (was file) and local is "directory" (now folder).
 
                         if record.hash == "directory" {
-                             log::info!("Local path {} changed from file to folder. Skipping upload (handled as create/move?).", path);
+                             tracing::info!("Local path {} changed from file to folder. Skipping upload (handled as create/move?).", path);
                              // If it changed type, strictly it should be a delete + create.
                              // But for now, just don't crash.
+                        } else if self.is_damaged(&db_rec, &record.hash, record.modified_at)? {
+                            // Uploading it would replace the good server copy
+                            self.report_corruption(&db_rec, &record.hash)?;
+                        } else if let (Some(fid), Divergence::Both) = (
+                            &db_rec.id,
+                            classify_change(Some(&db_rec), &record.hash, db_rec.remote_hash.as_deref().unwrap_or(&db_rec.hash)),
+                        ) {
+                            // A remote change that failed to download earlier
+                            self.resolve_conflict(fid, &path).await;
                         } else {
-                            log::info!("Local change for {}. Uploading...", path);
-                            if let Err(e) = self.upload_file(&path).await {
-                                log::error!("Upload failed {}: {}", path, e);
-                            }
+                            tracing::info!("Local change for {}. Queueing upload...", path);
+                            self.enqueue_upload(&path);
+                        }
+                    } else if let (Some(fid), Some(remote_hash)) = (&db_rec.id, &db_rec.remote_hash) {
+                        if record.hash != "directory" && *remote_hash != db_rec.hash {
+                            tracing::info!("Applying pending remote change to {}", path);
+                            self.transfers.enqueue(&path, Direction::Download, None, Some(fid), 0);
                         }
                     }
                     if db_rec.id.is_none() {
-                        log::warn!("Missing ID for {}. Linking...", path);
+                        tracing::warn!("Missing ID for {}. Linking...", path);
                          if record.hash == "directory" {
-                            if let Err(e) = self.create_remote_folder(&path).await {
-                                log::error!("Folder link failed {}: {}", path, e);
-                            }
+                            new_folders.push(path);
                         } else {
-                            if let Err(e) = self.upload_file(&path).await {
-                                log::error!("Link upload failed {}: {}", path, e);
-                            }
+                            self.enqueue_upload(&path);
                         }
                     }
                 } else {
-                    log::info!("New local item: {}. Creating...", path);
+                    tracing::info!("New local item: {}. Creating...", path);
                     if record.hash == "directory" {
-                        if let Err(e) = self.create_remote_folder(&path).await {
-                            log::error!("New folder creation failed {}: {}", path, e);
-                        }
+                        new_folders.push(path);
                     } else {
-                        if let Err(e) = self.upload_file(&path).await {
-                            log::error!("New upload failed {}: {}", path, e);
-                        }
+                        self.enqueue_upload(&path);
                     }
                 }
             }
+            self.create_remote_folders(new_folders).await;
+            self.run_transfers(&batch).await?;
+            // Files found busy in an earlier cycle that were not checked again
+            // were unchanged or deleted meanwhile
+            let cycle = self.cycle.load(Ordering::Relaxed);
+            self.busy.lock().unwrap().retain(|_, file| file.cycle == cycle);
 
-            log::debug!("Sync check completed.");
-            Ok::<(), String>(())
-        })
+            self.transfers.persist(&self.db)?;
+            batch.commit()?;
+            tracing::debug!("Sync check completed.");
+            Ok::<(), XynoxaError>(())
+        }.in_current_span());
+
+        telemetry::record_sync(started.elapsed(), result.as_ref().err());
+        match &result {
+            Ok(()) => {
+                if self.root_unavailable.swap(false, Ordering::Relaxed) {
+                    tracing::info!("Sync folder {:?} is available again", self.local_root);
+                    self.root_recovered.store(true, Ordering::Relaxed);
+                }
+                self.emit(SyncEvent::State {
+                    state: SyncState::Idle,
+                })
+            }
+            Err(XynoxaError::Unavailable(reason)) => {
+                // Reported once, then retried quietly every cycle
+                if !self.root_unavailable.swap(true, Ordering::Relaxed) {
+                    tracing::warn!("Sync paused: {}", reason);
+                    self.emit(SyncEvent::Warning {
+                        code: "root_unavailable",
+                        message: i18n::tr_args(
+                            "{0}. Sync is paused until it is available again.",
+                            &[reason.as_str()],
+                        ),
+                    });
+                }
+                self.emit(SyncEvent::State {
+                    state: SyncState::Unavailable,
+                });
+            }
+            Err(e) => {
+                self.emit(SyncEvent::Error {
+                    code: e.code(),
+                    message: e.to_string(),
+                });
+                self.emit(SyncEvent::State {
+                    state: SyncState::Error,
+                });
+            }
+        }
+        if let Err(e @ XynoxaError::Auth(_)) = &result {
+            if !self.auth_lost.swap(true, Ordering::Relaxed) {
+                if let Some(callback) = &self.on_auth_lost {
+                    callback(e);
+                }
+            }
+        }
+        result
     }
 
     // ... helpers ...
 
-    // ... helpers ...
-    fn scan_local_files(&self) -> HashMap<String, FileRecord> {
+    /// Fails with [`XynoxaError::Unavailable`] if the sync root is gone (e.g.
+    /// an unmounted drive) or read-only. A missing root is only created for a
+    /// fresh setup: recreated on the empty mount point, every synced file
+    /// would look deleted. An empty mount point that exists is told apart by
+    /// the missing sync database and the different filesystem.
+    fn check_sync_root(&self) -> Result<()> {
+        let root = self.local_root.to_string_lossy();
+        if !self.local_root.is_dir() && self.db.get_cursor()? > 0 {
+            return Err(XynoxaError::Unavailable(i18n::tr_args(
+                "The sync folder {0} is not available",
+                &[&root],
+            )));
+        }
+        ensure_sync_root(&self.local_root)?;
+        if !is_writable(&self.local_root) {
+            return Err(XynoxaError::Unavailable(i18n::tr_args(
+                "The sync folder {0} is read-only",
+                &[&root],
+            )));
+        }
+        if self.db_path.as_ref().is_some_and(|path| !path.is_file()) {
+            return Err(XynoxaError::Unavailable(i18n::tr_args(
+                "The sync folder {0} no longer holds its sync database",
+                &[&root],
+            )));
+        }
+        if self.root_device.is_some() && root_device(&self.local_root) != self.root_device {
+            return Err(XynoxaError::Unavailable(i18n::tr_args(
+                "The sync folder {0} is on a different drive than before",
+                &[&root],
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether `path` is in a folder the last scan could not read.
+    fn in_unreadable_folder(&self, path: &str) -> bool {
+        self.unreadable.lock().unwrap().iter().any(|folder| {
+            folder.is_empty() || path == folder || path.starts_with(&format!("{}/", folder))
+        })
+    }
+
+    /// Runs a sync cycle (`kind` names it in the log).
+    fn run_cycle(&self, has_local_changes: bool, kind: &str) {
+        match self.scan_and_sync(has_local_changes) {
+            Ok(()) => self.resolve_failure(CYCLE_ISSUE),
+            Err(e) => {
+                self.report_failure(CYCLE_ISSUE, None, format!("{} sync failed: {}", kind, e))
+            }
+        }
+        self.own_changes.prune();
+    }
+
+    /// `fs::create_dir_all` as a change of the sync, see [`OwnChanges`].
+    fn create_dirs(&self, dir: &Path) -> std::io::Result<()> {
+        let created = dir.ancestors().take_while(|dir| !dir.exists()).last();
+        let _own = created.map(|top| self.own_changes.change(top));
+        fs::create_dir_all(dir)
+    }
+
+    /// Records the failure `key` as a [`KnownIssue`] and logs `message`,
+    /// unless it was logged less than [`ERROR_LOG_INTERVAL`] ago.
+    fn report_failure(&self, key: &str, path: Option<&str>, message: String) {
+        let now = chrono::Utc::now().timestamp();
+        let count = match self.db.record_issue(key, path, &message, now) {
+            Ok(issue) => issue.count,
+            Err(e) => {
+                tracing::debug!("Failed to record issue {}: {}", key, e);
+                1
+            }
+        };
+        match self.log_throttle.check(key) {
+            Some(0) => tracing::error!("{}", message),
+            Some(skipped) => tracing::error!(
+                "{} (failed {} times in total, {} not logged)",
+                message,
+                count,
+                skipped
+            ),
+            None => {}
+        }
+    }
+
+    /// Forgets the failure `key` after the operation succeeded.
+    fn resolve_failure(&self, key: &str) {
+        match self.db.resolve_issue(key) {
+            Ok(true) => {
+                tracing::info!("{} works again", key);
+                self.log_throttle.reset(key);
+            }
+            Ok(false) => {}
+            Err(e) => tracing::debug!("Failed to resolve issue {}: {}", key, e),
+        }
+    }
+
+    /// Forgets failures with keys starting with `prefix` except `failing`,
+    /// after a pass that tried everything of that kind.
+    fn resolve_other_failures(&self, prefix: &str, failing: &HashSet<String>) {
+        match self.db.retain_issues(prefix, failing) {
+            Ok(resolved) => {
+                for key in resolved {
+                    tracing::info!("{} works again", key);
+                    self.log_throttle.reset(&key);
+                }
+            }
+            Err(e) => tracing::debug!("Failed to resolve {} issues: {}", prefix, e),
+        }
+    }
+
+    fn emit(&self, event: SyncEvent) {
+        self.log_activity(&event);
+        if let Some(callback) = &self.on_event {
+            callback(event);
+        }
+    }
+
+    /// Keeps finished transfers, conflicts and problems for the sync report.
+    fn log_activity(&self, event: &SyncEvent) {
+        let (kind, path, detail) = match event {
+            SyncEvent::FileFinished {
+                path,
+                direction,
+                error,
+            } => (direction.as_str(), Some(path.as_str()), error.as_deref()),
+            SyncEvent::Conflict { path, backup } => {
+                ("conflict", Some(path.as_str()), Some(backup.as_str()))
+            }
+            SyncEvent::Warning { message, .. } => ("warning", None, Some(message.as_str())),
+            SyncEvent::Error { message, .. } => ("error", None, Some(message.as_str())),
+            _ => return,
+        };
+        if let Err(e) = self.db.log_activity(kind, path, detail) {
+            tracing::debug!("Failed to record activity: {}", e);
+        }
+    }
+
+    /// Remembers remote file `path` if it is an Office lock file and reports the
+    /// document as open elsewhere. True if it was a lock file.
+    fn note_remote_lock(&self, file_id: &str, path: &str) -> bool {
+        let (folder, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let Some(target) = crate::ignore::lock_file_target(name) else {
+            return false;
+        };
+        // Microsoft Office may have cut the start of the name; find the document
+        let dir = local_path_from_relative(&self.local_root, folder);
+        let document = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|candidate| {
+                candidate.ends_with(target) && crate::ignore::lock_file_target(candidate).is_none()
+            })
+            .min_by_key(|candidate| candidate.len())
+            .unwrap_or_else(|| target.to_string());
+        let document = if folder.is_empty() {
+            document
+        } else {
+            format!("{}/{}", folder, document)
+        };
+        tracing::info!("{} is open on another device", document);
+        let known = self
+            .remote_locks
+            .lock()
+            .unwrap()
+            .insert(file_id.to_string(), document.clone());
+        if self.lock_notifications && known.is_none() {
+            self.emit(SyncEvent::DocumentLock {
+                path: document,
+                open: true,
+            });
+        }
+        true
+    }
+
+    /// Reports the document locked by remote file `file_id` as closed.
+    fn release_remote_lock(&self, file_id: &str) {
+        let Some(document) = self.remote_locks.lock().unwrap().remove(file_id) else {
+            return;
+        };
+        tracing::info!("{} was closed on the other device", document);
+        if self.lock_notifications {
+            self.emit(SyncEvent::DocumentLock {
+                path: document,
+                open: false,
+            });
+        }
+    }
+
+    /// Replaces the platform watcher by polling; true if it switched.
+    fn fall_back_to_polling(&mut self) -> bool {
+        let Some(watcher) = self.watcher.as_mut().filter(|w| !w.is_polling()) else {
+            return false;
+        };
+        if let Err(e) = watcher.fall_back_to_polling() {
+            tracing::error!("Failed to start polling watcher: {}", e);
+            return false;
+        }
+        self.warn_watch_limit();
+        true
+    }
+
+    fn warn_watch_limit(&self) {
+        let Some(watcher) = &self.watcher else {
+            return;
+        };
+        let interval = watcher.poll_interval().as_secs().to_string();
+        let message = if cfg!(target_os = "linux") {
+            i18n::tr_args(
+                "Too many folders to watch for changes. Checking every {0} seconds instead; raise the limit with: {1}",
+                &[&interval, watcher::WATCH_LIMIT_HINT],
+            )
+        } else {
+            i18n::tr_args(
+                "Too many folders to watch for changes. Checking every {0} seconds instead.",
+                &[&interval],
+            )
+        };
+        self.emit(SyncEvent::Warning {
+            code: "watch_limit",
+            message,
+        });
+    }
+
+    /// Commits the cycle's work so far, including transfer queue changes.
+    fn checkpoint(&self, batch: &WriteBatch<'_>) -> Result<()> {
+        self.transfers.persist(&self.db)?;
+        batch.checkpoint()?;
+        Ok(())
+    }
+
+    /// Content hash of a local file. Online-only placeholders report their
+    /// recorded hash, since reading them would download the content.
+    fn local_hash(&self, path: &Path, record: Option<&FileRecord>) -> String {
+        if is_dehydrated(path) {
+            if let Some(record) = record {
+                return record.hash.clone();
+            }
+        }
+        compute_hash(path).unwrap_or_default()
+    }
+
+    /// Creates an online-only placeholder for a server file when files on
+    /// demand are active. Returns false if the file has to be downloaded.
+    fn create_placeholder(
+        &self,
+        path: &str,
+        file_id: &str,
+        size: Option<u64>,
+        remote_hash: &str,
+        version: i64,
+        group_folder_id: Option<&str>,
+    ) -> bool {
+        let Some(placeholders) = &self.placeholders else {
+            return false;
+        };
+        // Without a hash the placeholder could never be compared to local edits
+        if remote_hash.is_empty() || self.db.is_pinned(path).unwrap_or(false) {
+            return false;
+        }
+        let _own = self
+            .own_changes
+            .change(&local_path_from_relative(&self.local_root, path));
+        if let Err(e) = placeholders.create(path, file_id, size.unwrap_or(0)) {
+            tracing::warn!("Failed to create placeholder for {}: {}", path, e);
+            return false;
+        }
+        self.transfers.remove_download(file_id);
+        let record = FileRecord {
+            path: path.to_string(),
+            id: Some(file_id.to_string()),
+            hash: remote_hash.to_string(),
+            modified_at: chrono::Utc::now().timestamp(),
+            server_version: version,
+            remote_hash: Some(remote_hash.to_string()),
+            group_folder_id: group_folder_id.map(str::to_string),
+            is_group_root: false,
+        };
+        if let Err(e) = self.db.insert_or_update(&record) {
+            tracing::error!("Failed to record placeholder {}: {}", path, e);
+        }
+        true
+    }
+
+    /// Shows an uploaded placeholder as up to date in Explorer.
+    fn mark_in_sync(&self, path: &str) {
+        if let Some(placeholders) = &self.placeholders {
+            let _own = self
+                .own_changes
+                .change(&local_path_from_relative(&self.local_root, path));
+            // Plain files (created locally, never converted) have no sync state
+            if let Err(e) = placeholders.mark_in_sync(path) {
+                tracing::debug!("Not marking {} in sync: {}", path, e);
+            }
+        }
+    }
+
+    fn enqueue_upload(&self, path: &str) {
+        let metadata = local_path_from_relative(&self.local_root, path)
+            .metadata()
+            .ok();
+        let size = metadata.as_ref().map(|m| m.len());
+        if !self.transfers.enqueue(path, Direction::Upload, size, None, 0) {
+            tracing::info!("Upload of {} was cancelled. Skipping.", path);
+            return;
+        }
+        let modified = metadata
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok());
+        if let Some(modified) = modified {
+            self.transfers.set_modified(path, modified.as_secs() as i64);
+        }
+    }
+
+    /// Queues the download of `file_id`; `hash` lets a LAN peer serve it.
+    fn enqueue_download(&self, path: &str, size: Option<u64>, file_id: &str, version: i64, hash: &str) {
+        if self.lan.is_some() && !hash.is_empty() {
+            self.expected_hashes
+                .lock()
+                .unwrap()
+                .insert(file_id.to_string(), hash.to_string());
+        }
+        self.transfers.enqueue(path, Direction::Download, size, Some(file_id), version);
+        // Pinned files are the ones needed offline soonest
+        if self.db.is_pinned(path).unwrap_or(false) {
+            let _ = self.transfers.prioritize(path);
+        }
+    }
+
+    /// Downloads the content of online-only copies of pinned files, e.g.
+    /// after a folder was pinned or files on demand was turned on.
+    fn hydrate_pinned(&self) {
+        let pinned = match self.db.pinned_files() {
+            Ok(pinned) => pinned,
+            Err(e) => {
+                tracing::error!("Failed to list pinned files: {}", e);
+                return;
+            }
+        };
+        for record in pinned {
+            let local_path = local_path_from_relative(&self.local_root, &record.path);
+            if !is_dehydrated(&local_path) {
+                continue;
+            }
+            tracing::info!("Downloading pinned file {}", record.path);
+            if let Err(e) = cloud_files::hydrate(&local_path) {
+                tracing::warn!("Could not download pinned file {}: {}", record.path, e);
+            }
+        }
+    }
+
+    /// Works through the transfer queue until it is empty.
+    async fn run_transfers(&self, batch: &WriteBatch<'_>) -> Result<()> {
+        // Held until the queue drains
+        let _inhibitor = (self.prevent_sleep
+            && self.transfers.pending_bytes() >= SLEEP_INHIBIT_BYTES)
+            .then(|| SleepInhibitor::acquire(&i18n::tr("Transferring files")))
+            .flatten();
+        let mut processed = 0;
+        loop {
+            self.apply_bandwidth_cap();
+            let Some(transfer) = self.transfers.next_queued() else {
+                break;
+            };
+            let path = transfer.path.as_str();
+            let result = match (transfer.direction, transfer.file_id.as_deref()) {
+                (Direction::Upload, _) => {
+                    let local_path = local_path_from_relative(&self.local_root, path);
+                    if !local_path.is_file() {
+                        // Deleted or replaced by a folder since it was queued
+                        self.transfers.remove(path);
+                        continue;
+                    }
+                    // Uploading mid-save would send a half-written file
+                    if let Some(reason) = write_in_progress(&local_path, &transfer) {
+                        tracing::info!("{} is still being written ({:?}). Retrying later.", path, reason);
+                        self.defer_upload(path, reason);
+                        self.transfers.remove(path);
+                        continue;
+                    }
+                    self.busy.lock().unwrap().remove(path);
+                    self.upload_file(path).await.map(|()| self.mark_in_sync(path))
+                }
+                (Direction::Download, Some(file_id)) => {
+                    self.download_file(file_id, path).await.and_then(|_| {
+                        if transfer.version > 0 {
+                            if let Some(record) = self.db.get_file(path)? {
+                                self.db.set_remote_state(path, &record.hash, transfer.version)?;
+                            }
+                        }
+                        Ok(())
+                    })
+                }
+                (Direction::Download, None) => {
+                    self.transfers.remove(path);
+                    continue;
+                }
+            };
+            let issue = format!("transfer:{}", path);
+            match &result {
+                Ok(()) => self.resolve_failure(&issue),
+                Err(e) => self.report_failure(
+                    &issue,
+                    Some(path),
+                    format!("Transfer of {} failed: {}", path, e),
+                ),
+            }
+            self.transfers.finish(path, &result);
+            let (done, total) = self.transfers.progress();
+            self.emit(SyncEvent::Progress { done, total });
+
+            processed += 1;
+            if processed % DB_CHECKPOINT_INTERVAL == 0 {
+                self.checkpoint(batch)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds bytes transferred with the server to today's usage.
+    fn count_bandwidth(&self, direction: Direction, bytes: u64) {
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        if let Err(e) = self.db.add_bandwidth(&today, direction, bytes) {
+            tracing::warn!("Could not record bandwidth usage: {}", e);
+        }
+    }
+
+    /// Holds back all but small and prioritized transfers while this month's
+    /// usage is at the cap, and tells the user when that starts.
+    fn apply_bandwidth_cap(&self) {
+        let cap = *self.bandwidth_cap.lock().unwrap();
+        let capped = cap.is_some()
+            && BandwidthUsage::load(&self.db, cap)
+                .inspect_err(|e| tracing::warn!("Could not read bandwidth usage: {}", e))
+                .is_ok_and(|usage| usage.capped);
+        self.transfers
+            .set_size_limit(capped.then_some(ESSENTIAL_TRANSFER_BYTES));
+        if capped == self.capped.swap(capped, Ordering::Relaxed) {
+            return;
+        }
+        if !capped {
+            tracing::info!("Below the monthly bandwidth cap again; all transfers run");
+            return;
+        }
+        let cap_mb = cap.unwrap_or(0) / (1024 * 1024);
+        tracing::warn!("Monthly bandwidth cap of {} MB reached", cap_mb);
+        self.emit(SyncEvent::Warning {
+            code: "bandwidth_cap",
+            message: i18n::tr_args(
+                "This month's transfer limit of {0} MB is reached. Only small files sync until next month.",
+                &[&cap_mb.to_string()],
+            ),
+        });
+    }
+
+    fn defer_upload(&self, path: &str, reason: BusyReason) {
+        let cycle = self.cycle.load(Ordering::Relaxed);
+        let mut busy = self.busy.lock().unwrap();
+        let file = busy.entry(path.to_string()).or_insert_with(|| BusyFile {
+            path: path.to_string(),
+            reason,
+            since: chrono::Utc::now().timestamp(),
+            cycle,
+        });
+        file.reason = reason;
+        file.cycle = cycle;
+    }
+
+    /// Resolves once the user cancelled the transfer of `path`.
+    async fn cancellation(&self, path: &str) {
+        while !self.transfers.is_cancelled(path) {
+            tokio::time::sleep(CANCEL_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Runs `transfer` of `path` as an entry of the transfer queue and reports
+    /// it. Aborted when the user cancels it.
+    async fn track_transfer<T>(
+        &self,
+        path: &str,
+        direction: Direction,
+        size: Option<u64>,
+        transfer: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        self.transfers.start(path, direction, size)?;
+        self.emit(SyncEvent::FileStarted {
+            path: path.to_string(),
+            direction,
+        });
+        let result = tokio::select! {
+            result = transfer => result,
+            _ = self.cancellation(path) => Err(transfers::cancelled(path)),
+        };
+        self.transfers.finish(path, &result);
+        self.emit(SyncEvent::FileFinished {
+            path: path.to_string(),
+            direction,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+        result
+    }
+
+    /// Drops rows the sync root cannot contain (unsafe or ignored paths).
+    fn maintain_db(&self) -> Result<MaintenanceReport> {
+        let report = self.db.maintain(|path| {
+            is_safe_relative_path(path) && !self.ignore.matches_path(path)
+        })?;
+        tracing::info!(
+            "DB maintenance: pruned {} entries and {} validators, {} -> {} bytes",
+            report.removed_files,
+            report.removed_validators,
+            report.size_before,
+            report.size_after
+        );
+        Ok(report)
+    }
+
+    /// Starts over from an empty index, for a database that was damaged or
+    /// lost: replays the server's event log, adopts local files whose hash
+    /// matches and syncs the rest like a first sync, so differing files become
+    /// conflicts instead of being overwritten. Returns the entries tracked.
+    fn rebuild_index(&self) -> Result<usize> {
+        tracing::warn!("Rebuilding the sync database from the server's event log");
+        self.db.reset_index()?;
+        self.transfers.restore(Vec::new());
+        self.scan_and_sync(true)?;
+        let tracked = self.db.get_all_files()?.len();
+        tracing::info!("Sync database rebuilt with {} entries", tracked);
+        Ok(tracked)
+    }
+
+    /// Re-hashes the files checked least recently, up to [`VERIFY_BATCH_FILES`]
+    /// or [`VERIFY_BATCH_BYTES`], and reports those that changed without a
+    /// write. Unchanged files are marked verified. Files with pending transfers or open in other programs are left
+    /// for a later run. Returns the number of files found damaged.
+    fn verify_integrity(&self) -> Result<usize> {
+        let transfers = transfer_states(&self.transfers);
+        let mut verified = Vec::new();
+        let mut damaged = 0;
+        let mut bytes = 0;
+        for record in self.db.files_to_verify(VERIFY_BATCH_FILES)? {
+            if bytes >= VERIFY_BATCH_BYTES {
+                break;
+            }
+            let local_path = local_path_from_relative(&self.local_root, &record.path);
+            let skip = transfers.contains_key(&record.path)
+                || self.busy.lock().unwrap().contains_key(&record.path)
+                || self.ignore.matches_path(&record.path)
+                || is_dehydrated(&local_path);
+            let Some(metadata) = fs::metadata(&local_path).ok().filter(|_| !skip) else {
+                continue;
+            };
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|time| time.as_secs() as i64)
+                .unwrap_or(0);
+            // Edited since the last sync; the next scan uploads it
+            if !metadata.is_file() || modified != record.modified_at {
+                continue;
+            }
+            bytes += metadata.len();
+            let Ok(hash) = compute_hash(&local_path) else {
+                continue;
+            };
+            if hash == record.hash {
+                verified.push(record.path);
+            } else if self.is_damaged(&record, &hash, modified)? {
+                self.report_corruption(&record, &hash)?;
+                damaged += 1;
+            }
+        }
+        self.db.mark_verified(&verified)?;
+        tracing::info!("Integrity check: {} files verified, {} damaged", verified.len(), damaged);
+        Ok(damaged)
+    }
+
+    /// Whether the file of `record` now hashing to `local_hash` changed
+    /// without a write. Only certain if the recorded content was seen after
+    /// the second of its modification time ended; an edit in that same second
+    /// would keep the time.
+    fn is_damaged(&self, record: &FileRecord, local_hash: &str, modified: i64) -> Result<bool> {
+        Ok(silently_changed(record, local_hash, modified)
+            && self.db.confirmed_at(&record.path)? > record.modified_at)
+    }
+
+    /// Makes files not opened for `reclaim.unused_days` online-only, least
+    /// recently used first, until the disk is no fuller than
+    /// `reclaim.disk_usage_percent`. Only files whose content the server has
+    /// and that are unchanged since the last sync qualify; pinned files never
+    /// do. Returns the bytes freed.
+    fn reclaim_space(&self) -> Result<u64> {
+        let Some(placeholders) = self.placeholders.as_ref().filter(|_| self.reclaim.enabled) else {
+            return Ok(0);
+        };
+        let (total, free) = cloud_files::disk_space(&self.local_root)?;
+        let limit = total / 100 * u64::from(self.reclaim.disk_usage_percent.min(100));
+        let used = total.saturating_sub(free);
+        if used <= limit {
+            return Ok(0);
+        }
+        let transfers = transfer_states(&self.transfers);
+        let issues: HashSet<String> =
+            self.db.sync_issues()?.into_iter().map(|issue| issue.path).collect();
+        let mut files = Vec::new();
+        for record in self.db.get_all_files()? {
+            let on_server = record.id.is_some() && record.remote_hash.as_ref() == Some(&record.hash);
+            if !on_server
+                || transfers.contains_key(&record.path)
+                || issues.contains(&record.path)
+                || self.busy.lock().unwrap().contains_key(&record.path)
+                || self.db.is_pinned(&record.path)?
+            {
+                continue;
+            }
+            let local_path = local_path_from_relative(&self.local_root, &record.path);
+            let Ok(metadata) = fs::metadata(&local_path) else {
+                continue;
+            };
+            let secs = |time: std::io::Result<std::time::SystemTime>| {
+                time.ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|time| time.as_secs() as i64)
+                    .unwrap_or(0)
+            };
+            let modified = secs(metadata.modified());
+            // Edited since the last sync; freeing it would lose the edit
+            if !metadata.is_file() || modified != record.modified_at || is_dehydrated(&local_path) {
+                continue;
+            }
+            let last_used = modified.max(secs(metadata.accessed()));
+            files.push((record, metadata.len(), last_used));
+        }
+
+        let unused_secs = i64::from(self.reclaim.unused_days) * 24 * 60 * 60;
+        let cutoff = chrono::Utc::now().timestamp() - unused_secs;
+        let mut freed = 0;
+        for (record, size) in reclaim_candidates(files, cutoff, used - limit) {
+            let file_id = record.id.as_deref().unwrap_or_default();
+            let _own = self
+                .own_changes
+                .change(&local_path_from_relative(&self.local_root, &record.path));
+            match placeholders.free_up(&record.path, file_id) {
+                Ok(()) => freed += size,
+                Err(e) => tracing::warn!("Could not free up {}: {}", record.path, e),
+            }
+        }
+        tracing::info!(
+            "Disk {}% full: made rarely used files online-only, freeing {} bytes",
+            used * 100 / total.max(1),
+            freed
+        );
+        Ok(freed)
+    }
+
+    /// Records `record`'s file as damaged and tells the user the first time.
+    fn report_corruption(&self, record: &FileRecord, actual_hash: &str) -> Result<()> {
+        let issue = SyncIssue {
+            path: record.path.clone(),
+            expected_hash: record.hash.clone(),
+            actual_hash: actual_hash.to_string(),
+            detected_at: chrono::Utc::now().timestamp(),
+        };
+        if self.db.add_sync_issue(&issue)? {
+            tracing::warn!("{} changed without a write. Not uploading it.", record.path);
+            self.emit(SyncEvent::Warning {
+                code: "corrupted_file",
+                message: i18n::tr_args(
+                    "{0} changed without being saved and was not uploaded. See sync issues.",
+                    &[record.path.as_str()],
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Local files and folders by relative path, plus the device and inode
+    /// number of each file where the platform has them.
+    fn scan_local_files(&self) -> (HashMap<String, FileRecord>, HashMap<String, (u64, u64)>) {
         let mut files = HashMap::new();
+        let mut identities = HashMap::new();
+        let mut special = HashSet::new();
+        let mut unreadable = Vec::new();
 
         // Use filter_entry to prevent descending into hidden directories (like .git)
         for entry in WalkDir::new(&self.local_root)
             .into_iter()
-            .filter_entry(|e| !is_ignored(e))
-            .filter_map(|e| e.ok())
+            .filter_entry(|e| {
+                e.depth() == 0 || !self.ignore.matches_name(&e.file_name().to_string_lossy())
+            })
         {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    // Missing from the scan, but not deleted
+                    let relative = e.path().and_then(|p| p.strip_prefix(&self.local_root).ok());
+                    match relative {
+                        Some(path) => {
+                            let path = normalize_local_path(&path.to_string_lossy());
+                            let key = format!("unreadable:{}", path);
+                            let message = format!("Cannot read {:?}: {}", e.path(), e);
+                            self.report_failure(&key, Some(&path), message);
+                            unreadable.push(path);
+                        }
+                        None => tracing::warn!("Cannot read {:?}: {}", e.path(), e),
+                    }
+                    continue;
+                }
+            };
             let path = entry.path();
             // Skip root itself
             if path == self.local_root {
@@ -721,9 +2591,17 @@ impl SyncWorker {
                 .to_string();
             let relative = normalize_local_path(&relative);
 
+            if let Some(kind) = special_file(entry.file_type(), path) {
+                special.insert((relative, kind));
+                continue;
+            }
             if entry.file_type().is_file() {
                 let existing = self.db.get_file(&relative).unwrap_or(None);
-                let hash = compute_hash(path).unwrap_or_default();
+                // Renamed over the document before the next scan
+                if existing.is_none() && self.saves.is_temp(path) {
+                    continue;
+                }
+                let hash = self.local_hash(path, existing.as_ref());
                 let metadata = path.metadata().unwrap();
                 let modified = metadata
                     .modified()
@@ -731,6 +2609,9 @@ impl SyncWorker {
                     .duration_since(std::time::UNIX_EPOCH)
                     .unwrap()
                     .as_secs() as i64;
+                if let Some(identity) = file_identity(&metadata) {
+                    identities.insert(relative.clone(), identity);
+                }
 
                 files.insert(
                     relative.clone(),
@@ -740,6 +2621,7 @@ impl SyncWorker {
                         hash,
                         modified_at: modified,
                         server_version: 0,
+                        remote_hash: None,
                         group_folder_id: existing.as_ref().and_then(|r| r.group_folder_id.clone()),
                         is_group_root: false,
                     },
@@ -755,23 +2637,294 @@ impl SyncWorker {
                         hash: "directory".to_string(), // Marker
                         modified_at: 0,
                         server_version: 0,
+                        remote_hash: None,
                         group_folder_id: existing.as_ref().and_then(|r| r.group_folder_id.clone()),
                         is_group_root: existing.map(|r| r.is_group_root).unwrap_or(false),
                     },
                 );
             }
         }
-        files
+        self.note_special_files(special);
+        let failing = unreadable
+            .iter()
+            .map(|path| format!("unreadable:{}", path))
+            .collect();
+        self.resolve_other_failures("unreadable:", &failing);
+        *self.unreadable.lock().unwrap() = unreadable;
+        (files, identities)
     }
 
-    async fn download_file(&self, file_id: &str, path: &str) -> Result<(), String> {
-        let existing = self.db.get_file_by_id(file_id).unwrap_or(None);
-        let mut parent_group_folder_id: Option<String> = None;
-        if let Some(parent) = Path::new(path).parent() {
-            let parent_str = parent.to_string_lossy();
-            if !parent_str.is_empty() && parent_str != "." {
-                if let Some(record) = self.db.get_file(&parent_str).unwrap_or(None) {
-                    parent_group_folder_id = if record.is_group_root {
+    /// Warns about special files the previous scan did not find.
+    fn note_special_files(&self, found: HashSet<(String, SpecialFile)>) {
+        let mut known = self.special_files.lock().unwrap();
+        let mut paths = HashSet::new();
+        for (path, kind) in found {
+            if !known.contains(&path) {
+                tracing::warn!("Skipping {:?} file {}", kind, path);
+                let message = match kind {
+                    SpecialFile::Device => i18n::tr_args(
+                        "{0} is a pipe, socket or device and is not synced.",
+                        &[path.as_str()],
+                    ),
+                    SpecialFile::Sparse => i18n::tr_args(
+                        "{0} is a sparse file, such as a disk image, and is not synced.",
+                        &[path.as_str()],
+                    ),
+                };
+                self.emit(SyncEvent::Warning {
+                    code: "special_file",
+                    message,
+                });
+            }
+            paths.insert(path);
+        }
+        *known = paths;
+    }
+
+    /// New location of tracked file `record` after a local rename: an
+    /// untracked path with the same device and inode number. Inode numbers
+    /// are reused after deletes, so the content or the extension must match.
+    fn local_move_target<'a>(
+        &self,
+        record: &FileRecord,
+        local_files: &'a HashMap<String, FileRecord>,
+        known: &HashMap<String, (u64, u64)>,
+        by_identity: &HashMap<(u64, u64), &'a String>,
+    ) -> Option<&'a String> {
+        if record.id.is_none() || record.hash == "directory" {
+            return None;
+        }
+        let candidate = *by_identity.get(known.get(&record.path)?)?;
+        if self.db.get_file(candidate).ok()?.is_some() {
+            return None;
+        }
+        let current = local_files.get(candidate)?;
+        let extension = |path: &str| {
+            Path::new(path)
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+        };
+        (current.hash == record.hash || extension(candidate) == extension(&record.path))
+            .then_some(candidate)
+    }
+
+    /// Queues the server calls that move `record` to `new_path` and returns
+    /// their positions in `batch`. `None` if the target folder is not on the
+    /// server yet. That is always the case for the files of a renamed folder,
+    /// so renaming a folder locally still deletes its files remotely and
+    /// uploads them again.
+    fn queue_local_move(
+        &self,
+        batch: &mut MutationBatch,
+        record: &FileRecord,
+        new_path: &str,
+    ) -> Result<Option<std::ops::Range<usize>>> {
+        let Some(file_id) = record.id.as_deref() else {
+            return Ok(None);
+        };
+        let (old_parent, old_name) = record.path.rsplit_once('/').unwrap_or(("", &record.path));
+        let (new_parent, new_name) = new_path.rsplit_once('/').unwrap_or(("", new_path));
+        let start = batch.len();
+        if old_parent != new_parent {
+            let folder_id = if new_parent.is_empty() {
+                None
+            } else {
+                match self.db.get_file(new_parent)?.and_then(|r| r.id) {
+                    Some(id) => Some(id),
+                    None => return Ok(None),
+                }
+            };
+            batch.move_file(file_id, folder_id.as_deref())?;
+        }
+        if old_name != new_name {
+            batch.rename_file(file_id, new_name)?;
+        }
+        Ok(Some(start..batch.len()))
+    }
+
+    /// Applies a remote folder rename/move: renames the local directory and
+    /// re-roots the folder and all descendant records in one DB transaction.
+    fn apply_remote_folder_move(
+        &self,
+        folder_id: &str,
+        old_record: Option<&FileRecord>,
+        new_path: &str,
+        data: &FileData,
+    ) -> Result<()> {
+        let new_local = local_path_from_relative(&self.local_root, new_path);
+        let is_group_root = data
+            .group_folder_id
+            .as_deref()
+            .map(|g| g == folder_id)
+            .unwrap_or(false)
+            && data.parent_id.is_none();
+
+        if let Some(old_record) = old_record {
+            let old_local = local_path_from_relative(&self.local_root, &old_record.path);
+            tracing::info!("Moving folder {} -> {}", old_record.path, new_path);
+            let _own = (
+                self.own_changes.change(&old_local),
+                self.own_changes.change(&new_local),
+            );
+
+            let op = self
+                .db
+                .begin_op(PendingOpKind::MoveFolder, &old_record.path, Some(new_path))?;
+            // Leave the DB untouched unless the files moved: rewriting paths
+            // without them would make the next PUSH phase delete them remotely.
+            // A missing source or an existing target is left to the scan.
+            if !old_local.is_dir() || new_local.exists() {
+                tracing::warn!(
+                    "Not moving folder {} -> {}: source missing or target exists",
+                    old_record.path,
+                    new_path
+                );
+                self.db.finish_op(op)?;
+                return Ok(());
+            }
+            if let Some(parent) = new_local.parent() {
+                self.create_dirs(parent)?;
+            }
+            if let Err(e) = fs::rename(&old_local, &new_local) {
+                tracing::error!(
+                    "Folder move {} -> {} failed: {}",
+                    old_record.path,
+                    new_path,
+                    e
+                );
+                self.db.finish_op(op)?;
+                return Ok(());
+            }
+
+            let moved = self.db.move_path_prefix(&old_record.path, new_path)?;
+            self.transfers.move_path_prefix(&old_record.path, new_path);
+            self.db.finish_op(op)?;
+            tracing::info!("Folder move updated {} index entries", moved);
+        } else {
+            tracing::warn!(
+                "Move event for unknown folder {}. Treating as create.",
+                folder_id
+            );
+            self.create_dirs(&new_local)?;
+        }
+
+        self.db.insert_or_update(&FileRecord {
+            path: new_path.to_string(),
+            id: Some(folder_id.to_string()),
+            hash: "directory".to_string(),
+            modified_at: 0,
+            server_version: 0,
+            remote_hash: None,
+            group_folder_id: data
+                .group_folder_id
+                .clone()
+                .or_else(|| old_record.and_then(|r| r.group_folder_id.clone())),
+            is_group_root,
+        })?;
+        Ok(())
+    }
+
+    /// Completes remote folder moves whose local rename happened but whose DB
+    /// update was lost. Without this the PUSH phase would see the old paths as
+    /// deleted and the new ones as new, and delete and re-upload the folder.
+    fn recover_folder_moves(&self) -> Result<()> {
+        for op in self.db.pending_ops()? {
+            if op.kind != PendingOpKind::MoveFolder {
+                continue;
+            }
+            if let Some(new_path) = &op.target {
+                let old_local = local_path_from_relative(&self.local_root, &op.path);
+                let new_local = local_path_from_relative(&self.local_root, new_path);
+                if new_local.is_dir() && !old_local.exists() {
+                    let moved = self.db.move_path_prefix(&op.path, new_path)?;
+                    tracing::info!(
+                        "Recovered interrupted folder move {} -> {} ({} entries)",
+                        op.path,
+                        new_path,
+                        moved
+                    );
+                }
+            }
+            self.db.finish_op(op.id)?;
+        }
+        Ok(())
+    }
+
+    /// Resolves uploads and remote deletes interrupted by a crash. Runs after
+    /// the PULL phase, which links uploads that reached the server via their
+    /// create events.
+    async fn recover_pending_ops(&self) -> Result<()> {
+        let mut deletions = MutationBatch::new();
+        let mut delete_ops = Vec::new();
+        for op in self.db.pending_ops()? {
+            match (op.kind, op.target.as_deref()) {
+                (PendingOpKind::Upload, _) => {
+                    let local_path = local_path_from_relative(&self.local_root, &op.path);
+                    let record = self.db.get_file(&op.path)?;
+                    match (compute_hash(&local_path).ok(), record) {
+                        (Some(hash), Some(record)) if record.id.is_some() && record.hash == hash => {
+                            tracing::info!("Interrupted upload of {} reached the server", op.path);
+                        }
+                        (None, _) => {}
+                        // PUSH uploads it again and replaces the entry
+                        _ => continue,
+                    }
+                    self.db.finish_op(op.id)?;
+                }
+                (PendingOpKind::DeleteFile, Some(id)) => {
+                    deletions.soft_delete_file(id)?;
+                    delete_ops.push(op.id);
+                }
+                (PendingOpKind::DeleteFolder, Some(id)) => {
+                    deletions.delete_folder(id)?;
+                    delete_ops.push(op.id);
+                }
+                _ => {}
+            }
+        }
+
+        if !deletions.is_empty() {
+            tracing::info!("Retrying {} interrupted remote deletes...", deletions.len());
+            // Per-call failures (typically already deleted) are not retried
+            self.client.execute_batch(deletions).await?;
+            for id in delete_ops {
+                self.db.finish_op(id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Both sides changed `path`: keeps the local copy as `*.conflict_backup`
+    /// and takes the server version.
+    async fn resolve_conflict(&self, file_id: &str, path: &str) {
+        tracing::warn!("Conflict detected for {}. Changed on both sides. Backing up...", path);
+        let local_path = local_path_from_relative(&self.local_root, path);
+        let backup_path = local_path.with_extension(crate::conflicts::BACKUP_EXTENSION);
+        let _own = self.own_changes.change(&backup_path);
+        // Copy instead of rename so the download can be conditional on the local content
+        let _ = fs::copy(&local_path, &backup_path);
+        match self.download_file(file_id, path).await {
+            Ok(DownloadOutcome::NotModified) => {
+                tracing::info!("No conflict for {}: local copy matches server", path);
+                let _ = fs::remove_file(&backup_path);
+            }
+            Ok(_) => self.emit(SyncEvent::Conflict {
+                path: path.to_string(),
+                backup: backup_path.to_string_lossy().to_string(),
+            }),
+            Err(e) => tracing::error!("Download failed for {}: {}", path, e),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn download_file(&self, file_id: &str, path: &str) -> Result<DownloadOutcome> {
+        let existing = self.db.get_file_by_id(file_id).unwrap_or(None);
+        let mut parent_group_folder_id: Option<String> = None;
+        if let Some(parent) = Path::new(path).parent() {
+            let parent_str = parent.to_string_lossy();
+            if !parent_str.is_empty() && parent_str != "." {
+                if let Some(record) = self.db.get_file(&parent_str).unwrap_or(None) {
+                    parent_group_folder_id = if record.is_group_root {
                         record.id.clone()
                     } else {
                         record.group_folder_id.clone()
@@ -781,13 +2934,76 @@ impl SyncWorker {
         }
         let local_path = local_path_from_relative(&self.local_root, path);
         if let Some(parent) = local_path.parent() {
-            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            self.create_dirs(parent)?;
         }
+        let _own = self.own_changes.change(&local_path);
+
+        // Conditional GET when a local copy exists: the cached ETag (if stored for
+        // this exact content) plus the quoted content hash, which servers that
+        // use content hashes as ETags can match even after the DB was lost.
+        let local_hash = compute_hash(&local_path).ok();
+        let if_none_match = local_hash.as_ref().map(|local_hash| {
+            let mut tags = vec![format!("\"{}\"", local_hash)];
+            if let Some(etag) = self.db.get_etag(file_id, local_hash).unwrap_or(None) {
+                if !tags.contains(&etag) {
+                    tags.insert(0, etag);
+                }
+            }
+            tags.join(", ")
+        });
 
-        self.client.download_file(file_id, &local_path).await?;
+        // A local copy with the announced content is left to the 304 path
+        let expected_hash = self.expected_hashes.lock().unwrap().remove(file_id);
+        let lan_hash = expected_hash.filter(|hash| local_hash.as_ref() != Some(hash));
+        // An interrupted download of this file continues where it stopped
+        let resume = self.db.get_resume(path, Direction::Download, file_id)?;
+        // Bytes received before the interruption were counted back then
+        let resumed = resume.as_ref().map_or(0, |point| point.offset);
+        let db = &self.db;
+        let save_resume = |point: &ResumePoint| {
+            if let Err(e) = db.set_resume(path, Direction::Download, point, file_id) {
+                tracing::warn!("Could not save download progress of {}: {}", path, e);
+            }
+        };
+        let via_lan = std::cell::Cell::new(false);
+        let download = async {
+            if let Some(hash) = lan_hash {
+                if self.fetch_from_lan(hash, local_path.clone()).await {
+                    via_lan.set(true);
+                    return Ok(DownloadOutcome::Downloaded { etag: None });
+                }
+            }
+            self.client
+                .download_file_resumable(
+                    file_id,
+                    &local_path,
+                    if_none_match.as_deref(),
+                    resume,
+                    &save_resume,
+                )
+                .await
+        };
+        let outcome = self
+            .track_transfer(path, Direction::Download, None, download)
+            .await
+            .inspect_err(telemetry::record_error)?;
+        self.db.clear_resume(path, Direction::Download)?;
 
         let hash = compute_hash(&local_path).unwrap_or_default();
-        let metadata = local_path.metadata().map_err(|e| e.to_string())?;
+        match &outcome {
+            DownloadOutcome::NotModified => {
+                tracing::info!("{} unchanged on server (304). Keeping local copy.", path)
+            }
+            DownloadOutcome::Downloaded { etag: Some(etag) } => {
+                self.db.set_etag(file_id, etag, &hash)?;
+            }
+            DownloadOutcome::Downloaded { etag: None } => {}
+        }
+        let metadata = local_path.metadata()?;
+        if matches!(outcome, DownloadOutcome::Downloaded { .. }) && !via_lan.get() {
+            let received = metadata.len().saturating_sub(resumed);
+            self.count_bandwidth(Direction::Download, received);
+        }
         let modified = metadata
             .modified()
             .unwrap()
@@ -799,21 +3015,134 @@ impl SyncWorker {
             .insert_or_update(&FileRecord {
                 path: path.to_string(),
                 id: Some(file_id.to_string()),
+                remote_hash: Some(hash.clone()),
                 hash,
                 modified_at: modified,
-                server_version: 0,
+                server_version: existing.as_ref().map(|r| r.server_version).unwrap_or(0),
                 group_folder_id: existing
                     .as_ref()
                     .and_then(|r| r.group_folder_id.clone())
                     .or(parent_group_folder_id),
                 is_group_root: false,
+            })?;
+        self.db.remove_sync_issue(path)?;
+
+        Ok(outcome)
+    }
+
+    /// Fetches `hash` from another device on the network; false if none had it.
+    async fn fetch_from_lan(&self, hash: String, dest: PathBuf) -> bool {
+        let Some(lan) = self.lan.clone().filter(|lan| lan.has_peers()) else {
+            return false;
+        };
+        matches!(
+            tokio::task::spawn_blocking(move || {
+                let _transfer = io_scheduler::transfer();
+                lan.fetch(&hash, &dest)
             })
-            .map_err(|e| e.to_string())?;
+            .await,
+            Ok(Ok(true))
+        )
+    }
 
-        Ok(())
+    /// Creates the remote folders for `paths` one tree level at a time, each
+    /// level as one batched request. Below a folder that failed nothing is
+    /// attempted until the next cycle.
+    async fn create_remote_folders(&self, paths: Vec<String>) {
+        let mut levels: std::collections::BTreeMap<usize, Vec<String>> = Default::default();
+        for path in paths {
+            levels.entry(path.matches('/').count()).or_default().push(path);
+        }
+        let mut failed: Vec<String> = Vec::new();
+        for (_, level) in levels {
+            let (ready, blocked): (Vec<String>, Vec<String>) = level.into_iter().partition(|path| {
+                !failed
+                    .iter()
+                    .any(|f| path.strip_prefix(f.as_str()).is_some_and(|rest| rest.starts_with('/')))
+            });
+            for path in blocked {
+                tracing::warn!("Skipping folder {}: its parent could not be created", path);
+                failed.push(path);
+            }
+            for (path, result) in self.create_remote_level(ready).await {
+                let issue = format!("folder:{}", path);
+                match result {
+                    Ok(()) => self.resolve_failure(&issue),
+                    Err(e) => {
+                        let message = format!("New folder creation failed {}: {}", path, e);
+                        self.report_failure(&issue, Some(&path), message);
+                        failed.push(path);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Creates the folders of one tree level with a single batched request
+    /// and returns the outcome per path.
+    async fn create_remote_level(&self, paths: Vec<String>) -> Vec<(String, Result<()>)> {
+        let mut results = Vec::new();
+        let mut batch = MutationBatch::new();
+        let mut queued: Vec<(String, FolderPlan, usize)> = Vec::new();
+        for path in paths {
+            let plan = match self.plan_remote_folder(&path) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    results.push((path, Err(e)));
+                    continue;
+                }
+            };
+            // Adopt a folder the server is known to have instead of provoking a conflict
+            if let Some(id) = plan.known.clone() {
+                tracing::info!("Remote folder {} already exists. Adopting {}...", path, id);
+                let result = self.record_remote_folder(&path, id, plan.group_folder_id);
+                results.push((path, result));
+                continue;
+            }
+            tracing::info!(
+                "Creating remote folder: {} (Parent: {:?})",
+                plan.name,
+                plan.parent_id
+            );
+            match batch.create_folder(&plan.name, plan.parent_id.as_deref()) {
+                Ok(index) => queued.push((path, plan, index)),
+                Err(e) => results.push((path, Err(e))),
+            }
+        }
+        if batch.is_empty() {
+            return results;
+        }
+
+        let mut created: Vec<Option<Result<serde_json::Value>>> =
+            match self.client.execute_batch(batch).await {
+                Ok(values) => values.into_iter().map(Some).collect(),
+                Err(e) => {
+                    // Nothing was created, so there is nothing to adopt either
+                    let message = e.to_string();
+                    results.extend(
+                        queued
+                            .into_iter()
+                            .map(|(path, _, _)| (path, Err(XynoxaError::Other(message.clone())))),
+                    );
+                    return results;
+                }
+            };
+        for (path, plan, index) in queued {
+            let entry = match created.get_mut(index).and_then(Option::take) {
+                Some(Ok(value)) => serde_json::from_value::<FolderEntry>(value).map_err(Into::into),
+                Some(Err(e)) => Err(e),
+                None => Err(XynoxaError::Decode(
+                    "Missing result in folder batch".to_string(),
+                )),
+            };
+            let result = self.finish_remote_folder(&path, plan, entry).await;
+            results.push((path, result));
+        }
+        results
     }
 
-    async fn create_remote_folder(&self, path: &str) -> Result<(), String> {
+    /// Looks up where the new local folder `path` goes on the server.
+    fn plan_remote_folder(&self, path: &str) -> Result<FolderPlan> {
         let relative_path = Path::new(path);
         let name = relative_path
             .file_name()
@@ -822,24 +3151,26 @@ impl SyncWorker {
             .to_string();
 
         // Find parent ID
-        let mut parent_group_folder_id: Option<String> = None;
+        let mut group_folder_id: Option<String> = None;
         let parent_id = if let Some(parent) = relative_path.parent() {
             let parent_str = parent.to_string_lossy();
             if parent_str.len() > 0 && parent_str != "." {
                 if let Some(record) = self.db.get_file(&parent_str).unwrap_or(None) {
-                    parent_group_folder_id = if record.is_group_root {
+                    group_folder_id = if record.is_group_root {
                         record.id.clone()
                     } else {
                         record.group_folder_id.clone()
                     };
                     record.id
+                } else if let Some(entry) = self.remote_folder(&parent_str)? {
+                    Some(entry.id)
                 } else {
                     let msg = format!(
                         "Parent {} not found for {}. Skipping to prevent flattening.",
                         parent_str, path
                     );
-                    log::warn!("{}", msg);
-                    return Err(msg);
+                    tracing::warn!("{}", msg);
+                    return Err(XynoxaError::Conflict(msg));
                 }
             } else {
                 None
@@ -848,69 +3179,227 @@ impl SyncWorker {
             None
         };
 
-        log::info!("Creating remote folder: {} (Parent: {:?})", name, parent_id);
+        let known = self
+            .db
+            .find_remote_child(parent_id.as_deref(), &name)?
+            .filter(|entry| entry.is_folder)
+            .map(|entry| entry.id);
+        Ok(FolderPlan {
+            name,
+            parent_id,
+            group_folder_id,
+            known,
+        })
+    }
 
-        match self.client.create_folder(&name, parent_id.as_deref()).await {
-            Ok(entry) => {
-                let group_folder_id = parent_group_folder_id.clone();
-                self.db
-                    .insert_or_update(&FileRecord {
-                        path: path.to_string(),
-                        id: Some(entry.id),
-                        hash: "directory".to_string(),
-                        modified_at: 0,
-                        server_version: 0, // Folders don't have versions
-                        group_folder_id,
-                        is_group_root: false,
-                    })
-                    .map_err(|e| e.to_string())?;
-                Ok(())
+    /// Records the folder created for `path`, or adopts an existing one of the
+    /// same name if the creation failed.
+    async fn finish_remote_folder(
+        &self,
+        path: &str,
+        plan: FolderPlan,
+        created: Result<FolderEntry>,
+    ) -> Result<()> {
+        let e = match created {
+            Ok(entry) => return self.record_remote_folder(path, entry.id, plan.group_folder_id),
+            Err(e) => e,
+        };
+        tracing::warn!(
+            "Create folder failed: {}. Attempting to resolve via adoption...",
+            e
+        );
+        // Fallback: Check if it already exists (Adoption)
+        match self
+            .find_remote_folder_id(&plan.name, plan.parent_id.as_deref())
+            .await?
+        {
+            Some(existing_id) => {
+                tracing::info!("Found existing remote folder {}. Adopting...", existing_id);
+                self.record_remote_folder(path, existing_id, plan.group_folder_id)
             }
-            Err(e) => {
-                log::warn!(
-                    "Create folder failed: {}. Attempting to resolve via adoption...",
-                    e
-                );
-                // Fallback: Check if it already exists (Adoption)
-                if let Some(existing_id) = self
-                    .find_remote_folder_id(&name, parent_id.as_deref())
-                    .await?
-                {
-                    log::info!("Found existing remote folder {}. Adopting...", existing_id);
-                    let group_folder_id = parent_group_folder_id.clone();
-                    self.db
-                        .insert_or_update(&FileRecord {
-                            path: path.to_string(),
-                            id: Some(existing_id),
-                            hash: "directory".to_string(),
-                            modified_at: 0,
-                            server_version: 0, // Unknown, but 0 is safe
-                            group_folder_id,
-                            is_group_root: false,
-                        })
-                        .map_err(|e| e.to_string())?;
-                    Ok(())
-                } else {
-                    Err(e)
+            None => Err(e),
+        }
+    }
+
+    fn record_remote_folder(
+        &self,
+        path: &str,
+        id: String,
+        group_folder_id: Option<String>,
+    ) -> Result<()> {
+        self.db.insert_or_update(&FileRecord {
+            path: path.to_string(),
+            id: Some(id),
+            hash: "directory".to_string(),
+            modified_at: 0,
+            server_version: 0, // Folders don't have versions
+            remote_hash: None,
+            group_folder_id,
+            is_group_root: false,
+        })?;
+        Ok(())
+    }
+
+    /// Keeps `remote_entries` and the tags and favorites in step with the server.
+    fn mirror_remote_event(&self, event: &crate::api::SyncEvent) -> Result<()> {
+        if let Some(data) = event.data.as_ref().filter(|_| event.action != "delete") {
+            if let Some(tags) = &data.tags {
+                self.db.set_tags(&event.entity_id, tags)?;
+            }
+            if let Some(favorite) = data.favorite {
+                self.db.set_favorite(&event.entity_id, favorite)?;
+            }
+        }
+        match event.action.as_str() {
+            "delete" => {
+                self.db.remove_remote_entry(&event.entity_id)?;
+                self.db.remove_labels(&event.entity_id)?;
+            }
+            "create" | "update" | "copy" | "restore" | "move" => {
+                let Some(data) = &event.data else {
+                    return Ok(());
+                };
+                let path = remote_event_path(data, event.owner_id.as_deref());
+                if path.is_empty() {
+                    return Ok(());
+                }
+                let (parent_path, name) = match path.rsplit_once('/') {
+                    Some((parent, name)) => (Some(parent), name),
+                    None => (None, path.as_str()),
+                };
+                // Events do not always name the parent; the mirror may know it
+                let parent_id = match (data.folder_id.as_ref().or(data.parent_id.as_ref()), parent_path) {
+                    (Some(id), _) => Some(id.clone()),
+                    (None, Some(parent)) => self.db.find_remote_by_path(parent)?.map(|entry| entry.id),
+                    (None, None) => None,
+                };
+                let is_folder = is_folder_entity(&event.entity_type);
+                self.db.upsert_remote_entry(&RemoteEntry {
+                    id: event.entity_id.clone(),
+                    is_folder,
+                    name: name.to_string(),
+                    parent_id,
+                    hash: data.hash.clone().filter(|_| !is_folder),
+                    path,
+                })?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Whether the sync root holds anything besides ignored and internal files.
+    fn has_local_content(&self) -> bool {
+        let Ok(entries) = fs::read_dir(&self.local_root) else {
+            return false;
+        };
+        entries
+            .flatten()
+            .any(|entry| !self.ignore.matches_name(&entry.file_name().to_string_lossy()))
+    }
+
+    /// Adopts local files whose content matches the server's, so a folder
+    /// that already mirrors the server is not downloaded again. Replays all server events
+    /// into the remote mirror first; the adopted records carry the last event
+    /// as their version, so the regular pull skips the history of those files.
+    /// Everything else syncs as usual, differing files as conflicts.
+    async fn seed_index(&self) -> Result<()> {
+        tracing::info!("Adopting existing files in {:?}", self.local_root);
+        let mut cursor = 0;
+        let mut last_event = 0;
+        let mut groups: HashMap<String, String> = HashMap::new();
+        loop {
+            let page = self.client.sync_pull(cursor, Some(PULL_PAGE_SIZE)).await?;
+            if page.events.is_empty() {
+                break;
+            }
+            for event in &page.events {
+                self.mirror_remote_event(event)?;
+                last_event = last_event.max(event.id);
+                if let Some(group) = event.data.as_ref().and_then(|d| d.group_folder_id.clone()) {
+                    groups.insert(event.entity_id.clone(), group);
                 }
             }
+            if page.is_last_page(cursor) {
+                break;
+            }
+            cursor = page.next_cursor;
+        }
+
+        let entries = self.db.remote_files()?;
+        let remote_files = entries.len();
+        let mut adopted = Vec::new();
+        for entry in entries {
+            let Some(hash) = entry.hash.filter(|hash| !hash.is_empty()) else {
+                continue;
+            };
+            let local_path = local_path_from_relative(&self.local_root, &entry.path);
+            let Ok(metadata) = fs::metadata(&local_path) else {
+                continue;
+            };
+            if !metadata.is_file() || compute_hash(&local_path).ok().as_deref() != Some(&hash) {
+                continue;
+            }
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            adopted.push(FileRecord {
+                path: entry.path,
+                group_folder_id: groups.get(&entry.id).cloned(),
+                id: Some(entry.id),
+                hash: hash.clone(),
+                modified_at: modified,
+                server_version: last_event as i64,
+                remote_hash: Some(hash),
+                is_group_root: false,
+            });
         }
+        self.db.insert_or_update_many(&adopted)?;
+        tracing::info!(
+            "Adopted {} of {} server files already present locally",
+            adopted.len(),
+            remote_files
+        );
+        Ok(())
+    }
+
+    /// The server folder at `path` according to the mirrored remote tree.
+    fn remote_folder(&self, path: &str) -> Result<Option<RemoteEntry>> {
+        Ok(self.db.find_remote_by_path(path)?.filter(|entry| entry.is_folder))
     }
 
-    // Helper to find a folder by name/parent by scanning the sync stream (Expensive but robust fallback)
+    /// Id of the existing remote folder `target_name` below `target_parent`.
     async fn find_remote_folder_id(
         &self,
         target_name: &str,
         target_parent: Option<&str>,
-    ) -> Result<Option<String>, String> {
-        // We scan from 0. In production this should be cached or optimized.
+    ) -> Result<Option<String>> {
+        if let Some(entry) = self
+            .db
+            .find_remote_child(target_parent, target_name)?
+            .filter(|entry| entry.is_folder)
+        {
+            return Ok(Some(entry.id));
+        }
+        match self.client.find_folder(target_name, target_parent).await {
+            Ok(found) => return Ok(found.map(|folder| folder.id)),
+            // Older servers lack the lookup; replay the event stream instead
+            Err(XynoxaError::Server { status: 404, .. }) => {
+                tracing::debug!("Server has no folder lookup, scanning the event stream");
+            }
+            Err(e) => return Err(e),
+        }
+
+        // O(history), so only for servers without `folders.getByNameAndParent`
         let mut cursor = 0;
         loop {
             let res = self
                 .client
-                .sync_pull(cursor)
-                .await
-                .map_err(|e| e.to_string())?;
+                .sync_pull(cursor, Some(PULL_PAGE_SIZE))
+                .await?;
             if res.events.is_empty() {
                 break;
             }
@@ -920,14 +3409,14 @@ impl SyncWorker {
                 if let Some(data) = &event.data {
                     if let Some(n) = &data.name {
                         if n == target_name {
-                            log::warn!("Adoption Scan: Found match Candidate! ID: {}, Type: {}, Action: {}, Parent: {:?}",
+                            tracing::warn!("Adoption Scan: Found match Candidate! ID: {}, Type: {}, Action: {}, Parent: {:?}",
                                 event.entity_id, event.entity_type, event.action, data.parent_id);
                         }
                     }
                 }
 
                 // We are looking for a folder or group
-                if event.entity_type == "folder" || event.entity_type == "group" || event.entity_type == "group_folder" {
+                if is_folder_entity(&event.entity_type) {
                     match event.action.as_str() {
                         "create" | "update" | "copy" => {
                             if let Some(data) = &event.data {
@@ -938,13 +3427,13 @@ impl SyncWorker {
                                         let remote_parent =
                                             data.folder_id.as_deref().or(data.parent_id.as_deref());
                                         if remote_parent == target_parent {
-                                            log::info!(
+                                            tracing::info!(
                                                 "Adoption Scan: Confirmed Match for {}",
                                                 target_name
                                             );
                                             return Ok(Some(event.entity_id.clone()));
                                         } else {
-                                            log::warn!("Adoption Scan: Name matched but Parent Match Failed. Local: {:?}, Remote: {:?}", target_parent, remote_parent);
+                                            tracing::warn!("Adoption Scan: Name matched but Parent Match Failed. Local: {:?}, Remote: {:?}", target_parent, remote_parent);
                                         }
                                     }
                                 }
@@ -953,22 +3442,23 @@ impl SyncWorker {
                         _ => {}
                     }
                 }
-                cursor = res.next_cursor;
             }
-            if cursor == res.next_cursor || res.events.len() < 50 {
+            if res.is_last_page(cursor) {
                 // End of stream
                 break;
             }
+            cursor = res.next_cursor;
         }
         Ok(None)
     }
 
-    async fn upload_file(&self, path: &str) -> Result<(), String> {
+    #[tracing::instrument(skip(self))]
+    async fn upload_file(&self, path: &str) -> Result<()> {
         let local_path = local_path_from_relative(&self.local_root, path);
 
         // Safety check: Never upload directories as files
         if local_path.is_dir() {
-            log::warn!("upload_file called on directory: {}. Skipping.", path);
+            tracing::warn!("upload_file called on directory: {}. Skipping.", path);
             return Ok(());
         }
 
@@ -988,7 +3478,7 @@ impl SyncWorker {
                     };
                     record.id
                 } else {
-                    None
+                    self.remote_folder(&parent_str)?.map(|entry| entry.id)
                 }
             } else {
                 None
@@ -997,18 +3487,88 @@ impl SyncWorker {
             None
         };
 
-        let entry = self
-            .client
-            .upload_file(
-                &local_path,
-                existing_id.as_deref(),
-                parent_folder_id.as_deref(),
-                path,
-            )
-            .await?;
+        // Same name and content already on the server (e.g. the index was
+        // lost): adopt it instead of uploading a duplicate
+        let duplicate = match existing_id {
+            Some(_) => None,
+            None => {
+                let name = Path::new(path).file_name().unwrap_or_default().to_string_lossy();
+                self.db
+                    .find_remote_child(parent_folder_id.as_deref(), &name)?
+                    .filter(|entry| {
+                        !entry.is_folder
+                            && entry.hash.is_some()
+                            && entry.hash == compute_hash(&local_path).ok()
+                    })
+            }
+        };
+
+        // An interrupted upload continues if the file did not change since
+        let fingerprint = local_path
+            .metadata()
+            .map(|m| {
+                let modified = m
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                format!("{}:{}", m.len(), modified)
+            })
+            .unwrap_or_default();
+        let resume = self.db.get_resume(path, Direction::Upload, &fingerprint)?;
+        let db = &self.db;
+        let transfers = &self.transfers;
+        let on_event = self.on_event.as_deref();
+        let save_resume = |point: &ResumePoint| {
+            if let Err(e) = db.set_resume(path, Direction::Upload, point, &fingerprint) {
+                tracing::warn!("Could not save upload progress of {}: {}", path, e);
+            }
+            // Called once per confirmed chunk
+            let progress = transfers.set_progress(path, point.offset);
+            if let (Some(progress), Some(on_event)) = (progress, on_event) {
+                on_event(SyncEvent::ChunkProgress {
+                    path: path.to_string(),
+                    progress,
+                });
+            }
+        };
+
+        let op = self.db.begin_op(PendingOpKind::Upload, path, None)?;
+        let remote_id = match duplicate {
+            Some(entry) => {
+                tracing::info!("{} is already on the server as {}. Adopting...", path, entry.id);
+                entry.id
+            }
+            None => {
+                let size = local_path.metadata().ok().map(|m| m.len());
+                // Chunks the server already has are not sent again
+                let resumed = resume.as_ref().map_or(0, |point| point.offset);
+                let uploaded = self
+                    .track_transfer(
+                        path,
+                        Direction::Upload,
+                        size,
+                        self.client.upload_file_resumable(
+                            &local_path,
+                            existing_id.as_deref(),
+                            parent_folder_id.as_deref(),
+                            path,
+                            resume,
+                            &save_resume,
+                        ),
+                    )
+                    .await
+                    .inspect_err(telemetry::record_error)?;
+                let sent = size.unwrap_or(0).saturating_sub(resumed);
+                self.count_bandwidth(Direction::Upload, sent);
+                uploaded.id
+            }
+        };
+        self.db.clear_resume(path, Direction::Upload)?;
 
         let hash = compute_hash(&local_path).unwrap_or_default();
-        let metadata = local_path.metadata().map_err(|e| e.to_string())?;
+        let metadata = local_path.metadata()?;
         let modified = metadata
             .modified()
             .unwrap()
@@ -1019,112 +3579,484 @@ impl SyncWorker {
         self.db
             .insert_or_update(&FileRecord {
                 path: path.to_string(),
-                id: Some(entry.id),
+                id: Some(remote_id),
+                remote_hash: Some(hash.clone()),
                 hash,
                 modified_at: modified,
-                server_version: 0, // UploadedFile doesn't have version
+                server_version: existing_record.as_ref().map(|r| r.server_version).unwrap_or(0),
                 group_folder_id: parent_group_folder_id,
                 is_group_root: false,
-            })
-            .map_err(|e| e.to_string())?;
+            })?;
+        self.db.remove_sync_issue(path)?;
+        self.db.finish_op(op)?;
 
         Ok(())
     }
 }
 
-fn resolve_db_path(local_root: &Path) -> PathBuf {
-    let new_path = local_root.join(".xynoxa.db");
-    if new_path.exists() {
-        return new_path;
-    }
-    let legacy_path = local_root.join(".xynoxa.db");
-    if legacy_path.exists() {
-        if fs::rename(&legacy_path, &new_path).is_ok() {
-            return new_path;
-        }
-        return legacy_path;
-    }
-    new_path
+/// Where a new local folder goes on the server.
+struct FolderPlan {
+    name: String,
+    parent_id: Option<String>,
+    /// Group folder the parent belongs to
+    group_folder_id: Option<String>,
+    /// Id of a folder of that name the server is known to have already
+    known: Option<String>,
 }
 
-fn ensure_sync_root(path: &Path) -> Result<(), String> {
-    if path.as_os_str().is_empty() {
-        return Err("Sync root is empty".to_string());
+/// Which side changed a file since the last sync.
+#[derive(Debug, PartialEq, Eq)]
+enum Divergence {
+    InSync,
+    Local,
+    Remote,
+    Both,
+}
+
+/// Three-way comparison of the local and remote content hashes against the
+/// merge base stored in `base`. Without a base there is no way to tell which
+/// side is newer, so differing content counts as changed on both sides.
+fn classify_change(base: Option<&FileRecord>, local_hash: &str, remote_hash: &str) -> Divergence {
+    if local_hash == remote_hash {
+        return Divergence::InSync;
     }
-    if !path.is_absolute() {
-        return Err("Sync root must be absolute".to_string());
+    match base {
+        Some(base) if base.hash == local_hash => Divergence::Remote,
+        Some(base) if base.hash == remote_hash => Divergence::Local,
+        _ => Divergence::Both,
     }
-    if path.exists() {
-        if path.is_dir() {
-            return Ok(());
+}
+
+/// Files with their size and last use time to make online-only to free
+/// `needed` bytes: those last used before `cutoff`, least recently used
+/// first. Fewer if they do not add up.
+fn reclaim_candidates(
+    mut files: Vec<(FileRecord, u64, i64)>,
+    cutoff: i64,
+    needed: u64,
+) -> Vec<(FileRecord, u64)> {
+    files.retain(|(_, _, last_used)| *last_used < cutoff);
+    files.sort_by_key(|(_, _, last_used)| *last_used);
+    let mut chosen = Vec::new();
+    let mut total = 0;
+    for (record, size, _) in files {
+        if total >= needed {
+            break;
         }
-        return Err("Sync root is not a directory".to_string());
-    }
-    fs::create_dir_all(path).map_err(|e| e.to_string())?;
-    if !path.is_dir() {
-        return Err("Failed to create sync root directory".to_string());
+        total += size;
+        chosen.push((record, size));
     }
-    Ok(())
+    chosen
 }
 
-fn normalize_local_path(path: &str) -> String {
-    if std::path::MAIN_SEPARATOR == '\\' {
-        path.replace('\\', "/")
-    } else {
-        path.to_string()
-    }
+/// Whether the local content of `record` changed while its modification
+/// time stayed the same, which a normal save never does. Content matching
+/// the server's is a lost DB update, not damage.
+fn silently_changed(record: &FileRecord, local_hash: &str, modified: i64) -> bool {
+    record.modified_at != 0
+        && modified == record.modified_at
+        && record.hash != "directory"
+        && local_hash != record.hash
+        && Some(local_hash) != record.remote_hash.as_deref()
 }
 
-fn normalize_remote_path(path: &str) -> String {
-    if std::path::MAIN_SEPARATOR == '\\' {
-        path.replace('\\', "/")
+pub(crate) fn is_folder_entity(entity_type: &str) -> bool {
+    entity_type == "folder" || entity_type == "group" || entity_type == "group_folder"
+}
+
+/// Serves LAN peers files whose synced content has the requested hash.
+fn content_lookup(local_root: PathBuf, db_path: PathBuf, db_key: DbKey) -> ContentLookup {
+    Box::new(move |hash| {
+        let db = Database::open(&db_path, &db_key).ok()?;
+        let record = db.get_file_by_hash(hash).ok()??;
+        if record.remote_hash.as_deref() != Some(hash) {
+            return None;
+        }
+        let path = local_path_from_relative(&local_root, &record.path);
+        (path.is_file() && !is_dehydrated(&path)).then_some(path)
+    })
+}
+
+/// Interval until the next periodic pull after one that did (`changed`) or
+/// did not bring server changes.
+fn next_poll_interval(current: Duration, changed: bool) -> Duration {
+    if changed {
+        PERIODIC_SYNC_INTERVAL
     } else {
-        path.to_string()
+        (current * 2).min(MAX_POLL_INTERVAL)
     }
 }
 
-fn local_path_from_relative(root: &Path, relative: &str) -> PathBuf {
-    let mut out = PathBuf::from(root);
-    for part in relative.split('/').filter(|p| !p.is_empty()) {
-        out.push(part);
+/// Monthly cap in bytes for a cap of `mb` megabytes; none for 0.
+pub fn bandwidth_cap(mb: Option<u64>) -> Option<u64> {
+    mb.filter(|&mb| mb > 0)
+        .map(|mb| mb.saturating_mul(1024 * 1024))
+}
+
+/// The server the worker syncs with: `client`, or the simulated one in demo
+/// mode (see [`crate::config::DEMO_SERVER_URL`]).
+pub(crate) fn worker_api(client: XynoxaClient, api_url: Option<&str>) -> Arc<dyn ApiClient> {
+    #[cfg(feature = "demo")]
+    if api_url == Some(crate::config::DEMO_SERVER_URL) {
+        tracing::info!("Demo mode: syncing with a simulated server");
+        return Arc::new(crate::mock_api::MockApiClient::demo());
     }
-    out
+    let _ = api_url;
+    Arc::new(client)
 }
 
-fn is_safe_relative_path(path: &str) -> bool {
-    if Path::new(path).is_absolute() {
-        return false;
+/// Database name of clients before databases were kept per account.
+const LEGACY_DB_NAME: &str = ".xynoxa.db";
+/// Files SQLite keeps next to a database while it is open.
+const DB_SIDECARS: [&str; 3] = ["-wal", "-shm", "-journal"];
+
+/// The sync database of `account` (see [`AppConfig::account_key`]) in the
+/// folder `local_root`.
+fn db_path(local_root: &Path, account: &str) -> PathBuf {
+    local_root.join(format!(".xynoxa-{}.db", account))
+}
+
+/// The database [`resolve_db_path`] would open, without adopting anything;
+/// `None` if `local_root` has none for `account`.
+pub fn existing_db_path(local_root: &Path, account: &str) -> Option<PathBuf> {
+    let server = account.split('-').next().unwrap_or(account);
+    [
+        db_path(local_root, account),
+        db_path(local_root, server),
+        local_root.join(LEGACY_DB_NAME),
+    ]
+    .into_iter()
+    .find(|p| p.is_file())
+}
+
+/// [`db_path`], adopting the database written before the user id was known,
+/// or the single database of older clients, if the account has none yet.
+fn resolve_db_path(local_root: &Path, account: &str) -> PathBuf {
+    let path = db_path(local_root, account);
+    let Some(earlier) = existing_db_path(local_root, account).filter(|p| *p != path) else {
+        return path;
+    };
+    tracing::info!("Adopting sync database {:?} as {:?}", earlier, path);
+    for suffix in DB_SIDECARS {
+        let sidecar = |p: &Path| PathBuf::from(format!("{}{}", p.display(), suffix));
+        if sidecar(&earlier).exists() {
+            let _ = fs::rename(sidecar(&earlier), sidecar(&path));
+        }
     }
-    for part in path.split('/') {
-        if part == ".." {
-            return false;
+    match fs::rename(&earlier, &path) {
+        Ok(()) => path,
+        Err(e) => {
+            tracing::warn!("Could not rename {:?}, using it as is: {}", earlier, e);
+            earlier
         }
     }
-    true
 }
 
-fn is_effectively_empty_root(root: &Path) -> Result<bool, String> {
-    let entries = fs::read_dir(root).map_err(|e| e.to_string())?;
-    for entry in entries {
-        let entry = entry.map_err(|e| e.to_string())?;
-        let name = entry
-            .file_name()
-            .to_string_lossy()
-            .to_string();
-        if name == ".xynoxa.db" || name == ".git" || name == "node_modules" {
-            continue;
+/// Whether `name` is a sync database of any account, or one of the files
+/// SQLite keeps next to it.
+pub fn is_database_file(name: &str) -> bool {
+    let base = DB_SIDECARS
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(name);
+    base == LEGACY_DB_NAME || (base.starts_with(".xynoxa-") && base.ends_with(".db"))
+}
+
+/// The sync databases of every account in the folder `dir`, without the
+/// files SQLite keeps next to them.
+pub fn database_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            is_database_file(&name) && !DB_SIDECARS.iter().any(|suffix| name.ends_with(suffix))
+        })
+        .collect()
+}
+
+/// Whether the folder `dir` holds a sync database of any account.
+pub fn has_database(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok_and(|entries| {
+        entries
+            .flatten()
+            .any(|entry| is_database_file(&entry.file_name().to_string_lossy()))
+    })
+}
+
+/// Sync databases in `root` of the server `server` (see
+/// [`crate::config::server_key`]), with their SQLite files.
+fn server_databases(root: &Path, server: &str) -> Result<Vec<String>> {
+    let prefix = format!(".xynoxa-{}", server);
+    Ok(fs::read_dir(root)?
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with(&prefix) && is_database_file(name))
+        .collect())
+}
+
+/// Hands the sync databases of the server `from` over to `to`, for a server
+/// that moved to a new address.
+pub fn rekey_databases(root: &Path, from: &str, to: &str) -> Result<()> {
+    for name in server_databases(root, from)? {
+        let renamed = format!(".xynoxa-{}{}", to, &name[".xynoxa-".len() + from.len()..]);
+        tracing::info!("Renaming sync database {} to {}", name, renamed);
+        fs::rename(root.join(&name), root.join(renamed))?;
+    }
+    Ok(())
+}
+
+/// Deletes the sync databases of the server `from` and those of clients
+/// before databases were kept per account, so a different server starts
+/// from the local files alone.
+pub fn remove_databases(root: &Path, from: &str) -> Result<()> {
+    let legacy = fs::read_dir(root)?
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with(LEGACY_DB_NAME) && is_database_file(name));
+    for name in server_databases(root, from)?.into_iter().chain(legacy) {
+        tracing::info!("Deleting sync database {}", name);
+        fs::remove_file(root.join(name))?;
+    }
+    Ok(())
+}
+
+/// Id of the sync folder at `root`, see [`Database::root_id`]. `None` if the
+/// folder has no sync database.
+pub fn root_id(root: &Path, account: &str, key: &DbKey) -> Result<Option<i64>> {
+    let db_path = db_path(root, account);
+    if !db_path.is_file() {
+        return Ok(None);
+    }
+    Ok(Some(Database::open(&db_path, key)?.root_id()?))
+}
+
+/// Folder levels searched below the closest existing ancestor of a moved root.
+const RELINK_SEARCH_DEPTH: usize = 3;
+
+/// Where the sync folder `id`, last seen at `old_root`, is now, if it was
+/// renamed or moved within its former surroundings (e.g. a renamed parent).
+/// Searches below the closest ancestor of `old_root` that still exists.
+pub fn find_moved_root(old_root: &Path, id: i64, account: &str, key: &DbKey) -> Option<PathBuf> {
+    if old_root.exists() {
+        return None;
+    }
+    let base = old_root.ancestors().skip(1).find(|p| p.is_dir())?;
+    let lost = old_root.components().count() - base.components().count();
+    let mut entries = WalkDir::new(base)
+        .min_depth(1)
+        .max_depth((lost + 1).min(RELINK_SEARCH_DEPTH))
+        .into_iter()
+        .filter_entry(|e| {
+            e.file_type().is_dir() && !e.file_name().to_string_lossy().starts_with('.')
+        });
+    while let Some(entry) = entries.next() {
+        let Ok(entry) = entry else { continue };
+        match root_id(entry.path(), account, key) {
+            Ok(Some(found)) if found == id => return Some(entry.into_path()),
+            // Another sync folder; nested ones are not allowed
+            Ok(Some(_)) => entries.skip_current_dir(),
+            Ok(None) => {}
+            Err(e) => tracing::debug!("Cannot read sync database in {:?}: {}", entry.path(), e),
+        }
+    }
+    None
+}
+
+/// Moves the sync folder `old_root` to `new_root`, which must be missing or
+/// empty. Renamed when both are on the same file system, otherwise copied
+/// with modification times kept, so the next scan sees no changes, and then
+/// deleted. `old_root` stays untouched if anything in it cannot be copied.
+pub fn move_root(old_root: &Path, new_root: &Path) -> Result<()> {
+    if new_root.starts_with(old_root) || old_root.starts_with(new_root) {
+        return Err(XynoxaError::Config(i18n::tr(
+            "The new sync folder cannot be inside the old one or contain it",
+        )));
+    }
+    if new_root.is_dir() {
+        if fs::read_dir(new_root)?.next().is_some() {
+            return Err(XynoxaError::Config(i18n::tr_args(
+                "{0} is not empty",
+                &[&new_root.to_string_lossy()],
+            )));
+        }
+        fs::remove_dir(new_root)?;
+    } else if let Some(parent) = new_root.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if let Err(e) = fs::rename(old_root, new_root) {
+        tracing::info!("Cannot rename {:?} ({}), copying it instead", old_root, e);
+        if let Err(e) = copy_tree(old_root, new_root) {
+            let _ = fs::remove_dir_all(new_root);
+            return Err(e);
+        }
+        fs::remove_dir_all(old_root)?;
+    }
+    tracing::info!("Moved sync folder {:?} to {:?}", old_root, new_root);
+    Ok(())
+}
+
+/// Copies the folder `from` to `to` with modification times. Symlinks are
+/// copied as symlinks. Fails on sockets, FIFOs and devices, which cannot be
+/// copied, so that `from` is kept rather than deleted without them.
+fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    for entry in WalkDir::new(from).follow_links(false) {
+        let entry = entry.map_err(|e| XynoxaError::Other(e.to_string()))?;
+        let dest = to.join(entry.path().strip_prefix(from).unwrap_or(entry.path()));
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            fs::create_dir_all(&dest)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), &dest)?;
+            let modified = fs::metadata(entry.path())?.modified()?;
+            fs::File::options()
+                .write(true)
+                .open(&dest)?
+                .set_modified(modified)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(entry.path(), file_type, &dest)?;
+        } else {
+            return Err(XynoxaError::Config(i18n::tr_args(
+                "{0} is neither a file, a folder nor a link and cannot be moved",
+                &[&entry.path().to_string_lossy()],
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Creates a symlink at `dest` pointing where `link` points.
+fn copy_symlink(link: &Path, file_type: fs::FileType, dest: &Path) -> Result<()> {
+    let target = fs::read_link(link)?;
+    #[cfg(unix)]
+    {
+        let _ = file_type;
+        std::os::unix::fs::symlink(&target, dest)?;
+    }
+    #[cfg(windows)]
+    {
+        // Windows has separate links to folders and to files
+        use std::os::windows::fs::FileTypeExt;
+        if file_type.is_symlink_dir() {
+            std::os::windows::fs::symlink_dir(&target, dest)?;
+        } else {
+            std::os::windows::fs::symlink_file(&target, dest)?;
+        }
+    }
+    Ok(())
+}
+
+fn ensure_sync_root(path: &Path) -> Result<()> {
+    if path.as_os_str().is_empty() {
+        return Err(XynoxaError::Config("Sync root is empty".to_string()));
+    }
+    if !path.is_absolute() {
+        return Err(XynoxaError::Config("Sync root must be absolute".to_string()));
+    }
+    if path.exists() {
+        if path.is_dir() {
+            return Ok(());
+        }
+        return Err(XynoxaError::Config("Sync root is not a directory".to_string()));
+    }
+    fs::create_dir_all(path)?;
+    if !path.is_dir() {
+        return Err(XynoxaError::Config("Failed to create sync root directory".to_string()));
+    }
+    Ok(())
+}
+
+fn normalize_local_path(path: &str) -> String {
+    if std::path::MAIN_SEPARATOR == '\\' {
+        path.replace('\\', "/")
+    } else {
+        path.to_string()
+    }
+}
+
+fn normalize_remote_path(path: &str) -> String {
+    if std::path::MAIN_SEPARATOR == '\\' {
+        path.replace('\\', "/")
+    } else {
+        path.to_string()
+    }
+}
+
+/// Index path of the entity a `sync.pull` event describes.
+pub(crate) fn remote_event_path(data: &FileData, owner_id: Option<&str>) -> String {
+    // The server provides "path" for all entity types (files AND folders)
+    if let Some(p) = &data.path {
+        normalize_remote_path(p)
+    } else if let Some(sp) = &data.storage_path {
+        // Fallback: strip owner prefix if available
+        match owner_id {
+            Some(owner) => {
+                let prefix = format!("{}/", owner);
+                normalize_remote_path(sp.strip_prefix(&prefix).unwrap_or(sp))
+            }
+            None => normalize_remote_path(sp),
+        }
+    } else {
+        // Last resort: use name only (for backward compatibility)
+        normalize_remote_path(&data.name.clone().unwrap_or_default())
+    }
+}
+
+fn local_path_from_relative(root: &Path, relative: &str) -> PathBuf {
+    let mut out = PathBuf::from(root);
+    for part in relative.split('/').filter(|p| !p.is_empty()) {
+        out.push(part);
+    }
+    out
+}
+
+/// Location of index path `relative` below `root`. Fails for paths that
+/// would escape the sync root.
+pub(crate) fn resolve_local_path(root: &Path, relative: &str) -> Result<PathBuf> {
+    let relative = normalize_local_path(relative);
+    if relative.is_empty() || !is_safe_relative_path(&relative) {
+        return Err(XynoxaError::Other(format!("Invalid path: {}", relative)));
+    }
+    Ok(local_path_from_relative(root, &relative))
+}
+
+pub(crate) fn is_safe_relative_path(path: &str) -> bool {
+    if Path::new(path).is_absolute() {
+        return false;
+    }
+    for part in path.split('/') {
+        if part == ".." {
+            return false;
+        }
+    }
+    true
+}
+
+fn is_effectively_empty_root(root: &Path) -> Result<bool> {
+    let entries = fs::read_dir(root)?;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry
+            .file_name()
+            .to_string_lossy()
+            .to_string();
+        if is_database_file(&name) || name == ".git" || name == "node_modules" {
+            continue;
         }
         return Ok(false);
     }
     Ok(true)
 }
 
-fn normalize_db_paths(db: &Database) -> Result<(), String> {
+fn normalize_db_paths(db: &Database) -> Result<()> {
     if std::path::MAIN_SEPARATOR != '\\' {
         return Ok(());
     }
 
-    let records = db.get_all_files().map_err(|e| e.to_string())?;
+    let records = db.get_all_files()?;
     for record in records {
         if !record.path.contains('\\') {
             continue;
@@ -1134,29 +4066,1285 @@ fn normalize_db_paths(db: &Database) -> Result<(), String> {
             continue;
         }
 
-        let existing = db.get_file(&normalized).map_err(|e| e.to_string())?;
+        let existing = db.get_file(&normalized)?;
         if existing.is_none() {
             let mut updated = record.clone();
             updated.path = normalized.clone();
-            db.insert_or_update(&updated).map_err(|e| e.to_string())?;
+            db.insert_or_update(&updated)?;
         }
-        db.delete_file(&record.path).map_err(|e| e.to_string())?;
+        db.delete_file(&record.path)?;
     }
 
     Ok(())
 }
 
-fn compute_hash(path: &Path) -> Result<String, String> {
-    let mut file = fs::File::open(path).map_err(|e| e.to_string())?;
+pub(crate) fn compute_hash(path: &Path) -> Result<String> {
+    let _permit = io_scheduler::hash_permit();
+    let file = fs::File::open(path)?;
     let mut hasher = Sha256::new();
-    std::io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
+    std::io::copy(&mut io_scheduler::throttled(file), &mut hasher)?;
     Ok(hex::encode(hasher.finalize()))
 }
 
-fn is_ignored(entry: &walkdir::DirEntry) -> bool {
-    entry
-        .file_name()
-        .to_str()
-        .map(|s| s == ".git" || s == "node_modules" || s == ".xynoxa.db" || s == ".xynoxa.db")
-        .unwrap_or(false)
+/// Why the queued upload `transfer` of the file at `path` has to wait, if
+/// another program is still writing the file.
+fn write_in_progress(path: &Path, transfer: &Transfer) -> Option<BusyReason> {
+    if is_locked_for_writing(path) {
+        return Some(BusyReason::Locked);
+    }
+    let metadata = path.metadata().ok()?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64);
+    let changed = transfer.size.is_some_and(|size| size != metadata.len())
+        || (transfer.modified.is_some() && modified != transfer.modified);
+    changed.then_some(BusyReason::Changing)
+}
+
+/// Whether another program has `path` open for writing. Only Windows
+/// enforces this; elsewhere growth between two checks has to do.
+#[cfg(windows)]
+fn is_locked_for_writing(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_SHARE_READ: u32 = 1;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    // Denying write sharing fails while someone else holds write access
+    match fs::OpenOptions::new()
+        .read(true)
+        .share_mode(FILE_SHARE_READ)
+        .open(path)
+    {
+        Ok(_) => false,
+        Err(e) => matches!(
+            e.raw_os_error(),
+            Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+        ),
+    }
+}
+
+#[cfg(not(windows))]
+fn is_locked_for_writing(_path: &Path) -> bool {
+    false
+}
+
+/// Creates and removes a probe file. Read-only mounts still report write
+/// permissions, so trying is the only reliable test.
+fn is_writable(root: &Path) -> bool {
+    let probe = root.join(WRITE_PROBE);
+    match fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            true
+        }
+        // A full disk is not a reason to pause
+        Err(e) => !matches!(
+            e.kind(),
+            std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::ReadOnlyFilesystem
+        ),
+    }
+}
+
+/// Entries the scan must not read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum SpecialFile {
+    /// FIFOs, sockets and device nodes, which block or never end when read
+    Device,
+    /// Mostly holes (VM disk images), would upload gigabytes of zeros
+    Sparse,
+}
+
+fn special_file(file_type: fs::FileType, path: &Path) -> Option<SpecialFile> {
+    if file_type.is_dir() || file_type.is_symlink() {
+        return None;
+    }
+    if !file_type.is_file() {
+        return Some(SpecialFile::Device);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = path.metadata().ok()?;
+        // Blocks are 512 bytes, whatever the filesystem's block size
+        let allocated = metadata.blocks() * 512;
+        if metadata.len() >= SPARSE_MIN_BYTES && allocated < metadata.len() / 64 {
+            return Some(SpecialFile::Sparse);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    None
+}
+
+/// Device and inode number of a local file; unchanged by renames.
+#[cfg(unix)]
+fn file_identity(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Filesystem `root` is on; `None` where unknown.
+fn root_device(root: &Path) -> Option<u64> {
+    let metadata = root.metadata().ok()?;
+    file_identity(&metadata).map(|(device, _)| device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_api::MockApiClient;
+
+    fn test_worker(root: &Path, client: &Arc<MockApiClient>) -> SyncWorker {
+        let (_tx, rx) = channel();
+        SyncWorker::with_client(
+            client.clone(),
+            root.to_path_buf(),
+            Database::new_in_memory().unwrap(),
+            rx,
+            None,
+            Arc::default(),
+        )
+    }
+
+    #[test]
+    fn test_pull_creates_folders_and_downloads_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_folder("d1", "Docs");
+        client.add_file("f1", "Docs/a.txt", b"hello");
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+
+        assert_eq!(fs::read(dir.path().join("Docs/a.txt")).unwrap(), b"hello");
+        let record = worker.db.get_file("Docs/a.txt").unwrap().unwrap();
+        assert_eq!(record.id.as_deref(), Some("f1"));
+        assert_eq!(worker.db.get_cursor().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_demo_server_syncs_both_ways() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::demo());
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+        assert!(dir.path().join("Welcome.md").is_file());
+        assert!(dir.path().join("Projects/Website/index.html").is_file());
+
+        fs::write(dir.path().join("Documents/Todo.txt"), b"try the demo").unwrap();
+        worker.scan_and_sync(true).unwrap();
+        let uploads = client.state().uploads.clone();
+        assert_eq!(
+            uploads,
+            vec![(
+                "Documents/Todo.txt".to_string(),
+                Some("demo-documents".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_foreign_sync_folder_is_adopted() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("._sync_4f1a2b.db"), b"").unwrap();
+        fs::write(dir.path().join("a.txt"), b"v2").unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "a.txt", b"v1");
+        client.add_file("f2", "b.txt", b"new");
+        client.state().contents.insert("f1".into(), b"v2".to_vec());
+        client.push_event(
+            "update",
+            "file",
+            "f1",
+            FileData {
+                path: Some("a.txt".to_string()),
+                hash: Some(hex::encode(Sha256::digest(b"v2"))),
+                ..Default::default()
+            },
+        );
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+
+        // The outdated create event neither conflicts nor downloads a.txt again
+        assert_eq!(client.state().downloads, vec!["f2".to_string()]);
+        let mut names: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["._sync_4f1a2b.db", "a.txt", "b.txt"]);
+        let record = worker.db.get_file("a.txt").unwrap().unwrap();
+        assert_eq!(record.id.as_deref(), Some("f1"));
+        assert_eq!(worker.db.get_cursor().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_restored_folder_is_adopted_without_transfers() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("Docs")).unwrap();
+        fs::write(dir.path().join("Docs/a.txt"), b"same").unwrap();
+        fs::write(dir.path().join("Docs/new.txt"), b"local only").unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_folder("d1", "Docs");
+        client.add_file("f1", "Docs/a.txt", b"old");
+        client.state().contents.insert("f1".into(), b"same".to_vec());
+        client.push_event(
+            "update",
+            "file",
+            "f1",
+            FileData {
+                path: Some("Docs/a.txt".to_string()),
+                folder_id: Some("d1".to_string()),
+                hash: Some(hex::encode(Sha256::digest(b"same"))),
+                ..Default::default()
+            },
+        );
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(true).unwrap();
+
+        let state = client.state();
+        assert!(state.downloads.is_empty());
+        assert!(state.created_folders.is_empty());
+        assert_eq!(state.uploads, vec![("Docs/new.txt".to_string(), Some("d1".to_string()))]);
+        drop(state);
+        assert_eq!(fs::read_dir(dir.path().join("Docs")).unwrap().count(), 2);
+        let record = worker.db.get_file("Docs/a.txt").unwrap().unwrap();
+        assert_eq!(record.id.as_deref(), Some("f1"));
+    }
+
+    #[test]
+    fn test_silent_corruption_is_reported_not_uploaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "a.txt", b"original");
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(true).unwrap();
+        // Downloaded an hour ago
+        let path = dir.path().join("a.txt");
+        let modified = std::time::SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        let record = worker.db.get_file("a.txt").unwrap().unwrap();
+        worker
+            .db
+            .insert_or_update(&FileRecord {
+                modified_at: modified.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64,
+                ..record
+            })
+            .unwrap();
+
+        // A flipped byte leaves the modification time alone
+        fs::write(&path, b"origiNal").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        drop(file);
+
+        assert_eq!(worker.verify_integrity().unwrap(), 1);
+        worker.scan_and_sync(true).unwrap();
+        assert!(client.state().uploads.is_empty());
+        let issues = worker.db.sync_issues().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "a.txt");
+        assert_eq!(issues[0].expected_hash, hex::encode(Sha256::digest(b"original")));
+
+        // Restoring the server copy resolves it
+        worker.transfers.enqueue("a.txt", Direction::Download, None, Some("f1"), 0);
+        worker.scan_and_sync(false).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+        assert!(worker.db.sync_issues().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unchanged_local_copy_is_not_downloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), b"same").unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.state().contents.insert("f1".into(), b"same".to_vec());
+        // No hash in the event, as with a lost DB or an older server
+        client.push_event(
+            "create",
+            "file",
+            "f1",
+            FileData {
+                path: Some("a.txt".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+
+        assert!(client.state().downloads.is_empty());
+        let record = worker.db.get_file("a.txt").unwrap().unwrap();
+        assert_eq!(record.id.as_deref(), Some("f1"));
+    }
+
+    #[test]
+    fn test_push_creates_uploads_and_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("Photos")).unwrap();
+        fs::write(dir.path().join("Photos/cat.jpg"), b"meow").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"todo").unwrap();
+        let client = Arc::new(MockApiClient::new());
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(true).unwrap();
+
+        {
+            let state = client.state();
+            assert_eq!(state.created_folders, vec![("Photos".to_string(), None)]);
+            let folder_id = worker.db.get_file("Photos").unwrap().unwrap().id;
+            assert!(state
+                .uploads
+                .contains(&("Photos/cat.jpg".to_string(), folder_id)));
+            assert_eq!(state.uploads.len(), 2);
+        }
+
+        fs::remove_file(dir.path().join("notes.txt")).unwrap();
+        worker.scan_and_sync(true).unwrap();
+
+        let state = client.state();
+        assert_eq!(state.batch_calls.len(), 1);
+        assert_eq!(state.batch_calls[0].0, "files.softDelete");
+        assert!(worker.db.get_file("notes.txt").unwrap().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_local_rename_is_pushed_as_move() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("Archive")).unwrap();
+        fs::write(dir.path().join("notes.txt"), b"todo").unwrap();
+        let client = Arc::new(MockApiClient::new());
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(true).unwrap();
+        let id = worker.db.get_file("notes.txt").unwrap().unwrap().id;
+        let folder_id = worker.db.get_file("Archive").unwrap().unwrap().id;
+
+        // Renamed into another folder and edited slightly
+        fs::rename(
+            dir.path().join("notes.txt"),
+            dir.path().join("Archive/todo.txt"),
+        )
+        .unwrap();
+        fs::write(dir.path().join("Archive/todo.txt"), b"todo!").unwrap();
+        worker.scan_and_sync(true).unwrap();
+
+        let state = client.state();
+        let procedures: Vec<&str> = state.batch_calls.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(procedures, ["files.move", "files.rename"]);
+        assert_eq!(state.batch_calls[0].1["folderId"], folder_id.unwrap());
+        assert_eq!(state.batch_calls[1].1["name"], "todo.txt");
+        assert!(worker.db.get_file("notes.txt").unwrap().is_none());
+        let record = worker.db.get_file("Archive/todo.txt").unwrap().unwrap();
+        assert_eq!(record.id, id);
+        assert_eq!(record.hash, compute_hash(&dir.path().join("Archive/todo.txt")).unwrap());
+    }
+
+    #[test]
+    fn test_recurring_failure_is_one_known_issue() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        let worker = test_worker(dir.path(), &client);
+        let transfer_once = || {
+            let batch = worker.db.begin_batch().unwrap();
+            worker.runtime.block_on(worker.run_transfers(&batch)).unwrap();
+            batch.commit().unwrap();
+        };
+
+        // The server does not have the content yet
+        worker.enqueue_download("a.txt", None, "f1", 1, "h1");
+        transfer_once();
+        worker.transfers.retry("a.txt").unwrap();
+        transfer_once();
+        let issues = worker.db.known_issues().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key, "transfer:a.txt");
+        assert_eq!(issues[0].path.as_deref(), Some("a.txt"));
+        assert_eq!(issues[0].count, 2);
+        assert!(worker.log_throttle.check("transfer:a.txt").is_none());
+
+        client.state().contents.insert("f1".into(), b"a".to_vec());
+        worker.transfers.retry("a.txt").unwrap();
+        transfer_once();
+        assert!(worker.db.known_issues().unwrap().is_empty());
+        // A relapse is logged right away
+        assert_eq!(worker.log_throttle.check("transfer:a.txt"), Some(0));
+    }
+
+    #[test]
+    fn test_ignore_patterns_skip_uploads_but_keep_server_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.tmp"), b"draft").unwrap();
+        fs::write(dir.path().join("build.log"), b"ok").unwrap();
+        let client = Arc::new(MockApiClient::new());
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(true).unwrap();
+        assert_eq!(client.state().uploads.len(), 1);
+
+        // Ignoring a tracked file must not delete it on the server
+        worker.ignore.set(&["*.log".to_string()]);
+        worker.scan_and_sync(true).unwrap();
+        assert!(client.state().batch_calls.is_empty());
+        assert_eq!(client.state().uploads.len(), 2);
+        assert_eq!(client.state().uploads[1].0, "notes.tmp");
+    }
+
+    #[test]
+    fn test_upload_waits_while_file_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("video.mp4"), b"part").unwrap();
+        let client = Arc::new(MockApiClient::new());
+
+        let worker = test_worker(dir.path(), &client);
+        worker.enqueue_upload("video.mp4");
+        // Still growing when its turn comes
+        fs::write(dir.path().join("video.mp4"), b"part two").unwrap();
+        let batch = worker.db.begin_batch().unwrap();
+        worker.runtime.block_on(worker.run_transfers(&batch)).unwrap();
+        drop(batch);
+
+        assert!(client.state().uploads.is_empty());
+        let busy = worker.busy.lock().unwrap().get("video.mp4").cloned().unwrap();
+        assert_eq!(busy.reason, BusyReason::Changing);
+
+        // Complete by the retry
+        worker.scan_and_sync(true).unwrap();
+        assert_eq!(client.state().uploads.len(), 1);
+        assert!(worker.busy.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_editor_save_uploads_only_the_document() {
+        use notify::event::{CreateKind, EventKind, ModifyKind, RenameMode};
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "report.txt", b"v1");
+
+        let mut worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+
+        // Scanned between writing the temp file and renaming it
+        let temp = dir.path().join(".report.txt.x1Y2");
+        fs::write(&temp, b"v2").unwrap();
+        let created = notify::Event::new(EventKind::Create(CreateKind::File));
+        worker.saves.record(&created.add_path(temp.clone()));
+        worker.scan_and_sync(true).unwrap();
+        assert!(client.state().uploads.is_empty());
+
+        fs::rename(&temp, dir.path().join("report.txt")).unwrap();
+        let renamed = notify::Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)));
+        worker
+            .saves
+            .record(&renamed.add_path(temp).add_path(dir.path().join("report.txt")));
+        assert!(!worker.saves.in_progress());
+        worker.scan_and_sync(true).unwrap();
+
+        let uploads = client.state().uploads.clone();
+        assert_eq!(uploads.len(), 1);
+        assert!(uploads[0].0.ends_with("report.txt"));
+        assert_eq!(client.state().contents["f1"], b"v2");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_special_files_are_skipped_with_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let _socket = std::os::unix::net::UnixListener::bind(dir.path().join("agent.sock")).unwrap();
+        let image = fs::File::create(dir.path().join("disk.img")).unwrap();
+        image.set_len(SPARSE_MIN_BYTES * 4).unwrap();
+        fs::write(dir.path().join("notes.txt"), b"notes").unwrap();
+        let client = Arc::new(MockApiClient::new());
+
+        let warnings = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut worker = test_worker(dir.path(), &client);
+        let sink = Arc::clone(&warnings);
+        worker.on_event = Some(Box::new(move |e| {
+            if let SyncEvent::Warning { code, .. } = e {
+                sink.lock().unwrap().push(code)
+            }
+        }));
+        worker.scan_and_sync(true).unwrap();
+        worker.scan_and_sync(true).unwrap();
+
+        let uploads = client.state().uploads.clone();
+        assert_eq!(uploads.len(), 1);
+        assert!(uploads[0].0.ends_with("notes.txt"));
+        // Once per file, not on every scan
+        assert_eq!(*warnings.lock().unwrap(), vec!["special_file"; 2]);
+    }
+
+    #[test]
+    fn test_missing_sync_root_pauses_instead_of_deleting() {
+        let parent = tempfile::tempdir().unwrap();
+        let root = parent.path().join("Xynoxa");
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "a.txt", b"a");
+
+        let warnings = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut worker = test_worker(&root, &client);
+        let sink = Arc::clone(&warnings);
+        worker.on_event = Some(Box::new(move |e| {
+            let name = match e {
+                SyncEvent::Warning { code, .. } => code,
+                SyncEvent::State {
+                    state: SyncState::Unavailable,
+                } => "unavailable",
+                SyncEvent::State { .. } => "state",
+                _ => return,
+            };
+            sink.lock().unwrap().push(name);
+        }));
+        worker.scan_and_sync(true).unwrap();
+        assert!(root.join("a.txt").exists());
+
+        // Drive unmounted
+        fs::remove_dir_all(&root).unwrap();
+        warnings.lock().unwrap().clear();
+        for _ in 0..2 {
+            let err = worker.scan_and_sync(true).unwrap_err();
+            assert!(matches!(err, XynoxaError::Unavailable(_)));
+        }
+        assert!(!root.exists());
+        assert!(client.state().batch_calls.is_empty());
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            vec!["state", "root_unavailable", "unavailable", "state", "unavailable"]
+        );
+
+        // Mounted again
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("a.txt"), b"a").unwrap();
+        worker.scan_and_sync(true).unwrap();
+        assert!(worker.root_recovered.load(Ordering::Relaxed));
+        assert!(client.state().batch_calls.is_empty());
+    }
+
+    #[test]
+    fn test_empty_mount_point_pauses_instead_of_deleting() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "a.txt", b"a");
+        client.add_file("f2", "b.txt", b"b");
+
+        let mut worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(true).unwrap();
+        fs::remove_file(dir.path().join("a.txt")).unwrap();
+
+        // The drive's database is gone with it
+        worker.db_path = Some(dir.path().join(".xynoxa-test.db"));
+        let err = worker.scan_and_sync(true).unwrap_err();
+        assert!(matches!(err, XynoxaError::Unavailable(_)));
+
+        // The database is there, but the folder is on another filesystem
+        fs::write(dir.path().join(".xynoxa-test.db"), b"").unwrap();
+        worker.root_device = root_device(dir.path()).map(|device| device + 1);
+        if worker.root_device.is_some() {
+            let err = worker.scan_and_sync(true).unwrap_err();
+            assert!(matches!(err, XynoxaError::Unavailable(_)));
+        }
+        assert!(client.state().batch_calls.is_empty());
+
+        worker.root_device = root_device(dir.path());
+        worker.scan_and_sync(true).unwrap();
+        assert_eq!(client.state().batch_calls.len(), 1);
+    }
+
+    #[test]
+    fn test_local_edit_survives_unchanged_remote_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "a.txt", b"v1");
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+
+        // Server re-announces the merge base (e.g. a metadata-only update)
+        fs::write(dir.path().join("a.txt"), b"v2").unwrap();
+        client.add_file("f1", "a.txt", b"v1");
+        worker.scan_and_sync(true).unwrap();
+
+        assert_eq!(fs::read(dir.path().join("a.txt")).unwrap(), b"v2");
+        assert!(!dir.path().join("a.conflict_backup").exists());
+        assert_eq!(client.state().contents["f1"], b"v2");
+    }
+
+    #[test]
+    fn test_edit_on_both_sides_keeps_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "a.txt", b"v1");
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+
+        fs::write(dir.path().join("a.txt"), b"local").unwrap();
+        client.add_file("f1", "a.txt", b"remote");
+        worker.scan_and_sync(false).unwrap();
+
+        assert_eq!(fs::read(dir.path().join("a.txt")).unwrap(), b"remote");
+        assert_eq!(
+            fs::read(dir.path().join("a.conflict_backup")).unwrap(),
+            b"local"
+        );
+        let record = worker.db.get_file("a.txt").unwrap().unwrap();
+        assert_eq!(record.remote_hash.as_deref(), Some(record.hash.as_str()));
+        assert_eq!(record.server_version, 2);
+    }
+
+    #[test]
+    fn test_remote_office_lock_is_reported_not_downloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("Report.docx"), b"draft").unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("l1", "~$port.docx", b"lock");
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut worker = test_worker(dir.path(), &client);
+        worker.lock_notifications = true;
+        let sink = Arc::clone(&events);
+        worker.on_event = Some(Box::new(move |e| {
+            if let SyncEvent::DocumentLock { .. } = e {
+                sink.lock().unwrap().push(e)
+            }
+        }));
+        worker.scan_and_sync(false).unwrap();
+        client.push_event("delete", "file", "l1", FileData::default());
+        worker.scan_and_sync(false).unwrap();
+
+        assert!(client.state().downloads.is_empty());
+        assert!(!dir.path().join("~$port.docx").exists());
+        let lock = |open| SyncEvent::DocumentLock {
+            path: "Report.docx".into(),
+            open,
+        };
+        assert_eq!(*events.lock().unwrap(), vec![lock(true), lock(false)]);
+    }
+
+    #[test]
+    fn test_cycle_reports_state_and_transfers() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "a.txt", b"hello");
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut worker = test_worker(dir.path(), &client);
+        let sink = Arc::clone(&events);
+        worker.on_event = Some(Box::new(move |e| sink.lock().unwrap().push(e)));
+        worker.scan_and_sync(false).unwrap();
+
+        let state = |state| SyncEvent::State { state };
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                state(SyncState::Syncing),
+                SyncEvent::FileStarted {
+                    path: "a.txt".into(),
+                    direction: Direction::Download,
+                },
+                SyncEvent::FileFinished {
+                    path: "a.txt".into(),
+                    direction: Direction::Download,
+                    error: None,
+                },
+                SyncEvent::Progress { done: 1, total: 1 },
+                state(SyncState::Idle),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cancelled_download_waits_for_retry() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "big.iso", b"data");
+
+        let worker = test_worker(dir.path(), &client);
+        worker
+            .transfers
+            .enqueue("big.iso", Direction::Download, None, Some("f1"), 0);
+        worker.transfers.cancel("big.iso").unwrap();
+        worker.scan_and_sync(false).unwrap();
+        assert!(!dir.path().join("big.iso").exists());
+
+        worker.transfers.retry("big.iso").unwrap();
+        worker.scan_and_sync(false).unwrap();
+        assert_eq!(fs::read(dir.path().join("big.iso")).unwrap(), b"data");
+        assert!(worker.transfers.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_move_root_keeps_files_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_root = dir.path().join("old");
+        fs::create_dir_all(old_root.join("docs")).unwrap();
+        fs::write(old_root.join("docs/a.txt"), b"a").unwrap();
+        let modified = std::time::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::options()
+            .write(true)
+            .open(old_root.join("docs/a.txt"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        // The fallback for moves across file systems
+        let copy = dir.path().join("copy");
+        copy_tree(&old_root, &copy).unwrap();
+        let copied = fs::metadata(copy.join("docs/a.txt")).unwrap();
+        assert_eq!(copied.modified().unwrap(), modified);
+
+        assert!(move_root(&old_root, &old_root.join("docs")).is_err());
+        assert!(move_root(&old_root, &copy).is_err());
+        let new_root = dir.path().join("new").join("Drive");
+        move_root(&old_root, &new_root).unwrap();
+        assert!(!old_root.exists());
+        assert_eq!(fs::read(new_root.join("docs/a.txt")).unwrap(), b"a");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_tree_keeps_links_and_refuses_special_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_root = dir.path().join("old");
+        fs::create_dir_all(&old_root).unwrap();
+        fs::write(old_root.join("a.txt"), b"a").unwrap();
+        std::os::unix::fs::symlink("a.txt", old_root.join("link")).unwrap();
+
+        let copy = dir.path().join("copy");
+        copy_tree(&old_root, &copy).unwrap();
+        assert_eq!(
+            fs::read_link(copy.join("link")).unwrap(),
+            PathBuf::from("a.txt")
+        );
+
+        let _socket = std::os::unix::net::UnixListener::bind(old_root.join("agent.sock")).unwrap();
+        assert!(copy_tree(&old_root, &dir.path().join("second")).is_err());
+        assert!(old_root.join("agent.sock").exists());
+    }
+
+    #[test]
+    fn test_moved_root_is_found_by_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = DbKey::default();
+        let old_root = dir.path().join("Docs").join("Xynoxa Drive");
+        fs::create_dir_all(&old_root).unwrap();
+        let id = Database::open(&resolve_db_path(&old_root, "a1"), &key)
+            .unwrap()
+            .root_id()
+            .unwrap();
+        assert_eq!(root_id(&old_root, "a1", &key).unwrap(), Some(id));
+        assert_eq!(root_id(&old_root, "b2", &key).unwrap(), None);
+        // Still there: nothing to relink
+        assert_eq!(find_moved_root(&old_root, id, "a1", &key), None);
+
+        fs::rename(dir.path().join("Docs"), dir.path().join("Documents")).unwrap();
+        let new_root = dir.path().join("Documents").join("Xynoxa Drive");
+        assert_eq!(find_moved_root(&old_root, id, "a1", &key), Some(new_root));
+        assert_eq!(find_moved_root(&old_root, id + 1, "a1", &key), None);
+        assert_eq!(root_id(dir.path(), "a1", &key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_database_per_account() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(".xynoxa.db"), b"old").unwrap();
+        fs::write(root.join(".xynoxa.db-wal"), b"wal").unwrap();
+
+        // The first account on the server adopts the old database
+        let first = resolve_db_path(root, "s1");
+        assert_eq!(first, root.join(".xynoxa-s1.db"));
+        assert_eq!(fs::read(&first).unwrap(), b"old");
+        assert_eq!(fs::read(root.join(".xynoxa-s1.db-wal")).unwrap(), b"wal");
+        assert!(!root.join(".xynoxa.db").exists());
+        // Then the account whose user id became known
+        let user = resolve_db_path(root, "s1-u1");
+        assert_eq!(fs::read(&user).unwrap(), b"old");
+        // Other servers and users start from scratch
+        assert!(!resolve_db_path(root, "s1-u2").exists());
+        assert!(!resolve_db_path(root, "s2").exists());
+
+        assert!(has_database(root));
+        assert!(is_database_file(".xynoxa-s1-u1.db-shm"));
+        assert!(is_database_file(".xynoxa.db"));
+        assert!(!is_database_file(".xynoxa-notes.txt"));
+    }
+
+    #[test]
+    fn test_server_change_keeps_or_drops_databases() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for name in [
+            ".xynoxa-aa-u1.db",
+            ".xynoxa-aa-u1.db-wal",
+            ".xynoxa-bb-u1.db",
+        ] {
+            fs::write(root.join(name), name).unwrap();
+        }
+        // Same server, new address
+        rekey_databases(root, "aa", "cc").unwrap();
+        assert_eq!(
+            fs::read(root.join(".xynoxa-cc-u1.db")).unwrap(),
+            b".xynoxa-aa-u1.db"
+        );
+        assert!(root.join(".xynoxa-cc-u1.db-wal").exists());
+        assert!(!root.join(".xynoxa-aa-u1.db").exists());
+
+        // A different server
+        fs::write(root.join(".xynoxa.db"), b"").unwrap();
+        remove_databases(root, "cc").unwrap();
+        let mut left: Vec<_> = fs::read_dir(root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        left.sort();
+        assert_eq!(left, [".xynoxa-bb-u1.db"]);
+    }
+
+    #[test]
+    fn test_rebuild_index_adopts_local_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_folder("d1", "Docs");
+        client.add_file("f1", "Docs/a.txt", b"alpha");
+        client.add_file("f2", "b.txt", b"beta");
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+        let cursor = worker.db.get_cursor().unwrap();
+        // Edited while the database was broken
+        fs::write(dir.path().join("b.txt"), b"local edit").unwrap();
+        client.state().downloads.clear();
+
+        // The conflict backup of b.txt is tracked as well
+        assert_eq!(worker.rebuild_index().unwrap(), 4);
+        // Matching files are adopted, only the differing one is downloaded
+        assert_eq!(client.state().downloads, vec!["f2".to_string()]);
+        assert!(worker.db.get_cursor().unwrap() >= cursor);
+        let record = worker.db.get_file("Docs/a.txt").unwrap().unwrap();
+        assert_eq!(record.id.as_deref(), Some("f1"));
+        // The edit survives as a conflict backup
+        let backup = fs::read(dir.path().join("b.conflict_backup")).unwrap();
+        assert_eq!(backup, b"local edit");
+    }
+
+    #[test]
+    fn test_pinned_files_are_downloaded_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "a.txt", b"a");
+        client.add_folder("d1", "Photos");
+        client.add_file("f2", "Photos/b.jpg", b"b");
+        client.add_file("f3", "z.txt", b"z");
+
+        let worker = test_worker(dir.path(), &client);
+        worker.db.pin("Photos").unwrap();
+        worker.scan_and_sync(false).unwrap();
+        assert_eq!(client.state().downloads[0], "f2");
+        assert!(dir.path().join("Photos/b.jpg").is_file());
+
+        let results = search_index(&worker.db, &worker.transfers, "b.jpg").unwrap();
+        assert!(results[0].pinned);
+        assert!(!worker.db.is_pinned("Photos2/c.jpg").unwrap());
+        // Pins follow folder renames
+        worker.db.move_path_prefix("Photos", "Pictures").unwrap();
+        assert!(worker.db.is_pinned("Pictures/b.jpg").unwrap());
+        let pinned = worker.db.pinned_files().unwrap();
+        assert_eq!(pinned.len(), 1);
+        assert_eq!(pinned[0].path, "Pictures/b.jpg");
+        assert!(worker.db.unpin("Pictures").unwrap());
+        assert!(!worker.db.unpin("Pictures").unwrap());
+    }
+
+    #[test]
+    fn test_remote_hits_get_local_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "Reports/q1.pdf", b"a");
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+
+        let hit = |id: &str, name: &str| RemoteSearchHit {
+            id: id.to_string(),
+            name: name.to_string(),
+            path: None,
+            folder_id: None,
+            size: None,
+            snippet: None,
+        };
+        let results = annotate_remote_hits(
+            &worker.db,
+            &worker.transfers,
+            vec![hit("f1", "q1.pdf"), hit("f9", "q2.pdf")],
+        )
+        .unwrap();
+        assert_eq!(results[0].local_path.as_deref(), Some("Reports/q1.pdf"));
+        assert_eq!(results[0].status, Some(FileStatus::Synced));
+        // Only on the server
+        assert_eq!(results[1].hit.name, "q2.pdf");
+        assert!(results[1].local_path.is_none() && results[1].status.is_none());
+    }
+
+    #[test]
+    fn test_tags_and_favorites_follow_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "a.txt", b"a");
+        client.push_event(
+            "update",
+            "file",
+            "f1",
+            FileData {
+                tags: Some(vec!["work".to_string(), "2024".to_string()]),
+                favorite: Some(true),
+                ..Default::default()
+            },
+        );
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+        let expected = FileLabels {
+            tags: vec!["2024".to_string(), "work".to_string()],
+            favorite: true,
+        };
+        assert_eq!(worker.db.labels().unwrap().get("f1"), Some(&expected));
+
+        // Events that leave a label out do not clear it
+        client.push_event(
+            "update",
+            "file",
+            "f1",
+            FileData {
+                favorite: Some(false),
+                ..Default::default()
+            },
+        );
+        worker.scan_and_sync(false).unwrap();
+        let labels = worker.db.labels().unwrap();
+        assert_eq!(labels["f1"].tags, expected.tags);
+        assert!(!labels["f1"].favorite);
+
+        client.push_event("delete", "file", "f1", FileData::default());
+        worker.scan_and_sync(false).unwrap();
+        assert!(worker.db.labels().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_matches_all_terms_with_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_folder("d1", "Reports");
+        client.add_file("f1", "Reports/annual_2024.pdf", b"a");
+        client.add_file("f2", "Reports/annual_2023.pdf", b"b");
+        client.add_file("f3", "notes.txt", b"c");
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+        worker
+            .transfers
+            .enqueue("Reports/annual_2023.pdf", Direction::Upload, None, None, 0);
+
+        let results = search_index(&worker.db, &worker.transfers, "report ANNUAL").unwrap();
+        let found: Vec<(&str, FileStatus)> = results
+            .iter()
+            .map(|r| (r.record.path.as_str(), r.status))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Reports/annual_2023.pdf", FileStatus::Queued),
+                ("Reports/annual_2024.pdf", FileStatus::Synced),
+            ]
+        );
+        // LIKE wildcards are matched literally
+        assert!(search_index(&worker.db, &worker.transfers, "%")
+            .unwrap()
+            .is_empty());
+        assert!(search_index(&worker.db, &worker.transfers, "  ")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_interrupted_folder_move_is_recovered() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_folder("d1", "Old");
+        client.add_file("f1", "Old/a.txt", b"a");
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+
+        // Crash after the local rename, before the DB update
+        worker
+            .db
+            .begin_op(PendingOpKind::MoveFolder, "Old", Some("New"))
+            .unwrap();
+        fs::rename(dir.path().join("Old"), dir.path().join("New")).unwrap();
+        worker.scan_and_sync(true).unwrap();
+
+        assert!(client.state().batch_calls.is_empty());
+        assert_eq!(client.state().uploads.len(), 0);
+        let record = worker.db.get_file_by_id("f1").unwrap().unwrap();
+        assert_eq!(record.path, "New/a.txt");
+        assert!(worker.db.pending_ops().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_maintenance_prunes_ignored_and_unsafe_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "a.txt", b"a");
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+        for path in ["../outside.txt", "node_modules/x.js"] {
+            let mut record = worker.db.get_file("a.txt").unwrap().unwrap();
+            record.path = path.to_string();
+            record.id = None;
+            worker.db.insert_or_update(&record).unwrap();
+        }
+
+        let report = worker.maintain_db().unwrap();
+
+        assert_eq!(report.removed_files, 2);
+        let paths: Vec<String> = worker
+            .db
+            .get_all_files()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.path)
+            .collect();
+        assert_eq!(paths, vec!["a.txt".to_string()]);
+        assert!(worker.db.last_maintenance().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_incompatible_server_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.state().api_version = crate::api::MAX_API_VERSION + 1;
+        client.add_file("f1", "a.txt", b"a");
+
+        let worker = test_worker(dir.path(), &client);
+        let err = worker.scan_and_sync(false).unwrap_err();
+
+        assert_eq!(err.code(), "incompatible");
+        assert!(!dir.path().join("a.txt").exists());
+    }
+
+    #[test]
+    fn test_remote_folder_move_reroots_children() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_folder("d1", "Old");
+        client.add_file("f1", "Old/a.txt", b"a");
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+
+        client.push_event(
+            "move",
+            "folder",
+            "d1",
+            FileData {
+                path: Some("New".to_string()),
+                ..Default::default()
+            },
+        );
+        worker.scan_and_sync(false).unwrap();
+
+        assert!(!dir.path().join("Old").exists());
+        assert_eq!(fs::read(dir.path().join("New/a.txt")).unwrap(), b"a");
+        let record = worker.db.get_file_by_id("f1").unwrap().unwrap();
+        assert_eq!(record.path, "New/a.txt");
+    }
+
+    #[test]
+    fn test_remote_folder_move_onto_existing_folder_keeps_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_folder("d1", "Old");
+        client.add_file("f1", "Old/a.txt", b"a");
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+        fs::create_dir(dir.path().join("New")).unwrap();
+
+        client.push_event(
+            "move",
+            "folder",
+            "d1",
+            FileData {
+                path: Some("New".to_string()),
+                ..Default::default()
+            },
+        );
+        worker.scan_and_sync(true).unwrap();
+
+        assert_eq!(fs::read(dir.path().join("Old/a.txt")).unwrap(), b"a");
+        let record = worker.db.get_file_by_id("f1").unwrap().unwrap();
+        assert_eq!(record.path, "Old/a.txt");
+        let state = client.state();
+        assert!(state
+            .batch_calls
+            .iter()
+            .all(|(procedure, _)| procedure != "files.softDelete"));
+        assert!(state.uploads.is_empty());
+    }
+
+    #[test]
+    fn test_existing_remote_folder_is_adopted_by_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_folder("d1", "Docs");
+
+        let worker = test_worker(dir.path(), &client);
+        worker
+            .runtime
+            .block_on(worker.create_remote_folders(vec!["Docs".to_string()]));
+
+        let record = worker.db.get_file("Docs").unwrap().unwrap();
+        assert_eq!(record.id.as_deref(), Some("d1"));
+        let state = client.state();
+        assert_eq!(state.folder_lookups, 1);
+        assert!(state.created_folders.is_empty());
+    }
+
+    #[test]
+    fn test_interrupted_download_resumes_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.state().contents.insert("f1".into(), b"content".to_vec());
+
+        let worker = test_worker(dir.path(), &client);
+        let point = ResumePoint {
+            token: "\"etag\"".into(),
+            offset: 3,
+        };
+        worker
+            .db
+            .set_resume("a.bin", Direction::Download, &point, "f1")
+            .unwrap();
+        // Progress of another file's content is not reused
+        assert_eq!(
+            worker.db.get_resume("a.bin", Direction::Download, "f2").unwrap(),
+            None
+        );
+        worker
+            .runtime
+            .block_on(worker.download_file("f1", "a.bin"))
+            .unwrap();
+
+        assert_eq!(client.state().resumed_downloads, [("f1".to_string(), 3)]);
+        assert_eq!(fs::read(dir.path().join("a.bin")).unwrap(), b"content");
+        // Only the bytes received after resuming count
+        let usage = BandwidthUsage::load(&worker.db, None).unwrap();
+        assert_eq!(usage.month_downloaded, 4);
+        assert_eq!(
+            worker.db.get_resume("a.bin", Direction::Download, "f1").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_remote_tree_is_mirrored_from_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(MockApiClient::new());
+        client.add_folder("d1", "Docs");
+        client.add_file("f1", "Docs/a.txt", b"a");
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(false).unwrap();
+        let file = worker.db.get_remote_entry("f1").unwrap().unwrap();
+        assert_eq!(file.parent_id.as_deref(), Some("d1"));
+        assert_eq!(
+            worker.db.find_remote_child(Some("d1"), "a.txt").unwrap(),
+            Some(file)
+        );
+
+        let moved = FileData {
+            path: Some("Archive".to_string()),
+            ..Default::default()
+        };
+        client.push_event("move", "folder", "d1", moved);
+        worker.scan_and_sync(false).unwrap();
+        let file = worker.db.get_remote_entry("f1").unwrap().unwrap();
+        assert_eq!(file.path, "Archive/a.txt");
+
+        client.push_event("delete", "folder", "d1", FileData::default());
+        worker.scan_and_sync(false).unwrap();
+        assert!(worker.db.get_remote_entry("f1").unwrap().is_none());
+        assert!(worker.db.find_remote_by_path("Archive").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_new_folders_are_created_parents_first() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("A/B/C")).unwrap();
+        fs::create_dir_all(dir.path().join("A/D")).unwrap();
+        let client = Arc::new(MockApiClient::new());
+
+        let worker = test_worker(dir.path(), &client);
+        worker.scan_and_sync(true).unwrap();
+
+        let id = |path: &str| worker.db.get_file(path).unwrap().unwrap().id;
+        let state = client.state();
+        assert_eq!(state.created_folders.len(), 4);
+        assert_eq!(state.created_folders[0], ("A".to_string(), None));
+        assert_eq!(state.created_folders[3], ("C".to_string(), id("A/B")));
+        assert!(state.created_folders.contains(&("D".to_string(), id("A"))));
+    }
+
+    #[test]
+    fn test_idle_polling_backs_off_until_capped() {
+        let mut interval = PERIODIC_SYNC_INTERVAL;
+        interval = next_poll_interval(interval, false);
+        assert_eq!(interval, Duration::from_secs(40));
+        for _ in 0..10 {
+            interval = next_poll_interval(interval, false);
+        }
+        assert_eq!(interval, MAX_POLL_INTERVAL);
+        assert_eq!(next_poll_interval(interval, true), PERIODIC_SYNC_INTERVAL);
+    }
+
+    #[test]
+    fn test_reclaim_takes_least_recently_used_first() {
+        let file = |path: &str, size: u64, last_used: i64| {
+            let record = FileRecord {
+                path: path.to_string(),
+                id: Some(path.to_string()),
+                hash: "h".to_string(),
+                modified_at: 0,
+                server_version: 0,
+                remote_hash: Some("h".to_string()),
+                group_folder_id: None,
+                is_group_root: false,
+            };
+            (record, size, last_used)
+        };
+        let files = vec![
+            file("recent.iso", 900, 2_000),
+            file("old.mkv", 300, 100),
+            file("older.zip", 200, 50),
+            file("middle.pdf", 500, 500),
+        ];
+        let paths = |chosen: Vec<(FileRecord, u64)>| -> Vec<String> {
+            chosen.into_iter().map(|(record, _)| record.path).collect()
+        };
+        // Recently used files stay even if that frees too little
+        assert_eq!(
+            paths(reclaim_candidates(files.clone(), 1_000, 10_000)),
+            ["older.zip", "old.mkv", "middle.pdf"]
+        );
+        assert_eq!(paths(reclaim_candidates(files.clone(), 1_000, 400)), ["older.zip", "old.mkv"]);
+        assert!(reclaim_candidates(files, 1_000, 0).is_empty());
+    }
 }
@@ -0,0 +1,290 @@
+//! Opt-in, anonymous reliability metrics.
+//!
+//! Counters contain no paths, file names, tokens or server URLs. Nothing is
+//! counted unless `AppConfig::telemetry_enabled` is set ([`start`] and
+//! [`set_enabled`]). While it is, the counters are kept in a file between runs
+//! (see [`save`]) and sent without credentials once per [`REPORT_INTERVAL`];
+//! `get_telemetry_preview` shows the exact payload that would be sent.
+
+use crate::error::XynoxaError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How often the aggregated report is sent when telemetry is enabled.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How often the counters are saved and the report checked for being due.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Metrics {
+    sync_cycles: u64,
+    failed_cycles: u64,
+    total_sync_ms: u64,
+    max_sync_ms: u64,
+    retries: u64,
+    /// Error count per [`XynoxaError::code`]
+    errors: BTreeMap<String, u64>,
+    /// Unix time the current counting period started
+    since: Option<i64>,
+}
+
+/// The counters and whether the user opted in.
+#[derive(Default)]
+struct Telemetry {
+    enabled: bool,
+    metrics: Metrics,
+}
+
+static TELEMETRY: Mutex<Telemetry> = Mutex::new(Telemetry {
+    enabled: false,
+    metrics: Metrics {
+        sync_cycles: 0,
+        failed_cycles: 0,
+        total_sync_ms: 0,
+        max_sync_ms: 0,
+        retries: 0,
+        errors: BTreeMap::new(),
+        since: None,
+    },
+});
+
+/// The exact payload sent to the server.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryReport {
+    pub client_version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub sync_cycles: u64,
+    pub failed_cycles: u64,
+    pub avg_sync_ms: u64,
+    pub max_sync_ms: u64,
+    pub retries: u64,
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl Telemetry {
+    fn record_sync(&mut self, duration: Duration, error: Option<&XynoxaError>) {
+        if !self.enabled {
+            return;
+        }
+        let m = &mut self.metrics;
+        let ms = duration.as_millis() as u64;
+        m.sync_cycles += 1;
+        m.total_sync_ms += ms;
+        m.max_sync_ms = m.max_sync_ms.max(ms);
+        if let Some(e) = error {
+            m.failed_cycles += 1;
+            *m.errors.entry(e.code().to_string()).or_insert(0) += 1;
+        }
+    }
+
+    fn record_error(&mut self, error: &XynoxaError) {
+        if self.enabled {
+            *self
+                .metrics
+                .errors
+                .entry(error.code().to_string())
+                .or_insert(0) += 1;
+        }
+    }
+
+    fn record_retry(&mut self) {
+        if self.enabled {
+            self.metrics.retries += 1;
+        }
+    }
+
+    fn report(&self) -> TelemetryReport {
+        let m = &self.metrics;
+        TelemetryReport {
+            client_version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            sync_cycles: m.sync_cycles,
+            failed_cycles: m.failed_cycles,
+            avg_sync_ms: m.total_sync_ms.checked_div(m.sync_cycles).unwrap_or(0),
+            max_sync_ms: m.max_sync_ms,
+            retries: m.retries,
+            errors: m.errors.clone(),
+        }
+    }
+
+    fn is_due(&self, now: i64) -> bool {
+        let since = self.metrics.since.unwrap_or(now);
+        self.enabled && now - since >= REPORT_INTERVAL.as_secs() as i64
+    }
+
+    fn reset(&mut self, now: i64) {
+        self.metrics = Metrics {
+            since: Some(now),
+            ..Metrics::default()
+        };
+    }
+
+    fn start(&mut self, path: &Path, enabled: bool, now: i64) {
+        self.enabled = enabled;
+        if !enabled {
+            self.discard(path);
+            return;
+        }
+        let saved: Metrics = std::fs::read(path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+        let m = &mut self.metrics;
+        m.sync_cycles += saved.sync_cycles;
+        m.failed_cycles += saved.failed_cycles;
+        m.total_sync_ms += saved.total_sync_ms;
+        m.max_sync_ms = m.max_sync_ms.max(saved.max_sync_ms);
+        m.retries += saved.retries;
+        for (code, count) in saved.errors {
+            *m.errors.entry(code).or_insert(0) += count;
+        }
+        m.since = saved.since.or(m.since).or(Some(now));
+    }
+
+    fn set_enabled(&mut self, enabled: bool, path: &Path, now: i64) {
+        if self.enabled == enabled {
+            return;
+        }
+        self.enabled = enabled;
+        if enabled {
+            self.reset(now);
+        } else {
+            self.discard(path);
+        }
+    }
+
+    /// Forgets the counters, in memory and at `path`.
+    fn discard(&mut self, path: &Path) {
+        self.metrics = Metrics::default();
+        if let Err(e) = std::fs::remove_file(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Failed to delete telemetry counters: {}", e);
+            }
+        }
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        std::fs::write(path, serde_json::to_vec(&self.metrics)?)
+    }
+}
+
+fn telemetry() -> std::sync::MutexGuard<'static, Telemetry> {
+    TELEMETRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Records one finished sync cycle.
+pub fn record_sync(duration: Duration, error: Option<&XynoxaError>) {
+    telemetry().record_sync(duration, error);
+}
+
+/// Records an error that did not fail the whole cycle (single upload, download, ...).
+pub fn record_error(error: &XynoxaError) {
+    telemetry().record_error(error);
+}
+
+/// Records a failed transfer that is attempted again.
+pub fn record_retry() {
+    telemetry().record_retry();
+}
+
+/// Snapshot of the current counters.
+pub fn report() -> TelemetryReport {
+    telemetry().report()
+}
+
+/// Whether a report is due at `now` (Unix time): once per [`REPORT_INTERVAL`]
+/// since counting started, across restarts.
+pub fn is_due(now: i64) -> bool {
+    telemetry().is_due(now)
+}
+
+/// Clears the counters after a report was delivered at `now`.
+pub fn reset(now: i64) {
+    telemetry().reset(now);
+}
+
+/// Called once at startup: continues the counters saved by an earlier run at
+/// `path` if the user opted in, and deletes them otherwise. Counting starts
+/// at `now` if nothing was saved yet.
+pub fn start(path: &Path, enabled: bool, now: i64) {
+    telemetry().start(path, enabled, now);
+}
+
+/// Follows a change of the setting: counting starts from zero at `now` when
+/// it turns on, and the counters and `path` are deleted when it turns off.
+pub fn set_enabled(enabled: bool, path: &Path, now: i64) {
+    telemetry().set_enabled(enabled, path, now);
+}
+
+/// Writes the current counters to `path` for the next run, while enabled.
+pub fn save(path: &Path) -> std::io::Result<()> {
+    telemetry().save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stale_file(path: &Path) {
+        let saved = Metrics {
+            sync_cycles: 5,
+            since: Some(0),
+            ..Metrics::default()
+        };
+        std::fs::write(path, serde_json::to_vec(&saved).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_nothing_is_counted_without_consent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("telemetry.json");
+        stale_file(&path);
+
+        let mut telemetry = Telemetry::default();
+        telemetry.start(&path, false, 1000);
+        assert!(!path.exists());
+        telemetry.record_sync(Duration::from_millis(10), None);
+        telemetry.record_error(&XynoxaError::Network("down".into()));
+        telemetry.record_retry();
+        telemetry.save(&path).unwrap();
+        let report = telemetry.report();
+        assert_eq!((report.sync_cycles, report.retries), (0, 0));
+        assert!(report.errors.is_empty());
+        assert!(!path.exists());
+        assert!(!telemetry.is_due(1000 + REPORT_INTERVAL.as_secs() as i64));
+    }
+
+    #[test]
+    fn test_opting_in_starts_a_fresh_period() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("telemetry.json");
+        stale_file(&path);
+
+        let mut telemetry = Telemetry::default();
+        telemetry.set_enabled(true, &path, 1000);
+        // The old file's period does not make a report due right away
+        assert!(!telemetry.is_due(1000));
+        assert_eq!(telemetry.report().sync_cycles, 0);
+        telemetry.record_sync(Duration::from_millis(10), None);
+        telemetry.save(&path).unwrap();
+
+        let mut restarted = Telemetry::default();
+        restarted.start(&path, true, 2000);
+        assert_eq!(restarted.report().sync_cycles, 1);
+        assert!(restarted.is_due(1000 + REPORT_INTERVAL.as_secs() as i64));
+
+        restarted.set_enabled(false, &path, 3000);
+        assert!(!path.exists());
+        assert_eq!(restarted.report().sync_cycles, 0);
+    }
+}
@@ -0,0 +1,156 @@
+//! On-disk cache of file previews shown in the UI's file listings.
+//!
+//! Entries are keyed by file id and content hash, so a changed file gets a new
+//! preview. The cache is pruned least-recently-used first once it grows past
+//! its size limit.
+
+use crate::error::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Edge length requested from the server.
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+/// Default size limit of the cache directory.
+pub const MAX_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Local images up to this size are shown as-is when the server has no preview.
+const MAX_LOCAL_IMAGE_BYTES: u64 = 1024 * 1024;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp", "svg"];
+
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl ThumbnailCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self { dir, max_bytes }
+    }
+
+    /// Cached preview of `file_id` with content `hash`.
+    pub fn get(&self, file_id: &str, hash: &str) -> Option<Vec<u8>> {
+        let path = self.entry_path(file_id, hash);
+        let bytes = fs::read(&path).ok()?;
+        // Reads count as use for pruning
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(bytes)
+    }
+
+    /// Stores a preview, replacing those of older versions of the file.
+    pub fn put(&self, file_id: &str, hash: &str, bytes: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let prefix = format!("{}.", sanitize(file_id));
+        for entry in fs::read_dir(&self.dir)?.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        fs::write(self.entry_path(file_id, hash), bytes)?;
+        self.prune()
+    }
+
+    /// Deletes least recently used entries until the cache fits its limit.
+    fn prune(&self) -> Result<()> {
+        let mut entries: Vec<(SystemTime, u64, PathBuf)> = fs::read_dir(&self.dir)?
+            .flatten()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((used, metadata.len(), entry.path()))
+            })
+            .collect();
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(&path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, file_id: &str, hash: &str) -> PathBuf {
+        let hash: String = sanitize(hash).chars().take(16).collect();
+        self.dir
+            .join(format!("{}.{}.thumb", sanitize(file_id), hash))
+    }
+}
+
+/// Keeps ids and hashes from escaping the cache directory.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect()
+}
+
+/// The local file itself, if it is a small image the webview can display.
+pub fn local_preview(path: &Path) -> Option<Vec<u8>> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    if !IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    if path.metadata().ok()?.len() > MAX_LOCAL_IMAGE_BYTES {
+        return None;
+    }
+    fs::read(path).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_new_version_replaces_preview() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ThumbnailCache::new(dir.path().to_path_buf(), MAX_CACHE_BYTES);
+        cache.put("f1", "aaaa", b"old").unwrap();
+        cache.put("f1", "bbbb", b"new").unwrap();
+
+        assert_eq!(cache.get("f1", "aaaa"), None);
+        assert_eq!(cache.get("f1", "bbbb").as_deref(), Some(&b"new"[..]));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_least_recently_used_is_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ThumbnailCache::new(dir.path().to_path_buf(), 8);
+        let age = |id: &str, secs: u64| {
+            fs::File::options()
+                .write(true)
+                .open(cache.entry_path(id, "1"))
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(secs))
+                .unwrap();
+        };
+        cache.put("a", "1", b"aaaa").unwrap();
+        cache.put("b", "1", b"bbbb").unwrap();
+        age("a", 60);
+        age("b", 30);
+        // Reading "a" makes "b" the oldest entry
+        cache.get("a", "1").unwrap();
+        cache.put("c", "1", b"cccc").unwrap();
+
+        assert!(cache.get("a", "1").is_some());
+        assert!(cache.get("b", "1").is_none());
+        assert!(cache.get("c", "1").is_some());
+    }
+
+    #[test]
+    fn test_ids_cannot_escape_cache_dir() {
+        let cache = ThumbnailCache::new(PathBuf::from("/cache"), MAX_CACHE_BYTES);
+        assert_eq!(
+            cache.entry_path("../../etc/passwd", "x/y"),
+            PathBuf::from("/cache/etcpasswd.xy.thumb")
+        );
+    }
+}
@@ -0,0 +1,771 @@
+//! Queue of uploads and downloads waiting for the sync worker.
+//!
+//! The worker enqueues transfers while it plans a cycle and drains the queue
+//! afterwards; the UI lists, cancels and retries entries through the shared
+//! [`TransferQueue`]. Entries are mirrored to the `transfers` table so
+//! cancelled and unfinished transfers survive a restart.
+
+use crate::db::Database;
+use crate::error::{Result, XynoxaError};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// With [`Scheduling::SmallFirst`], transfers from this size on wait until
+/// everything else is done.
+const HUGE_FILE_BYTES: u64 = 1024 * 1024 * 1024;
+/// Modified this recently (seconds), a file counts as part of the working set.
+const RECENT_SECS: i64 = 3 * 24 * 60 * 60;
+/// Assumed size of transfers whose size is unknown.
+const UNKNOWN_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+impl Direction {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Direction::Upload => "upload",
+            Direction::Download => "download",
+        }
+    }
+
+    pub(crate) fn parse(direction: &str) -> Option<Self> {
+        match direction {
+            "upload" => Some(Direction::Upload),
+            "download" => Some(Direction::Download),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferState {
+    Queued,
+    Active,
+    Failed,
+    /// Skipped by the user until retried
+    Cancelled,
+}
+
+impl TransferState {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            TransferState::Queued => "queued",
+            TransferState::Active => "active",
+            TransferState::Failed => "failed",
+            TransferState::Cancelled => "cancelled",
+        }
+    }
+
+    pub(crate) fn parse(state: &str) -> Option<Self> {
+        match state {
+            "queued" => Some(TransferState::Queued),
+            "active" => Some(TransferState::Active),
+            "failed" => Some(TransferState::Failed),
+            "cancelled" => Some(TransferState::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+/// Order in which [`TransferQueue::next_queued`] picks waiting transfers.
+/// Prioritized transfers always go first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scheduling {
+    /// In the order they were queued
+    #[default]
+    Fifo,
+    /// Recently modified files first, then by size; huge files last, so the
+    /// working set is consistent quickly during an initial sync
+    SmallFirst,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transfer {
+    pub path: String,
+    pub direction: Direction,
+    /// Bytes to transfer, if known
+    pub size: Option<u64>,
+    pub state: TransferState,
+    pub error: Option<String>,
+    /// Remote file to download
+    #[serde(skip)]
+    pub file_id: Option<String>,
+    /// Server event that caused a download, stored as the record's version
+    #[serde(skip)]
+    pub version: i64,
+    /// Enqueue order
+    #[serde(skip)]
+    pub seq: i64,
+    /// Raised by [`TransferQueue::prioritize`]; higher runs first
+    pub priority: i64,
+    /// Unix time the local file was modified (uploads), for scheduling
+    #[serde(skip)]
+    pub modified: Option<i64>,
+    /// Chunks of a large upload the server confirmed so far
+    pub progress: Option<ChunkProgress>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkProgress {
+    /// Chunks confirmed, the last one may be partial
+    pub chunk: u64,
+    /// Chunks in total, 0 if the size is unknown
+    pub chunks: u64,
+    /// Bytes confirmed
+    pub bytes: u64,
+}
+
+impl ChunkProgress {
+    pub fn new(bytes: u64, size: Option<u64>) -> Self {
+        let chunk_size = crate::api::CHUNK_SIZE_BYTES as u64;
+        Self {
+            chunk: bytes.div_ceil(chunk_size),
+            chunks: size.map_or(0, |size| size.div_ceil(chunk_size)),
+            bytes,
+        }
+    }
+}
+
+impl Transfer {
+    fn is_pending(&self) -> bool {
+        matches!(self.state, TransferState::Queued | TransferState::Active)
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    entries: HashMap<String, Transfer>,
+    /// Paths changed since the last [`TransferQueue::persist`]
+    dirty: HashSet<String>,
+    next_seq: i64,
+    /// Transfers finished since the last [`TransferQueue::reset_progress`]
+    finished: usize,
+    scheduling: Scheduling,
+    /// Larger transfers stay queued unless prioritized, see
+    /// [`TransferQueue::set_size_limit`]
+    size_limit: Option<u64>,
+}
+
+impl QueueState {
+    fn touch(&mut self, path: &str) {
+        self.dirty.insert(path.to_string());
+    }
+
+    /// Higher runs first.
+    fn schedule_key(
+        &self,
+        t: &Transfer,
+        now: i64,
+    ) -> (i64, bool, bool, Reverse<u64>, Reverse<i64>) {
+        match self.scheduling {
+            Scheduling::Fifo => (t.priority, false, false, Reverse(0), Reverse(t.seq)),
+            Scheduling::SmallFirst => {
+                let size = t.size.unwrap_or(UNKNOWN_SIZE);
+                let recent = t.modified.is_some_and(|m| now - m < RECENT_SECS);
+                (
+                    t.priority,
+                    size < HUGE_FILE_BYTES,
+                    recent,
+                    Reverse(size),
+                    Reverse(t.seq),
+                )
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TransferQueue {
+    state: Mutex<QueueState>,
+}
+
+impl TransferQueue {
+    pub fn set_scheduling(&self, scheduling: Scheduling) {
+        self.state.lock().unwrap().scheduling = scheduling;
+    }
+
+    /// Holds back queued transfers larger than `limit` (or of unknown size)
+    /// until the limit is lifted or the user prioritizes them. `None` lets
+    /// everything run.
+    pub fn set_size_limit(&self, limit: Option<u64>) {
+        self.state.lock().unwrap().size_limit = limit;
+    }
+
+    /// Replaces the queue with entries loaded from the database. Transfers that
+    /// were running when the app stopped are queued again.
+    pub fn restore(&self, transfers: Vec<Transfer>) {
+        let mut state = self.state.lock().unwrap();
+        state.next_seq = transfers.iter().map(|t| t.seq + 1).max().unwrap_or(0);
+        state.entries = transfers
+            .into_iter()
+            .map(|mut t| {
+                if t.state == TransferState::Active {
+                    t.state = TransferState::Queued;
+                }
+                (t.path.clone(), t)
+            })
+            .collect();
+        state.dirty.clear();
+    }
+
+    /// All entries: running first, then in the order they will be processed.
+    pub fn snapshot(&self) -> Vec<Transfer> {
+        let state = self.state.lock().unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let mut transfers: Vec<Transfer> = state.entries.values().cloned().collect();
+        transfers.sort_by_key(|t| {
+            let rank = match t.state {
+                TransferState::Active => 0,
+                TransferState::Queued => 1,
+                TransferState::Failed => 2,
+                TransferState::Cancelled => 3,
+            };
+            (rank, Reverse(state.schedule_key(t, now)))
+        });
+        transfers
+    }
+
+    /// Queues a transfer of `path`, replacing an earlier one. Returns false if
+    /// the user cancelled transfers of this path.
+    pub fn enqueue(
+        &self,
+        path: &str,
+        direction: Direction,
+        size: Option<u64>,
+        file_id: Option<&str>,
+        version: i64,
+    ) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let mut priority = 0;
+        let mut progress = None;
+        if let Some(existing) = state.entries.get(path) {
+            if existing.state == TransferState::Cancelled {
+                return false;
+            }
+            priority = existing.priority;
+            // Resumed where it stopped unless the file changed meanwhile
+            progress = existing
+                .progress
+                .filter(|_| existing.direction == direction);
+        }
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.entries.insert(
+            path.to_string(),
+            Transfer {
+                path: path.to_string(),
+                direction,
+                size,
+                state: TransferState::Queued,
+                error: None,
+                file_id: file_id.map(str::to_string),
+                version,
+                seq,
+                priority,
+                modified: None,
+                progress,
+            },
+        );
+        state.touch(path);
+        true
+    }
+
+    /// Records when the local file of a queued upload was modified.
+    pub fn set_modified(&self, path: &str, modified: i64) {
+        if let Some(transfer) = self.state.lock().unwrap().entries.get_mut(path) {
+            transfer.modified = Some(modified);
+        }
+    }
+
+    /// Records the bytes of a chunked upload the server confirmed.
+    pub fn set_progress(&self, path: &str, bytes: u64) -> Option<ChunkProgress> {
+        let mut state = self.state.lock().unwrap();
+        let transfer = state.entries.get_mut(path)?;
+        let progress = ChunkProgress::new(bytes, transfer.size);
+        transfer.progress = Some(progress);
+        Some(progress)
+    }
+
+    /// Takes the next queued transfer and marks it active.
+    pub fn next_queued(&self) -> Option<Transfer> {
+        let mut state = self.state.lock().unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let next = state
+            .entries
+            .values()
+            .filter(|t| t.state == TransferState::Queued)
+            .filter(|t| {
+                t.priority > 0
+                    || state
+                        .size_limit
+                        .is_none_or(|limit| t.size.is_some_and(|size| size <= limit))
+            })
+            .max_by_key(|t| state.schedule_key(t, now))?
+            .path
+            .clone();
+        state.touch(&next);
+        let transfer = state.entries.get_mut(&next)?;
+        transfer.state = TransferState::Active;
+        Some(transfer.clone())
+    }
+
+    /// Marks `path` as running, adding it if it was not queued. Fails if the
+    /// user cancelled it.
+    pub fn start(&self, path: &str, direction: Direction, size: Option<u64>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(transfer) = state.entries.get_mut(path) {
+            if transfer.state == TransferState::Cancelled {
+                return Err(cancelled(path));
+            }
+            transfer.state = TransferState::Active;
+            transfer.error = None;
+            if size.is_some() {
+                transfer.size = size;
+            }
+        } else {
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.entries.insert(
+                path.to_string(),
+                Transfer {
+                    path: path.to_string(),
+                    direction,
+                    size,
+                    state: TransferState::Active,
+                    error: None,
+                    file_id: None,
+                    version: 0,
+                    seq,
+                    priority: 0,
+                    modified: None,
+                    progress: None,
+                },
+            );
+        }
+        state.touch(path);
+        Ok(())
+    }
+
+    /// Drops `path` after success, otherwise keeps it as failed. Cancelled
+    /// transfers stay cancelled.
+    pub fn finish<T>(&self, path: &str, result: &Result<T>) {
+        let mut state = self.state.lock().unwrap();
+        if state.entries.get(path).is_some_and(|t| t.is_pending()) {
+            state.finished += 1;
+        }
+        match result {
+            Ok(_) => {
+                state.entries.remove(path);
+            }
+            Err(XynoxaError::Cancelled(_)) => {}
+            Err(e) => {
+                if let Some(transfer) = state.entries.get_mut(path) {
+                    transfer.state = TransferState::Failed;
+                    transfer.error = Some(e.to_string());
+                }
+            }
+        }
+        state.touch(path);
+    }
+
+    /// Finished and total transfers of the current sync cycle, for progress
+    /// displays.
+    pub fn progress(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        let pending = state.entries.values().filter(|t| t.is_pending()).count();
+        (state.finished, state.finished + pending)
+    }
+
+    /// Known size of the queued and running transfers.
+    pub fn pending_bytes(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .values()
+            .filter(|t| t.is_pending())
+            .filter_map(|t| t.size)
+            .sum()
+    }
+
+    /// Starts counting [`TransferQueue::progress`] from zero.
+    pub fn reset_progress(&self) {
+        self.state.lock().unwrap().finished = 0;
+    }
+
+    /// Forgets `path` and everything below it, e.g. after it was deleted.
+    pub fn remove(&self, path: &str) {
+        let mut state = self.state.lock().unwrap();
+        let prefix = format!("{}/", path);
+        let removed: Vec<String> = state
+            .entries
+            .keys()
+            .filter(|p| *p == path || p.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for path in removed {
+            state.entries.remove(&path);
+            state.touch(&path);
+        }
+    }
+
+    /// Takes the pending download of remote file `file_id` out of the queue,
+    /// e.g. because the file moved before it was fetched.
+    pub fn remove_download(&self, file_id: &str) -> Option<Transfer> {
+        let mut state = self.state.lock().unwrap();
+        let path = state
+            .entries
+            .values()
+            .find(|t| t.file_id.as_deref() == Some(file_id) && t.state != TransferState::Active)?
+            .path
+            .clone();
+        state.touch(&path);
+        state.entries.remove(&path)
+    }
+
+    /// Re-roots entries below `old_prefix` after a folder move.
+    pub fn move_path_prefix(&self, old_prefix: &str, new_prefix: &str) {
+        let mut state = self.state.lock().unwrap();
+        let prefix = format!("{}/", old_prefix);
+        let moved: Vec<String> = state
+            .entries
+            .keys()
+            .filter(|p| p.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for old_path in moved {
+            let Some(mut transfer) = state.entries.remove(&old_path) else {
+                continue;
+            };
+            transfer.path = format!("{}/{}", new_prefix, &old_path[prefix.len()..]);
+            state.touch(&old_path);
+            state.touch(&transfer.path);
+            state.entries.insert(transfer.path.clone(), transfer);
+        }
+    }
+
+    pub fn is_cancelled(&self, path: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .get(path)
+            .is_some_and(|t| t.state == TransferState::Cancelled)
+    }
+
+    /// Skips `path` until [`TransferQueue::retry`]. A running transfer is aborted.
+    pub fn cancel(&self, path: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let transfer = state
+            .entries
+            .get_mut(path)
+            .ok_or_else(|| not_queued(path))?;
+        transfer.state = TransferState::Cancelled;
+        transfer.error = None;
+        state.touch(path);
+        Ok(())
+    }
+
+    /// Queues a failed or cancelled transfer again.
+    pub fn retry(&self, path: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let transfer = state
+            .entries
+            .get_mut(path)
+            .ok_or_else(|| not_queued(path))?;
+        if transfer.state != TransferState::Active {
+            transfer.state = TransferState::Queued;
+            transfer.error = None;
+        }
+        state.touch(path);
+        Ok(())
+    }
+
+    /// Moves `path` ahead of every other waiting transfer. The most recently
+    /// prioritized transfer runs first.
+    pub fn prioritize(&self, path: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let top = state
+            .entries
+            .values()
+            .map(|t| t.priority)
+            .max()
+            .unwrap_or(0);
+        let transfer = state
+            .entries
+            .get_mut(path)
+            .ok_or_else(|| not_queued(path))?;
+        transfer.priority = top + 1;
+        if transfer.state == TransferState::Failed {
+            transfer.state = TransferState::Queued;
+            transfer.error = None;
+        }
+        state.touch(path);
+        Ok(())
+    }
+
+    /// Queues failed transfers again; called at the start of every cycle.
+    pub fn requeue_failed(&self) {
+        let mut state = self.state.lock().unwrap();
+        let mut requeued = Vec::new();
+        for transfer in state.entries.values_mut() {
+            if transfer.state == TransferState::Failed {
+                transfer.state = TransferState::Queued;
+                requeued.push(transfer.path.clone());
+                crate::telemetry::record_retry();
+            }
+        }
+        state.dirty.extend(requeued);
+    }
+
+    /// Writes entries changed since the last call to the database.
+    pub fn persist(&self, db: &Database) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.dirty.is_empty() {
+            return Ok(());
+        }
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        for path in &state.dirty {
+            match state.entries.get(path) {
+                Some(transfer) => changed.push(transfer.clone()),
+                None => removed.push(path.clone()),
+            }
+        }
+        db.save_transfers(&changed, &removed)?;
+        state.dirty.clear();
+        Ok(())
+    }
+}
+
+pub(crate) fn cancelled(path: &str) -> XynoxaError {
+    XynoxaError::Cancelled(format!("Transfer of {} was cancelled", path))
+}
+
+fn not_queued(path: &str) -> XynoxaError {
+    XynoxaError::Other(format!("No transfer queued for {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancelled_transfer_is_skipped_until_retried() {
+        let queue = TransferQueue::default();
+        assert!(queue.enqueue("big.iso", Direction::Upload, Some(20), None, 0));
+        queue.cancel("big.iso").unwrap();
+
+        assert!(!queue.enqueue("big.iso", Direction::Upload, Some(20), None, 0));
+        assert!(queue.next_queued().is_none());
+        assert!(queue.start("big.iso", Direction::Upload, None).is_err());
+
+        queue.retry("big.iso").unwrap();
+        let next = queue.next_queued().unwrap();
+        assert_eq!(next.path, "big.iso");
+        assert_eq!(next.state, TransferState::Active);
+        queue.finish("big.iso", &Ok(()));
+        assert!(queue.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_pending_bytes_skip_finished_and_cancelled() {
+        let queue = TransferQueue::default();
+        queue.enqueue("a", Direction::Upload, Some(100), None, 0);
+        queue.enqueue("b", Direction::Download, Some(50), Some("b"), 0);
+        queue.enqueue("c", Direction::Download, None, Some("c"), 0);
+        queue.enqueue("d", Direction::Upload, Some(7), None, 0);
+        queue.cancel("d").unwrap();
+        assert_eq!(queue.pending_bytes(), 150);
+
+        let next = queue.next_queued().unwrap();
+        queue.finish(&next.path, &Ok(()));
+        assert_eq!(queue.pending_bytes(), 50);
+    }
+
+    #[test]
+    fn test_prioritized_transfer_runs_first() {
+        let queue = TransferQueue::default();
+        for path in ["a", "b", "c", "d"] {
+            queue.enqueue(path, Direction::Download, None, Some(path), 0);
+        }
+        queue.prioritize("c").unwrap();
+        queue.prioritize("d").unwrap();
+        // Re-queueing keeps the bump
+        queue.enqueue("c", Direction::Download, None, Some("c"), 0);
+
+        let order: Vec<String> = std::iter::from_fn(|| queue.next_queued())
+            .map(|t| t.path)
+            .collect();
+        assert_eq!(order, ["d", "c", "a", "b"]);
+    }
+
+    #[test]
+    fn test_size_limit_holds_large_transfers() {
+        let queue = TransferQueue::default();
+        queue.enqueue("notes.txt", Direction::Upload, Some(2_000), None, 0);
+        queue.enqueue("video.mp4", Direction::Upload, Some(300_000_000), None, 0);
+        queue.enqueue("unknown", Direction::Download, None, Some("f1"), 0);
+        queue.enqueue(
+            "movie.mkv",
+            Direction::Download,
+            Some(700_000_000),
+            Some("f2"),
+            0,
+        );
+        queue.prioritize("movie.mkv").unwrap();
+        queue.set_size_limit(Some(1024 * 1024));
+
+        let order: Vec<String> = std::iter::from_fn(|| queue.next_queued())
+            .map(|t| t.path)
+            .collect();
+        assert_eq!(order, ["movie.mkv", "notes.txt"]);
+        assert_eq!(queue.progress(), (0, 4));
+
+        queue.set_size_limit(None);
+        assert_eq!(queue.next_queued().unwrap().path, "video.mp4");
+    }
+
+    #[test]
+    fn test_small_and_recent_files_go_first() {
+        let queue = TransferQueue::default();
+        queue.set_scheduling(Scheduling::SmallFirst);
+        queue.enqueue(
+            "backup.tar",
+            Direction::Upload,
+            Some(5 * HUGE_FILE_BYTES),
+            None,
+            0,
+        );
+        queue.enqueue("video.mp4", Direction::Upload, Some(300_000_000), None, 0);
+        queue.enqueue("notes.txt", Direction::Upload, Some(2_000), None, 0);
+        queue.enqueue("old.pdf", Direction::Upload, Some(900_000_000), None, 0);
+        queue.enqueue("draft.docx", Direction::Upload, Some(800_000_000), None, 0);
+        queue.set_modified("draft.docx", chrono::Utc::now().timestamp());
+
+        let order: Vec<String> = std::iter::from_fn(|| queue.next_queued())
+            .map(|t| t.path)
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "draft.docx",
+                "notes.txt",
+                "video.mp4",
+                "old.pdf",
+                "backup.tar"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_queue_survives_restart() {
+        let db = Database::new_in_memory().unwrap();
+        let queue = TransferQueue::default();
+        queue.enqueue("a.txt", Direction::Download, None, Some("f1"), 3);
+        queue.enqueue("b.txt", Direction::Upload, Some(5), None, 0);
+        queue.next_queued();
+        queue.cancel("b.txt").unwrap();
+        queue.persist(&db).unwrap();
+
+        let restored = TransferQueue::default();
+        restored.restore(db.load_transfers().unwrap());
+        let transfers = restored.snapshot();
+        assert_eq!(transfers.len(), 2);
+        // Interrupted while running, so it starts over
+        assert_eq!(transfers[0].state, TransferState::Queued);
+        assert_eq!(transfers[0].file_id.as_deref(), Some("f1"));
+        assert_eq!(transfers[0].version, 3);
+        assert_eq!(transfers[1].state, TransferState::Cancelled);
+    }
+
+    #[test]
+    fn test_chunk_progress_survives_restart() {
+        let db = Database::new_in_memory().unwrap();
+        let queue = TransferQueue::default();
+        let size = 3 * crate::api::CHUNK_SIZE_BYTES as u64 + 10;
+        queue.enqueue("video.mp4", Direction::Upload, Some(size), None, 0);
+        queue.next_queued();
+        let bytes = 2 * crate::api::CHUNK_SIZE_BYTES as u64;
+        let progress = queue.set_progress("video.mp4", bytes).unwrap();
+        assert_eq!((progress.chunk, progress.chunks), (2, 4));
+        let point = crate::api::ResumePoint {
+            token: "u1".to_string(),
+            offset: bytes,
+        };
+        db.set_resume("video.mp4", Direction::Upload, &point, "fp")
+            .unwrap();
+        queue.persist(&db).unwrap();
+
+        let restored = TransferQueue::default();
+        restored.restore(db.load_transfers().unwrap());
+        assert_eq!(restored.snapshot()[0].progress, Some(progress));
+        // Queued again by the next scan
+        restored.enqueue("video.mp4", Direction::Upload, Some(size), None, 0);
+        assert_eq!(restored.snapshot()[0].progress, Some(progress));
+    }
+
+    #[test]
+    fn test_folder_health_follows_queue() {
+        use crate::db::{FileRecord, FolderHealth};
+
+        let db = Database::new_in_memory().unwrap();
+        let queue = TransferQueue::default();
+        queue.enqueue("docs/a.txt", Direction::Upload, Some(1), None, 0);
+        queue.enqueue("docs/2024/b.txt", Direction::Upload, Some(1), None, 0);
+        queue.enqueue("photos/c.jpg", Direction::Download, None, Some("f1"), 1);
+        queue.persist(&db).unwrap();
+        db.insert_or_update(&FileRecord {
+            id: Some("f2".to_string()),
+            path: "docs/2024/b.conflict_backup".to_string(),
+            hash: "h".to_string(),
+            modified_at: 0,
+            server_version: 0,
+            remote_hash: None,
+            group_folder_id: None,
+            is_group_root: false,
+        })
+        .unwrap();
+
+        let root = db.folder_health("").unwrap();
+        assert_eq!((root.pending, root.failed, root.conflicts), (3, 0, 1));
+        let branches: Vec<(&str, u64)> = root
+            .branches
+            .iter()
+            .map(|b| (b.path.as_str(), b.pending))
+            .collect();
+        assert_eq!(branches, [("docs", 2), ("photos", 1)]);
+
+        queue
+            .start("docs/2024/b.txt", Direction::Upload, None)
+            .unwrap();
+        queue.finish::<()>("docs/2024/b.txt", &Err("offline".into()));
+        queue.finish("docs/a.txt", &Ok(()));
+        queue.persist(&db).unwrap();
+        // Written again unchanged: must not count twice
+        db.save_transfers(&queue.snapshot(), &[]).unwrap();
+        let docs = db.folder_health("docs").unwrap();
+        assert_eq!((docs.pending, docs.failed, docs.conflicts), (0, 1, 1));
+        assert_eq!(docs.branches[0].path, "docs/2024");
+        assert_eq!(db.folder_health("doc").unwrap().failed, 0);
+
+        db.delete_file("docs/2024/b.conflict_backup").unwrap();
+        queue.remove("docs/2024/b.txt");
+        queue.persist(&db).unwrap();
+        assert_eq!(
+            db.folder_health("docs").unwrap(),
+            FolderHealth {
+                path: "docs".to_string(),
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Typed helpers for the tRPC v10 HTTP protocol spoken by the Xynoxa server.
+//!
+//! Requests are always sent in batch form (`?batch=1`) with the input wrapped as
+//! `{"0": {"json": input}}`. Responses are an array of either
+//! `{"result": {"data": {"json": ...}}}` or `{"error": {"json": {...}}}`.
+
+use crate::error::{Result, XynoxaError};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize)]
+struct BatchInput<'a, T> {
+    #[serde(rename = "0")]
+    item: JsonWrap<&'a T>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonWrap<T> {
+    json: T,
+}
+
+/// tRPC error payload (`error.json`).
+#[derive(Deserialize, Debug, Clone)]
+pub struct TrpcError {
+    pub message: String,
+    #[serde(default)]
+    pub data: Option<TrpcErrorData>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TrpcErrorData {
+    /// tRPC error code, e.g. `UNAUTHORIZED`, `NOT_FOUND`, `CONFLICT`.
+    pub code: Option<String>,
+    #[serde(rename = "httpStatus")]
+    pub http_status: Option<u16>,
+    pub path: Option<String>,
+    /// Set when the account needs a second factor before the token is accepted
+    #[serde(default, rename = "twoFactor")]
+    pub two_factor: Option<TwoFactorChallenge>,
+}
+
+/// Second-factor challenge attached to an `UNAUTHORIZED` error.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorChallenge {
+    pub challenge_id: String,
+    /// Accepted methods, e.g. `totp` and `webauthn`
+    pub methods: Vec<String>,
+    /// `PublicKeyCredentialRequestOptions` for `navigator.credentials.get`
+    #[serde(default)]
+    pub webauthn_options: Option<Value>,
+}
+
+impl TrpcError {
+    fn into_error(self, procedure: &str, status: StatusCode) -> XynoxaError {
+        let data = self.data.unwrap_or(TrpcErrorData {
+            code: None,
+            http_status: None,
+            path: None,
+            two_factor: None,
+        });
+        if let Some(challenge) = data.two_factor {
+            return XynoxaError::TwoFactorRequired(challenge);
+        }
+        let code = data.code.unwrap_or_default();
+        let message = format!("{} ({}): {}", procedure, code, self.message);
+        match code.as_str() {
+            "UNAUTHORIZED" => XynoxaError::Auth(message),
+            "FORBIDDEN" => XynoxaError::Forbidden(message),
+            "CONFLICT" => XynoxaError::Conflict(message),
+            _ => XynoxaError::Server {
+                status: data.http_status.unwrap_or(status.as_u16()),
+                message,
+            },
+        }
+    }
+}
+
+/// Serializes `input` into the single-item batch envelope.
+pub fn batch_input<T: Serialize>(input: &T) -> Result<String> {
+    Ok(serde_json::to_string(&BatchInput {
+        item: JsonWrap { json: input },
+    })?)
+}
+
+/// Decodes the first item of a batch response, turning tRPC error envelopes into
+/// typed errors.
+pub fn decode_response<R: DeserializeOwned>(
+    procedure: &str,
+    status: StatusCode,
+    body: &str,
+) -> Result<R> {
+    let items = decode_batch(status, body)?;
+    let first = items
+        .into_iter()
+        .next()
+        .ok_or_else(|| XynoxaError::Decode(format!("Empty TRPC response for {}", procedure)))?;
+    decode_item(procedure, status, first)
+}
+
+/// Splits a batch response into its raw items. Falls back to treating a bare
+/// object as a one-item batch (some proxies unwrap single-item batches).
+pub fn decode_batch(status: StatusCode, body: &str) -> Result<Vec<Value>> {
+    match serde_json::from_str::<Value>(body) {
+        Ok(Value::Array(items)) => Ok(items),
+        Ok(other @ Value::Object(_)) => Ok(vec![other]),
+        _ if !status.is_success() => Err(XynoxaError::from_status(
+            status,
+            format!("Non-JSON TRPC response. Body: {}", body),
+        )),
+        _ => Err(XynoxaError::Decode(format!(
+            "Failed to decode TRPC response. Raw: {}",
+            body
+        ))),
+    }
+}
+
+/// Decodes a single batch item into `R`.
+pub fn decode_item<R: DeserializeOwned>(
+    procedure: &str,
+    status: StatusCode,
+    item: Value,
+) -> Result<R> {
+    if let Some(error) = item.get("error") {
+        let error = error.get("json").unwrap_or(error);
+        return match serde_json::from_value::<TrpcError>(error.clone()) {
+            Ok(e) => Err(e.into_error(procedure, status)),
+            Err(_) => Err(XynoxaError::from_status(
+                status,
+                format!("TRPC error in {}: {}", procedure, error),
+            )),
+        };
+    }
+
+    if let Some(data) = item.get("result").and_then(|r| r.get("data")) {
+        // Procedures returning void omit `json` entirely
+        let json = data.get("json").cloned().unwrap_or(Value::Null);
+        return serde_json::from_value(json).map_err(|e| {
+            XynoxaError::Decode(format!(
+                "Failed to decode TRPC response for {}: {}. Body: {}",
+                procedure, e, item
+            ))
+        });
+    }
+
+    if !status.is_success() {
+        return Err(XynoxaError::from_status(
+            status,
+            format!("TRPC call {} failed. Body: {}", procedure, item),
+        ));
+    }
+
+    // Bare payload without the tRPC envelope (mock servers, legacy proxies)
+    serde_json::from_value(item.clone()).map_err(|e| {
+        XynoxaError::Decode(format!(
+            "Failed to decode response for {}: {}. Raw: {}",
+            procedure, e, item
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_input_envelope() {
+        let input = serde_json::json!({ "cursor": 42 });
+        assert_eq!(
+            batch_input(&input).unwrap(),
+            r#"{"0":{"json":{"cursor":42}}}"#
+        );
+    }
+
+    #[test]
+    fn test_decode_result_and_error() {
+        let ok: u64 = decode_response(
+            "sync.pull",
+            StatusCode::OK,
+            r#"[{"result":{"data":{"json":7}}}]"#,
+        )
+        .unwrap();
+        assert_eq!(ok, 7);
+
+        let err = decode_response::<u64>(
+            "files.rename",
+            StatusCode::UNAUTHORIZED,
+            r#"[{"error":{"json":{"message":"bad token","code":-32001,"data":{"code":"UNAUTHORIZED","httpStatus":401}}}}]"#,
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "auth");
+        assert!(err.to_string().contains("bad token"));
+    }
+
+    #[test]
+    fn test_decode_two_factor_challenge() {
+        let err = decode_response::<u64>(
+            "devices.register",
+            StatusCode::UNAUTHORIZED,
+            r#"[{"error":{"json":{"message":"2fa","data":{"code":"UNAUTHORIZED","twoFactor":{"challengeId":"c1","methods":["totp"]}}}}}]"#,
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "two_factor_required");
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["challenge"]["challengeId"], "c1");
+        assert_eq!(json["challenge"]["methods"][0], "totp");
+    }
+}
@@ -0,0 +1,308 @@
+//! Version check against the GitHub releases of the desktop client, plus
+//! download of the matching installer. Every installer is published with a
+//! `<name>.sha256` and a `<name>.sig` asset, and is only handed out after the
+//! checksum matched and the signature verified against [`UPDATE_PUBKEY`].
+
+use crate::error::{Result, XynoxaError};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const RELEASES_URL: &str =
+    "https://api.github.com/repos/christinloehner/Xynoxa-Desktop-Client/releases/latest";
+
+/// Minisign public key the release installers are signed with, in the
+/// base64 form `tauri signer generate` prints. Set at build time; builds
+/// without it refuse to install updates.
+const UPDATE_PUBKEY: Option<&str> = option_env!("XYNOXA_UPDATE_PUBKEY");
+
+/// Prefix of the directories [`download_installer`] creates.
+const DOWNLOAD_DIR_PREFIX: &str = "xynoxa-update-";
+
+#[derive(Deserialize, Debug)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    body: Option<String>,
+    #[serde(default)]
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateInfo {
+    pub current_version: String,
+    pub latest_version: String,
+    pub available: bool,
+    pub release_url: String,
+    pub notes: Option<String>,
+    /// Installer for this platform, if the release has one
+    pub download_url: Option<String>,
+    /// SHA-256 file published next to the installer
+    #[serde(skip)]
+    pub checksum_url: Option<String>,
+    /// Minisign signature published next to the installer
+    #[serde(skip)]
+    pub signature_url: Option<String>,
+}
+
+fn http_client() -> Result<reqwest::Client> {
+    // GitHub rejects API requests without a User-Agent
+    Ok(reqwest::Client::builder()
+        .user_agent(concat!("xynoxa-desktop-client/", env!("CARGO_PKG_VERSION")))
+        .build()?)
+}
+
+/// Fetches the latest release and compares it with the running version.
+pub async fn check_for_updates() -> Result<UpdateInfo> {
+    let res = http_client()?.get(RELEASES_URL).send().await?;
+    let status = res.status();
+    if !status.is_success() {
+        let body = res.text().await.unwrap_or_else(|_| "No body".to_string());
+        return Err(XynoxaError::from_status(
+            status,
+            format!("Release check failed. Body: {}", body),
+        ));
+    }
+    let release: Release = res.json().await?;
+
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = release.tag_name.trim_start_matches('v').to_string();
+    let asset = pick_asset(&release.assets);
+    let sibling_url = |extension: &str| {
+        let name = format!("{}.{}", asset?.name, extension);
+        release
+            .assets
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.clone())
+    };
+    Ok(UpdateInfo {
+        current_version: current.to_string(),
+        available: is_newer(&latest, current),
+        download_url: asset.map(|a| a.browser_download_url.clone()),
+        checksum_url: sibling_url("sha256"),
+        signature_url: sibling_url("sig"),
+        latest_version: latest,
+        release_url: release.html_url,
+        notes: release.body,
+    })
+}
+
+/// Downloads the installer, its checksum and its signature, and writes the
+/// installer into a new private directory below `parent` once both were
+/// verified. Returns the installer's path.
+pub async fn download_installer(
+    url: &str,
+    checksum_url: &str,
+    signature_url: &str,
+    parent: &Path,
+) -> Result<PathBuf> {
+    let name = url
+        .rsplit('/')
+        .next()
+        .filter(|n| !n.is_empty())
+        .unwrap_or("xynoxa-update");
+    // Checked before downloading anything, so unsigned builds fail fast
+    let public_key = UPDATE_PUBKEY.ok_or_else(|| {
+        XynoxaError::Other("This build has no key to verify updates with".to_string())
+    })?;
+    let client = http_client()?;
+    let expected = fetch(&client, checksum_url, "Checksum download failed").await?;
+    let expected = parse_checksum(&String::from_utf8_lossy(&expected))
+        .ok_or_else(|| XynoxaError::Other(format!("Release has no valid checksum for {}", name)))?;
+    let content = fetch(&client, url, "Installer download failed").await?;
+    let actual = {
+        use sha2::{Digest, Sha256};
+        hex::encode(Sha256::digest(&content))
+    };
+    if actual != expected {
+        return Err(XynoxaError::Other(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            name, expected, actual
+        )));
+    }
+    let signature = fetch(&client, signature_url, "Signature download failed").await?;
+    verify_signature(&content, &signature, public_key)
+        .map_err(|e| XynoxaError::Other(format!("Invalid signature for {}: {}", name, e)))?;
+
+    let dir = create_private_dir(parent)?;
+    let path = dir.join(name);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        let executable = name.ends_with(".AppImage");
+        options.mode(if executable { 0o700 } else { 0o600 });
+    }
+    std::io::Write::write_all(&mut options.open(&path)?, &content)?;
+    Ok(path)
+}
+
+async fn fetch(client: &reqwest::Client, url: &str, context: &str) -> Result<Vec<u8>> {
+    let res = client.get(url).send().await?;
+    let status = res.status();
+    if !status.is_success() {
+        return Err(XynoxaError::from_status(status, context.to_string()));
+    }
+    Ok(res.bytes().await?.to_vec())
+}
+
+/// Creates a fresh directory below `parent` only the current user can
+/// access, so nobody can swap the installer before it is launched.
+fn create_private_dir(parent: &Path) -> Result<PathBuf> {
+    let mut random = [0u8; 8];
+    getrandom::getrandom(&mut random)
+        .map_err(|e| XynoxaError::Other(format!("No random directory name: {}", e)))?;
+    let dir = parent.join(format!("{}{}", DOWNLOAD_DIR_PREFIX, hex::encode(random)));
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    // Fails if the directory already exists
+    builder.create(&dir)?;
+    Ok(dir)
+}
+
+/// Deletes the directories earlier downloads left below `parent`. Called at
+/// startup, when no installer from them can still be running.
+pub fn remove_stale_downloads(parent: &Path) {
+    let Ok(entries) = std::fs::read_dir(parent) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let is_download = entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(DOWNLOAD_DIR_PREFIX)
+            && entry.file_type().is_ok_and(|t| t.is_dir());
+        if !is_download {
+            continue;
+        }
+        if let Err(e) = std::fs::remove_dir_all(entry.path()) {
+            tracing::warn!(
+                "Failed to remove old update download {:?}: {}",
+                entry.path(),
+                e
+            );
+        }
+    }
+}
+
+/// Verifies a signature made by `tauri signer sign`: `signature` and
+/// `public_key` are base64 of the minisign signature and key files.
+fn verify_signature(
+    content: &[u8],
+    signature: &[u8],
+    public_key: &str,
+) -> std::result::Result<(), String> {
+    use base64::Engine;
+    let decode = |data: &[u8]| {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(data.trim_ascii())
+            .map_err(|e| e.to_string())?;
+        String::from_utf8(bytes).map_err(|e| e.to_string())
+    };
+    let public_key = minisign_verify::PublicKey::decode(&decode(public_key.as_bytes())?)
+        .map_err(|e| e.to_string())?;
+    let signature =
+        minisign_verify::Signature::decode(&decode(signature)?).map_err(|e| e.to_string())?;
+    public_key
+        .verify(content, &signature, true)
+        .map_err(|e| e.to_string())
+}
+
+/// First field of a `sha256sum` line, lowercased.
+fn parse_checksum(content: &str) -> Option<String> {
+    let hash = content.split_whitespace().next()?.to_ascii_lowercase();
+    (hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())).then_some(hash)
+}
+
+/// Semver comparison; unparseable versions never count as newer.
+fn is_newer(latest: &str, current: &str) -> bool {
+    match (
+        semver::Version::parse(latest),
+        semver::Version::parse(current),
+    ) {
+        (Ok(latest), Ok(current)) => latest > current,
+        _ => false,
+    }
+}
+
+fn pick_asset(assets: &[ReleaseAsset]) -> Option<&ReleaseAsset> {
+    let suffixes: &[&str] = if cfg!(target_os = "windows") {
+        &[".exe", ".msi"]
+    } else if cfg!(target_os = "macos") {
+        &[".dmg"]
+    } else {
+        &[".AppImage", ".deb", ".rpm"]
+    };
+    suffixes
+        .iter()
+        .find_map(|suffix| assets.iter().find(|a| a.name.ends_with(suffix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer_handles_prereleases() {
+        assert!(is_newer("1.0.0-Alpha2", "1.0.0-Alpha1"));
+        assert!(is_newer("1.0.0", "1.0.0-Alpha1"));
+        assert!(!is_newer("1.0.0-Alpha1", "1.0.0-Alpha1"));
+        assert!(!is_newer("nightly", "1.0.0-Alpha1"));
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let hash = "a".repeat(64);
+        assert_eq!(
+            parse_checksum(&format!("{}  Xynoxa.AppImage\n", hash.to_uppercase())),
+            Some(hash)
+        );
+        assert_eq!(parse_checksum("abc  Xynoxa.AppImage"), None);
+        assert_eq!(parse_checksum(""), None);
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        use base64::Engine;
+        let encode = |text: &str| base64::engine::general_purpose::STANDARD.encode(text);
+        // Test vector of the minisign-verify crate, signing the content "test"
+        let public_key = encode(
+            "untrusted comment: minisign public key E7620F1842B4E81F\n\
+             RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3",
+        );
+        let signature = encode(
+            "untrusted comment: signature from minisign secret key\n\
+             RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/\
+             z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=\n\
+             trusted comment: timestamp:1556193335\tfile:test\n\
+             y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==",
+        );
+        assert!(verify_signature(b"test", signature.as_bytes(), &public_key).is_ok());
+        assert!(verify_signature(b"Test", signature.as_bytes(), &public_key).is_err());
+        assert!(verify_signature(b"test", b"", &public_key).is_err());
+    }
+
+    #[test]
+    fn test_remove_stale_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let download = create_private_dir(dir.path()).unwrap();
+        std::fs::write(download.join("Xynoxa.AppImage"), b"installer").unwrap();
+        std::fs::create_dir(dir.path().join("other")).unwrap();
+
+        remove_stale_downloads(dir.path());
+        assert!(!download.exists());
+        assert!(dir.path().join("other").exists());
+    }
+}
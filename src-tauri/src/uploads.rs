@@ -0,0 +1,163 @@
+//! Files and folders dropped onto the app window.
+//!
+//! Drops onto a synced folder are copied into the sync root and picked up by
+//! the sync worker; drops onto remote folders that are not synced locally are
+//! uploaded directly.
+
+use crate::api::ApiClient;
+use crate::error::{Result, XynoxaError};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Payload of the `upload://progress` event, sent after each dropped item.
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadProgress {
+    pub path: String,
+    pub done: usize,
+    pub total: usize,
+    pub error: Option<String>,
+}
+
+/// Copies `source` (file or folder) into `dir`. An existing entry is never
+/// overwritten; the copy gets a numbered name like `report (2).pdf` instead.
+pub fn copy_into(source: &Path, dir: &Path) -> Result<PathBuf> {
+    let name = source
+        .file_name()
+        .ok_or_else(|| XynoxaError::Other(format!("Cannot upload {}", source.display())))?;
+    if source.is_dir() && dir.starts_with(source) {
+        return Err(XynoxaError::Other(format!(
+            "Cannot copy {} into itself",
+            source.display()
+        )));
+    }
+    let target = unique_destination(dir, &name.to_string_lossy());
+
+    if source.is_dir() {
+        for entry in WalkDir::new(source).follow_links(false) {
+            let entry = entry.map_err(|e| XynoxaError::Other(e.to_string()))?;
+            let relative = entry.path().strip_prefix(source).unwrap_or(entry.path());
+            let dest = target.join(relative);
+            if entry.file_type().is_dir() {
+                fs::create_dir_all(&dest)?;
+            } else if entry.file_type().is_file() {
+                fs::copy(entry.path(), &dest)?;
+            }
+        }
+    } else {
+        fs::create_dir_all(dir)?;
+        fs::copy(source, &target)?;
+    }
+    Ok(target)
+}
+
+/// Uploads `source` into remote folder `folder_id` (the root if `None`),
+/// creating remote folders for directories.
+pub async fn upload_tree(
+    client: &dyn ApiClient,
+    source: &Path,
+    folder_id: Option<&str>,
+) -> Result<()> {
+    let name = source
+        .file_name()
+        .ok_or_else(|| XynoxaError::Other(format!("Cannot upload {}", source.display())))?
+        .to_string_lossy()
+        .to_string();
+    if !source.is_dir() {
+        client.upload_file(source, None, folder_id, &name).await?;
+        return Ok(());
+    }
+
+    // Parents are visited before their children, so their ids are known
+    let mut folder_ids: Vec<(PathBuf, String)> = Vec::new();
+    for entry in WalkDir::new(source).follow_links(false).sort_by_file_name() {
+        let entry = entry.map_err(|e| XynoxaError::Other(e.to_string()))?;
+        let parent_id = entry
+            .path()
+            .parent()
+            .and_then(|parent| folder_ids.iter().find(|(path, _)| path == parent))
+            .map(|(_, id)| id.as_str())
+            .or(folder_id);
+        let entry_name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type().is_dir() {
+            let folder = client.create_folder(&entry_name, parent_id).await?;
+            folder_ids.push((entry.path().to_path_buf(), folder.id));
+        } else if entry.file_type().is_file() {
+            client
+                .upload_file(entry.path(), None, parent_id, &entry_name)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+fn unique_destination(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return candidate;
+    }
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (name, String::new()),
+    };
+    (2..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_api::MockApiClient;
+
+    #[test]
+    fn test_copy_never_overwrites() {
+        let source = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let file = source.path().join("report.pdf");
+        fs::write(&file, b"new").unwrap();
+        fs::write(root.path().join("report.pdf"), b"old").unwrap();
+
+        let copied = copy_into(&file, root.path()).unwrap();
+        assert_eq!(copied, root.path().join("report (2).pdf"));
+        assert_eq!(fs::read(root.path().join("report.pdf")).unwrap(), b"old");
+
+        fs::create_dir(source.path().join("album")).unwrap();
+        fs::write(source.path().join("album/a.jpg"), b"a").unwrap();
+        copy_into(&source.path().join("album"), root.path()).unwrap();
+        assert_eq!(fs::read(root.path().join("album/a.jpg")).unwrap(), b"a");
+    }
+
+    #[test]
+    fn test_folder_upload_creates_remote_tree() {
+        let source = tempfile::tempdir().unwrap();
+        let album = source.path().join("album");
+        fs::create_dir_all(album.join("raw")).unwrap();
+        fs::write(album.join("a.jpg"), b"a").unwrap();
+        fs::write(album.join("raw/b.cr2"), b"b").unwrap();
+
+        let client = MockApiClient::new();
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(upload_tree(&client, &album, Some("remote")))
+            .unwrap();
+
+        let state = client.state();
+        assert_eq!(
+            state.created_folders,
+            [
+                ("album".to_string(), Some("remote".to_string())),
+                ("raw".to_string(), Some("folder-1".to_string())),
+            ]
+        );
+        assert_eq!(
+            state.uploads,
+            [
+                ("a.jpg".to_string(), Some("folder-1".to_string())),
+                ("b.cr2".to_string(), Some("folder-3".to_string())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,450 @@
+//! Virtual drive: the remote storage as a lazily loaded, read-only filesystem.
+//!
+//! The directory tree is rebuilt from the `sync.pull` event log, and file
+//! contents are downloaded on first read into a size-limited cache in the app
+//! data dir. Nothing is mirrored into the sync folder, so the whole cloud is
+//! reachable without a local copy. `fuse` mounts a [`VirtualDrive`] on Linux
+//! and macOS when built with the `virtual-drive` feature.
+
+use crate::api::{self, ApiClient};
+use crate::error::{Result, XynoxaError};
+use crate::sync::{is_folder_entity, is_safe_relative_path, remote_event_path};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Page size requested from `sync.pull` while building the tree.
+const PULL_PAGE_SIZE: u32 = 500;
+
+/// Remote changes show up in the drive after at most this long.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A file or folder in the remote tree. Paths are `/`-separated and relative
+/// to the storage root, which is `""`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteNode {
+    /// `None` for folders only known as the parent of something else
+    pub id: Option<String>,
+    pub is_dir: bool,
+    pub size: u64,
+    /// Event that last changed the content; part of the cache key
+    pub version: u64,
+}
+
+impl RemoteNode {
+    fn implicit_dir() -> Self {
+        Self {
+            id: None,
+            is_dir: true,
+            size: 0,
+            version: 0,
+        }
+    }
+}
+
+/// The remote tree as of `cursor`.
+#[derive(Debug)]
+pub struct RemoteTree {
+    nodes: HashMap<String, RemoteNode>,
+    children: HashMap<String, BTreeSet<String>>,
+    paths_by_id: HashMap<String, String>,
+    cursor: u64,
+}
+
+impl Default for RemoteTree {
+    fn default() -> Self {
+        let mut nodes = HashMap::new();
+        nodes.insert(String::new(), RemoteNode::implicit_dir());
+        Self {
+            nodes,
+            children: HashMap::new(),
+            paths_by_id: HashMap::new(),
+            cursor: 0,
+        }
+    }
+}
+
+impl RemoteTree {
+    pub fn get(&self, path: &str) -> Option<&RemoteNode> {
+        self.nodes.get(path)
+    }
+
+    /// Names of the entries in folder `path`, sorted.
+    pub fn children(&self, path: &str) -> Vec<String> {
+        self.children
+            .get(path)
+            .map(|names| names.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn apply(&mut self, event: &api::SyncEvent) {
+        self.cursor = self.cursor.max(event.id);
+        match event.action.as_str() {
+            "create" | "update" | "copy" | "restore" | "move" => {
+                let Some(data) = &event.data else {
+                    return;
+                };
+                let path = remote_event_path(data, event.owner_id.as_deref());
+                if path.is_empty() || !is_safe_relative_path(&path) {
+                    return;
+                }
+                let is_dir = is_folder_entity(&event.entity_type);
+                // Renames and moves arrive with the new path
+                if let Some(old) = self.paths_by_id.get(&event.entity_id).cloned() {
+                    if old != path {
+                        self.rename(&old, &path);
+                    }
+                }
+                let previous = self.nodes.get(&path);
+                let node = RemoteNode {
+                    id: Some(event.entity_id.clone()),
+                    is_dir,
+                    size: match data.size.as_deref().and_then(|s| s.parse().ok()) {
+                        Some(size) => size,
+                        None => previous.map(|n| n.size).unwrap_or(0),
+                    },
+                    // A move keeps the content
+                    version: match (event.action.as_str(), previous) {
+                        ("move", Some(previous)) => previous.version,
+                        _ => event.id,
+                    },
+                };
+                self.insert(path, node);
+            }
+            "delete" => {
+                if let Some(path) = self.paths_by_id.get(&event.entity_id).cloned() {
+                    self.remove(&path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn insert(&mut self, path: String, node: RemoteNode) {
+        let (parent, name) = split_parent(&path);
+        if !self.nodes.contains_key(parent) {
+            self.insert(parent.to_string(), RemoteNode::implicit_dir());
+        }
+        self.children
+            .entry(parent.to_string())
+            .or_default()
+            .insert(name.to_string());
+        if let Some(id) = &node.id {
+            self.paths_by_id.insert(id.clone(), path.clone());
+        }
+        self.nodes.insert(path, node);
+    }
+
+    /// Removes `path` and everything below it.
+    fn remove(&mut self, path: &str) {
+        if path.is_empty() {
+            return;
+        }
+        for child in self.children(path) {
+            self.remove(&join(path, &child));
+        }
+        self.children.remove(path);
+        if let Some(node) = self.nodes.remove(path) {
+            if let Some(id) = node.id {
+                self.paths_by_id.remove(&id);
+            }
+        }
+        let (parent, name) = split_parent(path);
+        if let Some(names) = self.children.get_mut(parent) {
+            names.remove(name);
+        }
+    }
+
+    /// Moves `from` and everything below it to `to`.
+    fn rename(&mut self, from: &str, to: &str) {
+        let mut moved = Vec::new();
+        self.collect(from, to, &mut moved);
+        self.remove(from);
+        for (path, node) in moved {
+            self.insert(path, node);
+        }
+    }
+
+    fn collect(&self, from: &str, to: &str, out: &mut Vec<(String, RemoteNode)>) {
+        if let Some(node) = self.nodes.get(from) {
+            out.push((to.to_string(), node.clone()));
+        }
+        for child in self.children(from) {
+            self.collect(&join(from, &child), &join(to, &child), out);
+        }
+    }
+}
+
+fn split_parent(path: &str) -> (&str, &str) {
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+pub fn join(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+/// Downloaded file contents, keyed by file id and version. Pruned least
+/// recently used first once it grows past its size limit.
+struct ReadCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl ReadCache {
+    fn entry_path(&self, id: &str, version: u64) -> PathBuf {
+        self.dir.join(format!("{}.{}", sanitize(id), version))
+    }
+
+    fn touch(path: &Path) {
+        if let Ok(file) = fs::File::options().write(true).open(path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+    }
+
+    /// Stores a downloaded file, replacing older versions of it.
+    fn insert(&self, id: &str, version: u64, downloaded: &Path) -> Result<PathBuf> {
+        let prefix = format!("{}.", sanitize(id));
+        for entry in fs::read_dir(&self.dir)?.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        let path = self.entry_path(id, version);
+        fs::rename(downloaded, &path)?;
+        self.prune(&path)?;
+        Ok(path)
+    }
+
+    /// Deletes least recently used entries other than `keep` until the cache
+    /// fits its limit.
+    fn prune(&self, keep: &Path) -> Result<()> {
+        let mut entries: Vec<(SystemTime, u64, PathBuf)> = fs::read_dir(&self.dir)?
+            .flatten()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let used = metadata.modified().ok()?;
+                Some((used, metadata.len(), entry.path()))
+            })
+            .collect();
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if path != keep && fs::remove_file(&path).is_ok() {
+                total -= len;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn sanitize(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The remote storage as a read-only filesystem, independent of any mount
+/// mechanism. Blocking; meant to be called from filesystem callback threads.
+pub struct VirtualDrive {
+    client: Arc<dyn ApiClient>,
+    tree: Mutex<RemoteTree>,
+    last_refresh: Mutex<Option<Instant>>,
+    cache: ReadCache,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl VirtualDrive {
+    pub fn new(client: Arc<dyn ApiClient>, cache_dir: PathBuf, cache_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(&cache_dir)?;
+        Ok(Self {
+            client,
+            tree: Mutex::new(RemoteTree::default()),
+            last_refresh: Mutex::new(None),
+            cache: ReadCache {
+                dir: cache_dir,
+                max_bytes: cache_bytes,
+            },
+            runtime: tokio::runtime::Runtime::new()?,
+        })
+    }
+
+    /// Applies remote changes since the last refresh.
+    pub fn refresh(&self) -> Result<()> {
+        let mut cursor = self.lock_tree().cursor;
+        loop {
+            let page = self
+                .runtime
+                .block_on(self.client.sync_pull(cursor, Some(PULL_PAGE_SIZE)))?;
+            let last_page = page.is_last_page(cursor);
+            let mut tree = self.lock_tree();
+            for event in &page.events {
+                tree.apply(event);
+            }
+            tree.cursor = tree.cursor.max(page.next_cursor);
+            cursor = tree.cursor;
+            if last_page {
+                break;
+            }
+        }
+        *self.last_refresh.lock().unwrap() = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Refreshes if the tree is older than [`REFRESH_INTERVAL`]. Failures keep
+    /// the old tree, so the drive stays browsable offline.
+    pub fn refresh_if_stale(&self) {
+        let stale = self
+            .last_refresh
+            .lock()
+            .unwrap()
+            .is_none_or(|at| at.elapsed() >= REFRESH_INTERVAL);
+        if stale {
+            if let Err(e) = self.refresh() {
+                tracing::warn!("Virtual drive refresh failed: {}", e);
+            }
+        }
+    }
+
+    pub fn node(&self, path: &str) -> Option<RemoteNode> {
+        self.lock_tree().get(path).cloned()
+    }
+
+    /// Entries of folder `path` with their nodes, sorted by name.
+    pub fn list(&self, path: &str) -> Vec<(String, RemoteNode)> {
+        let tree = self.lock_tree();
+        tree.children(path)
+            .into_iter()
+            .filter_map(|name| {
+                let node = tree.get(&join(path, &name))?.clone();
+                Some((name, node))
+            })
+            .collect()
+    }
+
+    /// Up to `size` bytes of file `path` from `offset`, downloading the file
+    /// into the cache on first access.
+    pub fn read(&self, path: &str, offset: u64, size: usize) -> Result<Vec<u8>> {
+        let node = self
+            .node(path)
+            .filter(|node| !node.is_dir)
+            .ok_or_else(|| XynoxaError::Other(format!("No such file: {}", path)))?;
+        let local = self.fetch(&node)?;
+        let mut file = fs::File::open(local)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buffer = Vec::with_capacity(size);
+        file.take(size as u64).read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn fetch(&self, node: &RemoteNode) -> Result<PathBuf> {
+        let id = node.id.as_deref().unwrap_or_default();
+        let cached = self.cache.entry_path(id, node.version);
+        if cached.is_file() {
+            ReadCache::touch(&cached);
+            return Ok(cached);
+        }
+        // Outside the entry name pattern, so `insert` does not delete it
+        let part = self.cache.dir.join(format!(".{}.part", sanitize(id)));
+        self.runtime
+            .block_on(self.client.download_file(id, &part, None))?;
+        self.cache.insert(id, node.version, &part)
+    }
+
+    fn lock_tree(&self) -> std::sync::MutexGuard<'_, RemoteTree> {
+        self.tree.lock().unwrap()
+    }
+}
+
+/// A mounted [`VirtualDrive`]; dropping it unmounts the drive.
+pub struct MountedDrive {
+    pub mount_point: PathBuf,
+    #[cfg(all(feature = "virtual-drive", unix))]
+    _session: crate::fuse::Session,
+}
+
+/// Mounts `drive` at `mount_point`, creating the folder if needed.
+#[cfg(all(feature = "virtual-drive", unix))]
+pub fn mount(drive: VirtualDrive, mount_point: &Path) -> Result<MountedDrive> {
+    fs::create_dir_all(mount_point)?;
+    if let Err(e) = drive.refresh() {
+        tracing::warn!("Mounting with an empty tree, refresh failed: {}", e);
+    }
+    Ok(MountedDrive {
+        mount_point: mount_point.to_path_buf(),
+        _session: crate::fuse::mount(drive, mount_point)?,
+    })
+}
+
+/// Mounts `drive` at `mount_point`. Needs FUSE; WinFsp is not supported yet.
+#[cfg(not(all(feature = "virtual-drive", unix)))]
+pub fn mount(_drive: VirtualDrive, _mount_point: &Path) -> Result<MountedDrive> {
+    Err(XynoxaError::Other(
+        "The virtual drive is not available in this build".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::FileData;
+    use crate::mock_api::MockApiClient;
+
+    fn moved_to(path: &str) -> FileData {
+        FileData {
+            path: Some(path.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tree_follows_the_event_log() {
+        let client = MockApiClient::new();
+        client.add_folder("d1", "Docs");
+        client.add_file("f1", "Docs/a.txt", b"alpha");
+        client.add_file("f2", "Photos/b.jpg", b"beta");
+        client.push_event("move", "folder", "d1", moved_to("Archive/Docs"));
+        client.push_event("delete", "file", "f2", FileData::default());
+
+        let mut tree = RemoteTree::default();
+        for event in &client.state().events {
+            tree.apply(event);
+        }
+        assert_eq!(tree.children(""), vec!["Archive", "Photos"]);
+        assert_eq!(tree.children("Archive/Docs"), vec!["a.txt"]);
+        let file = tree.get("Archive/Docs/a.txt").unwrap();
+        assert_eq!((file.id.as_deref(), file.size), (Some("f1"), 5));
+        assert!(tree.get("Docs/a.txt").is_none());
+        assert!(tree.children("Photos").is_empty());
+    }
+
+    #[test]
+    fn test_reads_download_once_into_the_cache() {
+        let client = Arc::new(MockApiClient::new());
+        client.add_file("f1", "notes.txt", b"hello world");
+        let cache = tempfile::tempdir().unwrap();
+        let drive = VirtualDrive::new(client.clone(), cache.path().to_path_buf(), 1024).unwrap();
+        drive.refresh().unwrap();
+
+        assert_eq!(drive.list("").len(), 1);
+        assert_eq!(drive.read("notes.txt", 6, 100).unwrap(), b"world");
+        assert_eq!(drive.read("notes.txt", 0, 5).unwrap(), b"hello");
+        assert_eq!(client.state().downloads, vec!["f1"]);
+        assert!(drive.read("missing.txt", 0, 5).is_err());
+    }
+}
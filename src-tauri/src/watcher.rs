@@ -0,0 +1,623 @@
+//! Change notifications for the sync root and auto-upload sources.
+//!
+//! Uses the platform watcher (inotify, FSEvents, ReadDirectoryChangesW) and
+//! falls back to polling when it cannot cover the whole tree. On Linux every
+//! directory takes one of the `fs.inotify.max_user_watches` watches, and once
+//! they run out inotify silently misses changes in the unwatched directories.
+//! Network and FUSE mounts (NFS, SMB, sshfs) deliver no events for changes
+//! made elsewhere at all, so [`WatchMode::Auto`] polls those from the start.
+
+use crate::config::WatchMode;
+use notify::{
+    ErrorKind, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Receives the events of whichever watcher is active.
+pub type EventHandler = Arc<dyn Fn(notify::Result<Event>) + Send + Sync>;
+
+/// Shown to Linux users once the watches ran out.
+pub const WATCH_LIMIT_HINT: &str = "sudo sysctl fs.inotify.max_user_watches=524288";
+
+/// Longest an editor save may take before its temp file counts as a file of
+/// its own.
+pub const MAX_SAVE_DURATION: Duration = Duration::from_secs(30);
+/// How long a change made by the sync is remembered for the events it
+/// causes. Polling reports them up to a poll interval late.
+const OWN_CHANGE_TTL: Duration = Duration::from_secs(10 * 60);
+
+pub struct FolderWatcher {
+    root: PathBuf,
+    poll_interval: Duration,
+    handler: EventHandler,
+    // Events arrive for as long as this is alive
+    inner: Box<dyn Watcher + Send>,
+    polling: bool,
+    watch_limit_hit: bool,
+}
+
+impl FolderWatcher {
+    /// Watches `root` recursively. Also polls if the platform watcher runs out
+    /// of watches.
+    pub fn start(
+        root: &Path,
+        mode: WatchMode,
+        poll_interval_secs: u64,
+        handler: EventHandler,
+    ) -> notify::Result<Self> {
+        let poll_interval = Duration::from_secs(poll_interval_secs.max(1));
+        let poll_requested = match mode {
+            WatchMode::Auto => match remote_fs_type(root) {
+                Some(fs_type) => {
+                    tracing::info!("{:?} is on {}, polling for changes", root, fs_type);
+                    true
+                }
+                None => false,
+            },
+            WatchMode::Native => false,
+            WatchMode::Polling => true,
+        };
+        if poll_requested {
+            return Ok(Self {
+                root: root.to_path_buf(),
+                poll_interval,
+                inner: poll(root, poll_interval, &handler)?,
+                handler,
+                polling: true,
+                watch_limit_hit: false,
+            });
+        }
+        let (inner, polling) = match native(root, &handler) {
+            Ok(inner) => (inner, false),
+            Err(e) if is_watch_limit(&e) => {
+                tracing::warn!(
+                    "Out of file watches for {:?} ({}), polling every {:?} instead. Raise the limit with: {}",
+                    root,
+                    e,
+                    poll_interval,
+                    WATCH_LIMIT_HINT
+                );
+                (poll(root, poll_interval, &handler)?, true)
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            root: root.to_path_buf(),
+            poll_interval,
+            handler,
+            inner,
+            polling,
+            watch_limit_hit: polling,
+        })
+    }
+
+    pub fn is_polling(&self) -> bool {
+        self.polling
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// Whether polling is used because the platform watcher ran out of watches.
+    pub fn watch_limit_hit(&self) -> bool {
+        self.watch_limit_hit
+    }
+
+    /// Watches the root again after it was unmounted or replaced, which ends
+    /// the platform watcher's watches.
+    pub fn restart(&mut self) -> notify::Result<()> {
+        self.inner = if self.polling {
+            poll(&self.root, self.poll_interval, &self.handler)?
+        } else {
+            native(&self.root, &self.handler)?
+        };
+        Ok(())
+    }
+
+    /// Switches to polling after the platform watcher ran out of watches for a
+    /// directory created later on. Changes in that directory may have been
+    /// missed, so the caller should rescan.
+    pub fn fall_back_to_polling(&mut self) -> notify::Result<()> {
+        if self.polling {
+            return Ok(());
+        }
+        // Release the inotify watches before the poller starts its own scan
+        let _ = self.inner.unwatch(&self.root);
+        self.inner = poll(&self.root, self.poll_interval, &self.handler)?;
+        self.polling = true;
+        self.watch_limit_hit = true;
+        tracing::warn!(
+            "Out of file watches for {:?}, polling every {:?} instead. Raise the limit with: {}",
+            self.root,
+            self.poll_interval,
+            WATCH_LIMIT_HINT
+        );
+        Ok(())
+    }
+}
+
+/// Editor saves that have written their temp file but not yet renamed it over
+/// the document. Many editors save this way, which shows up as a burst of
+/// create, modify and rename events; the sync waits for the rename and
+/// uploads only the final document, never the temp file.
+#[derive(Debug, Default)]
+pub struct PendingSaves {
+    temp_files: HashMap<PathBuf, Instant>,
+}
+
+impl PendingSaves {
+    pub fn record(&mut self, event: &Event) {
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            return;
+        }
+        // Renames report both names, the old one no longer exists
+        for path in &event.paths {
+            if !path.exists() {
+                self.temp_files.remove(path);
+            } else if is_save_temp(path) {
+                self.temp_files
+                    .entry(path.clone())
+                    .or_insert_with(Instant::now);
+            }
+        }
+    }
+
+    /// Whether a temp file is still waiting to be renamed. Saves taking
+    /// longer than [`MAX_SAVE_DURATION`] are no longer waited for.
+    pub fn in_progress(&mut self) -> bool {
+        self.temp_files
+            .retain(|path, since| since.elapsed() < MAX_SAVE_DURATION && path.exists());
+        !self.temp_files.is_empty()
+    }
+
+    /// Whether `path` is the temp file of a save in progress.
+    pub fn is_temp(&self, path: &Path) -> bool {
+        self.temp_files
+            .get(path)
+            .is_some_and(|since| since.elapsed() < MAX_SAVE_DURATION)
+    }
+}
+
+/// Whether `path` is named like the file an editor writes before renaming it
+/// over the document it saves.
+pub fn is_save_temp(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    // Vim's write test, GTK, atomic-write libraries, Word, JetBrains IDEs
+    // and backups of the previous version
+    if name == "4913"
+        || name.starts_with(".goutputstream-")
+        || name.starts_with(".tmp")
+        || name.starts_with("~WR")
+        || name.ends_with("___jb_tmp___")
+        || name.ends_with("___jb_old___")
+        || name.ends_with('~')
+    {
+        return true;
+    }
+    // The document's name with a suffix: `.report.txt.x1Y2`,
+    // `report.txt.tmp1234`, or Qt's `report.txt.aB3dE9`
+    let (hidden, rest) = match name.strip_prefix('.') {
+        Some(rest) => (true, rest),
+        None => (false, name),
+    };
+    let Some(dir) = path.parent() else {
+        return false;
+    };
+    rest.match_indices('.').any(|(i, _)| {
+        let suffix = &rest[i + 1..];
+        let temp_suffix = hidden || suffix.starts_with("tmp") || is_random_suffix(suffix);
+        i > 0 && temp_suffix && dir.join(&rest[..i]).is_file()
+    })
+}
+
+/// Six random letters and digits, unlike a word such as `backup`.
+fn is_random_suffix(suffix: &str) -> bool {
+    suffix.len() == 6
+        && suffix.chars().all(|c| c.is_ascii_alphanumeric())
+        && !suffix.chars().all(|c| c.is_ascii_lowercase())
+}
+
+/// Files and folders the sync itself is changing. Their events are dropped
+/// while the change runs and afterwards for as long as the path is the way
+/// the sync left it, so the user's own changes, even to the same files, still
+/// get through while a sync runs.
+#[derive(Debug, Default)]
+pub struct OwnChanges {
+    paths: Mutex<HashMap<PathBuf, OwnChange>>,
+}
+
+#[derive(Debug)]
+enum OwnChange {
+    Running,
+    Done {
+        state: Option<FileState>,
+        at: SystemTime,
+    },
+}
+
+/// Size and modification time; `None` for a missing path.
+type FileState = (u64, Option<SystemTime>);
+
+fn file_state(path: &Path) -> Option<FileState> {
+    fs::symlink_metadata(path)
+        .ok()
+        .map(|metadata| (metadata.len(), metadata.modified().ok()))
+}
+
+impl OwnChanges {
+    /// Marks `path` and everything below it as changed by the sync until the
+    /// guard is dropped.
+    pub fn change(&self, path: &Path) -> OwnChangeGuard<'_> {
+        self.paths
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), OwnChange::Running);
+        OwnChangeGuard {
+            changes: self,
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Remembers how `path` and its folder look once the sync is done, as
+    /// adding or removing an entry changes the folder too.
+    fn finish(&self, path: &Path) {
+        let at = SystemTime::now();
+        let mut paths = self.paths.lock().unwrap();
+        for changed in std::iter::once(path).chain(path.parent()) {
+            if changed != path && matches!(paths.get(changed), Some(OwnChange::Running)) {
+                continue;
+            }
+            let state = file_state(changed);
+            paths.insert(changed.to_path_buf(), OwnChange::Done { state, at });
+        }
+    }
+
+    /// Whether an event for `path` was caused by the sync: it or a folder
+    /// above it is being changed, it is the way the sync left it, or the
+    /// nearest folder the sync changed is and `path` was not modified since.
+    pub fn is_own(&self, path: &Path) -> bool {
+        let paths = self.paths.lock().unwrap();
+        if paths.is_empty() {
+            return false;
+        }
+        let changes = || {
+            path.ancestors()
+                .filter_map(|changed| paths.get(changed).map(|change| (changed, change)))
+        };
+        if changes().any(|(_, change)| matches!(change, OwnChange::Running)) {
+            return true;
+        }
+        if let Some(OwnChange::Done { state, .. }) = paths.get(path) {
+            if file_state(path) == *state {
+                return true;
+            }
+        }
+        let nearest = changes().find(|(changed, _)| *changed != path);
+        let Some((folder, OwnChange::Done { state, at })) = nearest else {
+            return false;
+        };
+        file_state(folder) == *state
+            && file_state(path)
+                .and_then(|(_, modified)| modified)
+                .is_none_or(|modified| modified <= *at)
+    }
+
+    /// Forgets finished changes older than [`OWN_CHANGE_TTL`].
+    pub fn prune(&self) {
+        self.paths.lock().unwrap().retain(|_, change| match change {
+            OwnChange::Running => true,
+            OwnChange::Done { at, .. } => at.elapsed().is_ok_and(|age| age < OWN_CHANGE_TTL),
+        });
+    }
+}
+
+pub struct OwnChangeGuard<'a> {
+    changes: &'a OwnChanges,
+    path: PathBuf,
+}
+
+impl Drop for OwnChangeGuard<'_> {
+    fn drop(&mut self) {
+        self.changes.finish(&self.path);
+    }
+}
+
+/// Whether `error` means the platform watcher ran out of watches or inotify
+/// instances.
+pub fn is_watch_limit(error: &notify::Error) -> bool {
+    match &error.kind {
+        ErrorKind::MaxFilesWatch => true,
+        // ENOSPC from inotify_add_watch, EMFILE from inotify_init
+        ErrorKind::Io(e) => cfg!(target_os = "linux") && matches!(e.raw_os_error(), Some(28 | 24)),
+        _ => false,
+    }
+}
+
+/// Filesystem type of `root` if it is a network or FUSE mount, where the
+/// platform watcher does not see changes made by other machines.
+pub fn remote_fs_type(root: &Path) -> Option<String> {
+    let root = root.canonicalize().ok()?;
+    let fs_type = fs_type(&root)?;
+    is_remote_fs(&fs_type).then_some(fs_type)
+}
+
+fn is_remote_fs(fs_type: &str) -> bool {
+    const REMOTE: &[&str] = &[
+        "nfs",
+        "nfs4",
+        "cifs",
+        "smb3",
+        "smbfs",
+        "afpfs",
+        "webdav",
+        "davfs",
+        "9p",
+        "afs",
+        "ceph",
+        "glusterfs",
+        "lustre",
+        "sshfs",
+        "osxfuse",
+        "macfuse",
+    ];
+    // fuseblk is a local disk driver (ntfs-3g, exfat)
+    REMOTE.contains(&fs_type) || (fs_type.starts_with("fuse") && fs_type != "fuseblk")
+}
+
+#[cfg(target_os = "linux")]
+fn fs_type(path: &Path) -> Option<String> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    mountinfo_fs_type(&mountinfo, path)
+}
+
+#[cfg(target_os = "macos")]
+fn fs_type(path: &Path) -> Option<String> {
+    let output = std::process::Command::new("/sbin/mount").output().ok()?;
+    mount_output_fs_type(&String::from_utf8_lossy(&output.stdout), path)
+}
+
+/// Only UNC paths are recognized; mapped network drives need
+/// [`WatchMode::Polling`] in the config.
+#[cfg(windows)]
+fn fs_type(path: &Path) -> Option<String> {
+    use std::path::{Component, Prefix};
+    match path.components().next()? {
+        Component::Prefix(prefix) => match prefix.kind() {
+            Prefix::UNC(..) | Prefix::VerbatimUNC(..) => Some("smb".to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn fs_type(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Type of the mount containing `path`, from `/proc/self/mountinfo` lines like
+/// `36 35 0:32 / /mnt/share rw,relatime shared:1 - nfs4 server:/ rw`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn mountinfo_fs_type(mountinfo: &str, path: &Path) -> Option<String> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (mount, rest) = line.split_once(" - ")?;
+            let mount_point = unescape_mount_path(mount.split(' ').nth(4)?);
+            let fs_type = rest.split(' ').next()?;
+            Some((PathBuf::from(mount_point), fs_type))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        // Later mounts hide earlier ones on the same mount point
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, fs_type)| fs_type.to_string())
+}
+
+/// Type of the mount containing `path`, from `mount` output lines like
+/// `//user@server/share on /Volumes/share (smbfs, nodev, nosuid)`.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn mount_output_fs_type(output: &str, path: &Path) -> Option<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (_, rest) = line.split_once(" on ")?;
+            let (mount_point, options) = rest.rsplit_once(" (")?;
+            let fs_type = options.split([',', ')']).next()?;
+            Some((PathBuf::from(mount_point), fs_type))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, fs_type)| fs_type.to_string())
+}
+
+/// mountinfo escapes space, tab, newline and backslash as octal `\NNN`.
+fn unescape_mount_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        let code = rest
+            .get(i + 1..i + 4)
+            .and_then(|c| u8::from_str_radix(c, 8).ok());
+        match code {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn native(root: &Path, handler: &EventHandler) -> notify::Result<Box<dyn Watcher + Send>> {
+    let handler = Arc::clone(handler);
+    let mut watcher = RecommendedWatcher::new(move |res| handler(res), notify::Config::default())?;
+    watcher.watch(root, RecursiveMode::Recursive)?;
+    Ok(Box::new(watcher))
+}
+
+fn poll(
+    root: &Path,
+    interval: Duration,
+    handler: &EventHandler,
+) -> notify::Result<Box<dyn Watcher + Send>> {
+    let handler = Arc::clone(handler);
+    let config = notify::Config::default().with_poll_interval(interval);
+    let mut watcher = PollWatcher::new(move |res| handler(res), config)?;
+    watcher.watch(root, RecursiveMode::Recursive)?;
+    Ok(Box::new(watcher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_watch_limit_errors() {
+        assert!(is_watch_limit(&notify::Error::new(
+            ErrorKind::MaxFilesWatch
+        )));
+        assert!(!is_watch_limit(&notify::Error::path_not_found()));
+        let enospc = std::io::Error::from_raw_os_error(28);
+        assert_eq!(
+            is_watch_limit(&notify::Error::io(enospc)),
+            cfg!(target_os = "linux")
+        );
+    }
+
+    #[test]
+    fn test_polling_fallback_sees_new_files() {
+        let root = tempfile::tempdir().unwrap();
+        let (tx, rx) = channel();
+        let handler: EventHandler = Arc::new(move |res| {
+            if let Ok(event) = res {
+                let _ = tx.send(event);
+            }
+        });
+        let mut watcher = FolderWatcher::start(root.path(), WatchMode::Native, 1, handler).unwrap();
+        watcher.fall_back_to_polling().unwrap();
+        assert!(watcher.is_polling() && watcher.watch_limit_hit());
+
+        std::fs::write(root.path().join("new.txt"), b"x").unwrap();
+        let event = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(event.paths.iter().any(|p| p.ends_with("new.txt")));
+    }
+
+    #[test]
+    fn test_editor_save_is_tracked_until_renamed() {
+        use notify::event::{CreateKind, ModifyKind, RenameMode};
+        let dir = tempfile::tempdir().unwrap();
+        let doc = dir.path().join("report.txt");
+        std::fs::write(&doc, b"v1").unwrap();
+        for name in [
+            ".goutputstream-X1Y2Z3",
+            "report.txt.tmp4711",
+            "report.txt.aB3dE9",
+        ] {
+            assert!(is_save_temp(&dir.path().join(name)), "{}", name);
+        }
+        for name in [
+            "report.txt",
+            "notes.txt.tmp4711",
+            "report.txt.backup",
+            "report",
+        ] {
+            assert!(!is_save_temp(&dir.path().join(name)), "{}", name);
+        }
+
+        let mut saves = PendingSaves::default();
+        let temp = dir.path().join(".report.txt.x1Y2");
+        std::fs::write(&temp, b"v2").unwrap();
+        saves.record(&Event::new(EventKind::Create(CreateKind::File)).add_path(temp.clone()));
+        saves.record(&Event::new(EventKind::Create(CreateKind::File)).add_path(doc.clone()));
+        assert!(saves.in_progress() && saves.is_temp(&temp) && !saves.is_temp(&doc));
+
+        std::fs::rename(&temp, &doc).unwrap();
+        saves.record(
+            &Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+                .add_path(temp.clone())
+                .add_path(doc.clone()),
+        );
+        assert!(!saves.in_progress() && !saves.is_temp(&temp));
+    }
+
+    #[test]
+    fn test_own_changes_let_user_edits_through() {
+        let dir = tempfile::tempdir().unwrap();
+        let own = OwnChanges::default();
+        let doc = dir.path().join("doc.txt");
+        let folder = dir.path().join("Photos");
+        let photo = folder.join("cat.jpg");
+        assert!(!own.is_own(&doc));
+
+        {
+            let _own = own.change(&doc);
+            assert!(own.is_own(&doc));
+            std::fs::write(&doc, b"from server").unwrap();
+        }
+        {
+            let _own = own.change(&folder);
+            std::fs::create_dir(&folder).unwrap();
+            std::fs::write(&photo, b"jpeg").unwrap();
+        }
+        assert!(own.is_own(&doc) && own.is_own(&folder) && own.is_own(&photo));
+
+        // Edited by the user after the sync wrote it
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::write(&doc, b"user edit, longer").unwrap();
+        assert!(!own.is_own(&doc));
+        std::fs::write(&photo, b"retouched").unwrap();
+        assert!(!own.is_own(&photo));
+        let added = folder.join("dog.jpg");
+        std::fs::write(&added, b"jpeg").unwrap();
+        assert!(!own.is_own(&added));
+        std::fs::remove_file(&photo).unwrap();
+        assert!(!own.is_own(&photo));
+
+        // Recent changes survive pruning
+        own.prune();
+        assert!(!own.paths.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mount_lookup() {
+        let mountinfo = "\
+22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw
+36 22 0:32 / /mnt/nas rw,relatime shared:2 - nfs4 nas:/export rw
+37 22 0:33 / /home/me/remote\\040box rw,nosuid shared:3 - fuse.sshfs me@box: rw
+38 22 8:17 / /media/usb rw shared:4 - fuseblk /dev/sdb1 rw
+";
+        let lookup = |path: &str| mountinfo_fs_type(mountinfo, Path::new(path)).unwrap();
+        assert_eq!(lookup("/home/me/Xynoxa"), "ext4");
+        assert_eq!(lookup("/mnt/nas/team"), "nfs4");
+        assert_eq!(lookup("/home/me/remote box/docs"), "fuse.sshfs");
+        assert_eq!(lookup("/mnt/nasty"), "ext4");
+
+        assert!(is_remote_fs("nfs4") && is_remote_fs("fuse.sshfs"));
+        assert!(!is_remote_fs("ext4") && !is_remote_fs("fuseblk"));
+
+        let mount = "\
+/dev/disk3s1 on / (apfs, local, journaled)
+//me@nas/share on /Volumes/share (smbfs, nodev, nosuid, mounted by me)
+";
+        let lookup = |path: &str| mount_output_fs_type(mount, Path::new(path)).unwrap();
+        assert_eq!(lookup("/Volumes/share/Xynoxa"), "smbfs");
+        assert_eq!(lookup("/Users/me/Xynoxa"), "apfs");
+    }
+}
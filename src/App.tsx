@@ -1,10 +1,25 @@
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { getCurrentWindow } from "@tauri-apps/api/window";
 import Setup from "./pages/Setup";
 import Dashboard from "./pages/Dashboard";
+import Conflicts from "./pages/Conflicts";
+import Transfers from "./pages/Transfers";
+import Trash from "./pages/Trash";
 import "./App.css";
 
+// Auxiliary windows load the same bundle and are told apart by their label
+const windowLabel = getCurrentWindow().label;
+
 function App() {
+  if (windowLabel === "conflicts") return <Conflicts />;
+  if (windowLabel === "transfers") return <Transfers />;
+  if (windowLabel === "trash") return <Trash />;
+  return <MainWindow />;
+}
+
+function MainWindow() {
   const [loading, setLoading] = useState(true);
   const [setupComplete, setSetupComplete] = useState(false);
 
@@ -12,6 +27,14 @@ function App() {
     checkConfig();
   }, []);
 
+  // Server revoked this device: backend already stopped sync and signed out
+  useEffect(() => {
+    const unlisten = listen("session://revoked", () => setSetupComplete(false));
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
   async function checkConfig() {
     try {
       const config: any = await invoke("get_config");
@@ -47,7 +70,7 @@ function App() {
       // Reset setup? Or just logout? Requirement says "Configurable...". 
       // For disconnect, we might want to clear config.
       invoke("logout");
-      invoke("save_config", { completed: false }).then(() => setSetupComplete(false));
+      invoke("save_config", { patch: { setupCompleted: false } }).then(() => setSetupComplete(false));
     }} />
   );
 }
@@ -4,3 +4,82 @@ import { twMerge } from "tailwind-merge"
 export function cn(...inputs: ClassValue[]) {
     return twMerge(clsx(inputs))
 }
+
+// Backend commands reject with { code, message, status }; older paths may still throw strings.
+export function errorMessage(err: unknown): string {
+    if (typeof err === "string") return err
+    if (err && typeof err === "object" && "message" in err) {
+        return String((err as { message: unknown }).message)
+    }
+    return String(err)
+}
+
+// Stable `code` of a backend error, e.g. "unavailable"; empty for plain strings.
+export function errorCode(err: unknown): string {
+    if (err && typeof err === "object" && "code" in err) {
+        return String((err as { code: unknown }).code)
+    }
+    return ""
+}
+
+export interface FieldError {
+    field: string
+    message: string
+}
+
+// Per-field messages of a `validation` error, keyed by ConfigPatch field name.
+export function fieldErrors(err: unknown): Record<string, string> {
+    const fields = Array.isArray(err)
+        ? err
+        : err && typeof err === "object" && "fields" in err
+            ? (err as { fields: FieldError[] }).fields
+            : []
+    return Object.fromEntries(fields.map((f: FieldError) => [f.field, f.message]))
+}
+
+// Payload of auth://two-factor, also attached to `two_factor_required` errors.
+export interface TwoFactorChallenge {
+    challengeId: string
+    methods: string[]
+    webauthnOptions: Record<string, any> | null
+}
+
+export function twoFactorChallenge(err: unknown): TwoFactorChallenge | null {
+    if (err && typeof err === "object" && "challenge" in err) {
+        return (err as { challenge: TwoFactorChallenge }).challenge
+    }
+    return null
+}
+
+const fromBase64Url = (value: string) =>
+    Uint8Array.from(atob(value.replace(/-/g, "+").replace(/_/g, "/")), (c) => c.charCodeAt(0))
+
+const toBase64Url = (buffer: ArrayBuffer) =>
+    btoa(String.fromCharCode(...new Uint8Array(buffer))).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "")
+
+// Runs the WebAuthn ceremony and serializes the assertion for the server.
+export async function getAssertion(options: Record<string, any>) {
+    const credential = (await navigator.credentials.get({
+        publicKey: {
+            ...options,
+            challenge: fromBase64Url(options.challenge),
+            allowCredentials: (options.allowCredentials ?? []).map((c: { id: string; type: string }) => ({
+                ...c,
+                id: fromBase64Url(c.id),
+            })),
+        } as PublicKeyCredentialRequestOptions,
+    })) as PublicKeyCredential | null
+    if (!credential) throw new Error("No security key response")
+    const response = credential.response as AuthenticatorAssertionResponse
+    return {
+        id: credential.id,
+        rawId: toBase64Url(credential.rawId),
+        type: credential.type,
+        response: {
+            clientDataJSON: toBase64Url(response.clientDataJSON),
+            authenticatorData: toBase64Url(response.authenticatorData),
+            signature: toBase64Url(response.signature),
+            userHandle: response.userHandle ? toBase64Url(response.userHandle) : null,
+        },
+    }
+}
@@ -0,0 +1,109 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { Button } from "@/components/ui/button";
+import { errorMessage } from "@/lib/utils";
+
+// Answer of list_conflicts
+interface ConflictVersion {
+    path: string;
+    size: number;
+    modified: number | null;
+    hash: string | null;
+}
+
+interface Conflict {
+    local: ConflictVersion;
+    server: ConflictVersion | null;
+}
+
+type Resolution = "keepServer" | "keepLocal" | "keepBoth";
+
+const formatDate = (secs: number | null) => (secs ? new Date(secs * 1000).toLocaleString() : "unknown");
+
+function VersionCard({ title, version }: { title: string; version: ConflictVersion | null }) {
+    return (
+        <div className="flex-1 min-w-0 rounded-lg border border-zinc-800 bg-zinc-900/60 p-3 text-xs">
+            <div className="mb-2 font-medium uppercase tracking-wider text-zinc-500">{title}</div>
+            {version ? (
+                <div className="space-y-1">
+                    <button
+                        onClick={() => invoke("reveal_in_file_manager", { path: version.path }).catch(console.error)}
+                        className="block w-full truncate text-left font-mono text-zinc-200 hover:text-white"
+                        title="Show in folder"
+                    >
+                        {version.path}
+                    </button>
+                    <div className="text-zinc-400">{version.size.toLocaleString()} bytes</div>
+                    <div className="text-zinc-400">Modified {formatDate(version.modified)}</div>
+                    <div className="truncate font-mono text-zinc-600" title={version.hash ?? ""}>
+                        {version.hash?.slice(0, 16) ?? "-"}
+                    </div>
+                </div>
+            ) : (
+                <div className="text-zinc-500">No longer exists</div>
+            )}
+        </div>
+    );
+}
+
+// Rendered in the "conflicts" window opened by open_conflict_resolver
+export default function Conflicts() {
+    const [conflicts, setConflicts] = useState<Conflict[]>([]);
+    const [error, setError] = useState("");
+
+    const load = async () => {
+        try {
+            setConflicts(await invoke<Conflict[]>("list_conflicts"));
+        } catch (e) {
+            setError(errorMessage(e));
+        }
+    };
+
+    useEffect(() => {
+        load();
+        const unlisten = listen("sync://conflict", load);
+        return () => {
+            unlisten.then((f) => f());
+        };
+    }, []);
+
+    const resolve = async (path: string, resolution: Resolution) => {
+        setError("");
+        try {
+            await invoke("resolve_conflict", { path, resolution });
+        } catch (e) {
+            setError(errorMessage(e));
+        }
+        load();
+    };
+
+    return (
+        <div className="min-h-screen bg-zinc-950 p-4 text-white">
+            <h1 className="mb-4 text-lg font-semibold">Conflicts</h1>
+            {error && <div className="mb-3 text-sm text-red-400">{error}</div>}
+            {conflicts.length === 0 && <p className="text-sm text-zinc-500">No unresolved conflicts.</p>}
+            <div className="space-y-4">
+                {conflicts.map((c) => (
+                    <div key={c.local.path} className="space-y-2">
+                        <div className="flex gap-3">
+                            <VersionCard title="Your version" version={c.local} />
+                            <VersionCard title="Server version" version={c.server} />
+                        </div>
+                        <div className="flex justify-end gap-2">
+                            <Button size="sm" variant="secondary" onClick={() => resolve(c.local.path, "keepLocal")}>
+                                Keep mine
+                            </Button>
+                            <Button size="sm" variant="secondary" onClick={() => resolve(c.local.path, "keepServer")}>
+                                Keep server
+                            </Button>
+                            <Button size="sm" onClick={() => resolve(c.local.path, "keepBoth")} className="bg-cyan-500 hover:bg-cyan-600">
+                                Keep both
+                            </Button>
+                        </div>
+                    </div>
+                ))}
+            </div>
+        </div>
+    );
+}
@@ -1,19 +1,429 @@
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { getVersion } from "@tauri-apps/api/app";
+import { getCurrentWebview } from "@tauri-apps/api/webview";
 import logo from "@/assets/xynoxa-logo-dark.png";
+import { errorCode, errorMessage } from "@/lib/utils";
 
-type SyncState = "idle" | "pulling" | "pushing" | "syncing";
+type SyncState = "idle" | "pulling" | "pushing" | "syncing" | "error" | "stopped" | "unavailable" | "paused";
+
+// A search_remote hit; localPath and status are null for files not on this device
+interface RemoteSearchResult {
+    id: string;
+    name: string;
+    path: string | null;
+    snippet: string | null;
+    localPath: string | null;
+    status: SearchResult["status"] | null;
+}
+
+// Payloads of the sync://* events emitted by the sync worker
+interface FileEvent {
+    path: string;
+    direction: "upload" | "download";
+    error?: string | null;
+}
+
+interface Transfer {
+    path: string;
+    direction: "upload" | "download";
+    size: number | null;
+    state: "queued" | "active" | "failed" | "cancelled";
+    error: string | null;
+    priority: number;
+}
+
+// Answer of get_session_info, also the payload of session://expiring
+interface SessionInfo {
+    user: { id: string; name: string | null; email: string | null };
+    scopes: string[];
+    expiresAt: string | null;
+}
+
+// A file whose content changed without being saved (bit rot, interrupted write)
+interface SyncIssue {
+    path: string;
+    expectedHash: string;
+    actualHash: string;
+    detectedAt: number;
+}
+
+// A failure that keeps recurring, from list_known_issues
+interface KnownIssue {
+    key: string;
+    path: string | null;
+    message: string;
+    count: number;
+    firstSeen: number;
+    lastSeen: number;
+}
+
+// Month totals from get_bandwidth_usage; cap in bytes
+interface BandwidthUsage {
+    monthUploaded: number;
+    monthDownloaded: number;
+    cap: number | null;
+    capped: boolean;
+}
+
+// One check of run_diagnostics; detail is already translated
+interface DiagnosticCheck {
+    name: string;
+    status: "pass" | "warn" | "fail" | "skipped";
+    detail: string;
+}
+
+const CHECK_LABELS: Record<string, string> = {
+    server: "Server",
+    auth: "Sign-in",
+    sync_folder: "Sync folder",
+    disk_space: "Disk space",
+    watcher: "File watching",
+    keyring: "Keyring",
+    database: "Sync database",
+};
+
+const CHECK_COLORS: Record<DiagnosticCheck["status"], string> = {
+    pass: "text-green-400",
+    warn: "text-amber-400",
+    fail: "text-red-400",
+    skipped: "text-zinc-500",
+};
+
+// A stored server version of a file, from get_file_versions
+interface FileVersion {
+    version: number;
+    size: string | null;
+    createdAt: string | null;
+}
+
+// A get_file_list entry; tags and favorite mirror the web UI
+interface ListedFile {
+    id: string | null;
+    path: string;
+    hash: string;
+    tags: string[];
+    favorite: boolean;
+}
+
+interface FolderHealth {
+    path: string;
+    pending: number;
+    failed: number;
+    conflicts: number;
+    branches: FolderHealth[];
+}
+
+// config::DEMO_SERVER_URL, set by the --demo flag
+const DEMO_SERVER_URL = "demo://xynoxa";
+
+const EXPIRY_WARNING_MS = 3 * 24 * 60 * 60 * 1000;
+
+interface SearchResult {
+    id: string | null;
+    path: string;
+    hash: string;
+    status: "synced" | "pending" | "queued" | "transferring" | "failed" | "skipped";
+    pinned: boolean;
+}
+
+// Server or local preview from get_thumbnail; renders nothing if there is none
+function Thumbnail({ fileId }: { fileId: string }) {
+    const [url, setUrl] = useState("");
+
+    useEffect(() => {
+        let objectUrl = "";
+        invoke<ArrayBuffer>("get_thumbnail", { fileId })
+            .then((bytes) => {
+                objectUrl = URL.createObjectURL(new Blob([bytes]));
+                setUrl(objectUrl);
+            })
+            .catch(() => setUrl(""));
+        return () => {
+            if (objectUrl) URL.revokeObjectURL(objectUrl);
+        };
+    }, [fileId]);
+
+    return url ? <img src={url} alt="" className="w-6 h-6 rounded object-cover shrink-0" /> : null;
+}
+
+const formatSize = (bytes: number | null) => {
+    if (bytes === null) return "";
+    const units = ["B", "KB", "MB", "GB", "TB"];
+    let value = bytes;
+    let unit = 0;
+    while (value >= 1024 && unit < units.length - 1) {
+        value /= 1024;
+        unit++;
+    }
+    return `${value.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+};
 
 export default function Dashboard({ onLogout }: { onLogout: () => void }) {
     const [syncStatus, setSyncStatus] = useState<SyncState>("idle");
     const [syncPath, setSyncPath] = useState("");
+    const [demo, setDemo] = useState(false);
     const [appVersion, setAppVersion] = useState("");
+    const [currentFile, setCurrentFile] = useState("");
+    const [lastError, setLastError] = useState("");
+    const [warning, setWarning] = useState("");
+    const [transfers, setTransfers] = useState<Transfer[]>([]);
+    const [query, setQuery] = useState("");
+    const [results, setResults] = useState<SearchResult[]>([]);
+    const [remoteResults, setRemoteResults] = useState<RemoteSearchResult[] | null>(null);
+    const [dragging, setDragging] = useState(false);
+    const [dropProgress, setDropProgress] = useState("");
+    const [session, setSession] = useState<SessionInfo | null>(null);
+    const [conflictCount, setConflictCount] = useState(0);
+    const [issues, setIssues] = useState<SyncIssue[]>([]);
+    const [knownIssues, setKnownIssues] = useState<KnownIssue[]>([]);
+    const [diagnostics, setDiagnostics] = useState<DiagnosticCheck[] | null>(null);
+    const [bandwidth, setBandwidth] = useState<BandwidthUsage | null>(null);
+    const [capInput, setCapInput] = useState("");
+    const [encryptDatabase, setEncryptDatabase] = useState(false);
+    const [converting, setConverting] = useState(false);
+    const [starred, setStarred] = useState<ListedFile[] | null>(null);
+    const [health, setHealth] = useState<FolderHealth | null>(null);
+    const [movedTo, setMovedTo] = useState<string | null>(null);
+    const [serverChanged, setServerChanged] = useState(false);
+    const [history, setHistory] = useState<{ file: SearchResult; versions: FileVersion[] } | null>(null);
+
+    // Files dropped onto the window go to the root of the sync folder
+    useEffect(() => {
+        const unlistenDrop = getCurrentWebview().onDragDropEvent((e) => {
+            if (e.payload.type === "enter" || e.payload.type === "over") {
+                setDragging(true);
+            } else if (e.payload.type === "leave") {
+                setDragging(false);
+            } else if (e.payload.type === "drop") {
+                setDragging(false);
+                if (e.payload.paths.length > 0) {
+                    invoke("upload_paths", { paths: e.payload.paths, remoteFolderId: null }).catch((err) =>
+                        setLastError(errorMessage(err))
+                    );
+                }
+            }
+        });
+        const unlistenProgress = listen<{ path: string; done: number; total: number; error: string | null }>(
+            "upload://progress",
+            (e) => {
+                const { done, total, error } = e.payload;
+                if (error) setLastError(error);
+                setDropProgress(done < total ? `Adding ${done} of ${total}...` : "");
+            }
+        );
+        return () => {
+            unlistenDrop.then((f) => f());
+            unlistenProgress.then((f) => f());
+        };
+    }, []);
+
+    useEffect(() => {
+        if (!query.trim()) {
+            setResults([]);
+            return;
+        }
+        const timer = setTimeout(() => {
+            invoke<SearchResult[]>("search_files", { query })
+                .then(setResults)
+                .catch((e) => console.error("Search failed", e));
+        }, 200);
+        return () => clearTimeout(timer);
+    }, [query]);
+
+    const loadTransfers = async () => {
+        try {
+            setTransfers(await invoke<Transfer[]>("get_transfer_queue"));
+        } catch (e) {
+            console.error("Failed to load transfer queue", e);
+        }
+    };
+
+    const loadBandwidth = () =>
+        invoke<BandwidthUsage>("get_bandwidth_usage")
+            .then((usage) => {
+                setBandwidth(usage);
+                setCapInput(usage.cap ? String(Math.round(usage.cap / (1024 * 1024))) : "");
+            })
+            .catch((e) => console.warn("Bandwidth usage unavailable", e));
+
+    const saveBandwidthCap = async () => {
+        const capMb = parseInt(capInput, 10);
+        try {
+            await invoke("set_bandwidth_cap", { capMb: capMb > 0 ? capMb : null });
+        } catch (e) {
+            setLastError(errorMessage(e));
+        }
+        loadBandwidth();
+    };
+
+    // Stops syncing while the databases are rewritten
+    const toggleDatabaseEncryption = async (enabled: boolean) => {
+        setConverting(true);
+        try {
+            await invoke("set_database_encryption", { enabled });
+            setEncryptDatabase(enabled);
+        } catch (e) {
+            setLastError(errorMessage(e));
+        } finally {
+            setConverting(false);
+        }
+    };
+
+    const loadConflicts = () =>
+        invoke<unknown[]>("list_conflicts")
+            .then((list) => setConflictCount(list.length))
+            .catch((e) => console.warn("Failed to list conflicts", e));
+
+    const searchServer = async () => {
+        try {
+            setRemoteResults(await invoke<RemoteSearchResult[]>("search_remote", { query }));
+        } catch (e) {
+            setLastError(errorMessage(e));
+        }
+    };
+
+    const togglePin = async (r: SearchResult) => {
+        try {
+            await invoke(r.pinned ? "unpin_path" : "pin_path", { path: r.path });
+            setResults(await invoke<SearchResult[]>("search_files", { query }));
+        } catch (e) {
+            setLastError(errorMessage(e));
+        }
+    };
+
+    const loadIssues = () => {
+        invoke<SyncIssue[]>("list_sync_issues")
+            .then(setIssues)
+            .catch((e) => console.warn("Failed to list sync issues", e));
+        invoke<KnownIssue[]>("list_known_issues")
+            .then(setKnownIssues)
+            .catch((e) => console.warn("Failed to list known issues", e));
+    };
+
+    const showHistory = async (file: SearchResult) => {
+        if (!file.id) return;
+        try {
+            setHistory({ file, versions: await invoke<FileVersion[]>("get_file_versions", { fileId: file.id }) });
+        } catch (e) {
+            setLastError(errorMessage(e));
+        }
+    };
+
+    const restoreVersion = async (file: SearchResult, version: number) => {
+        if (!window.confirm(`Replace ${file.path} with version ${version}? The current content stays in the history.`)) return;
+        try {
+            await invoke("restore_version", { fileId: file.id, version });
+            setHistory(null);
+        } catch (e) {
+            setLastError(errorMessage(e));
+        }
+    };
+
+    const toggleStarred = async () => {
+        if (starred) {
+            setStarred(null);
+            return;
+        }
+        try {
+            const files = await invoke<ListedFile[]>("get_file_list");
+            setStarred(files.filter((f) => f.favorite).sort((a, b) => a.path.localeCompare(b.path)));
+        } catch (e) {
+            setLastError(errorMessage(e));
+        }
+    };
+
+    const showHealth = async (path: string) => {
+        try {
+            setHealth(await invoke<FolderHealth>("get_folder_health", { path }));
+        } catch (e) {
+            setLastError(errorMessage(e));
+        }
+    };
+
+    const rebuildIndex = async () => {
+        if (!window.confirm("Rebuild the sync database from the server? Local files that differ from the server are kept as conflict copies.")) return;
+        try {
+            const tracked = await invoke<number>("rebuild_index");
+            setWarning(`Sync database rebuilt: ${tracked} files and folders tracked`);
+        } catch (e) {
+            setLastError(errorMessage(e));
+        }
+    };
+
+    const repairIssue = async (path: string, action: "download" | "upload") => {
+        try {
+            await invoke("repair_sync_issue", { path, action });
+        } catch (e) {
+            setLastError(errorMessage(e));
+        }
+        loadIssues();
+    };
+
+    const changeTransfer = async (command: "cancel_transfer" | "retry_transfer" | "prioritize", path: string) => {
+        try {
+            await invoke(command, { path });
+        } catch (e) {
+            console.error(e);
+        }
+        loadTransfers();
+    };
 
     useEffect(() => {
         loadConfig();
         startSyncOnMount();
         loadVersion();
+        loadConflicts();
+        loadIssues();
+        invoke<SessionInfo>("get_session_info")
+            .then(setSession)
+            .catch((e) => console.warn("Session info unavailable", e));
+        const unlisten = listen<SessionInfo>("session://expiring", (e) => setSession(e.payload));
+        return () => {
+            unlisten.then((f) => f());
+        };
+    }, []);
+
+    const expiresAt = session?.expiresAt ? new Date(session.expiresAt) : null;
+    const expiringSoon = !!expiresAt && expiresAt.getTime() - Date.now() < EXPIRY_WARNING_MS;
+
+    useEffect(() => {
+        const unlisteners = [
+            listen<{ state: SyncState }>("sync://state", (e) => {
+                setSyncStatus(e.payload.state);
+                loadTransfers();
+                if (e.payload.state === "idle" || e.payload.state === "error") {
+                    loadIssues();
+                    loadBandwidth();
+                }
+                if (e.payload.state !== "error") setLastError("");
+                if (e.payload.state !== "syncing") setCurrentFile("");
+            }),
+            listen<FileEvent>("sync://file-started", (e) => {
+                setSyncStatus(e.payload.direction === "upload" ? "pushing" : "pulling");
+                setCurrentFile(e.payload.path);
+                loadTransfers();
+            }),
+            listen<FileEvent>("sync://file-finished", () => {
+                setSyncStatus("syncing");
+                setCurrentFile("");
+                loadTransfers();
+            }),
+            listen<{ code: string; message: string }>("sync://error", (e) => setLastError(e.payload.message)),
+            listen<{ code: string; message: string }>("sync://warning", (e) => {
+                setWarning(e.payload.message);
+                if (e.payload.code === "corrupted_file") loadIssues();
+            }),
+            listen<{ path: string; backup: string }>("sync://conflict", (e) => {
+                console.warn(`Conflict in ${e.payload.path}; local copy kept as ${e.payload.backup}`);
+                loadConflicts();
+            }),
+            listen("conflicts://resolved", loadConflicts),
+        ];
+        return () => {
+            unlisteners.forEach((u) => u.then((f) => f()));
+        };
     }, []);
 
     const loadConfig = async () => {
@@ -22,6 +432,8 @@ export default function Dashboard({ onLogout }: { onLogout: () => void }) {
             if (config.sync_path) {
                 setSyncPath(config.sync_path);
             }
+            setDemo(config.server_url === DEMO_SERVER_URL);
+            setEncryptDatabase(!!config.encrypt_database);
         } catch (e) {
             console.error("Failed to load config", e);
         }
@@ -29,12 +441,51 @@ export default function Dashboard({ onLogout }: { onLogout: () => void }) {
 
     const startSyncOnMount = async () => {
         try {
-            setSyncStatus("syncing");
             await invoke("start_sync");
-            setSyncStatus("idle");
         } catch (e) {
             console.error(e);
-            setSyncStatus("idle");
+            setLastError(errorMessage(e));
+            setServerChanged(errorCode(e) === "server_changed");
+            invoke<string | null>("find_moved_sync_folder").then(setMovedTo).catch(console.error);
+        }
+    };
+
+    const confirmServerChange = async (sameServer: boolean) => {
+        if (!sameServer && !window.confirm("Sync the folder with the new server from scratch? Local files are kept.")) return;
+        try {
+            await invoke("confirm_server_change", { sameServer });
+            setServerChanged(false);
+            setLastError("");
+            await invoke("start_sync");
+        } catch (e) {
+            setLastError(errorMessage(e));
+        }
+    };
+
+    const moveSyncFolder = async () => {
+        try {
+            const target = await invoke<string | null>("pick_sync_folder");
+            if (!target) return;
+            if (!window.confirm(`Move the sync folder to ${target}? Sync pauses while the files are moved.`)) return;
+            setWarning("Moving the sync folder...");
+            await invoke("move_sync_folder", { newPath: target });
+            setSyncPath(target);
+            setWarning("");
+        } catch (e) {
+            setWarning("");
+            setLastError(errorMessage(e));
+        }
+    };
+
+    const relinkSyncFolder = async () => {
+        if (!movedTo) return;
+        try {
+            await invoke("relink_sync_folder", { newPath: movedTo });
+            setSyncPath(movedTo);
+            setMovedTo(null);
+            setLastError("");
+        } catch (e) {
+            setLastError(errorMessage(e));
         }
     };
 
@@ -55,6 +506,14 @@ export default function Dashboard({ onLogout }: { onLogout: () => void }) {
                 return { text: "Uploading...", color: "text-cyan-400", dot: "bg-cyan-400 animate-pulse" };
             case "syncing":
                 return { text: "Syncing...", color: "text-amber-400", dot: "bg-amber-400 animate-pulse" };
+            case "error":
+                return { text: "Sync error", color: "text-red-400", dot: "bg-red-400" };
+            case "stopped":
+                return { text: "Sync stopped", color: "text-zinc-400", dot: "bg-zinc-500" };
+            case "unavailable":
+                return { text: "Sync folder unavailable", color: "text-amber-400", dot: "bg-amber-400" };
+            case "paused":
+                return { text: "Sync paused", color: "text-zinc-400", dot: "bg-zinc-500" };
             default:
                 return { text: "All files synced", color: "text-green-400", dot: "bg-green-400" };
         }
@@ -64,6 +523,11 @@ export default function Dashboard({ onLogout }: { onLogout: () => void }) {
 
     return (
         <div className="min-h-screen bg-gradient-to-b from-zinc-950 to-zinc-900 text-zinc-100 flex flex-col items-center px-6 py-8 font-sans">
+            {dragging && (
+                <div className="fixed inset-0 z-20 flex items-center justify-center border-2 border-dashed border-cyan-500/60 bg-zinc-950/80 text-cyan-300 pointer-events-none">
+                    Drop to add to your Xynoxa folder
+                </div>
+            )}
             {/* Logo */}
             <div className="mb-8">
                 <img src={logo} alt="Xynoxa" className="h-10 object-contain" />
@@ -87,14 +551,410 @@ export default function Dashboard({ onLogout }: { onLogout: () => void }) {
                     <span className={`text-lg font-medium ${status.color}`}>
                         {status.text}
                     </span>
+                    <button
+                        onClick={() => invoke(syncStatus === "paused" ? "resume_sync" : "pause_sync").catch(console.error)}
+                        className="ml-auto text-xs text-zinc-500 hover:text-cyan-400 transition-colors"
+                    >
+                        {syncStatus === "paused" ? "Resume" : "Pause"}
+                    </button>
                 </div>
 
+                {currentFile && (
+                    <div className="text-xs text-zinc-400 truncate mb-2 font-mono" title={currentFile}>
+                        {currentFile}
+                    </div>
+                )}
+                {dropProgress && (
+                    <div className="text-xs text-zinc-400 mb-2">{dropProgress}</div>
+                )}
+                {demo && (
+                    <div className="text-xs text-cyan-400 mb-2">Demo mode: syncing with a simulated server</div>
+                )}
+                {warning && (
+                    <div className="text-xs text-amber-400 mb-2 select-text">{warning}</div>
+                )}
+                {lastError && (
+                    <div className="text-xs text-red-400 mb-2">{lastError}</div>
+                )}
+                {serverChanged && (
+                    <div className="text-xs text-amber-400 mb-2 flex gap-3">
+                        <button onClick={() => confirmServerChange(true)} className="underline hover:text-white">
+                            Same server, new address
+                        </button>
+                        <button onClick={() => confirmServerChange(false)} className="underline hover:text-white">
+                            Different server
+                        </button>
+                    </div>
+                )}
+                {movedTo && (
+                    <div className="text-xs text-amber-400 mb-2">
+                        The sync folder seems to have moved to <span className="font-mono">{movedTo}</span>.{" "}
+                        <button onClick={relinkSyncFolder} className="underline hover:text-white">
+                            Use it
+                        </button>
+                    </div>
+                )}
+
                 {syncPath && (
                     <div className="text-xs text-zinc-500 truncate" title={syncPath}>
                         <span className="text-zinc-600">Folder:</span>{" "}
-                        <span className="text-zinc-400 font-mono">{syncPath}</span>
+                        <span className="text-zinc-400 font-mono">{syncPath}</span>{" "}
+                        <button onClick={moveSyncFolder} className="text-zinc-500 hover:text-cyan-400">
+                            Move
+                        </button>
+                    </div>
+                )}
+            </div>
+
+            {/* Search */}
+            <div className="w-full max-w-xs mb-6">
+                <input
+                    value={query}
+                    onChange={(e) => {
+                        setQuery(e.target.value);
+                        setRemoteResults(null);
+                    }}
+                    placeholder="Search synced files"
+                    className="w-full rounded-xl bg-zinc-800/50 border border-zinc-700/50 px-3 py-2 text-sm text-zinc-200 placeholder:text-zinc-600 focus:outline-none focus:border-cyan-500/50"
+                />
+                {results.length > 0 && (
+                    <div className="mt-2 max-h-60 overflow-y-auto rounded-xl border border-zinc-700/50 bg-zinc-800/50 p-2">
+                        {results.map((r) => (
+                            <div key={r.path} className="flex items-center gap-2 text-xs py-1">
+                                {r.id && r.hash !== "directory" && <Thumbnail fileId={r.id} />}
+                                <button
+                                    onClick={() => invoke(r.hash === "directory" ? "reveal_in_file_manager" : "open_file", { path: r.path }).catch(console.error)}
+                                    className="flex-1 truncate text-left font-mono text-zinc-300 hover:text-white"
+                                    title={r.path}
+                                >
+                                    {r.path}
+                                </button>
+                                <span className={r.status === "failed" ? "text-red-400" : r.status === "synced" ? "text-green-400" : "text-zinc-500"}>
+                                    {r.status}
+                                </span>
+                                <button
+                                    onClick={() => togglePin(r)}
+                                    className={r.pinned ? "text-cyan-400 hover:text-cyan-300" : "text-zinc-500 hover:text-cyan-400"}
+                                    title={r.pinned ? "Always available offline" : "Keep available offline"}
+                                >
+                                    {r.pinned ? "Unpin" : "Pin"}
+                                </button>
+                                {r.id && r.hash !== "directory" && (
+                                    <button onClick={() => showHistory(r)} className="text-zinc-500 hover:text-cyan-400" title="Earlier versions">
+                                        History
+                                    </button>
+                                )}
+                                <button
+                                    onClick={() => invoke("reveal_in_file_manager", { path: r.path }).catch(console.error)}
+                                    className="text-zinc-500 hover:text-cyan-400"
+                                >
+                                    Show
+                                </button>
+                            </div>
+                        ))}
                     </div>
                 )}
+                {history && (
+                    <div className="mt-2 max-h-60 overflow-y-auto rounded-xl border border-zinc-700/50 bg-zinc-800/50 p-2">
+                        <div className="flex items-center justify-between text-xs mb-1">
+                            <span className="truncate font-mono text-zinc-300" title={history.file.path}>{history.file.path}</span>
+                            <button onClick={() => setHistory(null)} className="text-zinc-500 hover:text-white">Close</button>
+                        </div>
+                        {history.versions.length === 0 && <div className="text-xs text-zinc-500">No earlier versions</div>}
+                        {history.versions.map((v) => (
+                            <div key={v.version} className="flex items-center gap-2 text-xs py-1">
+                                <span className="text-zinc-400">v{v.version}</span>
+                                <span className="flex-1 truncate text-zinc-500">
+                                    {v.createdAt ? new Date(v.createdAt).toLocaleString() : ""}
+                                </span>
+                                <button onClick={() => restoreVersion(history.file, v.version)} className="text-cyan-400 hover:text-cyan-300">
+                                    Restore
+                                </button>
+                            </div>
+                        ))}
+                    </div>
+                )}
+                <div className="mt-2 flex gap-3">
+                    {query.trim() && (
+                        <button onClick={searchServer} className="text-xs text-zinc-500 hover:text-cyan-400">
+                            Search the server
+                        </button>
+                    )}
+                    <button onClick={toggleStarred} className={starred ? "text-xs text-cyan-400" : "text-xs text-zinc-500 hover:text-cyan-400"}>
+                        Starred
+                    </button>
+                    <button
+                        onClick={() => (health ? setHealth(null) : showHealth(""))}
+                        className={health ? "text-xs text-cyan-400" : "text-xs text-zinc-500 hover:text-cyan-400"}
+                    >
+                        Folder health
+                    </button>
+                </div>
+                {health && (
+                    <div className="mt-2 max-h-60 overflow-y-auto rounded-xl border border-zinc-700/50 bg-zinc-800/50 p-2">
+                        <div className="flex items-center gap-2 text-xs py-1">
+                            <span className="flex-1 truncate font-mono text-zinc-300">/{health.path}</span>
+                            {health.path && (
+                                <button
+                                    onClick={() => showHealth(health.path.split("/").slice(0, -1).join("/"))}
+                                    className="text-zinc-500 hover:text-cyan-400"
+                                >
+                                    Up
+                                </button>
+                            )}
+                        </div>
+                        {health.pending + health.failed + health.conflicts === 0 && (
+                            <div className="text-xs text-zinc-500">Everything here is in sync</div>
+                        )}
+                        {health.branches.map((b) => (
+                            <button
+                                key={b.path}
+                                onClick={() => showHealth(b.path)}
+                                className="flex w-full items-center gap-2 text-xs py-1 text-left hover:text-white"
+                            >
+                                <span className="flex-1 truncate font-mono text-zinc-300">{b.path.split("/").pop()}/</span>
+                                {b.pending > 0 && <span className="text-zinc-400">{b.pending} pending</span>}
+                                {b.failed > 0 && <span className="text-red-400">{b.failed} failed</span>}
+                                {b.conflicts > 0 && <span className="text-amber-400">{b.conflicts} conflicts</span>}
+                            </button>
+                        ))}
+                        {health.branches.length === 0 && health.pending + health.failed + health.conflicts > 0 && (
+                            <div className="text-xs text-zinc-400">
+                                {health.pending} pending, {health.failed} failed, {health.conflicts} conflicts in this folder
+                            </div>
+                        )}
+                    </div>
+                )}
+                {starred && (
+                    <div className="mt-2 max-h-60 overflow-y-auto rounded-xl border border-zinc-700/50 bg-zinc-800/50 p-2">
+                        {starred.length === 0 && <div className="text-xs text-zinc-500">No favorites yet</div>}
+                        {starred.map((f) => (
+                            <div key={f.path} className="flex items-center gap-2 text-xs py-1">
+                                <button
+                                    onClick={() => invoke(f.hash === "directory" ? "reveal_in_file_manager" : "open_file", { path: f.path }).catch(console.error)}
+                                    className="flex-1 truncate text-left font-mono text-zinc-300 hover:text-white"
+                                    title={f.path}
+                                >
+                                    {f.path}
+                                </button>
+                                {f.tags.length > 0 && <span className="truncate text-zinc-500">{f.tags.join(", ")}</span>}
+                            </div>
+                        ))}
+                    </div>
+                )}
+                {remoteResults && (
+                    <div className="mt-2 max-h-60 overflow-y-auto rounded-xl border border-zinc-700/50 bg-zinc-800/50 p-2">
+                        {remoteResults.length === 0 && (
+                            <div className="text-xs text-zinc-500">No matches on the server</div>
+                        )}
+                        {remoteResults.map((r) => (
+                            <div key={r.id} className="flex items-center gap-2 text-xs py-1">
+                                <div className="flex-1 min-w-0">
+                                    <div className="truncate font-mono text-zinc-300" title={r.path ?? r.name}>
+                                        {r.path ?? r.name}
+                                    </div>
+                                    {r.snippet && <div className="truncate text-zinc-500">{r.snippet}</div>}
+                                </div>
+                                <span className={r.status === "failed" ? "text-red-400" : r.status === "synced" ? "text-green-400" : "text-zinc-500"}>
+                                    {r.status ?? "server only"}
+                                </span>
+                                {r.localPath && (
+                                    <button
+                                        onClick={() => invoke("reveal_in_file_manager", { path: r.localPath }).catch(console.error)}
+                                        className="text-zinc-500 hover:text-cyan-400"
+                                    >
+                                        Show
+                                    </button>
+                                )}
+                            </div>
+                        ))}
+                    </div>
+                )}
+            </div>
+
+            {conflictCount > 0 && (
+                <button
+                    onClick={() => invoke("open_conflict_resolver").catch(console.error)}
+                    className="w-full max-w-xs mb-4 rounded-lg border border-amber-500/40 bg-amber-500/10 px-3 py-2 text-xs text-amber-300 hover:bg-amber-500/20"
+                >
+                    {conflictCount === 1 ? "1 conflict" : `${conflictCount} conflicts`} to resolve
+                </button>
+            )}
+
+            {issues.length > 0 && (
+                <div className="w-full max-w-xs bg-zinc-800/50 backdrop-blur-sm rounded-2xl border border-red-500/40 p-4 mb-4 max-h-48 overflow-y-auto">
+                    <span className="block text-xs text-red-300 uppercase tracking-wider font-medium mb-2">Sync issues</span>
+                    {issues.map((issue) => (
+                        <div key={issue.path} className="flex items-center gap-2 text-xs py-1">
+                            <span
+                                className="flex-1 truncate font-mono text-zinc-300"
+                                title={`Changed without being saved, detected ${new Date(issue.detectedAt * 1000).toLocaleString()}`}
+                            >
+                                {issue.path}
+                            </span>
+                            <button onClick={() => repairIssue(issue.path, "download")} className="text-cyan-400 hover:text-cyan-300" title="Replace with the server copy">
+                                Restore
+                            </button>
+                            <button onClick={() => repairIssue(issue.path, "upload")} className="text-zinc-500 hover:text-amber-400" title="Keep this content and upload it">
+                                Upload
+                            </button>
+                        </div>
+                    ))}
+                </div>
+            )}
+
+            {knownIssues.length > 0 && (
+                <div className="w-full max-w-xs bg-zinc-800/50 backdrop-blur-sm rounded-2xl border border-amber-500/40 p-4 mb-4 max-h-48 overflow-y-auto">
+                    <span className="block text-xs text-amber-300 uppercase tracking-wider font-medium mb-2">Known issues</span>
+                    {knownIssues.map((issue) => (
+                        <div
+                            key={issue.key}
+                            className="text-xs py-1 text-zinc-300"
+                            title={`Since ${new Date(issue.firstSeen * 1000).toLocaleString()}, last ${new Date(issue.lastSeen * 1000).toLocaleString()}`}
+                        >
+                            <span className="block truncate">{issue.message}</span>
+                            {issue.count > 1 && <span className="text-zinc-500">{issue.count} times</span>}
+                        </div>
+                    ))}
+                </div>
+            )}
+
+            {transfers.length > 0 && (
+                <div className="w-full max-w-xs bg-zinc-800/50 backdrop-blur-sm rounded-2xl border border-zinc-700/50 p-4 mb-6 max-h-60 overflow-y-auto">
+                    <div className="flex items-center justify-between mb-3">
+                        <span className="text-xs text-zinc-500 uppercase tracking-wider font-medium">Transfers</span>
+                        <button
+                            onClick={() => invoke("open_transfer_monitor").catch(console.error)}
+                            className="text-xs text-zinc-500 hover:text-cyan-400"
+                        >
+                            Details
+                        </button>
+                    </div>
+                    {transfers.map((t) => (
+                        <div key={t.path} className="flex items-center gap-2 text-xs py-1">
+                            <span className="text-zinc-500">{t.direction === "upload" ? "↑" : "↓"}</span>
+                            <button
+                                onClick={() => invoke("reveal_in_file_manager", { path: t.path }).catch(console.error)}
+                                className="flex-1 truncate text-left font-mono text-zinc-300 hover:text-white"
+                                title={t.error ?? "Show in folder"}
+                            >
+                                {t.path}
+                            </button>
+                            <span className={t.state === "failed" ? "text-red-400" : "text-zinc-500"}>
+                                {t.state === "active" ? formatSize(t.size) : t.state}
+                            </span>
+                            {t.state === "queued" && (
+                                <button onClick={() => changeTransfer("prioritize", t.path)} className="text-zinc-500 hover:text-cyan-400" title="Transfer next">
+                                    Next
+                                </button>
+                            )}
+                            {t.state === "failed" || t.state === "cancelled" ? (
+                                <button onClick={() => changeTransfer("retry_transfer", t.path)} className="text-cyan-400 hover:text-cyan-300">
+                                    Retry
+                                </button>
+                            ) : (
+                                <button onClick={() => changeTransfer("cancel_transfer", t.path)} className="text-zinc-500 hover:text-red-400">
+                                    Skip
+                                </button>
+                            )}
+                        </div>
+                    ))}
+                </div>
+            )}
+
+            {bandwidth && (
+                <div className={`text-xs mb-2 ${bandwidth.capped ? "text-amber-400" : "text-zinc-500"}`}>
+                    This month: ↑ {formatSize(bandwidth.monthUploaded)} ↓ {formatSize(bandwidth.monthDownloaded)}
+                    {bandwidth.cap !== null && ` of ${formatSize(bandwidth.cap)}`}
+                    {bandwidth.capped && " - only small files sync"}
+                    <label className="block mt-1">
+                        Monthly cap (MB):{" "}
+                        <input
+                            type="number"
+                            min={0}
+                            value={capInput}
+                            onChange={(e) => setCapInput(e.target.value)}
+                            onBlur={saveBandwidthCap}
+                            placeholder="none"
+                            className="w-20 bg-zinc-800 border border-zinc-700 rounded px-1 text-zinc-300"
+                        />
+                    </label>
+                </div>
+            )}
+
+            <label className="flex items-center gap-2 text-xs text-zinc-500 mb-2">
+                <input
+                    type="checkbox"
+                    checked={encryptDatabase}
+                    disabled={converting}
+                    onChange={(e) => toggleDatabaseEncryption(e.target.checked)}
+                />
+                {converting ? "Converting local databases..." : "Encrypt local databases"}
+            </label>
+
+            {session && (
+                <p className={`text-xs mb-2 ${expiringSoon ? "text-amber-400" : "text-zinc-500"}`}>
+                    Signed in as {session.user.name ?? session.user.email ?? session.user.id}
+                    {expiresAt && ` · token expires ${expiresAt.toLocaleDateString()}`}
+                    {expiringSoon && " - create a new token soon"}
+                </p>
+            )}
+
+            <button
+                onClick={() => invoke("open_trash").catch(console.error)}
+                className="text-sm text-zinc-500 hover:text-cyan-400 transition-colors mb-2"
+            >
+                Trash
+            </button>
+
+            <button
+                onClick={rebuildIndex}
+                className="text-sm text-zinc-500 hover:text-cyan-400 transition-colors mb-2"
+                title="Recover from a damaged sync database without starting over"
+            >
+                Rebuild database
+            </button>
+
+            <button
+                onClick={() =>
+                    invoke<{ checks: DiagnosticCheck[] }>("run_diagnostics")
+                        .then((report) => setDiagnostics(report.checks))
+                        .catch((e) => setLastError(errorMessage(e)))
+                }
+                className="text-sm text-zinc-500 hover:text-cyan-400 transition-colors mb-2"
+            >
+                Run diagnostics
+            </button>
+
+            {diagnostics && (
+                <div className="w-full max-w-xs bg-zinc-800/50 backdrop-blur-sm rounded-2xl border border-zinc-700/50 p-4 mb-4">
+                    <div className="flex items-center justify-between mb-2">
+                        <span className="text-xs text-zinc-500 uppercase tracking-wider font-medium">Diagnostics</span>
+                        <button onClick={() => setDiagnostics(null)} className="text-xs text-zinc-500 hover:text-white">
+                            Close
+                        </button>
+                    </div>
+                    {diagnostics.map((check) => (
+                        <div key={check.name} className="text-xs py-1">
+                            <span className="text-zinc-300">{CHECK_LABELS[check.name] ?? check.name}: </span>
+                            <span className={CHECK_COLORS[check.status]}>{check.status}</span>
+                            <span className="block text-zinc-500 break-words">{check.detail}</span>
+                        </div>
+                    ))}
+                </div>
+            )}
+
+            <div className="text-sm text-zinc-500 mb-2">
+                Export report:{" "}
+                {(["csv", "json"] as const).map((format) => (
+                    <button
+                        key={format}
+                        onClick={() => invoke("export_report", { format }).catch((e) => setLastError(errorMessage(e)))}
+                        className="ml-1 hover:text-cyan-400 transition-colors uppercase"
+                    >
+                        {format}
+                    </button>
+                ))}
             </div>
 
             {/* Disconnect Button */}
@@ -4,14 +4,35 @@ import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
 import { Card, CardContent, CardDescription, CardFooter, CardHeader, CardTitle } from "@/components/ui/card";
+import { errorMessage, getAssertion, twoFactorChallenge, type TwoFactorChallenge } from "@/lib/utils";
 
 export default function Login({ onLogin }: { onLogin: () => void }) {
     const [token, setToken] = useState("");
     const [loading, setLoading] = useState(false);
     const [error, setError] = useState("");
+    const [challenge, setChallenge] = useState<TwoFactorChallenge | null>(null);
+    const [otp, setOtp] = useState("");
+
+    const submitSecondFactor = async (method: string, response: () => Promise<unknown>) => {
+        if (!challenge) return;
+        setLoading(true);
+        setError("");
+        try {
+            await invoke("submit_second_factor", { challengeId: challenge.challengeId, method, response: await response() });
+            onLogin();
+        } catch (err) {
+            setError(errorMessage(err));
+        } finally {
+            setLoading(false);
+        }
+    };
 
     const handleLogin = async (e: React.FormEvent) => {
         e.preventDefault();
+        if (challenge) {
+            await submitSecondFactor("totp", async () => ({ code: otp.trim() }));
+            return;
+        }
         setLoading(true);
         setError("");
 
@@ -20,7 +41,13 @@ export default function Login({ onLogin }: { onLogin: () => void }) {
             onLogin(); // Navigate to dashboard
         } catch (err) {
             console.error(err);
-            setError(typeof err === 'string' ? err : "Invalid token or connection failed");
+            const pending = twoFactorChallenge(err);
+            if (pending) {
+                setOtp("");
+                setChallenge(pending);
+            } else {
+                setError(err ? errorMessage(err) : "Invalid token or connection failed");
+            }
         } finally {
             setLoading(false);
         }
@@ -45,11 +72,41 @@ export default function Login({ onLogin }: { onLogin: () => void }) {
                                 type="password"
                                 placeholder="xyn-..."
                                 value={token}
-                                onChange={(e) => setToken(e.target.value)}
+                                onChange={(e) => {
+                                    setToken(e.target.value);
+                                    setChallenge(null);
+                                }}
                                 className="bg-zinc-950/50 border-zinc-800 focus:ring-cyan-500/50 focus:border-cyan-500 text-zinc-100 placeholder:text-zinc-600 transition-all font-mono"
                                 required
                             />
                         </div>
+                        {challenge && (
+                            <div className="space-y-2 text-left">
+                                <Label htmlFor="otp" className="text-zinc-300">Second Factor</Label>
+                                {challenge.methods.includes("totp") && (
+                                    <Input
+                                        id="otp"
+                                        value={otp}
+                                        onChange={(e) => setOtp(e.target.value)}
+                                        placeholder="123456"
+                                        inputMode="numeric"
+                                        autoComplete="one-time-code"
+                                        className="bg-zinc-950/50 border-zinc-800 text-zinc-100 font-mono"
+                                    />
+                                )}
+                                {challenge.methods.includes("webauthn") && challenge.webauthnOptions && (
+                                    <Button
+                                        type="button"
+                                        variant="secondary"
+                                        className="w-full"
+                                        disabled={loading}
+                                        onClick={() => submitSecondFactor("webauthn", () => getAssertion(challenge.webauthnOptions!))}
+                                    >
+                                        Use security key
+                                    </Button>
+                                )}
+                            </div>
+                        )}
                         {error && <div className="text-sm text-red-400 font-medium">{error}</div>}
                     </CardContent>
                     <CardFooter>
@@ -58,7 +115,7 @@ export default function Login({ onLogin }: { onLogin: () => void }) {
                             className="w-full bg-cyan-500 hover:bg-cyan-600 text-white shadow-lg shadow-cyan-500/20 transition-all duration-300 h-10 font-medium"
                             disabled={loading}
                         >
-                            {loading ? "Connecting..." : "Connect Account"}
+                            {loading ? "Connecting..." : challenge ? "Verify" : "Connect Account"}
                         </Button>
                     </CardFooter>
                 </form>
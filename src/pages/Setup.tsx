@@ -1,12 +1,57 @@
 import { useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { homeDir, join } from "@tauri-apps/api/path";
-import { open } from "@tauri-apps/plugin-dialog";
-import { enable as enableAutostart } from "@tauri-apps/plugin-autostart";
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
 import { Card, CardContent, CardDescription, CardFooter, CardHeader, CardTitle } from "@/components/ui/card";
+import { errorMessage, fieldErrors, getAssertion, type FieldError, type TwoFactorChallenge } from "@/lib/utils";
+
+// Answer of probe_server
+interface ServerProbe {
+    url: string;
+    name: string | null;
+    version: string | null;
+    apiVersion: number;
+}
+
+// Entry of discover_servers
+interface DiscoveredServer {
+    name: string;
+    url: string;
+}
+
+// Answer of start_pairing
+interface PairingPrompt {
+    code: string;
+    verificationUrl: string;
+    qrSvg: string | null;
+}
+
+// Entry of find_foreign_sync_folders
+interface ForeignFolder {
+    client: string;
+    serverUrl: string;
+    user: string | null;
+    localPath: string;
+}
+
+// Answer of estimate_initial_sync
+interface SyncEstimate {
+    local: { files: number; bytes: number };
+    remote: { files: number; bytes: number };
+    seconds: number | null;
+}
+
+const formatGb = (bytes: number) => `${(bytes / 1e9).toFixed(1)} GB`;
+
+const formatDuration = (seconds: number) => {
+    if (seconds < 60) return "less than a minute";
+    if (seconds < 3600) return `about ${Math.round(seconds / 60)} minutes`;
+    if (seconds < 48 * 3600) return `about ${Math.round(seconds / 3600)} hours`;
+    return `about ${Math.round(seconds / 86400)} days`;
+};
 
 interface SetupProps {
     onComplete: () => void;
@@ -15,11 +60,26 @@ interface SetupProps {
 export default function Setup({ onComplete }: SetupProps) {
     const [step, setStep] = useState(1);
     const [serverUrl, setServerUrl] = useState("https://dev.xynoxa.com");
+    const [server, setServer] = useState<ServerProbe | null>(null);
+    const [discovered, setDiscovered] = useState<DiscoveredServer[]>([]);
+    const [pairing, setPairing] = useState<PairingPrompt | null>(null);
+    const [challenge, setChallenge] = useState<TwoFactorChallenge | null>(null);
+    const [otp, setOtp] = useState("");
     const [token, setToken] = useState("");
     const [syncPath, setSyncPath] = useState("");
+    const [foreignFolders, setForeignFolders] = useState<ForeignFolder[]>([]);
+    const [estimate, setEstimate] = useState<SyncEstimate | null>(null);
     const [loading, setLoading] = useState(false);
     const [selectingFolder, setSelectingFolder] = useState(false);
     const [error, setError] = useState("");
+    const [invalid, setInvalid] = useState<Record<string, string>>({});
+
+    // Backend validation; shows the messages next to the fields
+    const validate = async (patch: Record<string, unknown>) => {
+        const errors = fieldErrors(await invoke<FieldError[]>("validate_config", { patch }));
+        setInvalid(errors);
+        return Object.keys(errors).length === 0;
+    };
 
     useEffect(() => {
         let active = true;
@@ -41,20 +101,126 @@ export default function Setup({ onComplete }: SetupProps) {
         };
     }, [syncPath]);
 
+    // Folders of the Nextcloud or ownCloud client that could be taken over
+    useEffect(() => {
+        if (step !== 3) return;
+        invoke<ForeignFolder[]>("find_foreign_sync_folders")
+            .then(setForeignFolders)
+            .catch((e) => console.warn("Looking for other sync clients failed", e));
+    }, [step]);
+
+    // What the first sync of the chosen folder is in for
+    useEffect(() => {
+        setEstimate(null);
+        if (step !== 3 || !syncPath) return;
+        let active = true;
+        invoke<SyncEstimate>("estimate_initial_sync", { path: syncPath })
+            .then((result) => {
+                if (active) setEstimate(result);
+            })
+            .catch((e) => console.warn("Initial sync estimate failed", e));
+        return () => {
+            active = false;
+        };
+    }, [step, syncPath]);
+
+    // Servers announcing themselves on the local network
+    useEffect(() => {
+        let active = true;
+        invoke<DiscoveredServer[]>("discover_servers")
+            .then((servers) => {
+                if (active) setDiscovered(servers);
+            })
+            .catch((e) => console.warn("Server discovery failed", e));
+        return () => {
+            active = false;
+        };
+    }, []);
+
+    useEffect(() => {
+        const unlisten = listen<TwoFactorChallenge>("auth://two-factor", (e) => {
+            setOtp("");
+            setChallenge(e.payload);
+        });
+        return () => {
+            unlisten.then((f) => f());
+        };
+    }, []);
+
+    const submitSecondFactor = async (method: string, response: unknown) => {
+        if (!challenge) return;
+        setError("");
+        setLoading(true);
+        try {
+            await invoke("submit_second_factor", { challengeId: challenge.challengeId, method, response });
+            setChallenge(null);
+            setStep(3);
+        } catch (e) {
+            setError("Verification failed: " + errorMessage(e));
+        } finally {
+            setLoading(false);
+        }
+    };
+
+    const handleSecurityKey = async () => {
+        if (!challenge?.webauthnOptions) return;
+        try {
+            await submitSecondFactor("webauthn", await getAssertion(challenge.webauthnOptions));
+        } catch (e) {
+            setError("Security key failed: " + errorMessage(e));
+        }
+    };
+
+    // A pairing approved in the browser replaces typing a token
+    useEffect(() => {
+        const unlisten = listen<{ approved: boolean; error: string | null }>("pairing://finished", (e) => {
+            setPairing(null);
+            if (e.payload.approved) {
+                setError("");
+                setStep(3);
+            } else {
+                setError("Pairing failed: " + (e.payload.error ?? "unknown error"));
+            }
+        });
+        return () => {
+            unlisten.then((f) => f());
+            invoke("cancel_pairing").catch(() => {});
+        };
+    }, []);
+
+    const handleStartPairing = async () => {
+        setError("");
+        setLoading(true);
+        try {
+            setPairing(await invoke<PairingPrompt>("start_pairing", { serverUrl }));
+        } catch (e) {
+            setError("Pairing failed: " + errorMessage(e));
+        } finally {
+            setLoading(false);
+        }
+    };
+
+    const handleCancelPairing = async () => {
+        setPairing(null);
+        await invoke("cancel_pairing").catch(() => {});
+    };
+
     const handleChooseFolder = async () => {
         setError("");
         setSelectingFolder(true);
         try {
-            const selected = await open({
-                directory: true,
-                multiple: false,
-                title: "Select Sync Folder"
-            });
-            if (typeof selected === "string" && selected.length > 0) {
+            // Validated by the backend; null when the dialog was cancelled
+            const selected = await invoke<string | null>("pick_sync_folder");
+            if (selected) {
+                setInvalid({});
                 setSyncPath(selected);
             }
         } catch (e) {
-            setError("Folder selection failed: " + e);
+            const errors = fieldErrors(e);
+            setInvalid(errors);
+            if (Object.keys(errors).length === 0) {
+                setError("Folder selection failed: " + errorMessage(e));
+            }
         } finally {
             setSelectingFolder(false);
         }
@@ -64,17 +230,32 @@ export default function Setup({ onComplete }: SetupProps) {
         setError("");
 
         if (step === 1) {
-            // Validate URL format
+            // Find the API first, so typos like a trailing /api are fixed before validating
+            setLoading(true);
+            let probe: ServerProbe;
             try {
-                new URL(serverUrl);
+                probe = await invoke<ServerProbe>("probe_server", { url: serverUrl });
+            } catch (e) {
+                setServer(null);
+                setError(errorMessage(e));
+                return;
+            } finally {
+                setLoading(false);
+            }
+            setServerUrl(probe.url);
+            setServer(probe);
+            if (await validate({ serverUrl: probe.url })) {
+                // Login talks to the server, so it needs the URL already
+                await invoke("save_config", { patch: { serverUrl: probe.url } });
                 setStep(2);
-            } catch {
-                setError("Invalid URL");
             }
         } else if (step === 2) {
+            if (challenge) {
+                await submitSecondFactor("totp", { code: otp.trim() });
+                return;
+            }
             // Validate Token (attempt login)
-            if (!(token.startsWith("xyn-") || token.startsWith("syn-"))) {
-                setError("Token must start with 'xyn-'");
+            if (!(await validate({ authToken: token }))) {
                 return;
             }
             setLoading(true);
@@ -82,25 +263,34 @@ export default function Setup({ onComplete }: SetupProps) {
                 await invoke("login", { token });
                 setStep(3);
             } catch (e) {
-                setError("Login failed: " + e);
+                // The challenge arrives as auth://two-factor and is shown below
+                if ((e as { code?: string })?.code !== "two_factor_required") {
+                    setError("Login failed: " + errorMessage(e));
+                }
             } finally {
                 setLoading(false);
             }
         } else if (step === 3) {
             // Save Config and Finish
-            if (!syncPath) {
-                setError("Please choose a local sync folder.");
+            if (!(await validate({ syncPath }))) {
                 return;
             }
             setLoading(true);
             try {
+                // Also ignores the other client's journal files
+                if (foreignFolders.some((f) => f.localPath === syncPath)) {
+                    await invoke("import_foreign_folder", { path: syncPath });
+                }
                 await invoke("save_config", {
-                    url: serverUrl,
-                    path: syncPath,
-                    completed: true
+                    patch: {
+                        serverUrl,
+                        syncPath,
+                        setupCompleted: true
+                    }
                 });
                 try {
-                    await enableAutostart();
+                    // Goes through the background portal under Flatpak
+                    await invoke("set_autostart", { enabled: true });
                 } catch (e) {
                     console.warn("Failed to enable autostart", e);
                 }
@@ -108,7 +298,11 @@ export default function Setup({ onComplete }: SetupProps) {
                 await invoke("start_sync", { token });
                 onComplete();
             } catch (e) {
-                setError("Setup failed: " + e);
+                const errors = fieldErrors(e);
+                setInvalid(errors);
+                if (Object.keys(errors).length === 0) {
+                    setError("Setup failed: " + errorMessage(e));
+                }
             } finally {
                 setLoading(false);
             }
@@ -133,11 +327,41 @@ export default function Setup({ onComplete }: SetupProps) {
                             <Label>Server Address</Label>
                             <Input
                                 value={serverUrl}
-                                onChange={(e) => setServerUrl(e.target.value)}
+                                onChange={(e) => {
+                                    setServerUrl(e.target.value);
+                                    setServer(null);
+                                }}
                                 placeholder="https://dev.xynoxa.com"
                                 className="bg-zinc-950/50 border-zinc-800"
                             />
                             <p className="text-xs text-zinc-500">Enter the URL of your Xynoxa instance.</p>
+                            {discovered.length > 0 && (
+                                <div className="space-y-1">
+                                    <p className="text-xs text-zinc-400">Found on your network:</p>
+                                    {discovered.map((d) => (
+                                        <Button
+                                            key={d.url}
+                                            type="button"
+                                            variant="secondary"
+                                            size="sm"
+                                            onClick={() => {
+                                                setServerUrl(d.url);
+                                                setServer(null);
+                                            }}
+                                            className="w-full justify-between"
+                                        >
+                                            <span>{d.name}</span>
+                                            <span className="text-xs text-zinc-400">{d.url}</span>
+                                        </Button>
+                                    ))}
+                                </div>
+                            )}
+                            {invalid.serverUrl && <p className="text-xs text-red-400">{invalid.serverUrl}</p>}
+                            {server && (
+                                <p className="text-xs text-cyan-400">
+                                    Found {server.name ?? "Xynoxa"}{server.version ? ` ${server.version}` : ""} at {server.url}
+                                </p>
+                            )}
                         </div>
                     )}
                     {step === 2 && (
@@ -146,11 +370,62 @@ export default function Setup({ onComplete }: SetupProps) {
                             <Input
                                 type="password"
                                 value={token}
-                                onChange={(e) => setToken(e.target.value)}
+                                onChange={(e) => {
+                                    setToken(e.target.value);
+                                    setChallenge(null);
+                                }}
                                 placeholder="xyn-..."
                                 className="bg-zinc-950/50 border-zinc-800 font-mono"
                             />
                             <p className="text-xs text-zinc-500">Create a token in your user settings.</p>
+                            {invalid.authToken && <p className="text-xs text-red-400">{invalid.authToken}</p>}
+                            {challenge && (
+                                <div className="space-y-2 rounded-md border border-zinc-800 p-3">
+                                    <Label>Second Factor</Label>
+                                    {challenge.methods.includes("totp") && (
+                                        <Input
+                                            value={otp}
+                                            onChange={(e) => setOtp(e.target.value)}
+                                            placeholder="123456"
+                                            inputMode="numeric"
+                                            autoComplete="one-time-code"
+                                            className="bg-zinc-950/50 border-zinc-800 font-mono"
+                                        />
+                                    )}
+                                    {challenge.methods.includes("webauthn") && challenge.webauthnOptions && (
+                                        <Button variant="secondary" onClick={handleSecurityKey} disabled={loading} className="w-full">
+                                            Use security key
+                                        </Button>
+                                    )}
+                                    <p className="text-xs text-zinc-500">Your account requires a second factor to sign in.</p>
+                                </div>
+                            )}
+                            {pairing ? (
+                                <div className="space-y-2 rounded-md border border-zinc-800 p-3 text-center">
+                                    {pairing.qrSvg && (
+                                        <div
+                                            className="mx-auto w-48 rounded bg-white p-2"
+                                            dangerouslySetInnerHTML={{ __html: pairing.qrSvg }}
+                                        />
+                                    )}
+                                    <p className="text-xs text-zinc-400">
+                                        Open {pairing.verificationUrl} in your browser and confirm this code:
+                                    </p>
+                                    <p className="font-mono text-2xl tracking-widest text-white">{pairing.code}</p>
+                                    <Button variant="ghost" size="sm" onClick={handleCancelPairing} className="text-zinc-400">
+                                        Cancel
+                                    </Button>
+                                </div>
+                            ) : (
+                                <Button
+                                    variant="secondary"
+                                    onClick={handleStartPairing}
+                                    disabled={loading}
+                                    className="w-full"
+                                >
+                                    Sign in with your browser instead
+                                </Button>
+                            )}
                         </div>
                     )}
                     {step === 3 && (
@@ -176,6 +451,35 @@ export default function Setup({ onComplete }: SetupProps) {
                             <p className="text-xs text-zinc-500">
                                 Pick a local folder to sync. You can create a new folder in the dialog.
                             </p>
+                            {foreignFolders.map((f) => (
+                                <button
+                                    key={f.localPath}
+                                    type="button"
+                                    onClick={() => {
+                                        setInvalid({});
+                                        setSyncPath(f.localPath);
+                                    }}
+                                    className="block w-full text-left text-xs text-cyan-400 hover:text-cyan-300"
+                                    title={f.user ? `${f.user} on ${f.serverUrl}` : f.serverUrl}
+                                >
+                                    Use your {f.client} folder {f.localPath}
+                                </button>
+                            ))}
+                            {foreignFolders.some((f) => f.localPath === syncPath) && (
+                                <p className="text-xs text-amber-400">
+                                    Files that already match the server are kept, not downloaded again. Remove this folder
+                                    from the other client first so both do not sync it.
+                                </p>
+                            )}
+                            {estimate && (
+                                <p className="text-xs text-zinc-400">
+                                    First sync: {estimate.local.files} files ({formatGb(estimate.local.bytes)}) to upload,{" "}
+                                    {estimate.remote.files} files ({formatGb(estimate.remote.bytes)}) to download
+                                    {estimate.seconds !== null && `, ${formatDuration(estimate.seconds)} at the current speed`}
+                                    . Files already on both sides are not transferred again.
+                                </p>
+                            )}
+                            {invalid.syncPath && <p className="text-xs text-red-400">{invalid.syncPath}</p>}
                         </div>
                     )}
                     {error && <div className="text-sm text-red-400 font-medium">{error}</div>}
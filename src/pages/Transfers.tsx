@@ -0,0 +1,128 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { errorMessage } from "@/lib/utils";
+
+interface Transfer {
+    path: string;
+    direction: "upload" | "download";
+    size: number | null;
+    state: "queued" | "active" | "failed" | "cancelled";
+    error: string | null;
+    priority: number;
+    // Chunks of a large upload the server confirmed
+    progress: ChunkProgress | null;
+}
+
+interface ChunkProgress {
+    chunk: number;
+    chunks: number;
+    bytes: number;
+}
+
+const formatSize = (bytes: number | null) => (bytes === null ? "-" : `${(bytes / 1024 / 1024).toFixed(1)} MB`);
+
+// Rendered in the "transfers" window opened by open_transfer_monitor
+export default function Transfers() {
+    const [transfers, setTransfers] = useState<Transfer[]>([]);
+    const [progress, setProgress] = useState<{ done: number; total: number } | null>(null);
+    const [error, setError] = useState("");
+
+    const load = async () => {
+        try {
+            setTransfers(await invoke<Transfer[]>("get_transfer_queue"));
+        } catch (e) {
+            setError(errorMessage(e));
+        }
+    };
+
+    useEffect(() => {
+        load();
+        const unlisteners = [
+            listen("sync://file-started", load),
+            listen("sync://file-finished", load),
+            listen<{ done: number; total: number }>("sync://progress", (e) => setProgress(e.payload)),
+            listen<ChunkProgress & { path: string }>("sync://chunk-progress", (e) => {
+                const { path, ...chunks } = e.payload;
+                setTransfers((current) => current.map((t) => (t.path === path ? { ...t, progress: chunks } : t)));
+            }),
+            listen<{ state: string }>("sync://state", (e) => {
+                if (e.payload.state !== "syncing") setProgress(null);
+                load();
+            }),
+        ];
+        return () => {
+            unlisteners.forEach((u) => u.then((f) => f()));
+        };
+    }, []);
+
+    const change = async (command: "cancel_transfer" | "retry_transfer" | "prioritize", path: string) => {
+        setError("");
+        try {
+            await invoke(command, { path });
+        } catch (e) {
+            setError(errorMessage(e));
+        }
+        load();
+    };
+
+    return (
+        <div className="min-h-screen bg-zinc-950 p-4 text-white">
+            <h1 className="mb-1 text-lg font-semibold">Transfers</h1>
+            <p className="mb-4 text-xs text-zinc-500">
+                {progress ? `${progress.done} of ${progress.total} done in this sync` : `${transfers.length} in queue`}
+            </p>
+            {error && <div className="mb-3 text-sm text-red-400">{error}</div>}
+            <table className="w-full table-fixed text-xs">
+                <thead className="text-left text-zinc-500">
+                    <tr>
+                        <th className="w-6" />
+                        <th>Path</th>
+                        <th className="w-20">Size</th>
+                        <th className="w-20">State</th>
+                        <th className="w-24" />
+                    </tr>
+                </thead>
+                <tbody>
+                    {transfers.map((t) => (
+                        <tr key={t.path} className="border-t border-zinc-800 align-top">
+                            <td className="py-1 text-zinc-500">{t.direction === "upload" ? "↑" : "↓"}</td>
+                            <td className="py-1">
+                                <div className="truncate font-mono text-zinc-200" title={t.path}>
+                                    {t.path}
+                                </div>
+                                {t.error && <div className="text-red-400">{t.error}</div>}
+                            </td>
+                            <td className="py-1 text-zinc-400">{formatSize(t.size)}</td>
+                            <td className={`py-1 ${t.state === "failed" ? "text-red-400" : "text-zinc-400"}`}>
+                                {t.state}
+                                {t.priority > 0 && " (next)"}
+                                {t.progress && t.progress.chunks > 0 && (
+                                    <div className="text-zinc-500">
+                                        chunk {t.progress.chunk} of {t.progress.chunks}
+                                    </div>
+                                )}
+                            </td>
+                            <td className="space-x-2 py-1 text-right">
+                                {t.state === "queued" && (
+                                    <button onClick={() => change("prioritize", t.path)} className="text-zinc-500 hover:text-cyan-400">
+                                        Next
+                                    </button>
+                                )}
+                                {t.state === "failed" || t.state === "cancelled" ? (
+                                    <button onClick={() => change("retry_transfer", t.path)} className="text-cyan-400 hover:text-cyan-300">
+                                        Retry
+                                    </button>
+                                ) : (
+                                    <button onClick={() => change("cancel_transfer", t.path)} className="text-zinc-500 hover:text-red-400">
+                                        Skip
+                                    </button>
+                                )}
+                            </td>
+                        </tr>
+                    ))}
+                </tbody>
+            </table>
+        </div>
+    );
+}
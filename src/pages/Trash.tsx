@@ -0,0 +1,107 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from "@/lib/utils";
+
+// Answer of list_trashed_files
+interface TrashedFile {
+    id: string;
+    name: string;
+    path: string | null;
+    folderId: string | null;
+    size: string | null;
+    deletedAt: string | null;
+}
+
+const formatSize = (size: string | null) => (size === null ? "-" : `${(Number(size) / 1024 / 1024).toFixed(1)} MB`);
+
+const formatDate = (date: string | null) => (date ? new Date(date).toLocaleString() : "-");
+
+// Rendered in the "trash" window opened by open_trash
+export default function Trash() {
+    const [files, setFiles] = useState<TrashedFile[]>([]);
+    const [loading, setLoading] = useState(true);
+    const [error, setError] = useState("");
+
+    const load = async () => {
+        try {
+            setFiles(await invoke<TrashedFile[]>("list_trashed_files"));
+        } catch (e) {
+            setError(errorMessage(e));
+        } finally {
+            setLoading(false);
+        }
+    };
+
+    useEffect(() => {
+        load();
+    }, []);
+
+    const change = async (command: "restore_file" | "purge_trashed_file", fileId: string) => {
+        setError("");
+        try {
+            await invoke(command, { fileId });
+        } catch (e) {
+            setError(errorMessage(e));
+        }
+        load();
+    };
+
+    const emptyTrash = async () => {
+        if (!window.confirm(`Delete ${files.length} files for good? This cannot be undone.`)) return;
+        setError("");
+        try {
+            await invoke("empty_trash");
+        } catch (e) {
+            setError(errorMessage(e));
+        }
+        load();
+    };
+
+    return (
+        <div className="min-h-screen bg-zinc-950 p-4 text-white">
+            <div className="mb-4 flex items-center justify-between">
+                <div>
+                    <h1 className="mb-1 text-lg font-semibold">Trash</h1>
+                    <p className="text-xs text-zinc-500">{loading ? "Loading..." : `${files.length} deleted files`}</p>
+                </div>
+                {files.length > 0 && (
+                    <button onClick={emptyTrash} className="text-xs text-zinc-500 hover:text-red-400">
+                        Empty trash
+                    </button>
+                )}
+            </div>
+            {error && <div className="mb-3 text-sm text-red-400">{error}</div>}
+            <table className="w-full table-fixed text-xs">
+                <thead className="text-left text-zinc-500">
+                    <tr>
+                        <th>Name</th>
+                        <th className="w-20">Size</th>
+                        <th className="w-36">Deleted</th>
+                        <th className="w-28" />
+                    </tr>
+                </thead>
+                <tbody>
+                    {files.map((f) => (
+                        <tr key={f.id} className="border-t border-zinc-800 align-top">
+                            <td className="py-1">
+                                <div className="truncate font-mono text-zinc-200" title={f.path ?? f.name}>
+                                    {f.path ?? f.name}
+                                </div>
+                            </td>
+                            <td className="py-1 text-zinc-400">{formatSize(f.size)}</td>
+                            <td className="py-1 text-zinc-400">{formatDate(f.deletedAt)}</td>
+                            <td className="space-x-2 py-1 text-right">
+                                <button onClick={() => change("restore_file", f.id)} className="text-cyan-400 hover:text-cyan-300">
+                                    Restore
+                                </button>
+                                <button onClick={() => change("purge_trashed_file", f.id)} className="text-zinc-500 hover:text-red-400">
+                                    Delete
+                                </button>
+                            </td>
+                        </tr>
+                    ))}
+                </tbody>
+            </table>
+        </div>
+    );
+}